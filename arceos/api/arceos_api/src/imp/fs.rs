@@ -78,6 +78,22 @@ pub fn ax_rename(old: &str, new: &str) -> AxResult {
     axfs::api::rename(old, new)
 }
 
+pub fn ax_symlink(target: &str, link: &str) -> AxResult {
+    axfs::api::symlink(target, link)
+}
+
+pub fn ax_read_link(path: &str) -> AxResult<String> {
+    axfs::api::read_link(path)
+}
+
+pub fn ax_hard_link(original: &str, link: &str) -> AxResult {
+    axfs::api::hard_link(original, link)
+}
+
+pub fn ax_symlink_metadata(path: &str) -> AxResult<AxFileAttr> {
+    axfs::api::symlink_metadata(path).map(|m| *m.raw_metadata())
+}
+
 pub fn ax_current_dir() -> AxResult<String> {
     axfs::api::current_dir()
 }
@@ -85,3 +101,19 @@ pub fn ax_current_dir() -> AxResult<String> {
 pub fn ax_set_current_dir(path: &str) -> AxResult {
     axfs::api::set_current_dir(path)
 }
+
+pub fn ax_mount(source: &str, target: &str, fstype: &str) -> AxResult {
+    axfs::api::mount_fs(source, target, fstype)
+}
+
+pub fn ax_umount(target: &str, lazy: bool) -> AxResult {
+    if lazy {
+        axfs::api::umount_lazy(target)
+    } else {
+        axfs::api::umount(target)
+    }
+}
+
+pub fn ax_sync() -> AxResult {
+    axfs::api::sync()
+}
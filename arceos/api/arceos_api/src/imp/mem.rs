@@ -12,6 +12,44 @@ cfg_alloc! {
     }
 }
 
+#[cfg(feature = "alloc-stats")]
+mod stats {
+    use alloc::vec::Vec;
+    use core::fmt;
+
+    pub use axalloc::stats::{
+        AllocRecord as AxAllocRecord, AllocStats as AxAllocStats, Checkpoint as AxAllocCheckpoint,
+        TagGuard as AxAllocTagGuard, TagStats as AxAllocTagStats,
+    };
+
+    pub fn ax_alloc_stats() -> AxAllocStats {
+        axalloc::stats::stats()
+    }
+
+    pub fn ax_alloc_tag_stats() -> Vec<AxAllocTagStats> {
+        axalloc::stats::tag_stats()
+    }
+
+    pub fn ax_alloc_set_tag(tag: &'static str) -> AxAllocTagGuard {
+        axalloc::stats::set_tag(tag)
+    }
+
+    pub fn ax_alloc_checkpoint() -> AxAllocCheckpoint {
+        axalloc::stats::checkpoint()
+    }
+
+    pub fn ax_alloc_outstanding_since(checkpoint: &AxAllocCheckpoint) -> Vec<AxAllocRecord> {
+        axalloc::stats::outstanding_since(checkpoint)
+    }
+
+    pub fn ax_dump_meminfo(out: &mut dyn fmt::Write) -> fmt::Result {
+        axalloc::stats::dump_meminfo(out)
+    }
+}
+
+#[cfg(feature = "alloc-stats")]
+pub use self::stats::*;
+
 cfg_dma! {
     pub use axdma::DMAInfo;
 
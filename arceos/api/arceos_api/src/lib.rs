@@ -30,7 +30,8 @@ pub mod config {
 /// System operations.
 pub mod sys {
     define_api! {
-        /// Shutdown the whole system and all CPUs.
+        /// Shutdown the whole system and all CPUs, after writing back the
+        /// cached data of the filesystems.
         pub fn ax_terminate() -> !;
     }
 }
@@ -75,6 +76,40 @@ pub mod mem {
         pub unsafe fn ax_dealloc(ptr: NonNull<u8>, layout: Layout);
     }
 
+    define_api_type! {
+        @cfg "alloc-stats";
+        /// Allocation counters of the global allocator.
+        pub type AxAllocStats;
+        /// Byte totals of the live allocations with the same tag.
+        pub type AxAllocTagStats;
+        /// A live allocation, returned by [`ax_alloc_outstanding_since`].
+        pub type AxAllocRecord;
+        /// A point in the allocation history.
+        pub type AxAllocCheckpoint;
+        /// Restores the previous allocation tag when dropped.
+        pub type AxAllocTagGuard;
+    }
+
+    define_api! {
+        @cfg "alloc-stats";
+        /// Returns the allocation counters: per-size-class counts, the
+        /// current and peak usage, and the number of failed allocations.
+        pub fn ax_alloc_stats() -> AxAllocStats;
+        /// Returns the byte totals of live allocations for each tag.
+        pub fn ax_alloc_tag_stats() -> alloc::vec::Vec<AxAllocTagStats>;
+        /// Charges the following allocations to `tag`, until the returned
+        /// guard is dropped.
+        pub fn ax_alloc_set_tag(tag: &'static str) -> AxAllocTagGuard;
+        /// Creates a checkpoint of the allocation history.
+        pub fn ax_alloc_checkpoint() -> AxAllocCheckpoint;
+        /// Returns the allocations made after `checkpoint` that are still
+        /// outstanding, e.g. to find leaks.
+        pub fn ax_alloc_outstanding_since(checkpoint: &AxAllocCheckpoint) -> alloc::vec::Vec<AxAllocRecord>;
+        /// Writes a summary of the memory usage in the style of
+        /// `/proc/meminfo`.
+        pub fn ax_dump_meminfo(out: &mut dyn core::fmt::Write) -> core::fmt::Result;
+    }
+
     define_api_type! {
         @cfg "dma";
         pub type DMAInfo;
@@ -249,11 +284,31 @@ pub mod fs {
         ///
         /// It will delete the original file if `old` already exists.
         pub fn ax_rename(old: &str, new: &str) -> AxResult;
+        /// Creates a symbolic link at `link` pointing to `target`.
+        pub fn ax_symlink(target: &str, link: &str) -> AxResult;
+        /// Returns the target of the symbolic link at `path`.
+        pub fn ax_read_link(path: &str) -> AxResult<alloc::string::String>;
+        /// Creates a hard link at `link` to the file `original`.
+        pub fn ax_hard_link(original: &str, link: &str) -> AxResult;
+        /// Returns the attributes of the file at `path`, without following
+        /// the symbolic link.
+        pub fn ax_symlink_metadata(path: &str) -> AxResult<AxFileAttr>;
 
         /// Returns the current working directory.
         pub fn ax_current_dir() -> AxResult<alloc::string::String>;
         /// Changes the current working directory to the specified path.
         pub fn ax_set_current_dir(path: &str) -> AxResult;
+
+        /// Creates a filesystem of the type `fstype` from the device `source`,
+        /// and mounts it on the directory `target`.
+        pub fn ax_mount(source: &str, target: &str, fstype: &str) -> AxResult;
+        /// Unmounts the filesystem mounted on `target`.
+        ///
+        /// If `lazy` is set, it is unmounted even if busy, together with all
+        /// filesystems mounted under it.
+        pub fn ax_umount(target: &str, lazy: bool) -> AxResult;
+        /// Writes all cached filesystem data back to the storage devices.
+        pub fn ax_sync() -> AxResult;
     }
 }
 
@@ -37,6 +37,8 @@ typedef struct {{
 
         let allow_types = [
             "stat",
+            "statfs",
+            "flock",
             "size_t",
             "ssize_t",
             "off_t",
@@ -62,12 +64,18 @@ typedef struct {{
             "IPPROTO_.*",
             "FD_.*",
             "F_.*",
+            "LOCK_.*",
             "_SC_.*",
             "EPOLL_CTL_.*",
             "EPOLL.*",
             "RLIMIT_.*",
             "EAI_.*",
             "MAXADDRS",
+            "MS_.*",
+            "MNT_.*",
+            "AT_.*",
+            "UTIME_.*",
+            "TMPFS_MAGIC",
         ];
 
         #[derive(Debug)]
@@ -1,22 +1,31 @@
+use alloc::string::String;
 use alloc::sync::Arc;
-use core::ffi::{c_char, c_int};
+use core::ffi::{c_char, c_int, c_void};
 
-use axerrno::{LinuxError, LinuxResult};
-use axfs::fops::OpenOptions;
+use axerrno::{AxError, LinuxError, LinuxResult};
+use axfs::fops::{
+    FileAttr, FileMeta, FilePerm, FsStat, LockHandle, LockType, OpenOptions, RecordLock,
+};
 use axio::{PollState, SeekFrom};
 use axsync::Mutex;
 
 use super::fd_ops::{get_file_like, FileLike};
 use crate::{ctypes, utils::char_ptr_to_str};
 
+/// File wrapper for `axfs::fops::File`.
 pub struct File {
     inner: Mutex<axfs::fops::File>,
+    path: String,
+    /// The advisory locks, which are waited for without locking `inner`.
+    locks: LockHandle,
 }
 
 impl File {
-    fn new(inner: axfs::fops::File) -> Self {
+    fn new(inner: axfs::fops::File, path: String) -> Self {
         Self {
+            locks: inner.lock_handle(),
             inner: Mutex::new(inner),
+            path,
         }
     }
 
@@ -24,7 +33,13 @@ impl File {
         super::fd_ops::add_file_like(Arc::new(self))
     }
 
-    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+    /// Returns the path the file was opened with.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Gets the opened file by `fd`.
+    pub fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
         let f = super::fd_ops::get_file_like(fd)?;
         f.into_any()
             .downcast::<Self>()
@@ -42,21 +57,8 @@ impl FileLike for File {
     }
 
     fn stat(&self) -> LinuxResult<ctypes::stat> {
-        let metadata = self.inner.lock().get_attr()?;
-        let ty = metadata.file_type() as u8;
-        let perm = metadata.perm().bits() as u32;
-        let st_mode = ((ty as u32) << 12) | perm;
-        Ok(ctypes::stat {
-            st_ino: 1,
-            st_nlink: 1,
-            st_mode,
-            st_uid: 1000,
-            st_gid: 1000,
-            st_size: metadata.size() as _,
-            st_blocks: metadata.blocks() as _,
-            st_blksize: 512,
-            ..Default::default()
-        })
+        let file = self.inner.lock();
+        Ok(attr_to_stat(&file.get_attr()?, file.get_meta()))
     }
 
     fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
@@ -75,6 +77,130 @@ impl FileLike for File {
     }
 }
 
+/// Convert the attributes of a node to `struct stat`.
+///
+/// The owner is reported as 1000 and the timestamps as zeros if `meta` is
+/// `None`, i.e. the filesystem does not keep them.
+fn attr_to_stat(metadata: &FileAttr, meta: Option<FileMeta>) -> ctypes::stat {
+    let ty = metadata.file_type() as u8;
+    let perm = metadata.perm().bits() as u32;
+    let st_mode = ((ty as u32) << 12) | perm;
+    let mut st = ctypes::stat {
+        st_ino: 1,
+        st_nlink: 1,
+        st_mode,
+        st_uid: 1000,
+        st_gid: 1000,
+        st_size: metadata.size() as _,
+        st_blocks: metadata.blocks() as _,
+        st_blksize: 512,
+        ..Default::default()
+    };
+    if let Some(meta) = meta {
+        st.st_uid = meta.uid;
+        st.st_gid = meta.gid;
+        st.st_atim = meta.atime.into();
+        st.st_mtim = meta.mtime.into();
+        st.st_ctim = meta.ctime.into();
+    }
+    st
+}
+
+/// Convert the space usage of a filesystem to `struct statfs`. Only ramfs
+/// reports it, so it is always of tmpfs.
+fn fs_stat_to_statfs(stat: FsStat) -> ctypes::statfs {
+    ctypes::statfs {
+        f_type: ctypes::TMPFS_MAGIC as _,
+        f_bsize: stat.block_size as _,
+        f_blocks: stat.blocks,
+        f_bfree: stat.free_blocks,
+        f_bavail: stat.free_blocks,
+        f_namelen: 255,
+        f_frsize: stat.block_size as _,
+        ..Default::default()
+    }
+}
+
+/// Convert an error of `statfs`, which is reported as
+/// [`AxError::Unsupported`] by the filesystems that do not support it.
+fn statfs_error(e: AxError) -> LinuxError {
+    match e {
+        AxError::Unsupported => LinuxError::ENOSYS,
+        e => path_error(e),
+    }
+}
+
+/// Convert an error of changing the metadata, which is reported as
+/// [`AxError::Unsupported`] by the filesystems that do not keep it.
+fn meta_error(e: AxError) -> LinuxError {
+    match e {
+        AxError::Unsupported => LinuxError::EPERM,
+        e => path_error(e),
+    }
+}
+
+/// Convert an error of a lock, which reports a deadlock as
+/// [`AxError::ResourceBusy`].
+fn lock_error(e: AxError) -> LinuxError {
+    match e {
+        AxError::ResourceBusy => LinuxError::EDEADLK,
+        e => e.into(),
+    }
+}
+
+/// Convert `struct flock` to a record lock of `file`. The range starts at
+/// `l_start` from the start, the current offset or the end of the file by
+/// `l_whence`, and ends before it if `l_len` is negative.
+fn flock_to_record_lock(file: &File, flock: &ctypes::flock) -> LinuxResult<RecordLock> {
+    let ty = match flock.l_type as u32 {
+        ctypes::F_RDLCK => LockType::Shared,
+        ctypes::F_WRLCK => LockType::Exclusive,
+        ctypes::F_UNLCK => LockType::Unlock,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let base = match flock.l_whence {
+        0 => 0,
+        1 => file.inner.lock().seek(SeekFrom::Current(0))?,
+        2 => file.inner.lock().get_attr()?.size(),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let start = (base as i64)
+        .checked_add(flock.l_start)
+        .ok_or(LinuxError::EOVERFLOW)?;
+    let (start, len) = if flock.l_len < 0 {
+        let len = flock.l_len.checked_neg().ok_or(LinuxError::EINVAL)?;
+        (start.checked_sub(len).ok_or(LinuxError::EINVAL)?, len)
+    } else {
+        (start, flock.l_len)
+    };
+    if start < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(RecordLock {
+        ty,
+        start: start as u64,
+        len: len as u64,
+        owner: 0,
+    })
+}
+
+/// Convert the owner IDs of `chown`, where `-1` leaves it unchanged.
+fn owner_ids(uid: ctypes::uid_t, gid: ctypes::gid_t) -> (Option<u32>, Option<u32>) {
+    let id = |id: u32| (id != u32::MAX).then_some(id);
+    (id(uid as u32), id(gid as u32))
+}
+
+/// Convert an error of path resolution, which reports too many symbolic
+/// links as [`axfs::api::TOO_MANY_LINKS`]. Every syscall taking a path must
+/// convert its errors by this.
+fn path_error(e: AxError) -> LinuxError {
+    if e == axfs::api::TOO_MANY_LINKS {
+        LinuxError::ELOOP
+    } else {
+        e.into()
+    }
+}
+
 /// Convert open flags to [`OpenOptions`].
 fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
     let flags = flags as u32;
@@ -111,24 +237,38 @@ pub fn sys_open(filename: *const c_char, flags: c_int, mode: ctypes::mode_t) ->
     debug!("sys_open <= {:?} {:#o} {:#o}", filename, flags, mode);
     syscall_body!(sys_open, {
         let options = flags_to_options(flags, mode);
-        let file = axfs::fops::File::open(filename?, &options)?;
-        File::new(file).add_to_fd_table()
+        let filename = filename?;
+        let file = axfs::fops::File::open(filename, &options).map_err(path_error)?;
+        File::new(file, filename.into()).add_to_fd_table()
     })
 }
 
 /// Set the position of the file indicated by `fd`.
 ///
-/// Return its position after seek.
+/// `SEEK_DATA` and `SEEK_HOLE` skip to the next data or hole of a sparse
+/// file, failing with `ENXIO` past the end. Return its position after seek.
 pub fn sys_lseek(fd: c_int, offset: ctypes::off_t, whence: c_int) -> ctypes::off_t {
     debug!("sys_lseek <= {} {} {}", fd, offset, whence);
     syscall_body!(sys_lseek, {
+        let file = File::from_fd(fd)?;
+        let mut file = file.inner.lock();
         let pos = match whence {
             0 => SeekFrom::Start(offset as _),
             1 => SeekFrom::Current(offset as _),
             2 => SeekFrom::End(offset as _),
+            // SEEK_DATA and SEEK_HOLE, which fail past the end of the file
+            3 | 4 => {
+                let offset = u64::try_from(offset).map_err(|_| LinuxError::ENXIO)?;
+                let pos = if whence == 3 {
+                    file.seek_data(offset)?
+                } else {
+                    file.seek_hole(offset)?
+                };
+                return pos.ok_or(LinuxError::ENXIO);
+            }
             _ => return Err(LinuxError::EINVAL),
         };
-        let off = File::from_fd(fd)?.inner.lock().seek(pos)?;
+        let off = file.seek(pos)?;
         Ok(off)
     })
 }
@@ -145,8 +285,9 @@ pub unsafe fn sys_stat(path: *const c_char, buf: *mut ctypes::stat) -> c_int {
         }
         let mut options = OpenOptions::new();
         options.read(true);
-        let file = axfs::fops::File::open(path?, &options)?;
-        let st = File::new(file).stat()?;
+        let path = path?;
+        let file = axfs::fops::File::open(path, &options).map_err(path_error)?;
+        let st = File::new(file, path.into()).stat()?;
         unsafe { *buf = st };
         Ok(0)
     })
@@ -177,7 +318,221 @@ pub unsafe fn sys_lstat(path: *const c_char, buf: *mut ctypes::stat) -> ctypes::
         if buf.is_null() {
             return Err(LinuxError::EFAULT);
         }
-        unsafe { *buf = Default::default() }; // TODO
+        let metadata = axfs::api::symlink_metadata(path?).map_err(path_error)?;
+        let meta = metadata.raw_meta().copied();
+        unsafe { *buf = attr_to_stat(metadata.raw_metadata(), meta) };
+        Ok(0)
+    })
+}
+
+/// Get the space usage of the filesystem that `path` is in, and write into
+/// `buf`.
+///
+/// Return 0 if success, or `ENOSYS` if the filesystem does not report it.
+pub unsafe fn sys_statfs(path: *const c_char, buf: *mut ctypes::statfs) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("sys_statfs <= {:?} {:#x}", path, buf as usize);
+    syscall_body!(sys_statfs, {
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let stat = axfs::api::statfs(path?).map_err(statfs_error)?;
+        unsafe { *buf = fs_stat_to_statfs(stat) };
+        Ok(0)
+    })
+}
+
+/// Like [`sys_statfs`], for the filesystem that the file `fd` is in.
+pub unsafe fn sys_fstatfs(fd: c_int, buf: *mut ctypes::statfs) -> c_int {
+    debug!("sys_fstatfs <= {} {:#x}", fd, buf as usize);
+    syscall_body!(sys_fstatfs, {
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let stat = File::from_fd(fd)?.inner.lock().fs_stat();
+        unsafe { *buf = fs_stat_to_statfs(stat.map_err(statfs_error)?) };
+        Ok(0)
+    })
+}
+
+/// Change the permission bits of the file at `path` to `mode`.
+///
+/// Return 0 if success, or `EPERM` if the filesystem does not keep them.
+pub fn sys_chmod(path: *const c_char, mode: ctypes::mode_t) -> c_int {
+    syscall_body!(sys_chmod, {
+        let path = char_ptr_to_str(path)?;
+        debug!("sys_chmod <= {:?} {:#o}", path, mode);
+        let perm = FilePerm::from_bits_truncate(mode as u16);
+        axfs::api::set_permissions(path, perm).map_err(meta_error)?;
+        Ok(0)
+    })
+}
+
+/// Change the permission bits of the file `fd` to `mode`.
+///
+/// Return 0 if success.
+pub fn sys_fchmod(fd: c_int, mode: ctypes::mode_t) -> c_int {
+    debug!("sys_fchmod <= {} {:#o}", fd, mode);
+    syscall_body!(sys_fchmod, {
+        let perm = FilePerm::from_bits_truncate(mode as u16);
+        File::from_fd(fd)?
+            .inner
+            .lock()
+            .set_perm(perm)
+            .map_err(meta_error)?;
+        Ok(0)
+    })
+}
+
+/// Change the owner and the group of the file at `path`. An ID of `-1` is
+/// left unchanged.
+///
+/// Return 0 if success, or `EPERM` if the filesystem does not keep them.
+pub fn sys_chown(path: *const c_char, uid: ctypes::uid_t, gid: ctypes::gid_t) -> c_int {
+    syscall_body!(sys_chown, {
+        let path = char_ptr_to_str(path)?;
+        debug!("sys_chown <= {:?} {} {}", path, uid, gid);
+        let (uid, gid) = owner_ids(uid, gid);
+        axfs::api::chown(path, uid, gid).map_err(meta_error)?;
+        Ok(0)
+    })
+}
+
+/// Like [`sys_chown`], but does not follow the symbolic link at `path`.
+pub fn sys_lchown(path: *const c_char, uid: ctypes::uid_t, gid: ctypes::gid_t) -> c_int {
+    syscall_body!(sys_lchown, {
+        let path = char_ptr_to_str(path)?;
+        debug!("sys_lchown <= {:?} {} {}", path, uid, gid);
+        let (uid, gid) = owner_ids(uid, gid);
+        axfs::api::lchown(path, uid, gid).map_err(meta_error)?;
+        Ok(0)
+    })
+}
+
+/// Change the owner and the group of the file `fd`. An ID of `-1` is left
+/// unchanged.
+///
+/// Return 0 if success.
+pub fn sys_fchown(fd: c_int, uid: ctypes::uid_t, gid: ctypes::gid_t) -> c_int {
+    debug!("sys_fchown <= {} {} {}", fd, uid, gid);
+    syscall_body!(sys_fchown, {
+        let (uid, gid) = owner_ids(uid, gid);
+        File::from_fd(fd)?
+            .inner
+            .lock()
+            .set_owner(uid, gid)
+            .map_err(meta_error)?;
+        Ok(0)
+    })
+}
+
+/// Change the access and modification times of the file at `path`, or of
+/// the file `dirfd` if `path` is null.
+///
+/// `times` holds the two times, where `UTIME_NOW` and `UTIME_OMIT` in
+/// `tv_nsec` mean the current time and unchanged respectively. Both are set
+/// to the current time if `times` is null. Only `AT_FDCWD` is supported as
+/// `dirfd` for relative paths, and `flags` must be 0.
+///
+/// Return 0 if success.
+pub unsafe fn sys_utimensat(
+    dirfd: c_int,
+    path: *const c_char,
+    times: *const ctypes::timespec,
+    flags: c_int,
+) -> c_int {
+    debug!(
+        "sys_utimensat <= {} {:#x} {:#x} {:#x}",
+        dirfd, path as usize, times as usize, flags
+    );
+    syscall_body!(sys_utimensat, {
+        if flags != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let now = axhal::time::wall_time();
+        let time = |ts: ctypes::timespec| {
+            if ts.tv_nsec == ctypes::UTIME_NOW as _ {
+                Ok(Some(now))
+            } else if ts.tv_nsec == ctypes::UTIME_OMIT as _ {
+                Ok(None)
+            } else if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+                Err(LinuxError::EINVAL)
+            } else {
+                Ok(Some(ts.into()))
+            }
+        };
+        let (atime, mtime) = if times.is_null() {
+            (Some(now), Some(now))
+        } else {
+            let times = unsafe { core::slice::from_raw_parts(times, 2) };
+            (time(times[0])?, time(times[1])?)
+        };
+
+        if path.is_null() {
+            File::from_fd(dirfd)?
+                .inner
+                .lock()
+                .set_times(atime, mtime)
+                .map_err(meta_error)?;
+        } else {
+            let path = char_ptr_to_str(path)?;
+            if dirfd != ctypes::AT_FDCWD && !path.starts_with('/') {
+                return Err(LinuxError::EINVAL);
+            }
+            axfs::api::set_times(path, atime, mtime).map_err(meta_error)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Create a symbolic link `linkpath` pointing to `target`.
+///
+/// Return 0 if success.
+pub fn sys_symlink(target: *const c_char, linkpath: *const c_char) -> c_int {
+    syscall_body!(sys_symlink, {
+        let target = char_ptr_to_str(target)?;
+        let linkpath = char_ptr_to_str(linkpath)?;
+        debug!("sys_symlink <= {:?} {:?}", target, linkpath);
+        axfs::api::symlink(target, linkpath).map_err(path_error)?;
+        Ok(0)
+    })
+}
+
+/// Read the target of the symbolic link `path` into `buf`, without the
+/// terminating null byte. It is truncated if `buf` is too small.
+///
+/// Return the number of bytes placed in `buf`.
+pub fn sys_readlink(path: *const c_char, buf: *mut c_char, bufsize: usize) -> ctypes::ssize_t {
+    syscall_body!(sys_readlink, {
+        let path = char_ptr_to_str(path)?;
+        debug!("sys_readlink <= {:?} {:#x} {}", path, buf as usize, bufsize);
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let target = axfs::api::read_link(path).map_err(|e| match e {
+            AxError::InvalidInput => LinuxError::EINVAL, // not a symbolic link
+            e => path_error(e),
+        })?;
+        let len = target.len().min(bufsize);
+        let dst = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, len) };
+        dst.copy_from_slice(&target.as_bytes()[..len]);
+        Ok(len as ctypes::ssize_t)
+    })
+}
+
+/// Create a hard link `new` to the file `old`.
+///
+/// Return 0 if success.
+pub fn sys_link(old: *const c_char, new: *const c_char) -> c_int {
+    syscall_body!(sys_link, {
+        let old_path = char_ptr_to_str(old)?;
+        let new_path = char_ptr_to_str(new)?;
+        debug!("sys_link <= old: {:?}, new: {:?}", old_path, new_path);
+        axfs::api::hard_link(old_path, new_path).map_err(|e| match e {
+            AxError::PermissionDenied => LinuxError::EPERM, // a directory
+            AxError::Unsupported => LinuxError::EXDEV,
+            e => path_error(e),
+        })?;
         Ok(0)
     })
 }
@@ -211,7 +566,143 @@ pub fn sys_rename(old: *const c_char, new: *const c_char) -> c_int {
         let old_path = char_ptr_to_str(old)?;
         let new_path = char_ptr_to_str(new)?;
         debug!("sys_rename <= old: {:?}, new: {:?}", old_path, new_path);
-        axfs::api::rename(old_path, new_path)?;
+        axfs::api::rename(old_path, new_path).map_err(path_error)?;
         Ok(0)
     })
 }
+
+/// Mount the filesystem `source` of the type `fstype` on `target`.
+///
+/// `target` must be an existing directory, or it fails with `ENOENT`. `data`
+/// is the comma-separated options string, of which only `size=` of tmpfs is
+/// supported, and the mount flags are ignored. Return 0 if success.
+pub fn sys_mount(
+    source: *const c_char,
+    target: *const c_char,
+    fstype: *const c_char,
+    flags: usize,
+    data: *const c_void,
+) -> c_int {
+    syscall_body!(sys_mount, {
+        let source = if source.is_null() {
+            ""
+        } else {
+            char_ptr_to_str(source)?
+        };
+        let target = char_ptr_to_str(target)?;
+        let fstype = char_ptr_to_str(fstype)?;
+        let options = if data.is_null() {
+            ""
+        } else {
+            char_ptr_to_str(data as *const c_char)?
+        };
+        debug!(
+            "sys_mount <= {:?} {:?} {:?} {:#x} {:?}",
+            source, target, fstype, flags, options
+        );
+        if flags & (ctypes::MS_REMOUNT | ctypes::MS_BIND | ctypes::MS_MOVE) as usize != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        axfs::api::mount_fs_with_options(source, target, fstype, options).map_err(|e| match e {
+            AxError::Unsupported => LinuxError::ENODEV,
+            e => path_error(e),
+        })?;
+        Ok(0)
+    })
+}
+
+/// Unmount the filesystem mounted on `target`.
+///
+/// With `MNT_DETACH`, it is unmounted even if busy. Return 0 if success.
+pub fn sys_umount2(target: *const c_char, flags: c_int) -> c_int {
+    syscall_body!(sys_umount2, {
+        let target = char_ptr_to_str(target)?;
+        debug!("sys_umount2 <= {:?} {:#x}", target, flags);
+        if flags as u32 & ctypes::MNT_DETACH != 0 {
+            axfs::api::umount_lazy(target).map_err(path_error)?;
+        } else {
+            axfs::api::umount(target).map_err(path_error)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Apply or remove an advisory lock on the whole file `fd`.
+///
+/// `operation` is `LOCK_SH`, `LOCK_EX` or `LOCK_UN`, or'ed with `LOCK_NB` to
+/// fail with `EWOULDBLOCK` instead of waiting for a conflicting lock.
+pub fn sys_flock(fd: c_int, operation: c_int) -> c_int {
+    debug!("sys_flock <= {} {:#x}", fd, operation);
+    syscall_body!(sys_flock, {
+        let ty = match operation as u32 & !ctypes::LOCK_NB {
+            ctypes::LOCK_SH => LockType::Shared,
+            ctypes::LOCK_EX => LockType::Exclusive,
+            ctypes::LOCK_UN => LockType::Unlock,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        let wait = operation as u32 & ctypes::LOCK_NB == 0;
+        let file = File::from_fd(fd)?;
+        file.locks.flock(ty, wait).map_err(lock_error)?;
+        Ok(0)
+    })
+}
+
+/// Get, apply or remove a record lock of the file `fd` described by `flock`,
+/// for the commands `F_GETLK`, `F_SETLK` and `F_SETLKW` of `fcntl`.
+///
+/// The locks are owned by the tasks, and `F_GETLK` reports the ID of the
+/// task holding a conflicting lock as `l_pid`.
+pub(crate) fn fcntl_lock(fd: c_int, cmd: u32, flock: *mut ctypes::flock) -> LinuxResult<c_int> {
+    if flock.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    let file = File::from_fd(fd)?;
+    let flock = unsafe { &mut *flock };
+    let lock = flock_to_record_lock(&file, flock)?;
+    if cmd != ctypes::F_GETLK {
+        let wait = cmd == ctypes::F_SETLKW;
+        file.locks
+            .set_record_lock(&lock, wait)
+            .map_err(lock_error)?;
+        return Ok(0);
+    }
+    match file.locks.get_record_lock(&lock).map_err(lock_error)? {
+        Some(held) => {
+            flock.l_type = match held.ty {
+                LockType::Shared => ctypes::F_RDLCK,
+                _ => ctypes::F_WRLCK,
+            } as _;
+            flock.l_whence = 0;
+            flock.l_start = held.start as _;
+            flock.l_len = held.len as _;
+            flock.l_pid = held.owner as _;
+        }
+        None => flock.l_type = ctypes::F_UNLCK as _,
+    }
+    Ok(0)
+}
+
+/// Write the cached data of the file `fd` back to the storage device.
+///
+/// Return 0 if success.
+pub fn sys_fsync(fd: c_int) -> c_int {
+    debug!("sys_fsync <= {}", fd);
+    syscall_body!(sys_fsync, {
+        File::from_fd(fd)?.inner.lock().flush()?;
+        Ok(0)
+    })
+}
+
+/// Like [`sys_fsync`], as the metadata is always written back together.
+pub fn sys_fdatasync(fd: c_int) -> c_int {
+    debug!("sys_fdatasync <= {}", fd);
+    sys_fsync(fd)
+}
+
+/// Write all cached filesystem data back to the storage devices.
+pub fn sys_sync() {
+    debug!("sys_sync");
+    if let Err(e) = axfs::api::sync() {
+        warn!("sys_sync: {:?}", e);
+    }
+}
@@ -37,9 +37,16 @@ pub use imp::task::{sys_exit, sys_getpid, sys_sched_yield};
 pub use imp::time::{sys_clock_gettime, sys_nanosleep};
 
 #[cfg(feature = "fd")]
-pub use imp::fd_ops::{sys_close, sys_dup, sys_dup2, sys_fcntl, get_file_like};
+pub use imp::fd_ops::{add_file_like, get_file_like, sys_close, sys_dup, sys_dup2, sys_fcntl, FileLike};
 #[cfg(feature = "fs")]
-pub use imp::fs::{sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_rename, sys_stat};
+pub use imp::fs::File;
+#[cfg(feature = "fs")]
+pub use imp::fs::{
+    sys_chmod, sys_chown, sys_fchmod, sys_fchown, sys_fdatasync, sys_flock, sys_fstat, sys_fstatfs,
+    sys_fsync, sys_getcwd, sys_lchown, sys_link, sys_lseek, sys_lstat, sys_mount, sys_open,
+    sys_readlink, sys_rename, sys_stat, sys_statfs, sys_symlink, sys_sync, sys_umount2,
+    sys_utimensat,
+};
 #[cfg(feature = "select")]
 pub use imp::io_mpx::sys_select;
 #[cfg(feature = "epoll")]
@@ -22,6 +22,8 @@
 //! - Upperlayer stacks (fs, net, display)
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
+//!     - `overlayfs`: Keep the root filesystem intact, writing the changes to the memory.
+//!     - `initramfs`: Embed a cpio archive as the root filesystem if there is no disk.
 //!     - `net`: Enable networking support.
 //!     - `display`: Enable graphics support.
 //! - Device drivers
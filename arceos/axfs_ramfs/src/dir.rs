@@ -3,24 +3,31 @@ use alloc::sync::{Arc, Weak};
 use alloc::{string::String, vec::Vec};
 
 use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
-use axfs_vfs::{VfsError, VfsResult};
+use axfs_vfs::{VfsError, VfsNodePerm, VfsResult};
 use spin::RwLock;
 
 use crate::file::FileNode;
+use crate::quota::Quota;
+use crate::symlink::SymlinkNode;
+use crate::{node_meta, MetaCell};
 
 /// The directory node in the RAM filesystem.
 ///
 /// It implements [`axfs_vfs::VfsNodeOps`].
 pub struct DirNode {
     this: Weak<DirNode>,
+    meta: MetaCell,
+    quota: Arc<Quota>,
     parent: RwLock<Weak<dyn VfsNodeOps>>,
     children: RwLock<BTreeMap<String, VfsNodeRef>>,
 }
 
 impl DirNode {
-    pub(super) fn new(parent: Option<Weak<dyn VfsNodeOps>>) -> Arc<Self> {
+    pub(super) fn new(parent: Option<Weak<dyn VfsNodeOps>>, quota: Arc<Quota>) -> Arc<Self> {
         Arc::new_cyclic(|this| Self {
             this: this.clone(),
+            meta: MetaCell::new(VfsNodePerm::default_dir()),
+            quota,
             parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
             children: RwLock::new(BTreeMap::new()),
         })
@@ -30,6 +37,15 @@ impl DirNode {
         *self.parent.write() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
     }
 
+    /// Returns the ownership, permission bits and timestamps.
+    pub fn meta(&self) -> &MetaCell {
+        &self.meta
+    }
+
+    pub(crate) fn quota(&self) -> &Arc<Quota> {
+        &self.quota
+    }
+
     /// Returns a string list of all entries in this directory.
     pub fn get_entries(&self) -> Vec<String> {
         self.children.read().keys().cloned().collect()
@@ -47,11 +63,41 @@ impl DirNode {
             return Err(VfsError::AlreadyExists);
         }
         let node: VfsNodeRef = match ty {
-            VfsNodeType::File => Arc::new(FileNode::new()),
-            VfsNodeType::Dir => Self::new(Some(self.this.clone())),
+            VfsNodeType::File => Arc::new(FileNode::new(self.quota.clone())),
+            VfsNodeType::Dir => Self::new(Some(self.this.clone()), self.quota.clone()),
+            VfsNodeType::SymLink => Arc::new(SymlinkNode::new()),
             _ => return Err(VfsError::Unsupported),
         };
         self.children.write().insert(name.into(), node);
+        self.meta.modified();
+        Ok(())
+    }
+
+    /// Adds `node` to this directory with the given name, as a hard link.
+    ///
+    /// Directories cannot be linked, and nodes of other filesystems, including
+    /// other RAM filesystems, are rejected with [`VfsError::Unsupported`].
+    pub fn link_node(&self, name: &str, node: VfsNodeRef) -> VfsResult {
+        let any = node.as_any();
+        if any.is::<DirNode>() {
+            return Err(VfsError::PermissionDenied);
+        } else if let Some(file) = any.downcast_ref::<FileNode>() {
+            // the pages are counted against the limit of its own filesystem
+            if !Arc::ptr_eq(file.quota(), &self.quota) {
+                return Err(VfsError::Unsupported);
+            }
+        } else if !any.is::<SymlinkNode>() {
+            return Err(VfsError::Unsupported);
+        }
+        let mut children = self.children.write();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        if let Some(meta) = node_meta(node.as_ref()) {
+            meta.changed(); // the link count
+        }
+        children.insert(name.into(), node);
+        self.meta.modified();
         Ok(())
     }
 
@@ -64,7 +110,11 @@ impl DirNode {
                 return Err(VfsError::DirectoryNotEmpty);
             }
         }
+        if let Some(meta) = node_meta(node.as_ref()) {
+            meta.changed(); // the link count
+        }
         children.remove(name);
+        self.meta.modified();
         Ok(())
     }
 }
@@ -73,7 +123,12 @@ impl VfsNodeOps for DirNode {
     axfs_vfs::impl_vfs_dir_default! {}
 
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_dir(4096, 0))
+        Ok(VfsNodeAttr::new(
+            self.meta.get().perm,
+            VfsNodeType::Dir,
+            4096,
+            0,
+        ))
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
@@ -111,11 +166,13 @@ impl VfsNodeOps for DirNode {
                     if let Some((name, node)) = children.next() {
                         *ent = VfsDirEntry::new(name, node.get_attr().unwrap().file_type());
                     } else {
+                        self.meta.accessed();
                         return Ok(i);
                     }
                 }
             }
         }
+        self.meta.accessed();
         Ok(dirents.len())
     }
 
@@ -202,7 +259,11 @@ impl VfsNodeOps for DirNode {
         
         //更新child
         children.remove(src_name);
+        if let Some(meta) = node_meta(node.as_ref()) {
+            meta.changed();
+        }
         children.insert(dst_name.into(), node);
+        self.meta.modified();
         
        
         Ok(())
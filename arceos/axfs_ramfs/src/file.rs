@@ -1,56 +1,164 @@
-use alloc::vec::Vec;
+use alloc::collections::{btree_map::Entry, BTreeMap};
+use alloc::{boxed::Box, sync::Arc};
 use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeType};
 use spin::RwLock;
 
+use crate::quota::{Quota, PAGE_SIZE};
+use crate::MetaCell;
+
+type Page = Box<[u8; PAGE_SIZE]>;
+
+/// The sparse content of a file, where the missing pages are holes that
+/// read as zeros.
+struct Content {
+    size: u64,
+    pages: BTreeMap<u64, Page>,
+}
+
 /// The file node in the RAM filesystem.
 ///
-/// It implements [`axfs_vfs::VfsNodeOps`].
+/// It implements [`axfs_vfs::VfsNodeOps`]. The content is allocated in pages
+/// only when written, and the pages are counted against the size limit of
+/// the filesystem.
 pub struct FileNode {
-    content: RwLock<Vec<u8>>,
+    meta: MetaCell,
+    quota: Arc<Quota>,
+    content: RwLock<Content>,
 }
 
 impl FileNode {
-    pub(super) const fn new() -> Self {
+    pub(super) fn new(quota: Arc<Quota>) -> Self {
         Self {
-            content: RwLock::new(Vec::new()),
+            meta: MetaCell::new(VfsNodePerm::default_file()),
+            quota,
+            content: RwLock::new(Content {
+                size: 0,
+                pages: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Returns the ownership, permission bits and timestamps.
+    pub fn meta(&self) -> &MetaCell {
+        &self.meta
+    }
+
+    pub(crate) fn quota(&self) -> &Arc<Quota> {
+        &self.quota
+    }
+
+    /// Returns the offset of the first data at or after `offset`, i.e.
+    /// `SEEK_DATA`, or `None` if there is no more data.
+    pub fn seek_data(&self, offset: u64) -> Option<u64> {
+        let content = self.content.read();
+        let (&index, _) = content.pages.range(offset / PAGE_SIZE as u64..).next()?;
+        let pos = offset.max(index * PAGE_SIZE as u64);
+        (pos < content.size).then_some(pos)
+    }
+
+    /// Returns the offset of the first hole at or after `offset`, i.e.
+    /// `SEEK_HOLE`, or `None` if `offset` is past the end of the file. There
+    /// is an implicit hole at the end of the file.
+    pub fn seek_hole(&self, offset: u64) -> Option<u64> {
+        let content = self.content.read();
+        if offset >= content.size {
+            return None;
         }
+        let mut index = offset / PAGE_SIZE as u64;
+        while content.pages.contains_key(&index) {
+            index += 1;
+        }
+        Some(offset.max(index * PAGE_SIZE as u64).min(content.size))
     }
 }
 
 impl VfsNodeOps for FileNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_file(self.content.read().len() as _, 0))
+        let content = self.content.read();
+        Ok(VfsNodeAttr::new(
+            self.meta.get().perm,
+            VfsNodeType::File,
+            content.size,
+            (content.pages.len() * PAGE_SIZE / 512) as _,
+        ))
+    }
+
+    fn fsync(&self) -> VfsResult {
+        Ok(()) // nothing to write back
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
         let mut content = self.content.write();
-        if size < content.len() as u64 {
-            content.truncate(size as _);
-        } else {
-            content.resize(size as _, 0);
+        if size < content.size {
+            // free the pages past the end, and zero the tail of the last one
+            let first_freed = size.div_ceil(PAGE_SIZE as u64);
+            let freed = content.pages.split_off(&first_freed);
+            self.quota.free(freed.len());
+            let tail = size as usize % PAGE_SIZE;
+            if let Some(page) = content.pages.get_mut(&(size / PAGE_SIZE as u64)) {
+                page[tail..].fill(0);
+            }
         }
+        content.size = size;
+        self.meta.modified();
         Ok(())
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
         let content = self.content.read();
-        let start = content.len().min(offset as usize);
-        let end = content.len().min(offset as usize + buf.len());
-        let src = &content[start..end];
-        buf[..src.len()].copy_from_slice(src);
-        Ok(src.len())
+        let len = content.size.saturating_sub(offset).min(buf.len() as u64) as usize;
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let start = pos as usize % PAGE_SIZE;
+            let n = (PAGE_SIZE - start).min(len - done);
+            let dst = &mut buf[done..done + n];
+            match content.pages.get(&(pos / PAGE_SIZE as u64)) {
+                Some(page) => dst.copy_from_slice(&page[start..start + n]),
+                None => dst.fill(0),
+            }
+            done += n;
+        }
+        self.meta.accessed();
+        Ok(len)
     }
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
-        let offset = offset as usize;
+        offset
+            .checked_add(buf.len() as u64)
+            .ok_or(VfsError::InvalidInput)?;
         let mut content = self.content.write();
-        if offset + buf.len() > content.len() {
-            content.resize(offset + buf.len(), 0);
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let start = pos as usize % PAGE_SIZE;
+            let n = (PAGE_SIZE - start).min(buf.len() - done);
+            let page = match content.pages.entry(pos / PAGE_SIZE as u64) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    if !self.quota.alloc() {
+                        break; // a short write if anything is written
+                    }
+                    entry.insert(Box::new([0; PAGE_SIZE]))
+                }
+            };
+            page[start..start + n].copy_from_slice(&buf[done..done + n]);
+            done += n;
+        }
+        if done == 0 && !buf.is_empty() {
+            return Err(VfsError::StorageFull);
         }
-        let dst = &mut content[offset..offset + buf.len()];
-        dst.copy_from_slice(&buf[..dst.len()]);
-        Ok(buf.len())
+        content.size = content.size.max(offset + done as u64);
+        self.meta.modified();
+        Ok(done)
     }
 
     impl_vfs_non_dir_default! {}
 }
+
+impl Drop for FileNode {
+    fn drop(&mut self) {
+        self.quota.free(self.content.get_mut().pages.len());
+    }
+}
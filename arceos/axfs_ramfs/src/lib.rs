@@ -8,15 +8,22 @@ extern crate alloc;
 
 mod dir;
 mod file;
+mod meta;
+mod quota;
+mod symlink;
 
 #[cfg(test)]
 mod tests;
 
 pub use self::dir::DirNode;
 pub use self::file::FileNode;
+pub use self::meta::{node_meta, set_clock, MetaCell, NodeMeta};
+pub use self::quota::{node_usage, FsUsage, PAGE_SIZE};
+pub use self::symlink::SymlinkNode;
 
 use alloc::sync::Arc;
 use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult};
+use quota::Quota;
 use spin::once::Once;
 
 /// A RAM filesystem that implements [`axfs_vfs::VfsOps`].
@@ -28,12 +35,29 @@ pub struct RamFileSystem {
 impl RamFileSystem {
     /// Create a new instance.
     pub fn new() -> Self {
+        Self::with_quota(Quota::new(usize::MAX))
+    }
+
+    /// Create a new instance whose files can take up to `limit` bytes, which
+    /// is rounded up to [`PAGE_SIZE`]. Writes beyond it fail with
+    /// [`VfsError::StorageFull`](axfs_vfs::VfsError::StorageFull).
+    pub fn with_limit(limit: u64) -> Self {
+        let pages = limit.div_ceil(PAGE_SIZE as u64);
+        Self::with_quota(Quota::new(pages.try_into().unwrap_or(usize::MAX)))
+    }
+
+    fn with_quota(quota: Quota) -> Self {
         Self {
             parent: Once::new(),
-            root: DirNode::new(None),
+            root: DirNode::new(None, Arc::new(quota)),
         }
     }
 
+    /// Returns the space used by the files and the size limit.
+    pub fn usage(&self) -> FsUsage {
+        self.root.quota().usage()
+    }
+
     /// Returns the root directory node in [`Arc<DirNode>`](DirNode).
     pub fn root_dir_node(&self) -> Arc<DirNode> {
         self.root.clone()
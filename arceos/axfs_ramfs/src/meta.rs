@@ -0,0 +1,108 @@
+use core::time::Duration;
+
+use axfs_vfs::{VfsNodeOps, VfsNodePerm};
+use spin::{Once, RwLock};
+
+use crate::{DirNode, FileNode, SymlinkNode};
+
+static CLOCK: Once<fn() -> Duration> = Once::new();
+
+/// Sets the clock for the timestamps of the nodes, which returns the time
+/// since the epoch. The timestamps are zeros before it is set.
+pub fn set_clock(clock: fn() -> Duration) {
+    CLOCK.call_once(|| clock);
+}
+
+fn now() -> Duration {
+    CLOCK.get().map_or(Duration::ZERO, |clock| clock())
+}
+
+/// The ownership, permission bits and timestamps of a node.
+#[derive(Debug, Clone, Copy)]
+pub struct NodeMeta {
+    /// The permission bits.
+    pub perm: VfsNodePerm,
+    /// The user ID of the owner.
+    pub uid: u32,
+    /// The group ID of the owner.
+    pub gid: u32,
+    /// The time of the last access.
+    pub atime: Duration,
+    /// The time of the last modification of the content.
+    pub mtime: Duration,
+    /// The time of the last change of the content or the metadata.
+    pub ctime: Duration,
+}
+
+/// The metadata of a node, which is updated in place.
+pub struct MetaCell(RwLock<NodeMeta>);
+
+impl MetaCell {
+    pub(crate) fn new(perm: VfsNodePerm) -> Self {
+        let now = now();
+        Self(RwLock::new(NodeMeta {
+            perm,
+            uid: 0,
+            gid: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+        }))
+    }
+
+    /// Returns a snapshot of the metadata.
+    pub fn get(&self) -> NodeMeta {
+        *self.0.read()
+    }
+
+    /// Sets the permission bits.
+    pub fn set_perm(&self, perm: VfsNodePerm) {
+        let mut meta = self.0.write();
+        meta.perm = perm;
+        meta.ctime = now();
+    }
+
+    /// Sets the owner and the group. `None` keeps the current one.
+    pub fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) {
+        let mut meta = self.0.write();
+        meta.uid = uid.unwrap_or(meta.uid);
+        meta.gid = gid.unwrap_or(meta.gid);
+        meta.ctime = now();
+    }
+
+    /// Sets the access and modification times. `None` keeps the current one.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) {
+        let mut meta = self.0.write();
+        meta.atime = atime.unwrap_or(meta.atime);
+        meta.mtime = mtime.unwrap_or(meta.mtime);
+        meta.ctime = now();
+    }
+
+    pub(crate) fn accessed(&self) {
+        self.0.write().atime = now();
+    }
+
+    pub(crate) fn modified(&self) {
+        let now = now();
+        let mut meta = self.0.write();
+        meta.mtime = now;
+        meta.ctime = now;
+    }
+
+    pub(crate) fn changed(&self) {
+        self.0.write().ctime = now();
+    }
+}
+
+/// Returns the metadata of a node in the RAM filesystem, or `None` if it is
+/// in another filesystem.
+pub fn node_meta(node: &dyn VfsNodeOps) -> Option<&MetaCell> {
+    let any = node.as_any();
+    if let Some(dir) = any.downcast_ref::<DirNode>() {
+        Some(dir.meta())
+    } else if let Some(file) = any.downcast_ref::<FileNode>() {
+        Some(file.meta())
+    } else {
+        any.downcast_ref::<SymlinkNode>().map(SymlinkNode::meta)
+    }
+}
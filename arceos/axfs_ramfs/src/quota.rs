@@ -0,0 +1,74 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axfs_vfs::VfsNodeOps;
+
+use crate::{DirNode, FileNode};
+
+/// The size of a page of the file content, the unit of the allocation.
+pub const PAGE_SIZE: usize = 4096;
+
+/// The pages allocated by the files of a filesystem, up to a limit.
+pub(crate) struct Quota {
+    /// The maximum number of pages, or `usize::MAX` if unlimited.
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl Quota {
+    pub(crate) const fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Allocates a page, or returns `false` if the limit is reached.
+    pub(crate) fn alloc(&self) -> bool {
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                (used < self.limit).then_some(used + 1)
+            })
+            .is_ok()
+    }
+
+    /// Frees `pages` pages.
+    pub(crate) fn free(&self, pages: usize) {
+        self.used.fetch_sub(pages, Ordering::Relaxed);
+    }
+
+    pub(crate) fn usage(&self) -> FsUsage {
+        FsUsage {
+            used: (self.used.load(Ordering::Relaxed) * PAGE_SIZE) as u64,
+            limit: (self.limit != usize::MAX)
+                .then(|| (self.limit as u64).saturating_mul(PAGE_SIZE as u64)),
+        }
+    }
+}
+
+/// The space used by the file content of a RAM filesystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsUsage {
+    /// The size of the allocated pages, in bytes.
+    pub used: u64,
+    /// The size limit, in bytes, or `None` if unlimited.
+    pub limit: Option<u64>,
+}
+
+impl FsUsage {
+    /// Returns the free space in bytes, or `None` if unlimited.
+    pub fn free(&self) -> Option<u64> {
+        self.limit.map(|limit| limit.saturating_sub(self.used))
+    }
+}
+
+/// Returns the space usage of the RAM filesystem that the node is in, or
+/// `None` if it is in another filesystem.
+pub fn node_usage(node: &dyn VfsNodeOps) -> Option<FsUsage> {
+    let any = node.as_any();
+    if let Some(dir) = any.downcast_ref::<DirNode>() {
+        Some(dir.quota().usage())
+    } else {
+        any.downcast_ref::<FileNode>()
+            .map(|file| file.quota().usage())
+    }
+}
@@ -0,0 +1,70 @@
+use alloc::vec::Vec;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+use axfs_vfs::{VfsNodePerm, VfsNodeType};
+use spin::RwLock;
+
+use crate::MetaCell;
+
+/// The symbolic link node in the RAM filesystem.
+///
+/// Its content is the target path, which is written after creation and read
+/// when the link is followed. It implements [`axfs_vfs::VfsNodeOps`].
+pub struct SymlinkNode {
+    meta: MetaCell,
+    target: RwLock<Vec<u8>>,
+}
+
+impl SymlinkNode {
+    pub(super) fn new() -> Self {
+        Self {
+            meta: MetaCell::new(VfsNodePerm::from_bits_truncate(0o777)),
+            target: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Returns the ownership, permission bits and timestamps. The permission
+    /// bits of a link are not used.
+    pub fn meta(&self) -> &MetaCell {
+        &self.meta
+    }
+}
+
+impl VfsNodeOps for SymlinkNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            self.meta.get().perm,
+            VfsNodeType::SymLink,
+            self.target.read().len() as _,
+            0,
+        ))
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        self.target.write().resize(size as _, 0);
+        self.meta.modified();
+        Ok(())
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let target = self.target.read();
+        let start = target.len().min(offset as usize);
+        let end = target.len().min(offset as usize + buf.len());
+        let src = &target[start..end];
+        buf[..src.len()].copy_from_slice(src);
+        self.meta.accessed();
+        Ok(src.len())
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let offset = offset as usize;
+        let mut target = self.target.write();
+        if offset + buf.len() > target.len() {
+            target.resize(offset + buf.len(), 0);
+        }
+        target[offset..offset + buf.len()].copy_from_slice(buf);
+        self.meta.modified();
+        Ok(buf.len())
+    }
+
+    impl_vfs_non_dir_default! {}
+}
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+use axfs_vfs::{VfsError, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
 
 use crate::*;
 
@@ -134,3 +134,167 @@ fn test_ramfs() {
     assert_eq!(root.remove("./foo"), Ok(()));
     assert!(ramfs.root_dir_node().get_entries().is_empty());
 }
+
+#[test]
+fn test_symlink_hard_link() -> VfsResult {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("f1", VfsNodeType::File)?;
+    root.create("foo", VfsNodeType::Dir)?;
+    root.create("link", VfsNodeType::SymLink)?;
+
+    let link = root.clone().lookup("link")?;
+    assert_eq!(link.write_at(0, b"foo/f1")?, 6);
+    assert_eq!(link.get_attr()?.file_type(), VfsNodeType::SymLink);
+    assert_eq!(link.get_attr()?.size(), 6);
+    let mut buf = [0; 16];
+    assert_eq!(link.read_at(0, &mut buf)?, 6);
+    assert_eq!(&buf[..6], b"foo/f1");
+    assert_eq!(
+        root.clone().lookup("link/f1").err(),
+        Some(VfsError::NotADirectory)
+    );
+
+    let f1 = root.clone().lookup("f1")?;
+    root.link_node("f1-link", f1.clone())?;
+    assert!(Arc::ptr_eq(&root.clone().lookup("f1-link")?, &f1));
+    assert_eq!(
+        root.link_node("f1-link", f1.clone()).err(),
+        Some(VfsError::AlreadyExists)
+    );
+    let foo = root.clone().lookup("foo")?;
+    assert_eq!(
+        root.link_node("foo-link", foo).err(),
+        Some(VfsError::PermissionDenied)
+    );
+
+    // the content is kept by the other link
+    f1.write_at(0, b"hello")?;
+    root.remove("f1")?;
+    assert_eq!(root.lookup("f1-link")?.read_at(0, &mut buf)?, 5);
+    Ok(())
+}
+
+#[test]
+fn test_meta() -> VfsResult {
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use core::time::Duration;
+
+    static SECS: AtomicU64 = AtomicU64::new(100);
+    set_clock(|| Duration::from_secs(SECS.fetch_add(1, Ordering::Relaxed)));
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("f1", VfsNodeType::File)?;
+    let f1 = root.clone().lookup("f1")?;
+    let meta = node_meta(f1.as_ref()).unwrap();
+    let created = meta.get();
+    assert_eq!(created.perm.bits(), 0o666);
+    assert_eq!((created.uid, created.gid), (0, 0));
+    assert_eq!(root.meta().get().mtime, root.meta().get().ctime);
+    assert!(root.meta().get().mtime > created.mtime);
+
+    f1.write_at(0, b"hello")?;
+    let written = meta.get();
+    assert!(written.mtime > created.mtime);
+    assert_eq!(written.ctime, written.mtime);
+    assert_eq!(written.atime, created.atime);
+    f1.read_at(0, &mut [0; 5])?;
+    assert!(meta.get().atime > written.mtime);
+
+    meta.set_perm(VfsNodePerm::from_bits_truncate(0o400));
+    assert_eq!(f1.get_attr()?.perm().bits(), 0o400);
+    assert!(!f1.get_attr()?.perm().owner_writable());
+    meta.set_owner(Some(1000), None);
+    assert_eq!((meta.get().uid, meta.get().gid), (1000, 0));
+    let time = Duration::from_secs(7);
+    meta.set_times(None, Some(time));
+    assert_eq!(meta.get().mtime, time);
+    assert!(meta.get().ctime > written.ctime);
+
+    assert_eq!(root.meta().get().perm.bits(), 0o755);
+
+    struct OtherNode;
+    impl VfsNodeOps for OtherNode {
+        axfs_vfs::impl_vfs_non_dir_default! {}
+    }
+    assert!(node_meta(&OtherNode).is_none());
+    Ok(())
+}
+
+#[test]
+fn test_sparse_file() -> VfsResult {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("f1", VfsNodeType::File)?;
+    let node = root.lookup("f1")?;
+    let file = node.as_any().downcast_ref::<FileNode>().unwrap();
+
+    // a write far away only allocates its page
+    let offset = 1 << 30;
+    assert_eq!(node.write_at(offset + 10, b"data")?, 4);
+    assert_eq!(node.get_attr()?.size(), offset + 14);
+    assert_eq!(node.get_attr()?.blocks(), (PAGE_SIZE / 512) as u64);
+    assert_eq!(ramfs.usage().used, PAGE_SIZE as u64);
+    let mut buf = [1; 8];
+    assert_eq!(node.read_at(offset - 4, &mut buf)?, 8);
+    assert_eq!(buf, *b"\0\0\0\0\0\0\0\0");
+    assert_eq!(node.read_at(offset + 8, &mut buf)?, 6);
+    assert_eq!(buf[..6], *b"\0\0data");
+
+    assert_eq!(file.seek_data(0), Some(offset));
+    assert_eq!(file.seek_data(offset + 12), Some(offset + 12));
+    assert_eq!(file.seek_hole(0), Some(0));
+    assert_eq!(file.seek_hole(offset), Some(offset + 14));
+    assert_eq!(file.seek_hole(offset + 14), None);
+
+    // shrinking frees the pages, and the tail reads as zeros if regrown
+    node.truncate(offset + 12)?;
+    node.truncate(offset + 14)?;
+    assert_eq!(node.read_at(offset + 10, &mut buf)?, 4);
+    assert_eq!(buf[..4], *b"da\0\0");
+    node.truncate(10)?;
+    assert_eq!(file.seek_data(0), None);
+    assert_eq!(ramfs.usage().used, 0);
+    Ok(())
+}
+
+#[test]
+fn test_size_limit() -> VfsResult {
+    let ramfs = RamFileSystem::with_limit(2 * PAGE_SIZE as u64);
+    let root = ramfs.root_dir();
+    root.create("f1", VfsNodeType::File)?;
+    root.create("f2", VfsNodeType::File)?;
+    let f1 = root.clone().lookup("f1")?;
+    let f2 = root.clone().lookup("f2")?;
+
+    let buf = [1; PAGE_SIZE];
+    assert_eq!(f1.write_at(0, &buf)?, PAGE_SIZE);
+    // a short write up to the limit, then no space at all
+    assert_eq!(f2.write_at(10, &buf)?, PAGE_SIZE - 10);
+    assert_eq!(
+        f2.write_at(PAGE_SIZE as _, &buf).err(),
+        Some(VfsError::StorageFull)
+    );
+    let usage = ramfs.usage();
+    assert_eq!(usage.used, 2 * PAGE_SIZE as u64);
+    assert_eq!(usage.free(), Some(0));
+    // holes take no space
+    f2.truncate(1 << 20)?;
+    assert_eq!(node_usage(f2.as_ref()), Some(usage));
+
+    // removed files release their pages once closed
+    root.remove("f1")?;
+    drop(f1);
+    assert_eq!(ramfs.usage().free(), Some(PAGE_SIZE as u64));
+    assert_eq!(f2.write_at(PAGE_SIZE as _, &buf)?, PAGE_SIZE);
+
+    // files are not linked across filesystems with different limits
+    let other = RamFileSystem::new();
+    assert_eq!(other.usage().limit, None);
+    assert_eq!(
+        other.root_dir_node().link_node("f2", f2).err(),
+        Some(VfsError::Unsupported)
+    );
+    Ok(())
+}
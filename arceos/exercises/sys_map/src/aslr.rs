@@ -0,0 +1,56 @@
+//! Address-space layout randomization for user apps.
+//!
+//! The load address of PIE executables, the stack top, the `mmap` base and
+//! the heap start are shifted by random page-aligned offsets taken from
+//! [`axhal::misc::random`]. It is opt-in: build with `ASLR=y` to enable it,
+//! otherwise the layout is fixed.
+
+use axhal::mem::PAGE_SIZE_4K;
+
+/// Load address of PIE executables before randomization.
+const PIE_BASE: usize = 0x10_0000_0000;
+/// Start of the area searched by `mmap` without an address hint, before
+/// randomization.
+const MMAP_BASE: usize = 0x20_0000_0000;
+
+/// Randomization ranges, in pages.
+const PIE_RAND_PAGES: usize = 1 << 18; // 1 GiB
+const MMAP_RAND_PAGES: usize = 1 << 18; // 1 GiB
+const STACK_RAND_PAGES: usize = 1 << 14; // 64 MiB
+const HEAP_RAND_PAGES: usize = 1 << 13; // 32 MiB
+
+/// Whether address-space layout randomization is enabled.
+pub fn enabled() -> bool {
+    axconfig::ASLR != 0
+}
+
+/// Returns a random page-aligned offset below `max_pages` pages, or 0 if
+/// ASLR is disabled.
+fn random_offset(max_pages: usize) -> usize {
+    if enabled() {
+        (axhal::misc::random() as usize % max_pages) * PAGE_SIZE_4K
+    } else {
+        0
+    }
+}
+
+/// Returns the load bias of a PIE executable.
+pub fn pie_load_bias() -> usize {
+    PIE_BASE + random_offset(PIE_RAND_PAGES)
+}
+
+/// Returns the address where `mmap` starts searching for free areas.
+pub fn mmap_base() -> usize {
+    MMAP_BASE + random_offset(MMAP_RAND_PAGES)
+}
+
+/// Returns the gap between the end of the user address space and the stack
+/// top.
+pub fn stack_gap() -> usize {
+    random_offset(STACK_RAND_PAGES)
+}
+
+/// Returns the gap between the end of the loaded segments and the heap start.
+pub fn heap_gap() -> usize {
+    random_offset(HEAP_RAND_PAGES)
+}
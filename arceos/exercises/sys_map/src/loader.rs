@@ -2,24 +2,55 @@ use std::io::{self, Read};
 use std::io::SeekFrom;
 use std::io::Seek;
 use std::fs::File;
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 use alloc::vec;
 use axhal::paging::MappingFlags;
 use axhal::mem::{PAGE_SIZE_4K, VirtAddr, MemoryAddr};
 use axmm::AddrSpace;
 
-use elf::abi::{PT_INTERP, PT_LOAD};
+use elf::abi::{ET_DYN, PT_INTERP, PT_LOAD};
 use elf::endian::AnyEndian;
+use elf::file::FileHeader;
 use elf::parse::ParseAt;
 use elf::segment::ProgramHeader;
 use elf::segment::SegmentTable;
 use elf::ElfBytes;
 
+use crate::aslr;
+
 const ELF_HEAD_BUF_SIZE: usize = 256;
 
-pub fn load_user_app(fname: &str, uspace: &mut AddrSpace) -> io::Result<usize> {
+/// Auxiliary vector entry types, see `<elf.h>`.
+const AT_PHDR: u8 = 3;
+const AT_PHENT: u8 = 4;
+const AT_PHNUM: u8 = 5;
+const AT_PAGESZ: u8 = 6;
+const AT_BASE: u8 = 7;
+const AT_ENTRY: u8 = 9;
+
+/// The result of loading a user app.
+pub struct LoadInfo {
+    /// The entry point.
+    pub entry: usize,
+    /// The initial program break, above the highest loaded segment.
+    pub brk: VirtAddr,
+    /// The auxiliary vector to put on the initial user stack.
+    pub auxv: BTreeMap<u8, usize>,
+}
+
+/// Loads the user app into `uspace`.
+///
+/// PIE executables are loaded at a randomized bias, see [`crate::aslr`];
+/// others are loaded at their link addresses.
+pub fn load_user_app(fname: &str, uspace: &mut AddrSpace) -> io::Result<LoadInfo> {
     let mut file = File::open(fname)?;
-    let (phdrs, entry, _, _) = load_elf_phdrs(&mut file)?;
+    let (phdrs, ehdr) = load_elf_phdrs(&mut file)?;
+    let entry = ehdr.e_entry as usize;
+    let phoff = ehdr.e_phoff as usize;
+    let bias = if ehdr.e_type == ET_DYN { aslr::pie_load_bias() } else { 0 };
+    let mut brk = VirtAddr::from(0);
+    let mut phdr_vaddr = 0;
 
     for phdr in &phdrs {
         ax_println!(
@@ -27,12 +58,21 @@ pub fn load_user_app(fname: &str, uspace: &mut AddrSpace) -> io::Result<usize> {
             phdr.p_offset, phdr.p_vaddr, phdr.p_filesz, phdr.p_memsz
         );
 
-        let vaddr = VirtAddr::from(phdr.p_vaddr as usize).align_down_4k();
-        let vaddr_end = VirtAddr::from((phdr.p_vaddr+phdr.p_memsz) as usize)
+        let seg_start = bias + phdr.p_vaddr as usize;
+        let vaddr = VirtAddr::from(seg_start).align_down_4k();
+        let vaddr_end = VirtAddr::from(seg_start + phdr.p_memsz as usize)
             .align_up_4k();
 
         ax_println!("{:#x} - {:#x}", vaddr, vaddr_end);
+        brk = brk.max(vaddr_end);
         uspace.map_alloc(vaddr, vaddr_end-vaddr, MappingFlags::READ|MappingFlags::WRITE|MappingFlags::EXECUTE|MappingFlags::USER, true)?;
+        let file_offset = phdr.p_offset as usize - phdr.p_vaddr as usize % PAGE_SIZE_4K;
+        uspace.set_area_name(vaddr, vaddr_end-vaddr, fname, Some(file_offset));
+
+        // The program headers are loaded as part of the segment covering them.
+        if (phdr.p_offset..phdr.p_offset + phdr.p_filesz).contains(&(phoff as u64)) {
+            phdr_vaddr = seg_start + (phoff - phdr.p_offset as usize);
+        }
 
         let mut data = vec![0u8; phdr.p_memsz as usize];
         file.seek(SeekFrom::Start(phdr.p_offset))?;
@@ -44,13 +84,25 @@ pub fn load_user_app(fname: &str, uspace: &mut AddrSpace) -> io::Result<usize> {
             index += n;
         }
         assert_eq!(index, filesz);
-        uspace.write(VirtAddr::from(phdr.p_vaddr as usize), &data)?;
+        uspace.write(VirtAddr::from(seg_start), &data)?;
     }
 
-    Ok(entry)
+    let mut auxv = BTreeMap::new();
+    auxv.insert(AT_PHDR, phdr_vaddr);
+    auxv.insert(AT_PHENT, ehdr.e_phentsize as usize);
+    auxv.insert(AT_PHNUM, ehdr.e_phnum as usize);
+    auxv.insert(AT_PAGESZ, PAGE_SIZE_4K);
+    auxv.insert(AT_BASE, 0);
+    auxv.insert(AT_ENTRY, bias + entry);
+
+    Ok(LoadInfo {
+        entry: bias + entry,
+        brk: brk + aslr::heap_gap(),
+        auxv,
+    })
 }
 
-fn load_elf_phdrs(file: &mut File) -> io::Result<(Vec<ProgramHeader>, usize, usize, usize)> {
+fn load_elf_phdrs(file: &mut File) -> io::Result<(Vec<ProgramHeader>, FileHeader<AnyEndian>)> {
     let mut buf: [u8; ELF_HEAD_BUF_SIZE] = [0; ELF_HEAD_BUF_SIZE];
     file.read(&mut buf)?;
 
@@ -72,5 +124,5 @@ fn load_elf_phdrs(file: &mut File) -> io::Result<(Vec<ProgramHeader>, usize, usi
         .iter()
         .filter(|phdr| phdr.p_type == PT_LOAD || phdr.p_type == PT_INTERP)
         .collect();
-    Ok((phdrs, ehdr.e_entry as usize, ehdr.e_phoff as usize, ehdr.e_phnum as usize))
+    Ok((phdrs, ehdr))
 }
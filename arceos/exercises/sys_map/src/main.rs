@@ -11,6 +11,8 @@ extern crate axlog;
 mod task;
 mod syscall;
 mod loader;
+mod proc;
+mod aslr;
 
 use axstd::io;
 use axhal::paging::MappingFlags;
@@ -24,6 +26,10 @@ use axmm::AddrSpace;
 use loader::load_user_app;
 
 const USER_STACK_SIZE: usize = 0x10000;
+/// The range reserved below the stack top for the stack to grow into.
+const USER_STACK_MAX_SIZE: usize = 0x400_0000; // 64 MiB
+/// The initial `RLIMIT_STACK`.
+const USER_STACK_LIMIT: usize = 0x80_0000; // 8 MiB
 const KERNEL_STACK_SIZE: usize = 0x40000; // 256 KiB
 
 #[cfg_attr(feature = "axstd", no_mangle)]
@@ -32,20 +38,22 @@ fn main() {
     let mut uspace = axmm::new_user_aspace().unwrap();
 
     // Load user app binary file into address space.
-    let entry = match load_user_app("/sbin/mapfile", &mut uspace) {
-        Ok(e) => e,
+    let info = match load_user_app("/sbin/mapfile", &mut uspace) {
+        Ok(info) => info,
         Err(err) => panic!("Cannot load app! {:?}", err),
     };
-    ax_println!("entry: {:#x}", entry);
+    ax_println!("entry: {:#x}", info.entry);
 
     // Init user stack.
-    let ustack_top = init_user_stack(&mut uspace, true).unwrap();
+    let ustack_top = init_user_stack(&mut uspace, &info.auxv, true).unwrap();
     ax_println!("New user address space: {:#x?}", uspace);
 
     // Let's kick off the user process.
     let user_task = task::spawn_user_task(
         Arc::new(Mutex::new(uspace)),
-        UspaceContext::new(entry, ustack_top),
+        UspaceContext::new(info.entry, ustack_top),
+        info.brk.as_usize(),
+        aslr::mmap_base(),
     );
 
     // Wait for user process to exit ...
@@ -53,26 +61,33 @@ fn main() {
     ax_println!("monolithic kernel exit [{:?}] normally!", exit_code);
 }
 
-fn init_user_stack(uspace: &mut AddrSpace, populating: bool) -> io::Result<VirtAddr> {
-    let ustack_top = uspace.end();
+fn init_user_stack(
+    uspace: &mut AddrSpace,
+    auxv: &BTreeMap<u8, usize>,
+    populating: bool,
+) -> io::Result<VirtAddr> {
+    let ustack_top = uspace.end() - aslr::stack_gap();
     let ustack_vaddr = ustack_top - crate::USER_STACK_SIZE;
     ax_println!(
         "Mapping user stack: {:#x?} -> {:#x?}",
         ustack_vaddr, ustack_top
     );
-    uspace.map_alloc(
-        ustack_vaddr,
+    uspace.set_stack_limit(crate::USER_STACK_LIMIT);
+    uspace.map_grows_down(
+        ustack_top,
         crate::USER_STACK_SIZE,
+        crate::USER_STACK_MAX_SIZE,
         MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
         populating,
+        true,
     ).unwrap();
+    uspace.set_area_name(ustack_vaddr, crate::USER_STACK_SIZE, "[stack]", None);
 
     let app_name = "hello";
-    let av = BTreeMap::new();
     let (stack_data, ustack_pointer) = kernel_elf_parser::get_app_stack_region(
         &[String::from(app_name)],
         &[],
-        &av,
+        auxv,
         ustack_vaddr,
         crate::USER_STACK_SIZE,
     );
@@ -0,0 +1,143 @@
+//! `/proc/<pid>/maps` and `/proc/<pid>/smaps` for user tasks.
+//!
+//! The contents are rendered from [`AddrSpace::areas`] when the file is
+//! opened, so a reader sees a consistent snapshot.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ffi::c_int;
+use core::fmt::Write;
+
+use arceos_posix_api::{self as api, FileLike};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::paging::MappingFlags;
+use axio::PollState;
+use axmm::{AddrSpace, BackendType};
+use axsync::Mutex;
+use axtask::{current, TaskExtRef};
+
+/// The column where `/proc/<pid>/maps` starts printing the area name.
+const MAPS_NAME_COLUMN: usize = 73;
+
+/// A read-only file holding a snapshot of generated contents.
+struct ProcFile {
+    data: Vec<u8>,
+    pos: Mutex<usize>,
+}
+
+impl FileLike for ProcFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let mut pos = self.pos.lock();
+        let start = (*pos).min(self.data.len());
+        let len = buf.len().min(self.data.len() - start);
+        buf[..len].copy_from_slice(&self.data[start..start + len]);
+        *pos = start + len;
+        Ok(len)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EBADF)
+    }
+
+    fn stat(&self) -> LinuxResult<api::ctypes::stat> {
+        let st_mode = 0o100000 | 0o444u32; // S_IFREG | r--r--r--
+        Ok(api::ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode,
+            st_blksize: 4096,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// Opens `path` if it is a per-task proc file of the current task.
+///
+/// Returns `None` if `path` is not handled here, so that the caller can open
+/// it from the filesystem.
+pub fn open(path: &str) -> Option<LinuxResult<c_int>> {
+    let rest = path.strip_prefix("/proc/")?;
+    let (pid, name) = rest.split_once('/')?;
+    let curr = current();
+    if pid != "self" && pid.parse::<usize>().ok()? != curr.task_ext().proc_id {
+        return None;
+    }
+
+    let aspace = curr.task_ext().aspace.lock();
+    let data = match name {
+        "maps" => render_maps(&aspace, false),
+        "smaps" => render_maps(&aspace, true),
+        _ => return Some(Err(LinuxError::ENOENT)),
+    };
+    let file = ProcFile {
+        data: data.into_bytes(),
+        pos: Mutex::new(0),
+    };
+    Some(api::add_file_like(Arc::new(file)))
+}
+
+/// Renders the areas of `aspace` in the format of Linux `/proc/<pid>/maps`,
+/// or `/proc/<pid>/smaps` if `detailed` is `true`.
+fn render_maps(aspace: &AddrSpace, detailed: bool) -> String {
+    let mut out = String::new();
+    for area in aspace.areas() {
+        let line_start = out.len();
+        let perm = |flag, c| if area.flags.contains(flag) { c } else { '-' };
+        let _ = write!(
+            out,
+            "{:08x}-{:08x} {}{}{}p {:08x} 00:00 0",
+            area.va_range.start,
+            area.va_range.end,
+            perm(MappingFlags::READ, 'r'),
+            perm(MappingFlags::WRITE, 'w'),
+            perm(MappingFlags::EXECUTE, 'x'),
+            area.file_offset,
+        );
+        if !area.name.is_empty() {
+            let width = out.len() - line_start;
+            let pad = MAPS_NAME_COLUMN.saturating_sub(width).max(1);
+            let _ = write!(out, "{:pad$}{}", "", area.name);
+        }
+        out.push('\n');
+
+        if detailed {
+            let kb = |pages: usize| pages * 4;
+            let size = area.va_range.size() / 1024;
+            let rss = kb(area.resident_pages);
+            let dirty = kb(area.dirty_pages);
+            let anon = if area.backend == BackendType::Linear { 0 } else { rss };
+            for (key, value) in [
+                ("Size", size),
+                ("KernelPageSize", 4),
+                ("MMUPageSize", 4),
+                ("Rss", rss),
+                ("Pss", rss),
+                ("Shared_Clean", 0),
+                ("Shared_Dirty", 0),
+                ("Private_Clean", rss - dirty),
+                ("Private_Dirty", dirty),
+                ("Anonymous", anon),
+                ("Swap", 0),
+            ] {
+                let _ = writeln!(out, "{:<16}{:>8} kB", alloc::format!("{key}:"), value);
+            }
+        }
+    }
+    out
+}
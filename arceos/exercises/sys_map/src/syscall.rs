@@ -9,21 +9,55 @@ use axerrno::LinuxError;
 use axtask::current;
 use axtask::TaskExtRef;
 use axhal::paging::MappingFlags;
+use axhal::uaccess::{UserPtr, UserSlice};
 use arceos_posix_api as api;
+use alloc::vec;
+use memory_addr::{MemoryAddr, VirtAddr};
 
 const SYS_IOCTL: usize = 29;
+const SYS_UMOUNT2: usize = 39;
+const SYS_MOUNT: usize = 40;
 const SYS_OPENAT: usize = 56;
 const SYS_CLOSE: usize = 57;
+const SYS_SYNC: usize = 81;
+const SYS_FSYNC: usize = 82;
+const SYS_FDATASYNC: usize = 83;
 const SYS_READ: usize = 63;
 const SYS_WRITE: usize = 64;
 const SYS_WRITEV: usize = 66;
 const SYS_EXIT: usize = 93;
 const SYS_EXIT_GROUP: usize = 94;
 const SYS_SET_TID_ADDRESS: usize = 96;
+const SYS_GETRLIMIT: usize = 163;
+const SYS_SETRLIMIT: usize = 164;
+const SYS_BRK: usize = 214;
+const SYS_MUNMAP: usize = 215;
+const SYS_MREMAP: usize = 216;
 const SYS_MMAP: usize = 222;
+const SYS_MPROTECT: usize = 226;
+const SYS_MADVISE: usize = 233;
+const SYS_PRLIMIT64: usize = 261;
 
 const AT_FDCWD: i32 = -100;
 
+/// Allow `sys_mremap` to move the mapping to a new address.
+const MREMAP_MAYMOVE: i32 = 1;
+/// Move the mapping to the address given by `new_addr`.
+const MREMAP_FIXED: i32 = 2;
+
+/// Don't expect access in the near future; drop the contents of the range.
+const MADV_DONTNEED: i32 = 4;
+
+/// The value of an unlimited resource limit.
+const RLIM_INFINITY: u64 = u64::MAX;
+
+const PAGE_SIZE: usize = 0x1000;
+const PATH_MAX: usize = 4096;
+
+/// Maximum bytes transferred by a single `read` or `write`, which go through
+/// a kernel buffer.
+const MAX_RW_LEN: usize = 0x10000;
+
 /// Macro to generate syscall body
 ///
 /// It will receive a function which return Result<_, LinuxError> and convert it to
@@ -91,6 +125,8 @@ bitflags::bitflags! {
         const MAP_FIXED = 1 << 4;
         /// Don't use a file.
         const MAP_ANONYMOUS = 1 << 5;
+        /// Stack-like segment; grows downward on page faults below it.
+        const MAP_GROWSDOWN = 0x0100;
         /// Don't check for reservations.
         const MAP_NORESERVE = 1 << 14;
         /// Allocation is for a stack.
@@ -104,8 +140,19 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
     let ret = match syscall_num {
          SYS_IOCTL => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _) as _,
         SYS_SET_TID_ADDRESS => sys_set_tid_address(tf.arg0() as _),
+        SYS_UMOUNT2 => sys_umount2(tf.arg0() as _, tf.arg1() as _),
+        SYS_MOUNT => sys_mount(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+        ),
         SYS_OPENAT => sys_openat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _, tf.arg3() as _),
         SYS_CLOSE => sys_close(tf.arg0() as _),
+        SYS_SYNC => sys_sync(),
+        SYS_FSYNC => sys_fsync(tf.arg0() as _),
+        SYS_FDATASYNC => sys_fdatasync(tf.arg0() as _),
         SYS_READ => sys_read(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_WRITE => sys_write(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_WRITEV => sys_writev(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
@@ -125,6 +172,20 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg4() as _,
             tf.arg5() as _,
         ),
+        SYS_MUNMAP => sys_munmap(tf.arg0() as _, tf.arg1() as _),
+        SYS_MPROTECT => sys_mprotect(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        SYS_MREMAP => sys_mremap(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+        ),
+        SYS_MADVISE => sys_madvise(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        SYS_BRK => sys_brk(tf.arg0() as _),
+        SYS_GETRLIMIT => sys_prlimit64(0, tf.arg0() as _, core::ptr::null(), tf.arg1() as _),
+        SYS_SETRLIMIT => sys_prlimit64(0, tf.arg0() as _, tf.arg1() as _, core::ptr::null_mut()),
+        SYS_PRLIMIT64 => sys_prlimit64(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _, tf.arg3() as _),
         _ => {
             ax_println!("Unimplemented syscall: {}", syscall_num);
             -LinuxError::ENOSYS.code() as _
@@ -140,9 +201,9 @@ fn sys_mmap(
     prot: i32, // 页面权限
     flags: i32, 
     fd: i32,
-    _offset: isize, 
+    offset: isize, 
 ) -> isize {
-use memory_addr::{VirtAddr, VirtAddrRange};
+use memory_addr::VirtAddrRange;
 //修复：考虑文件映射
     syscall_body!(sys_mmap,{
        // ax_println!(
@@ -169,7 +230,6 @@ use memory_addr::{VirtAddr, VirtAddrRange};
             return Err(LinuxError::EINVAL);
         }
         //对齐长度!!!
-        const PAGE_SIZE: usize = 0x1000;//4096 
         let aligned_length = (length + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
         //获取地址空间
         let curr = current();
@@ -187,14 +247,18 @@ use memory_addr::{VirtAddr, VirtAddrRange};
                 aspace.base(),
                 aspace.size()
             );
-            aspace.find_free_area(//在指定范围随机查找空闲区域用来满足mmap
-                if addr as usize != 0 { addr_hint/*从这里开始向后搜索 */ } else { aspace.base() },
-                aligned_length,
-                va_range
-            ).ok_or_else(|| {
-           //     ax_println!("sys_mmap:find_free_area failed,no memory");
-                LinuxError::ENOMEM
-            })?
+            let hint = if addr as usize != 0 {
+                addr_hint/*从这里开始向后搜索 */
+            } else {
+                VirtAddr::from(curr.task_ext().mmap_base)
+            };
+            // Fall back to the lowest hole if nothing is free above the hint.
+            aspace.find_free_area(hint, aligned_length, va_range)
+                .or_else(|| aspace.find_free_area(aspace.base(), aligned_length, va_range))
+                .ok_or_else(|| {
+               //     ax_println!("sys_mmap:find_free_area failed,no memory");
+                    LinuxError::ENOMEM
+                })?
         };
         
         //ax_println!("sys_mmap:alloc_addr={:#x},aligned_length={:#x}",alloc_addr,aligned_length);
@@ -206,7 +270,17 @@ use memory_addr::{VirtAddr, VirtAddrRange};
        // ax_println!("sys_mmap:mapping_flags={:?}",mapping_flags);
         
         //创建映射
-        if is_anonymous {
+        if is_anonymous && map_flags.contains(MmapFlags::MAP_GROWSDOWN) {
+            // Only limited by RLIMIT_STACK; the range below is not reserved.
+            aspace.map_grows_down(
+                alloc_addr + aligned_length,
+                aligned_length,
+                usize::MAX,
+                mapping_flags,
+                false,
+                false,
+            ).map_err(|_| LinuxError::ENOMEM)?;
+        } else if is_anonymous {
             // 匿名映射：延迟分配
             aspace.map_alloc(
                 alloc_addr,//起始地址
@@ -244,6 +318,9 @@ use memory_addr::{VirtAddr, VirtAddrRange};
                     LinuxError::EFAULT
                 })?;
             
+            if let Ok(file) = api::File::from_fd(fd) {
+                aspace.set_area_name(alloc_addr, aligned_length, file.path(), Some(offset as usize));
+            }
             //ax_println!("sys_mmap:file mapping,read{}bytes",read_len);
         }
         
@@ -254,25 +331,290 @@ use memory_addr::{VirtAddr, VirtAddrRange};
     })
 }
 
+fn sys_munmap(addr: usize, length: usize) -> isize {
+    syscall_body!(sys_munmap, {
+        if addr % PAGE_SIZE != 0 || length == 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let length = length
+            .checked_next_multiple_of(PAGE_SIZE)
+            .ok_or(LinuxError::EINVAL)?;
+        current()
+            .task_ext()
+            .aspace
+            .lock()
+            .unmap(VirtAddr::from(addr), length)?;
+        Ok(0)
+    })
+}
+
+fn sys_mprotect(addr: usize, length: usize, prot: i32) -> isize {
+    syscall_body!(sys_mprotect, {
+        if addr % PAGE_SIZE != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        // like Linux, a length that overflows when rounded up is out of memory
+        let length = length
+            .checked_next_multiple_of(PAGE_SIZE)
+            .ok_or(LinuxError::ENOMEM)?;
+        if length == 0 {
+            return Ok(0);
+        }
+        let flags = MappingFlags::from(MmapProt::from_bits_truncate(prot));
+        current()
+            .task_ext()
+            .aspace
+            .lock()
+            .protect(VirtAddr::from(addr), length, flags)?;
+        Ok(0)
+    })
+}
+
+fn sys_mremap(old_addr: usize, old_size: usize, new_size: usize, flags: i32, _new_addr: usize) -> isize {
+    syscall_body!(sys_mremap, {
+        if old_addr % PAGE_SIZE != 0 || new_size == 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        if flags & MREMAP_FIXED != 0 {
+            return Err(LinuxError::ENOSYS);
+        }
+        let old_size = old_size
+            .checked_next_multiple_of(PAGE_SIZE)
+            .ok_or(LinuxError::EINVAL)?;
+        let new_size = new_size
+            .checked_next_multiple_of(PAGE_SIZE)
+            .ok_or(LinuxError::EINVAL)?;
+        let new_addr = current().task_ext().aspace.lock().remap(
+            VirtAddr::from(old_addr),
+            old_size,
+            new_size,
+            flags & MREMAP_MAYMOVE != 0,
+        )?;
+        Ok(new_addr.as_usize())
+    })
+}
+
+fn sys_madvise(addr: usize, length: usize, advice: i32) -> isize {
+    syscall_body!(sys_madvise, {
+        if addr % PAGE_SIZE != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let length = length
+            .checked_next_multiple_of(PAGE_SIZE)
+            .ok_or(LinuxError::EINVAL)?;
+        if advice == MADV_DONTNEED && length != 0 {
+            current()
+                .task_ext()
+                .aspace
+                .lock()
+                .discard(VirtAddr::from(addr), length)?;
+        }
+        // Other advices are only hints, ignore them.
+        Ok(0)
+    })
+}
+
+/// Sets the program break. `sbrk` is implemented by libc on top of it.
+///
+/// Returns the new program break on success, or the current one if `addr` is
+/// out of the heap or the heap cannot be grown.
+fn sys_brk(addr: usize) -> isize {
+    syscall_body!(sys_brk, {
+        let curr = current();
+        let ext = curr.task_ext();
+        let old_top = ext.heap_top() as usize;
+        if addr < ext.heap_bottom() as usize {
+            return Ok(old_top);
+        }
+
+        let old_end = VirtAddr::from(old_top).align_up_4k();
+        let new_end = VirtAddr::from(addr).align_up_4k();
+        let mut aspace = ext.aspace.lock();
+        if new_end > old_end {
+            let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+            if aspace.map_alloc(old_end, new_end - old_end, flags, false).is_err() {
+                return Ok(old_top);
+            }
+            aspace.set_area_name(old_end, new_end - old_end, "[heap]", None);
+        } else if new_end < old_end {
+            aspace.unmap(new_end, old_end - new_end)?;
+        }
+        ext.set_heap_top(addr as u64);
+        Ok(addr)
+    })
+}
+
+/// Gets and sets resource limits of the current process.
+///
+/// `RLIMIT_STACK` bounds the growth of the user stack; the other resources
+/// are handled by the POSIX API layer.
+fn sys_prlimit64(
+    pid: c_int,
+    resource: c_int,
+    new_limit: *const api::ctypes::rlimit,
+    old_limit: *mut api::ctypes::rlimit,
+) -> isize {
+    syscall_body!(sys_prlimit64, {
+        let curr = current();
+        if pid != 0 && pid as usize != curr.task_ext().proc_id {
+            return Err(LinuxError::ESRCH);
+        }
+        let new_limit = UserPtr::from(new_limit);
+        let old_limit = UserPtr::from(old_limit);
+        let new = if new_limit.is_null() {
+            None
+        } else {
+            let new = new_limit.read()?;
+            if new.rlim_cur > new.rlim_max {
+                return Err(LinuxError::EINVAL);
+            }
+            Some(new)
+        };
+
+        if resource as u32 != api::ctypes::RLIMIT_STACK {
+            let mut buf: api::ctypes::rlimit = unsafe { core::mem::zeroed() };
+            if !old_limit.is_null() {
+                if unsafe { api::sys_getrlimit(resource, &mut buf) } < 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                old_limit.write(buf)?;
+            }
+            if let Some(mut new) = new {
+                if unsafe { api::sys_setrlimit(resource, &mut new) } < 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+            }
+            return Ok(0);
+        }
+
+        let mut aspace = curr.task_ext().aspace.lock();
+        if !old_limit.is_null() {
+            let cur = match aspace.stack_limit() {
+                usize::MAX => RLIM_INFINITY,
+                limit => limit as u64,
+            };
+            old_limit.write(api::ctypes::rlimit {
+                rlim_cur: cur as _,
+                rlim_max: RLIM_INFINITY as _,
+            })?;
+        }
+        if let Some(new) = new {
+            let limit = match new.rlim_cur as u64 {
+                RLIM_INFINITY => usize::MAX,
+                limit => limit as usize,
+            };
+            aspace.set_stack_limit(limit);
+        }
+        Ok(0)
+    })
+}
+
 fn sys_openat(dfd: c_int, fname: *const c_char, flags: c_int, mode: api::ctypes::mode_t) -> isize {
     assert_eq!(dfd, AT_FDCWD);
-    api::sys_open(fname, flags, mode) as isize
+    syscall_body!(sys_openat, {
+        let mut buf = [0u8; PATH_MAX];
+        let path = UserPtr::from(fname).read_str(&mut buf)?;
+        if let Some(res) = crate::proc::open(path) {
+            return res;
+        }
+        Ok(api::sys_open(buf.as_ptr() as *const c_char, flags, mode))
+    })
+}
+
+fn sys_mount(
+    source: *const c_char,
+    target: *const c_char,
+    fstype: *const c_char,
+    flags: usize,
+    data: *const c_void,
+) -> isize {
+    syscall_body!(sys_mount, {
+        let mut source_buf = vec![0u8; PATH_MAX];
+        let mut target_buf = vec![0u8; PATH_MAX];
+        let mut fstype_buf = vec![0u8; PATH_MAX];
+        let source = if source.is_null() {
+            core::ptr::null()
+        } else {
+            UserPtr::from(source).read_str(&mut source_buf)?;
+            source_buf.as_ptr() as *const c_char
+        };
+        UserPtr::from(target).read_str(&mut target_buf)?;
+        UserPtr::from(fstype).read_str(&mut fstype_buf)?;
+        Ok(api::sys_mount(
+            source,
+            target_buf.as_ptr() as *const c_char,
+            fstype_buf.as_ptr() as *const c_char,
+            flags,
+            data,
+        ))
+    })
+}
+
+fn sys_umount2(target: *const c_char, flags: c_int) -> isize {
+    syscall_body!(sys_umount2, {
+        let mut buf = [0u8; PATH_MAX];
+        UserPtr::from(target).read_str(&mut buf)?;
+        Ok(api::sys_umount2(buf.as_ptr() as *const c_char, flags))
+    })
 }
 
 fn sys_close(fd: i32) -> isize {
     api::sys_close(fd) as isize
 }
 
+fn sys_sync() -> isize {
+    api::sys_sync();
+    0
+}
+
+fn sys_fsync(fd: i32) -> isize {
+    api::sys_fsync(fd) as isize
+}
+
+fn sys_fdatasync(fd: i32) -> isize {
+    api::sys_fdatasync(fd) as isize
+}
+
 fn sys_read(fd: i32, buf: *mut c_void, count: usize) -> isize {
-    api::sys_read(fd, buf, count)
+    syscall_body!(sys_read, {
+        let mut kbuf = vec![0u8; count.min(MAX_RW_LEN)];
+        let n = api::sys_read(fd, kbuf.as_mut_ptr() as *mut c_void, kbuf.len());
+        if n > 0 {
+            UserSlice::new(buf as usize, n as usize).write(&kbuf[..n as usize])?;
+        }
+        Ok(n)
+    })
 }
 
 fn sys_write(fd: i32, buf: *const c_void, count: usize) -> isize {
-    api::sys_write(fd, buf, count)
+    syscall_body!(sys_write, {
+        let mut kbuf = vec![0u8; count.min(MAX_RW_LEN)];
+        UserSlice::new(buf as usize, kbuf.len()).read(&mut kbuf)?;
+        Ok(api::sys_write(fd, kbuf.as_ptr() as *const c_void, kbuf.len()))
+    })
 }
 
 fn sys_writev(fd: i32, iov: *const api::ctypes::iovec, iocnt: i32) -> isize {
-    unsafe { api::sys_writev(fd, iov, iocnt) }
+    syscall_body!(sys_writev, {
+        if iocnt < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let iovs = UserSlice::<api::ctypes::iovec>::new(iov as usize, iocnt as usize);
+        let mut total = 0;
+        for i in 0..iovs.len() {
+            let iov = iovs.get(i)?;
+            let n = sys_write(fd, iov.iov_base, iov.iov_len);
+            if n < 0 {
+                // Report the error only if nothing has been written.
+                return Ok(if total > 0 { total } else { n });
+            }
+            total += n;
+            if (n as usize) < iov.iov_len {
+                break;
+            }
+        }
+        Ok(total)
+    })
 }
 
 fn sys_set_tid_address(tid_ptd: *const i32) -> isize {
@@ -295,13 +637,19 @@ fn pagefault_handler_to_area_backend(viradr: memory_addr::VirtAddr, mapflag: Map
     //接下来应该传入maparea的backend端来处理页帧分配和映射逻辑,还是模块分离，减少代码耦合！！
     
     //参考了答案梳理执行流程
-    if from_user {//不处理没有user映射权限的区域
+    // Kernel accesses to user memory through `axhal::uaccess` carry the USER
+    // flag as well.
+    if from_user || mapflag.contains(MappingFlags::USER) {//不处理没有user映射权限的区域
         if !current()
             .task_ext()
             .aspace
             .lock()
             .handle_page_fault(viradr, mapflag)//传到area的backend处理
         {
+            if !from_user {
+                // Recovered by the exception table, the syscall fails with EFAULT.
+                return false;
+            }
             ax_println!("{}: segmentation fault, exit!", current().id_name());
             axtask::exit(-1);
         } else {
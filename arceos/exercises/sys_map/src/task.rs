@@ -19,6 +19,12 @@ pub struct TaskExt {
     ///
     /// When the thread exits, the kernel clears the word at this address if it is not NULL.
     clear_child_tid: AtomicU64,
+    /// The start of the heap, i.e., the initial program break.
+    heap_bottom: AtomicU64,
+    /// The current program break, set by `brk`.
+    heap_top: AtomicU64,
+    /// Where `mmap` starts searching for free areas if no hint is given.
+    pub mmap_base: usize,
     /// The user space context.
     pub uctx: UspaceContext,
     /// The virtual memory address space.
@@ -26,11 +32,19 @@ pub struct TaskExt {
 }
 
 impl TaskExt {
-    pub const fn new(uctx: UspaceContext, aspace: Arc<Mutex<AddrSpace>>) -> Self {
+    pub const fn new(
+        uctx: UspaceContext,
+        aspace: Arc<Mutex<AddrSpace>>,
+        heap_bottom: u64,
+        mmap_base: usize,
+    ) -> Self {
         Self {
             proc_id: 233,
             uctx,
             clear_child_tid: AtomicU64::new(0),
+            heap_bottom: AtomicU64::new(heap_bottom),
+            heap_top: AtomicU64::new(heap_bottom),
+            mmap_base,
             aspace,
         }
     }
@@ -44,11 +58,31 @@ impl TaskExt {
         self.clear_child_tid
             .store(clear_child_tid, core::sync::atomic::Ordering::Relaxed);
     }
+
+    pub(crate) fn heap_bottom(&self) -> u64 {
+        self.heap_bottom
+            .load(core::sync::atomic::Ordering::Acquire)
+    }
+
+    pub(crate) fn heap_top(&self) -> u64 {
+        self.heap_top
+            .load(core::sync::atomic::Ordering::Acquire)
+    }
+
+    pub(crate) fn set_heap_top(&self, top: u64) {
+        self.heap_top
+            .store(top, core::sync::atomic::Ordering::Release);
+    }
 }
 
 axtask::def_task_ext!(TaskExt);
 
-pub fn spawn_user_task(aspace: Arc<Mutex<AddrSpace>>, uctx: UspaceContext) -> AxTaskRef {
+pub fn spawn_user_task(
+    aspace: Arc<Mutex<AddrSpace>>,
+    uctx: UspaceContext,
+    heap_bottom: usize,
+    mmap_base: usize,
+) -> AxTaskRef {
     let mut task = TaskInner::new(
         || {
             let curr = axtask::current();
@@ -66,6 +100,6 @@ pub fn spawn_user_task(aspace: Arc<Mutex<AddrSpace>>, uctx: UspaceContext) -> Ax
     );
     task.ctx_mut()
         .set_page_table_root(aspace.lock().page_table_root());
-    task.init_task_ext(TaskExt::new(uctx, aspace));
+    task.init_task_ext(TaskExt::new(uctx, aspace, heap_bottom as u64, mmap_base));
     axtask::spawn_task(task)
 }
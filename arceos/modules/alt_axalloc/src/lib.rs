@@ -1,88 +1,258 @@
+//! A two-phase global memory allocator.
+//!
+//! Before [`global_init`], allocations are served by a bump
+//! [`EarlyAllocator`] set up by [`global_early_init`]. [`global_init`] then
+//! hands the remaining free memory over to a full allocator, which combines a
+//! [`TlsfByteAllocator`] and a [`BitmapPageAllocator`]. Pages allocated in the
+//! early phase are kept forever, while the early byte area is given to the
+//! full allocator as soon as all allocations in it are freed.
+
 #![no_std]
 
 #[macro_use]
 extern crate log;
 extern crate alloc;
 
-use allocator::{AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use allocator::{
+    AllocError, AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator,
+    TlsfByteAllocator,
+};
 use bump_allocator::EarlyAllocator;
 use core::alloc::{GlobalAlloc, Layout};
+use core::ops::Range;
 use core::ptr::NonNull;
 use kspin::SpinNoIrq;
 
 const PAGE_SIZE: usize = 0x1000;
+const MIN_HEAP_SIZE: usize = 0x8000; // 32 K
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Uninit,
+    Early,
+    Full,
+}
+
+struct Inner {
+    phase: Phase,
+    early: EarlyAllocator<PAGE_SIZE>,
+    balloc: TlsfByteAllocator,
+    palloc: BitmapPageAllocator<PAGE_SIZE>,
+    /// The early byte area still holding live allocations after the handoff.
+    early_bytes: Range<usize>,
+    /// The pages allocated in the early phase, which are never freed.
+    early_pages: Range<usize>,
+}
 
 /// The global allocator used by ArceOS.
 pub struct GlobalAllocator {
-    inner: SpinNoIrq<EarlyAllocator<PAGE_SIZE>>,
+    inner: SpinNoIrq<Inner>,
 }
 
 impl GlobalAllocator {
     /// Creates an empty [`GlobalAllocator`].
     pub const fn new() -> Self {
         Self {
-            inner: SpinNoIrq::new(EarlyAllocator::new()),
+            inner: SpinNoIrq::new(Inner {
+                phase: Phase::Uninit,
+                early: EarlyAllocator::new(),
+                balloc: TlsfByteAllocator::new(),
+                palloc: BitmapPageAllocator::new(),
+                early_bytes: 0..0,
+                early_pages: 0..0,
+            }),
         }
     }
 
     /// Returns the name of the allocator.
-    pub const fn name(&self) -> &'static str {
-        "early"
+    pub fn name(&self) -> &'static str {
+        match self.inner.lock().phase {
+            Phase::Full => "TLSF",
+            _ => "early",
+        }
+    }
+
+    /// Initializes the early allocator with the given region.
+    pub fn early_init(&self, start_vaddr: usize, size: usize) {
+        let mut inner = self.inner.lock();
+        assert_eq!(inner.phase, Phase::Uninit);
+        inner.early.init(start_vaddr, size);
+        inner.phase = Phase::Early;
     }
 
-    /// Initializes the allocator with the given region.
+    /// Initializes the full allocator with the given region.
+    ///
+    /// If the early allocator is in use, the region must be the one given to
+    /// [`early_init`](Self::early_init), and the full allocator takes over
+    /// the part of it that the early allocator has not used.
     pub fn init(&self, start_vaddr: usize, size: usize) {
-        self.inner.lock().init(start_vaddr, size);
+        let mut inner = self.inner.lock();
+        let (start, end) = match inner.phase {
+            Phase::Uninit => (start_vaddr, start_vaddr + size),
+            Phase::Early => {
+                let (early_start, _) = inner.early.byte_area();
+                let (_, early_end) = inner.early.page_area();
+                assert_eq!((early_start, early_end), (start_vaddr, start_vaddr + size));
+                let (free_start, free_end) = inner.early.take_free_area();
+                inner.early_pages = free_end..early_end;
+                inner.early_bytes = early_start..free_start;
+                (free_start, free_end)
+            }
+            Phase::Full => panic!("global allocator initialized twice"),
+        };
+        let start = align_up(start, PAGE_SIZE);
+        let end = end & !(PAGE_SIZE - 1);
+        assert!(end > start && end - start > MIN_HEAP_SIZE);
+        debug!("full allocator takes over: [{:#x}, {:#x})", start, end);
+
+        let inner = &mut *inner;
+        inner.palloc.init(start, end - start);
+        let heap_ptr = inner
+            .palloc
+            .alloc_pages(MIN_HEAP_SIZE / PAGE_SIZE, PAGE_SIZE)
+            .unwrap();
+        inner.balloc.init(heap_ptr, MIN_HEAP_SIZE);
+        inner.phase = Phase::Full;
+        // The early byte area may already be free.
+        inner.reclaim_early_bytes();
     }
 
     /// Add the given region to the allocator.
-    pub fn add_memory(&self, _start_vaddr: usize, _size: usize) -> AllocResult {
-        unimplemented!()
+    ///
+    /// It will add the whole region to the byte allocator. Only supported
+    /// after [`init`](Self::init).
+    pub fn add_memory(&self, start_vaddr: usize, size: usize) -> AllocResult {
+        let mut inner = self.inner.lock();
+        if inner.phase != Phase::Full {
+            return Err(AllocError::InvalidParam);
+        }
+        inner.balloc.add_memory(start_vaddr, size)
     }
 
     /// Allocate arbitrary number of bytes. Returns the left bound of the
     /// allocated region.
     pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
-        self.inner.lock().alloc(layout)
+        let mut inner = self.inner.lock();
+        match inner.phase {
+            Phase::Uninit => Err(AllocError::NoMemory),
+            Phase::Early => inner.early.alloc(layout),
+            Phase::Full => inner.alloc_full(layout),
+        }
     }
 
     /// Gives back the allocated region to the byte allocator.
     pub fn dealloc(&self, pos: NonNull<u8>, layout: Layout) {
-        self.inner.lock().dealloc(pos, layout)
+        let mut inner = self.inner.lock();
+        let addr = pos.as_ptr() as usize;
+        if inner.phase == Phase::Early || inner.early_bytes.contains(&addr) {
+            inner.early.dealloc(pos, layout);
+            inner.reclaim_early_bytes();
+        } else {
+            inner.balloc.dealloc(pos, layout);
+        }
     }
 
     /// Allocates contiguous pages.
     pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
-        self.inner.lock().alloc_pages(num_pages, align_pow2)
+        let mut inner = self.inner.lock();
+        match inner.phase {
+            Phase::Uninit => Err(AllocError::NoMemory),
+            Phase::Early => inner.early.alloc_pages(num_pages, align_pow2),
+            Phase::Full => inner.palloc.alloc_pages(num_pages, align_pow2),
+        }
     }
 
     /// Gives back the allocated pages starts from `pos` to the page allocator.
-    /// [`alloc_pages`]: GlobalAllocator::alloc_pages
+    ///
+    /// Pages allocated before [`init`](Self::init) are never freed.
     pub fn dealloc_pages(&self, pos: usize, num_pages: usize) {
-        self.inner.lock().dealloc_pages(pos, num_pages)
+        let mut inner = self.inner.lock();
+        if inner.phase == Phase::Full && !inner.early_pages.contains(&pos) {
+            inner.palloc.dealloc_pages(pos, num_pages)
+        }
     }
 
     /// Returns the number of allocated bytes in the byte allocator.
     pub fn used_bytes(&self) -> usize {
-        self.inner.lock().used_bytes()
+        let inner = self.inner.lock();
+        inner.early.used_bytes() + inner.balloc.used_bytes()
     }
 
     /// Returns the number of available bytes in the byte allocator.
     pub fn available_bytes(&self) -> usize {
-        self.inner.lock().available_bytes()
+        let inner = self.inner.lock();
+        match inner.phase {
+            Phase::Full => inner.balloc.available_bytes(),
+            _ => inner.early.available_bytes(),
+        }
     }
 
     /// Returns the number of allocated pages in the page allocator.
     pub fn used_pages(&self) -> usize {
-        self.inner.lock().used_pages()
+        let inner = self.inner.lock();
+        match inner.phase {
+            Phase::Full => inner.early_pages.len() / PAGE_SIZE + inner.palloc.used_pages(),
+            _ => inner.early.used_pages(),
+        }
     }
 
     /// Returns the number of available pages in the page allocator.
     pub fn available_pages(&self) -> usize {
-        self.inner.lock().available_pages()
+        let inner = self.inner.lock();
+        match inner.phase {
+            Phase::Full => inner.palloc.available_pages(),
+            _ => inner.early.available_pages(),
+        }
+    }
+}
+
+impl Inner {
+    /// Allocates from the full allocator, expanding the byte allocator with
+    /// pages when it runs out of memory.
+    fn alloc_full(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        loop {
+            if let Ok(ptr) = self.balloc.alloc(layout) {
+                return Ok(ptr);
+            }
+            let expand_size = self
+                .balloc
+                .total_bytes()
+                .max(layout.size())
+                .next_power_of_two()
+                .max(PAGE_SIZE);
+            let heap_ptr = self
+                .palloc
+                .alloc_pages(expand_size / PAGE_SIZE, PAGE_SIZE)?;
+            debug!(
+                "expand heap memory: [{:#x}, {:#x})",
+                heap_ptr,
+                heap_ptr + expand_size
+            );
+            self.balloc.add_memory(heap_ptr, expand_size)?;
+        }
+    }
+
+    /// Gives the early byte area to the full byte allocator once all early
+    /// allocations are freed.
+    fn reclaim_early_bytes(&mut self) {
+        if self.phase != Phase::Full || self.early.alloc_count() != 0 {
+            return;
+        }
+        self.early_bytes = 0..0;
+        let (start, end) = self.early.take_free_area();
+        if end > start {
+            debug!("reuse early heap memory: [{:#x}, {:#x})", start, end);
+            self.balloc
+                .add_memory(start, end - start)
+                .expect("add early heap memory failed");
+        }
     }
 }
 
+const fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
 unsafe impl GlobalAlloc for GlobalAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         if let Ok(ptr) = GlobalAllocator::alloc(self, layout) {
@@ -105,7 +275,21 @@ pub fn global_allocator() -> &'static GlobalAllocator {
     &GLOBAL_ALLOCATOR
 }
 
-/// Initializes the global allocator with the given memory region.
+/// Initializes the early allocator with the given memory region.
+///
+/// It serves all allocations until [`global_init`] is called with the same
+/// region.
+pub fn global_early_init(start_vaddr: usize, size: usize) {
+    debug!(
+        "initialize early allocator at: [{:#x}, {:#x})",
+        start_vaddr,
+        start_vaddr + size
+    );
+    GLOBAL_ALLOCATOR.early_init(start_vaddr, size);
+}
+
+/// Initializes the global allocator with the given memory region, taking
+/// over from the early allocator if it is in use.
 pub fn global_init(start_vaddr: usize, size: usize) {
     debug!(
         "initialize global allocator at: [{:#x}, {:#x})",
@@ -116,6 +300,13 @@ pub fn global_init(start_vaddr: usize, size: usize) {
 }
 
 /// Add the given memory region to the global allocator.
-pub fn global_add_memory(_start_vaddr: usize, _size: usize) -> AllocResult {
-    unimplemented!()
+///
+/// It can be called only after [`global_init`].
+pub fn global_add_memory(start_vaddr: usize, size: usize) -> AllocResult {
+    debug!(
+        "add a memory region to global allocator: [{:#x}, {:#x})",
+        start_vaddr,
+        start_vaddr + size
+    );
+    GLOBAL_ALLOCATOR.add_memory(start_vaddr, size)
 }
@@ -0,0 +1,21 @@
+/// Sets the `frame_pointers` cfg if the crate is built with frame pointers,
+/// which the debug heap needs to find the allocation sites on x86_64.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo::rustc-check-cfg=cfg(frame_pointers)");
+    let flags = std::env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default();
+    let frame_pointers = flags.split('\x1f').any(|flag| {
+        let flag = flag.strip_prefix("-C").unwrap_or(flag);
+        matches!(
+            flag,
+            "force-frame-pointers"
+                | "force-frame-pointers=yes"
+                | "force-frame-pointers=y"
+                | "force-frame-pointers=on"
+                | "force-frame-pointers=true"
+        )
+    });
+    if frame_pointers {
+        println!("cargo:rustc-cfg=frame_pointers");
+    }
+}
@@ -0,0 +1,461 @@
+//! A buddy page allocator, used by the global allocator with the
+//! `page-buddy` feature.
+//!
+//! Unlike the bitmap page allocator, it has no compile-time capacity: each
+//! memory region added to it becomes a zone that keeps its metadata, one
+//! byte per page, in the first pages of the region. Free blocks of `2^order`
+//! pages are naturally aligned to their size, and linked into per-order free
+//! lists through their first bytes.
+
+use allocator::{AllocError, AllocResult, BaseAllocator, PageAllocator};
+
+/// The number of block orders, i.e. the largest block has `2^(MAX_ORDER - 1)`
+/// pages.
+pub const MAX_ORDER: usize = 20;
+
+/// The maximum number of memory regions.
+const MAX_ZONES: usize = 8;
+
+/// Set in the metadata of the first page of a free block, together with the
+/// block order.
+const META_FREE: u8 = 0x80;
+
+/// Fragmentation statistics of a [`BuddyPageAllocator`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FragStats {
+    /// The number of free blocks of each order.
+    pub free_blocks: [usize; MAX_ORDER],
+    /// The total number of free pages.
+    pub free_pages: usize,
+}
+
+impl FragStats {
+    /// Returns the order of the largest free block, or `None` if there is no
+    /// free memory.
+    pub fn largest_free_order(&self) -> Option<usize> {
+        self.free_blocks.iter().rposition(|&n| n > 0)
+    }
+
+    /// Returns the share of free pages, in percent, that cannot serve a
+    /// request of `2^order` contiguous pages because they are in smaller
+    /// blocks.
+    pub fn unusable_index(&self, order: usize) -> usize {
+        if self.free_pages == 0 {
+            return 0;
+        }
+        let usable: usize = (order..MAX_ORDER).map(|o| self.free_blocks[o] << o).sum();
+        (self.free_pages - usable) * 100 / self.free_pages
+    }
+}
+
+/// The links of a free block, stored in its first bytes.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct FreeNode {
+    next: usize,
+    prev: usize,
+}
+
+/// A contiguous memory region managed by the buddy system.
+struct Zone<const PAGE_SIZE: usize> {
+    /// The page frame number of the first managed page.
+    start_pfn: usize,
+    /// The number of managed pages.
+    num_pages: usize,
+    /// The address of the metadata bytes, one per managed page.
+    meta: usize,
+    /// The first block of each free list, or 0.
+    free_lists: [usize; MAX_ORDER],
+}
+
+impl<const PAGE_SIZE: usize> Zone<PAGE_SIZE> {
+    /// Creates a zone over `[start, start + size)`, with its metadata at the
+    /// beginning, and frees all its pages.
+    fn new(start: usize, size: usize) -> Option<Self> {
+        let start = start.next_multiple_of(PAGE_SIZE);
+        let end = (start + size) & !(PAGE_SIZE - 1);
+        let total_pages = end.checked_sub(start)? / PAGE_SIZE;
+        let meta_pages = total_pages.div_ceil(PAGE_SIZE + 1);
+        let num_pages = total_pages.checked_sub(meta_pages).filter(|&n| n > 0)?;
+        unsafe { core::ptr::write_bytes(start as *mut u8, 0, num_pages) };
+        let mut zone = Self {
+            start_pfn: start / PAGE_SIZE + meta_pages,
+            num_pages,
+            meta: start,
+            free_lists: [0; MAX_ORDER],
+        };
+        zone.free_range(zone.start_pfn, num_pages);
+        Some(zone)
+    }
+
+    fn contains(&self, pfn: usize) -> bool {
+        (self.start_pfn..self.start_pfn + self.num_pages).contains(&pfn)
+    }
+
+    fn meta(&mut self, pfn: usize) -> &mut u8 {
+        debug_assert!(self.contains(pfn));
+        unsafe { &mut *((self.meta + pfn - self.start_pfn) as *mut u8) }
+    }
+
+    fn node(pfn: usize) -> &'static mut FreeNode {
+        unsafe { &mut *((pfn * PAGE_SIZE) as *mut FreeNode) }
+    }
+
+    fn push(&mut self, pfn: usize, order: usize) {
+        *self.meta(pfn) = META_FREE | order as u8;
+        let head = self.free_lists[order];
+        *Self::node(pfn) = FreeNode {
+            next: head,
+            prev: 0,
+        };
+        if head != 0 {
+            Self::node(head).prev = pfn;
+        }
+        self.free_lists[order] = pfn;
+    }
+
+    fn remove(&mut self, pfn: usize, order: usize) {
+        *self.meta(pfn) = 0;
+        let FreeNode { next, prev } = *Self::node(pfn);
+        if prev != 0 {
+            Self::node(prev).next = next;
+        } else {
+            self.free_lists[order] = next;
+        }
+        if next != 0 {
+            Self::node(next).prev = prev;
+        }
+    }
+
+    /// Frees the block of `2^order` pages at `pfn`, merging it with its free
+    /// buddies.
+    fn free_block(&mut self, mut pfn: usize, mut order: usize) {
+        while order + 1 < MAX_ORDER {
+            let buddy = pfn ^ (1 << order);
+            let buddy_end = buddy + (1 << order);
+            if !self.contains(buddy)
+                || buddy_end > self.start_pfn + self.num_pages
+                || *self.meta(buddy) != META_FREE | order as u8
+            {
+                break;
+            }
+            self.remove(buddy, order);
+            pfn = pfn.min(buddy);
+            order += 1;
+        }
+        self.push(pfn, order);
+    }
+
+    /// Frees `[pfn, pfn + count)` as the largest naturally aligned blocks.
+    fn free_range(&mut self, mut pfn: usize, count: usize) {
+        let end = pfn + count;
+        while pfn < end {
+            let align_order = pfn.trailing_zeros() as usize;
+            let size_order = (end - pfn).ilog2() as usize;
+            let order = align_order.min(size_order).min(MAX_ORDER - 1);
+            self.free_block(pfn, order);
+            pfn += 1 << order;
+        }
+    }
+
+    /// Allocates `count` pages aligned to `2^align_order` pages.
+    fn alloc(&mut self, count: usize, align_order: usize) -> Option<usize> {
+        let order = (count.next_power_of_two().ilog2() as usize).max(align_order);
+        let found = (order..MAX_ORDER).find(|&o| self.free_lists[o] != 0)?;
+        let pfn = self.free_lists[found];
+        self.remove(pfn, found);
+        // Give back the part of the block beyond `count` pages.
+        let block_pages = 1 << found;
+        self.free_range(pfn + count, block_pages - count);
+        Some(pfn)
+    }
+
+    fn add_stats(&self, stats: &mut FragStats) {
+        for (order, &head) in self.free_lists.iter().enumerate() {
+            let mut pfn = head;
+            while pfn != 0 {
+                stats.free_blocks[order] += 1;
+                stats.free_pages += 1 << order;
+                pfn = Self::node(pfn).next;
+            }
+        }
+    }
+}
+
+/// A buddy page allocator over any number of memory regions, up to
+/// [`MAX_ZONES`].
+pub struct BuddyPageAllocator<const PAGE_SIZE: usize> {
+    zones: [Option<Zone<PAGE_SIZE>>; MAX_ZONES],
+    total_pages: usize,
+    used_pages: usize,
+}
+
+impl<const PAGE_SIZE: usize> BuddyPageAllocator<PAGE_SIZE> {
+    /// Creates an empty allocator.
+    pub const fn new() -> Self {
+        Self {
+            zones: [const { None }; MAX_ZONES],
+            total_pages: 0,
+            used_pages: 0,
+        }
+    }
+
+    /// Returns the fragmentation statistics over all zones.
+    pub fn frag_stats(&self) -> FragStats {
+        let mut stats = FragStats::default();
+        for zone in self.zones.iter().flatten() {
+            zone.add_stats(&mut stats);
+        }
+        stats
+    }
+}
+
+impl<const PAGE_SIZE: usize> BaseAllocator for BuddyPageAllocator<PAGE_SIZE> {
+    fn init(&mut self, start: usize, size: usize) {
+        self.add_memory(start, size)
+            .expect("failed to initialize the buddy page allocator");
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        let slot = self
+            .zones
+            .iter_mut()
+            .find(|z| z.is_none())
+            .ok_or(AllocError::NoMemory)?;
+        let zone = Zone::new(start, size).ok_or(AllocError::InvalidParam)?;
+        self.total_pages += zone.num_pages;
+        *slot = Some(zone);
+        Ok(())
+    }
+}
+
+impl<const PAGE_SIZE: usize> PageAllocator for BuddyPageAllocator<PAGE_SIZE> {
+    const PAGE_SIZE: usize = PAGE_SIZE;
+
+    fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        if num_pages == 0 || !align_pow2.is_power_of_two() || num_pages > 1 << (MAX_ORDER - 1) {
+            return Err(AllocError::InvalidParam);
+        }
+        let align_order = (align_pow2 / PAGE_SIZE).max(1).ilog2() as usize;
+        if align_order >= MAX_ORDER {
+            return Err(AllocError::InvalidParam);
+        }
+        let pfn = self
+            .zones
+            .iter_mut()
+            .flatten()
+            .find_map(|z| z.alloc(num_pages, align_order))
+            .ok_or(AllocError::NoMemory)?;
+        self.used_pages += num_pages;
+        Ok(pfn * PAGE_SIZE)
+    }
+
+    fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
+        let pfn = pos / PAGE_SIZE;
+        let zone = self
+            .zones
+            .iter_mut()
+            .flatten()
+            .find(|z| z.contains(pfn))
+            .expect("dealloc pages not in any zone");
+        zone.free_range(pfn, num_pages);
+        self.used_pages -= num_pages;
+    }
+
+    fn total_pages(&self) -> usize {
+        self.total_pages
+    }
+
+    fn used_pages(&self) -> usize {
+        self.used_pages
+    }
+
+    fn available_pages(&self) -> usize {
+        self.total_pages - self.used_pages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::alloc::Layout;
+
+    const PAGE_SIZE: usize = 0x1000;
+
+    type Allocator = BuddyPageAllocator<PAGE_SIZE>;
+
+    /// Returns a region of a metadata page followed by `pages` pages aligned
+    /// to their size, where `pages` is a power of two.
+    fn region(pages: usize) -> (usize, usize) {
+        let layout = Layout::from_size_align(2 * pages * PAGE_SIZE, pages * PAGE_SIZE).unwrap();
+        let base = unsafe { std::alloc::alloc(layout) } as usize;
+        assert_ne!(base, 0);
+        (base + (pages - 1) * PAGE_SIZE, (pages + 1) * PAGE_SIZE)
+    }
+
+    fn new_allocator(pages: usize) -> (Allocator, usize) {
+        let (start, size) = region(pages);
+        let mut allocator = Allocator::new();
+        allocator.init(start, size);
+        (allocator, start + PAGE_SIZE)
+    }
+
+    #[test]
+    fn test_alloc_merge() {
+        let (mut allocator, start) = new_allocator(64);
+        assert_eq!(allocator.total_pages(), 64);
+        assert_eq!(allocator.frag_stats().free_blocks[6], 1);
+
+        let a = allocator.alloc_pages(1, PAGE_SIZE).unwrap();
+        assert_eq!(a, start);
+        // the rest of the block is split into one block of each smaller order
+        let stats = allocator.frag_stats();
+        assert_eq!(stats.free_blocks[..7], [1, 1, 1, 1, 1, 1, 0]);
+        assert_eq!(stats.free_pages, 63);
+
+        let b = allocator.alloc_pages(1, PAGE_SIZE).unwrap();
+        assert_eq!(b, start + PAGE_SIZE);
+        assert_eq!(allocator.used_pages(), 2);
+        assert_eq!(allocator.available_pages(), 62);
+
+        allocator.dealloc_pages(a, 1);
+        assert_eq!(allocator.frag_stats().free_blocks[0], 1);
+        // freeing the buddy merges the blocks back into one
+        allocator.dealloc_pages(b, 1);
+        let stats = allocator.frag_stats();
+        assert_eq!(stats.free_blocks[..7], [0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(allocator.used_pages(), 0);
+    }
+
+    #[test]
+    fn test_aligned_alloc() {
+        let (mut allocator, start) = new_allocator(64);
+        // 3 pages take a block of 4, and give back the last page
+        let a = allocator.alloc_pages(3, PAGE_SIZE).unwrap();
+        assert_eq!(a, start);
+        assert_eq!(allocator.frag_stats().free_blocks[0], 1);
+        assert_eq!(
+            allocator.alloc_pages(1, PAGE_SIZE),
+            Ok(start + 3 * PAGE_SIZE)
+        );
+
+        let b = allocator.alloc_pages(1, 8 * PAGE_SIZE).unwrap();
+        assert_eq!(b, start + 8 * PAGE_SIZE);
+        let c = allocator.alloc_pages(5, 32 * PAGE_SIZE).unwrap();
+        assert_eq!(c, start + 32 * PAGE_SIZE);
+        let d = allocator.alloc_pages(2, 2 * PAGE_SIZE).unwrap();
+        assert_eq!(d % (2 * PAGE_SIZE), 0);
+        assert!((start..start + 64 * PAGE_SIZE).contains(&d));
+        assert_eq!(allocator.used_pages(), 12);
+
+        allocator.dealloc_pages(a, 3);
+        allocator.dealloc_pages(start + 3 * PAGE_SIZE, 1);
+        allocator.dealloc_pages(b, 1);
+        allocator.dealloc_pages(c, 5);
+        allocator.dealloc_pages(d, 2);
+        assert_eq!(allocator.frag_stats().free_blocks[6], 1);
+        assert_eq!(allocator.used_pages(), 0);
+    }
+
+    #[test]
+    fn test_no_memory() {
+        let (mut allocator, start) = new_allocator(16);
+        assert_eq!(
+            allocator.alloc_pages(17, PAGE_SIZE),
+            Err(AllocError::NoMemory)
+        );
+        assert_eq!(
+            allocator.alloc_pages(0, PAGE_SIZE),
+            Err(AllocError::InvalidParam)
+        );
+        assert_eq!(
+            allocator.alloc_pages(1, 3 * PAGE_SIZE),
+            Err(AllocError::InvalidParam)
+        );
+        assert_eq!(
+            allocator.alloc_pages(1 << MAX_ORDER, PAGE_SIZE),
+            Err(AllocError::InvalidParam)
+        );
+
+        assert_eq!(allocator.alloc_pages(16, PAGE_SIZE), Ok(start));
+        assert_eq!(
+            allocator.alloc_pages(1, PAGE_SIZE),
+            Err(AllocError::NoMemory)
+        );
+        assert_eq!(allocator.available_pages(), 0);
+        assert_eq!(allocator.frag_stats().largest_free_order(), None);
+        allocator.dealloc_pages(start, 16);
+        assert_eq!(allocator.alloc_pages(1, PAGE_SIZE), Ok(start));
+    }
+
+    #[test]
+    fn test_zones() {
+        let (mut allocator, first) = new_allocator(16);
+        let (start, size) = region(16);
+        allocator.add_memory(start, size).unwrap();
+        let second = start + PAGE_SIZE;
+        assert_eq!(allocator.total_pages(), 32);
+
+        // a block never spans two zones
+        assert_eq!(
+            allocator.alloc_pages(32, PAGE_SIZE),
+            Err(AllocError::NoMemory)
+        );
+        assert_eq!(allocator.alloc_pages(16, PAGE_SIZE), Ok(first));
+        assert_eq!(allocator.alloc_pages(16, PAGE_SIZE), Ok(second));
+        allocator.dealloc_pages(first, 16);
+        assert_eq!(allocator.alloc_pages(8, PAGE_SIZE), Ok(first));
+        allocator.dealloc_pages(second, 16);
+        allocator.dealloc_pages(first, 8);
+        assert_eq!(allocator.frag_stats().free_blocks[4], 2);
+
+        // no room for the metadata and a page
+        let (start, _) = region(1);
+        assert_eq!(
+            allocator.add_memory(start, PAGE_SIZE),
+            Err(AllocError::InvalidParam)
+        );
+        for _ in 2..MAX_ZONES {
+            let (start, size) = region(1);
+            allocator.add_memory(start, size).unwrap();
+        }
+        let (start, size) = region(1);
+        assert_eq!(allocator.add_memory(start, size), Err(AllocError::NoMemory));
+        assert_eq!(allocator.total_pages(), 32 + MAX_ZONES - 2);
+    }
+
+    #[test]
+    fn test_frag_stats() {
+        let stats = FragStats::default();
+        assert_eq!(stats.largest_free_order(), None);
+        assert_eq!(stats.unusable_index(0), 0);
+
+        let (mut allocator, start) = new_allocator(16);
+        for i in 0..16 {
+            assert_eq!(
+                allocator.alloc_pages(1, PAGE_SIZE),
+                Ok(start + i * PAGE_SIZE)
+            );
+        }
+        // free every other page, which cannot be merged
+        for i in (0..16).step_by(2) {
+            allocator.dealloc_pages(start + i * PAGE_SIZE, 1);
+        }
+        let stats = allocator.frag_stats();
+        assert_eq!(stats.free_blocks[0], 8);
+        assert_eq!(stats.free_pages, 8);
+        assert_eq!(stats.largest_free_order(), Some(0));
+        assert_eq!(stats.unusable_index(0), 0);
+        assert_eq!(stats.unusable_index(1), 100);
+
+        // 4 pages merged into a block of order 2
+        for i in [1, 3] {
+            allocator.dealloc_pages(start + i * PAGE_SIZE, 1);
+        }
+        let stats = allocator.frag_stats();
+        assert_eq!(stats.free_blocks[..3], [6, 0, 1]);
+        assert_eq!(stats.largest_free_order(), Some(2));
+        assert_eq!(stats.unusable_index(1), 60);
+        assert_eq!(stats.unusable_index(3), 100);
+    }
+}
@@ -0,0 +1,283 @@
+//! Debug heap, enabled by the `alloc-debug` feature.
+//!
+//! Every allocation is laid out as follows:
+//!
+//! ```text
+//! [ header | front redzone | user data | back redzone ]
+//! ```
+//!
+//! The header records the size, the allocation site and a sequence number.
+//! The redzones are filled with a canary pattern, and new user data with a
+//! junk pattern. On `dealloc`, the header and both redzones are checked, and
+//! the block is filled with a poison pattern and kept in a quarantine of
+//! recently freed blocks instead of being freed at once. When it is evicted
+//! from the quarantine, the poison is checked to detect writes after free.
+//!
+//! Any corruption found is reported with the size, the allocation site and
+//! the sequence number of the block, and panics. The allocation site is the
+//! return address of the allocation call, or the one given to
+//! [`GlobalAllocator::alloc_at`] by wrappers such as `malloc` in axlibc. It
+//! can be resolved with `addr2line` against the kernel image.
+//!
+//! On x86_64, the return address is found through the frame pointer, so the
+//! site is only recorded if the kernel is built with
+//! `-C force-frame-pointers=yes`, which the build scripts add along with the
+//! `alloc-debug` feature. Otherwise it is reported as 0.
+
+use core::alloc::Layout;
+use core::mem::{align_of, size_of};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use allocator::{AllocError, AllocResult};
+use kspin::SpinNoIrq;
+
+use crate::GlobalAllocator;
+
+/// The size of each redzone in bytes.
+const REDZONE_SIZE: usize = 16;
+/// The number of freed blocks kept in the quarantine.
+const QUARANTINE_LEN: usize = 256;
+
+const REDZONE_BYTE: u8 = 0xaa;
+const JUNK_BYTE: u8 = 0x5a;
+const POISON_BYTE: u8 = 0x6b;
+
+const MAGIC_LIVE: usize = 0xa110_c8ed;
+const MAGIC_FREED: usize = 0xdead_f4ee;
+
+#[repr(C)]
+struct Header {
+    magic: usize,
+    size: usize,
+    site: usize,
+    seq: usize,
+}
+
+static NEXT_SEQ: AtomicUsize = AtomicUsize::new(1);
+
+/// Blocks freed by the user but not yet given back to the byte allocator, as
+/// a ring of block start addresses and layouts.
+struct Quarantine {
+    blocks: [(usize, Layout); QUARANTINE_LEN],
+    head: usize,
+    len: usize,
+}
+
+static QUARANTINE: SpinNoIrq<Quarantine> = SpinNoIrq::new(Quarantine {
+    blocks: [(0, Layout::new::<u8>()); QUARANTINE_LEN],
+    head: 0,
+    len: 0,
+});
+
+impl Quarantine {
+    /// Adds a block, and returns the oldest one if the quarantine is full.
+    fn push(&mut self, block: (usize, Layout)) -> Option<(usize, Layout)> {
+        let tail = (self.head + self.len) % QUARANTINE_LEN;
+        if self.len < QUARANTINE_LEN {
+            self.blocks[tail] = block;
+            self.len += 1;
+            None
+        } else {
+            let evicted = core::mem::replace(&mut self.blocks[self.head], block);
+            self.head = (self.head + 1) % QUARANTINE_LEN;
+            Some(evicted)
+        }
+    }
+}
+
+/// Returns the return address of the calling function.
+///
+/// It must be inlined into a function that is not inlined itself, and be
+/// called before anything else in it. On x86_64 it is read from the frame
+/// of that function, and needs frame pointers. Returns 0 if not supported.
+#[inline(always)]
+pub fn caller_site() -> usize {
+    #[allow(unused_mut)]
+    let mut ra = 0;
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        core::arch::asm!("mv {}, ra", out(reg) ra)
+    };
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("mov {}, x30", out(reg) ra)
+    };
+    #[cfg(target_arch = "loongarch64")]
+    unsafe {
+        core::arch::asm!("move {}, $ra", out(reg) ra)
+    };
+    #[cfg(all(target_arch = "x86_64", frame_pointers))]
+    unsafe {
+        core::arch::asm!("mov {}, [rbp + 8]", out(reg) ra)
+    };
+    ra
+}
+
+/// Returns the layout of the whole block for a user layout, and the offset
+/// of the user data in it.
+fn block_layout(layout: Layout) -> AllocResult<(Layout, usize)> {
+    let align = layout.align().max(align_of::<Header>());
+    let offset = (size_of::<Header>() + REDZONE_SIZE).next_multiple_of(align);
+    let size = offset + layout.size() + REDZONE_SIZE;
+    let block = Layout::from_size_align(size, align).map_err(|_| AllocError::InvalidParam)?;
+    Ok((block, offset))
+}
+
+fn is_filled(start: usize, len: usize, byte: u8) -> bool {
+    let bytes = unsafe { core::slice::from_raw_parts(start as *const u8, len) };
+    bytes.iter().all(|&b| b == byte)
+}
+
+fn fill(start: usize, len: usize, byte: u8) {
+    unsafe { core::ptr::write_bytes(start as *mut u8, byte, len) }
+}
+
+fn report(what: &str, addr: usize, header: &Header) -> ! {
+    panic!(
+        "heap corruption: {} at {:#x} (size {}, allocation #{} from {:#x})",
+        what, addr, header.size, header.seq, header.site
+    );
+}
+
+/// Allocates a block with redzones around the user data.
+pub(crate) fn alloc(ga: &GlobalAllocator, layout: Layout, site: usize) -> AllocResult<NonNull<u8>> {
+    let (block, offset) = block_layout(layout)?;
+    let start = ga.alloc_inner(block)?.as_ptr() as usize;
+    let user = start + offset;
+    let header = Header {
+        magic: MAGIC_LIVE,
+        size: layout.size(),
+        site,
+        seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+    };
+    unsafe { (start as *mut Header).write(header) };
+    fill(
+        start + size_of::<Header>(),
+        offset - size_of::<Header>(),
+        REDZONE_BYTE,
+    );
+    fill(user, layout.size(), JUNK_BYTE);
+    fill(user + layout.size(), REDZONE_SIZE, REDZONE_BYTE);
+    Ok(NonNull::new(user as *mut u8).unwrap())
+}
+
+/// Checks the block of the user data at `pos`, poisons it and puts it into
+/// the quarantine.
+pub(crate) fn dealloc(ga: &GlobalAllocator, pos: NonNull<u8>, layout: Layout) {
+    let user = pos.as_ptr() as usize;
+    let (block, offset) = block_layout(layout).expect("invalid layout");
+    let start = user - offset;
+    let header = unsafe { &mut *(start as *mut Header) };
+
+    match header.magic {
+        MAGIC_LIVE => {}
+        MAGIC_FREED => report("double free", user, header),
+        _ => panic!("heap corruption: invalid free or smashed header at {user:#x}"),
+    }
+    if header.size != layout.size() {
+        report("free with a wrong size", user, header);
+    }
+    let front = start + size_of::<Header>();
+    if !is_filled(front, offset - size_of::<Header>(), REDZONE_BYTE) {
+        report("buffer underflow", user, header);
+    }
+    if !is_filled(user + layout.size(), REDZONE_SIZE, REDZONE_BYTE) {
+        report("buffer overflow", user, header);
+    }
+
+    header.magic = MAGIC_FREED;
+    fill(user, layout.size(), POISON_BYTE);
+    let evicted = QUARANTINE.lock().push((start, block));
+    if let Some((start, block)) = evicted {
+        check_poison(start, block);
+        ga.dealloc_inner(NonNull::new(start as *mut u8).unwrap(), block);
+    }
+}
+
+/// Checks that a block leaving the quarantine has not been written to since
+/// it was freed.
+fn check_poison(start: usize, block: Layout) {
+    let header = unsafe { &*(start as *const Header) };
+    if header.magic != MAGIC_FREED {
+        panic!("heap corruption: smashed header of a freed block at {start:#x}");
+    }
+    let user = start + block.size() - REDZONE_SIZE - header.size;
+    if !is_filled(user, header.size, POISON_BYTE) {
+        report("write after free", user, header);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{heap, SERIAL};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    const SITE: usize = 0xc0de;
+
+    /// Runs `f`, which should report a corruption, and returns the report.
+    fn report_of(f: impl FnOnce()) -> String {
+        let err = catch_unwind(AssertUnwindSafe(f)).expect_err("no corruption reported");
+        *err.downcast::<String>().unwrap()
+    }
+
+    #[test]
+    fn test_fill() {
+        let _guard = SERIAL.lock().unwrap();
+        let layout = Layout::from_size_align(40, 8).unwrap();
+        let ptr = heap().alloc_at(layout, SITE).unwrap();
+        assert!(is_filled(ptr.as_ptr() as usize, 40, JUNK_BYTE));
+        heap().dealloc(ptr, layout);
+        assert!(is_filled(ptr.as_ptr() as usize, 40, POISON_BYTE));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let _guard = SERIAL.lock().unwrap();
+        let layout = Layout::from_size_align(24, 8).unwrap();
+        let ptr = heap().alloc_at(layout, SITE).unwrap();
+        unsafe { ptr.as_ptr().add(24).write(0) };
+        let report = report_of(|| heap().dealloc(ptr, layout));
+        assert!(report.starts_with("heap corruption: buffer overflow at"));
+        assert!(report.contains("(size 24, allocation #"));
+        assert!(report.ends_with("from 0xc0de)"));
+
+        let ptr = heap().alloc_at(layout, SITE).unwrap();
+        unsafe { ptr.as_ptr().sub(1).write(0) };
+        let report = report_of(|| heap().dealloc(ptr, layout));
+        assert!(report.starts_with("heap corruption: buffer underflow at"));
+    }
+
+    #[test]
+    fn test_double_free() {
+        let _guard = SERIAL.lock().unwrap();
+        let layout = Layout::from_size_align(16, 8).unwrap();
+        let ptr = heap().alloc_at(layout, SITE).unwrap();
+        heap().dealloc(ptr, layout);
+        let report = report_of(|| heap().dealloc(ptr, layout));
+        assert!(report.starts_with("heap corruption: double free at"));
+        assert!(report.ends_with("from 0xc0de)"));
+    }
+
+    #[test]
+    fn test_write_after_free() {
+        let _guard = SERIAL.lock().unwrap();
+        let layout = Layout::from_size_align(32, 16).unwrap();
+        let ptr = heap().alloc_at(layout, SITE).unwrap();
+        heap().dealloc(ptr, layout);
+        unsafe { ptr.as_ptr().add(8).write(0) };
+        // the block is checked when it is evicted from the quarantine
+        let report = report_of(|| {
+            for _ in 0..QUARANTINE_LEN {
+                let other = heap().alloc_at(layout, 0).unwrap();
+                heap().dealloc(other, layout);
+            }
+        });
+        assert!(report.starts_with(&format!(
+            "heap corruption: write after free at {:#x}",
+            ptr.as_ptr() as usize
+        )));
+        assert!(report.ends_with("from 0xc0de)"));
+    }
+}
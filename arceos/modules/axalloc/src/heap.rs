@@ -0,0 +1,210 @@
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator};
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use crate::DefaultByteAllocator;
+
+/// The maximum number of expansion chunks that can be returned to the page
+/// allocator. Further expansions are added to the base allocator for good.
+const MAX_HEAP_CHUNKS: usize = 16;
+
+/// A region taken from the page allocator to expand the heap.
+struct HeapChunk {
+    start: usize,
+    size: usize,
+    balloc: DefaultByteAllocator,
+}
+
+impl HeapChunk {
+    fn contains(&self, pos: usize) -> bool {
+        (self.start..self.start + self.size).contains(&pos)
+    }
+}
+
+/// The byte-level heap of the global allocator.
+///
+/// The byte allocators cannot give memory back once it is added to them, so
+/// every expansion chunk gets a byte allocator of its own. A chunk whose
+/// allocations are all freed can then be removed as a whole by
+/// [`take_free_chunk`](Self::take_free_chunk).
+pub(crate) struct ByteHeap {
+    /// Holds the initial heap and the regions added by `add_memory`.
+    base: DefaultByteAllocator,
+    chunks: [Option<HeapChunk>; MAX_HEAP_CHUNKS],
+}
+
+impl ByteHeap {
+    pub const fn new() -> Self {
+        const NO_CHUNK: Option<HeapChunk> = None;
+        Self {
+            base: DefaultByteAllocator::new(),
+            chunks: [NO_CHUNK; MAX_HEAP_CHUNKS],
+        }
+    }
+
+    pub fn init(&mut self, start: usize, size: usize) {
+        self.base.init(start, size);
+    }
+
+    pub fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        self.base.add_memory(start, size)
+    }
+
+    /// Adds an expansion chunk taken from the page allocator.
+    pub fn add_chunk(&mut self, start: usize, size: usize) -> AllocResult {
+        match self.chunks.iter_mut().find(|c| c.is_none()) {
+            Some(slot) => {
+                let mut balloc = DefaultByteAllocator::new();
+                balloc.init(start, size);
+                *slot = Some(HeapChunk {
+                    start,
+                    size,
+                    balloc,
+                });
+                Ok(())
+            }
+            None => self.base.add_memory(start, size),
+        }
+    }
+
+    /// Removes an expansion chunk with no live allocations, and returns its
+    /// region.
+    pub fn take_free_chunk(&mut self) -> Option<(usize, usize)> {
+        let slot = self
+            .chunks
+            .iter_mut()
+            .find(|c| c.as_ref().is_some_and(|c| c.balloc.used_bytes() == 0))?;
+        slot.take().map(|c| (c.start, c.size))
+    }
+
+    fn allocators(&self) -> impl Iterator<Item = &DefaultByteAllocator> {
+        let chunks = self.chunks.iter().flatten().map(|c| &c.balloc);
+        core::iter::once(&self.base).chain(chunks)
+    }
+
+    pub fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        // Try the base allocator first, so that the chunks are more likely to
+        // become free.
+        if let Ok(ptr) = self.base.alloc(layout) {
+            return Ok(ptr);
+        }
+        self.chunks
+            .iter_mut()
+            .flatten()
+            .find_map(|c| c.balloc.alloc(layout).ok())
+            .ok_or(AllocError::NoMemory)
+    }
+
+    pub fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        let addr = pos.as_ptr() as usize;
+        match self.chunks.iter_mut().flatten().find(|c| c.contains(addr)) {
+            Some(chunk) => chunk.balloc.dealloc(pos, layout),
+            None => self.base.dealloc(pos, layout),
+        }
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.allocators().map(|a| a.total_bytes()).sum()
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.allocators().map(|a| a.used_bytes()).sum()
+    }
+
+    pub fn available_bytes(&self) -> usize {
+        self.allocators().map(|a| a.available_bytes()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GlobalAllocator, PAGE_SIZE};
+
+    /// Returns a page-aligned region of `size` bytes.
+    fn region(size: usize) -> usize {
+        let layout = Layout::from_size_align(size, PAGE_SIZE).unwrap();
+        let start = unsafe { std::alloc::alloc(layout) } as usize;
+        assert_ne!(start, 0);
+        start
+    }
+
+    #[test]
+    fn test_take_free_chunk() {
+        let mut heap = ByteHeap::new();
+        heap.init(region(PAGE_SIZE), PAGE_SIZE);
+        let chunk = region(8 * PAGE_SIZE);
+        heap.add_chunk(chunk, 8 * PAGE_SIZE).unwrap();
+        assert_eq!(heap.total_bytes(), 9 * PAGE_SIZE);
+
+        // too large for the base allocator
+        let layout = Layout::from_size_align(2 * PAGE_SIZE, 8).unwrap();
+        let ptr = heap.alloc(layout).unwrap();
+        assert!((chunk..chunk + 8 * PAGE_SIZE).contains(&(ptr.as_ptr() as usize)));
+        let small = Layout::from_size_align(64, 8).unwrap();
+        let in_base = heap.alloc(small).unwrap();
+        assert!(heap.take_free_chunk().is_none());
+
+        heap.dealloc(ptr, layout);
+        assert_eq!(heap.take_free_chunk(), Some((chunk, 8 * PAGE_SIZE)));
+        assert!(heap.take_free_chunk().is_none());
+        assert_eq!(heap.total_bytes(), PAGE_SIZE);
+        // the allocations of the base allocator are kept
+        assert!(heap.used_bytes() >= 64);
+        heap.dealloc(in_base, small);
+        assert!(heap.alloc(layout).is_err());
+    }
+
+    #[test]
+    fn test_chunk_slots_full() {
+        let mut heap = ByteHeap::new();
+        heap.init(region(PAGE_SIZE), PAGE_SIZE);
+        let chunks: Vec<usize> = (0..MAX_HEAP_CHUNKS + 1)
+            .map(|_| region(PAGE_SIZE))
+            .collect();
+        for &chunk in &chunks {
+            heap.add_chunk(chunk, PAGE_SIZE).unwrap();
+        }
+        assert_eq!(heap.total_bytes(), (MAX_HEAP_CHUNKS + 2) * PAGE_SIZE);
+
+        // the last one is added to the base allocator for good
+        let mut taken: Vec<usize> = core::iter::from_fn(|| heap.take_free_chunk())
+            .map(|(start, _)| start)
+            .collect();
+        taken.sort_unstable();
+        let mut expected = chunks[..MAX_HEAP_CHUNKS].to_vec();
+        expected.sort_unstable();
+        assert_eq!(taken, expected);
+        assert_eq!(heap.total_bytes(), 2 * PAGE_SIZE);
+
+        // the slots can be used again
+        heap.add_chunk(chunks[0], PAGE_SIZE).unwrap();
+        assert_eq!(heap.take_free_chunk(), Some((chunks[0], PAGE_SIZE)));
+    }
+
+    #[test]
+    fn test_reclaim_chunks() {
+        const SIZE: usize = 0x10_0000; // 1 M
+        let ga = GlobalAllocator::new();
+        ga.init(region(SIZE), SIZE);
+        let pages = ga.available_pages();
+        let heap_bytes = ga.balloc.lock().total_bytes();
+        // unlike `reclaim_heap`, it leaves the CPU caches, which are shared
+        // by the allocators of all tests
+        let reclaim =
+            || GlobalAllocator::reclaim_chunks(&mut ga.balloc.lock(), &mut ga.palloc.lock());
+
+        let layout = Layout::from_size_align(64 * 1024, 8).unwrap();
+        let ptr = ga.alloc_from_heap(layout).unwrap();
+        assert!(ga.available_pages() < pages);
+        assert!(ga.balloc.lock().total_bytes() > heap_bytes);
+        assert_eq!(reclaim(), 0);
+
+        ga.balloc.lock().dealloc(ptr, layout);
+        let expanded = pages - ga.available_pages();
+        assert_eq!(reclaim(), expanded);
+        assert_eq!(ga.available_pages(), pages);
+        assert_eq!(ga.balloc.lock().total_bytes(), heap_bytes);
+        assert_eq!(reclaim(), 0);
+    }
+}
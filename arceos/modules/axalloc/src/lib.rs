@@ -5,19 +5,33 @@
 //! [`GlobalAllocator`] is defined with the `#[global_allocator]` attribute, to
 //! be registered as the standard library’s default allocator.
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 #[macro_use]
 extern crate log;
 extern crate alloc;
 
+mod heap;
 mod page;
 
-use allocator::{AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator};
+pub mod buddy_page;
+
+#[cfg(feature = "alloc-debug")]
+mod debug;
+
+#[cfg(feature = "percpu-cache")]
+mod magazine;
+
+#[cfg(feature = "alloc-stats")]
+pub mod stats;
+
+use allocator::{AllocError, AllocResult, BaseAllocator, PageAllocator};
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::NonNull;
 use kspin::SpinNoIrq;
 
+use self::heap::ByteHeap;
+
 const PAGE_SIZE: usize = 0x1000;
 const MIN_HEAP_SIZE: usize = 0x8000; // 32 K
 
@@ -36,6 +50,16 @@ cfg_if::cfg_if! {
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(feature = "page-buddy")] {
+        /// The default page allocator.
+        pub type DefaultPageAllocator = buddy_page::BuddyPageAllocator<PAGE_SIZE>;
+    } else {
+        /// The default page allocator.
+        pub type DefaultPageAllocator = allocator::BitmapPageAllocator<PAGE_SIZE>;
+    }
+}
+
 /// The global allocator used by ArceOS.
 ///
 /// It combines a [`ByteAllocator`] and a [`PageAllocator`] into a simple
@@ -43,21 +67,30 @@ cfg_if::cfg_if! {
 /// there is no memory, asks the page allocator for more memory and adds it to
 /// the byte allocator.
 ///
+/// Each expansion chunk is managed by a byte allocator of its own, so that it
+/// can be returned to the page allocator once all its allocations are freed.
+/// This happens when the page allocator runs out of memory, or when
+/// [`reclaim_heap`](Self::reclaim_heap) is called.
+///
 /// Currently, [`TlsfByteAllocator`] is used as the byte allocator, while
-/// [`BitmapPageAllocator`] is used as the page allocator.
+/// [`BitmapPageAllocator`] is used as the page allocator, or
+/// [`BuddyPageAllocator`] with the `page-buddy` feature.
 ///
+/// [`ByteAllocator`]: allocator::ByteAllocator
 /// [`TlsfByteAllocator`]: allocator::TlsfByteAllocator
+/// [`BitmapPageAllocator`]: allocator::BitmapPageAllocator
+/// [`BuddyPageAllocator`]: buddy_page::BuddyPageAllocator
 pub struct GlobalAllocator {
-    balloc: SpinNoIrq<DefaultByteAllocator>,
-    palloc: SpinNoIrq<BitmapPageAllocator<PAGE_SIZE>>,
+    balloc: SpinNoIrq<ByteHeap>,
+    palloc: SpinNoIrq<DefaultPageAllocator>,
 }
 
 impl GlobalAllocator {
     /// Creates an empty [`GlobalAllocator`].
     pub const fn new() -> Self {
         Self {
-            balloc: SpinNoIrq::new(DefaultByteAllocator::new()),
-            palloc: SpinNoIrq::new(BitmapPageAllocator::new()),
+            balloc: SpinNoIrq::new(ByteHeap::new()),
+            palloc: SpinNoIrq::new(DefaultPageAllocator::new()),
         }
     }
 
@@ -91,9 +124,14 @@ impl GlobalAllocator {
 
     /// Add the given region to the allocator.
     ///
-    /// It will add the whole region to the byte allocator.
+    /// It will add the whole region to the byte allocator, or to the page
+    /// allocator with the `page-buddy` feature.
     pub fn add_memory(&self, start_vaddr: usize, size: usize) -> AllocResult {
-        self.balloc.lock().add_memory(start_vaddr, size)
+        if cfg!(feature = "page-buddy") {
+            self.palloc.lock().add_memory(start_vaddr, size)
+        } else {
+            self.balloc.lock().add_memory(start_vaddr, size)
+        }
     }
 
     /// Allocate arbitrary number of bytes. Returns the left bound of the
@@ -102,9 +140,45 @@ impl GlobalAllocator {
     /// It firstly tries to allocate from the byte allocator. If there is no
     /// memory, it asks the page allocator for more memory and adds it to the
     /// byte allocator.
+    #[cfg_attr(feature = "alloc-debug", inline(never))]
     pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let site = caller_site();
+        self.alloc_at(layout, site)
+    }
+
+    /// Allocates like [`alloc`], but with the allocation site reported by
+    /// the debug heap given by the caller.
+    ///
+    /// It is for the wrappers of the allocator, such as `malloc` in C, so that
+    /// the site is their caller rather than the wrapper. `site` should be a
+    /// code address, e.g. one returned by [`caller_site`].
+    ///
+    /// [`alloc`]: GlobalAllocator::alloc
+    pub fn alloc_at(&self, layout: Layout, site: usize) -> AllocResult<NonNull<u8>> {
+        #[cfg(feature = "alloc-debug")]
+        let res = debug::alloc(self, layout, site);
+        #[cfg(not(feature = "alloc-debug"))]
+        let res = {
+            let _ = site;
+            self.alloc_inner(layout)
+        };
+        #[cfg(feature = "alloc-stats")]
+        stats::record_alloc(layout, &res);
+        res
+    }
+
+    fn alloc_inner(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        #[cfg(feature = "percpu-cache")]
+        if let Some(class) = magazine::size_class(layout) {
+            return magazine::alloc(self, class);
+        }
+        self.alloc_from_heap(layout)
+    }
+
+    fn alloc_from_heap(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
         // simple two-level allocator: if no heap memory, allocate from the page allocator.
         let mut balloc = self.balloc.lock();
+        let mut drained = false;
         loop {
             if let Ok(ptr) = balloc.alloc(layout) {
                 return Ok(ptr);
@@ -113,14 +187,30 @@ impl GlobalAllocator {
                 let expand_size = old_size
                     .max(layout.size())
                     .next_power_of_two()
-                    .max(PAGE_SIZE);
-                let heap_ptr = self.alloc_pages(expand_size / PAGE_SIZE, PAGE_SIZE)?;
+                    .max(MIN_HEAP_SIZE);
+                let num_pages = expand_size / PAGE_SIZE;
+                let mut palloc = self.palloc.lock();
+                let heap_ptr = match palloc.alloc_pages(num_pages, PAGE_SIZE) {
+                    Err(AllocError::NoMemory) if !drained => {
+                        // The blocks cached by the CPUs may keep the free
+                        // chunks in use. The caches are locked first.
+                        drop(palloc);
+                        drop(balloc);
+                        self.drain_all_caches();
+                        drained = true;
+                        balloc = self.balloc.lock();
+                        Self::reclaim_chunks(&mut balloc, &mut self.palloc.lock());
+                        continue;
+                    }
+                    res => res?,
+                };
+                drop(palloc);
                 debug!(
                     "expand heap memory: [{:#x}, {:#x})",
                     heap_ptr,
                     heap_ptr + expand_size
                 );
-                balloc.add_memory(heap_ptr, expand_size)?;
+                balloc.add_chunk(heap_ptr, expand_size)?;
             }
         }
     }
@@ -133,17 +223,60 @@ impl GlobalAllocator {
     ///
     /// [`alloc`]: GlobalAllocator::alloc
     pub fn dealloc(&self, pos: NonNull<u8>, layout: Layout) {
+        // the region may be allocated again by another CPU once it is freed,
+        // so its record is removed first
+        #[cfg(feature = "alloc-stats")]
+        stats::record_dealloc(pos, layout);
+        #[cfg(feature = "alloc-debug")]
+        debug::dealloc(self, pos, layout);
+        #[cfg(not(feature = "alloc-debug"))]
+        self.dealloc_inner(pos, layout);
+    }
+
+    fn dealloc_inner(&self, pos: NonNull<u8>, layout: Layout) {
+        #[cfg(feature = "percpu-cache")]
+        if let Some(class) = magazine::size_class(layout) {
+            return magazine::dealloc(self, class, pos);
+        }
         self.balloc.lock().dealloc(pos, layout)
     }
 
+    /// Gives the blocks cached by the current CPU back to the byte allocator.
+    ///
+    /// It should be called when the CPU goes idle. Does nothing without the
+    /// `percpu-cache` feature.
+    pub fn drain_cpu_cache(&self) {
+        #[cfg(feature = "percpu-cache")]
+        magazine::drain(self);
+    }
+
+    /// Gives the blocks cached by all CPUs back to the byte allocator, so
+    /// that they do not keep heap chunks from being reclaimed.
+    fn drain_all_caches(&self) {
+        #[cfg(feature = "percpu-cache")]
+        magazine::drain_all(self);
+    }
+
     /// Allocates contiguous pages.
     ///
     /// It allocates `num_pages` pages from the page allocator.
     ///
     /// `align_pow2` must be a power of 2, and the returned region bound will be
     /// aligned to it.
+    ///
+    /// If the page allocator runs out of memory, free heap chunks are returned
+    /// to it before giving up.
     pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
-        self.palloc.lock().alloc_pages(num_pages, align_pow2)
+        match self.palloc.lock().alloc_pages(num_pages, align_pow2) {
+            Err(AllocError::NoMemory) => {}
+            res => return res,
+        }
+        // Lock in the same order as `alloc`: byte allocator first.
+        self.drain_all_caches();
+        let mut balloc = self.balloc.lock();
+        let mut palloc = self.palloc.lock();
+        Self::reclaim_chunks(&mut balloc, &mut palloc);
+        palloc.alloc_pages(num_pages, align_pow2)
     }
 
     /// Gives back the allocated pages starts from `pos` to the page allocator.
@@ -157,6 +290,26 @@ impl GlobalAllocator {
         self.palloc.lock().dealloc_pages(pos, num_pages)
     }
 
+    /// Returns the heap chunks with no live allocations to the page allocator.
+    ///
+    /// Returns the number of pages given back.
+    pub fn reclaim_heap(&self) -> usize {
+        self.drain_all_caches();
+        let mut balloc = self.balloc.lock();
+        let mut palloc = self.palloc.lock();
+        Self::reclaim_chunks(&mut balloc, &mut palloc)
+    }
+
+    fn reclaim_chunks(balloc: &mut ByteHeap, palloc: &mut DefaultPageAllocator) -> usize {
+        let mut num_pages = 0;
+        while let Some((start, size)) = balloc.take_free_chunk() {
+            debug!("shrink heap memory: [{:#x}, {:#x})", start, start + size);
+            palloc.dealloc_pages(start, size / PAGE_SIZE);
+            num_pages += size / PAGE_SIZE;
+        }
+        num_pages
+    }
+
     /// Returns the number of allocated bytes in the byte allocator.
     pub fn used_bytes(&self) -> usize {
         self.balloc.lock().used_bytes()
@@ -176,11 +329,32 @@ impl GlobalAllocator {
     pub fn available_pages(&self) -> usize {
         self.palloc.lock().available_pages()
     }
+
+    /// Returns the fragmentation statistics of the page allocator.
+    #[cfg(feature = "page-buddy")]
+    pub fn page_frag_stats(&self) -> buddy_page::FragStats {
+        self.palloc.lock().frag_stats()
+    }
+}
+
+#[cfg(feature = "alloc-debug")]
+pub use self::debug::caller_site;
+
+/// Returns the return address of the calling function, to be passed to
+/// [`GlobalAllocator::alloc_at`].
+///
+/// Always returns 0 without the `alloc-debug` feature.
+#[cfg(not(feature = "alloc-debug"))]
+#[inline(always)]
+pub fn caller_site() -> usize {
+    0
 }
 
 unsafe impl GlobalAlloc for GlobalAllocator {
+    #[cfg_attr(feature = "alloc-debug", inline(never))]
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        if let Ok(ptr) = GlobalAllocator::alloc(self, layout) {
+        let site = caller_site();
+        if let Ok(ptr) = GlobalAllocator::alloc_at(self, layout, site) {
             ptr.as_ptr()
         } else {
             alloc::alloc::handle_alloc_error(layout)
@@ -231,3 +405,37 @@ pub fn global_add_memory(start_vaddr: usize, size: usize) -> AllocResult {
     );
     GLOBAL_ALLOCATOR.add_memory(start_vaddr, size)
 }
+
+#[cfg(all(
+    test,
+    any(
+        feature = "alloc-debug",
+        feature = "alloc-stats",
+        feature = "percpu-cache"
+    )
+))]
+mod tests {
+    use std::sync::Mutex;
+
+    /// Runs the tests that use the global state, such as the statistics or
+    /// the CPU caches, one at a time.
+    pub(crate) static SERIAL: Mutex<()> = Mutex::new(());
+
+    /// Returns the heap shared by the tests, as the blocks in the CPU caches
+    /// and the quarantine of the debug heap are given back to any allocator.
+    #[cfg(any(feature = "alloc-debug", feature = "percpu-cache"))]
+    pub(crate) fn heap() -> &'static super::GlobalAllocator {
+        use super::{GlobalAllocator, Layout, PAGE_SIZE};
+        use std::sync::Once;
+
+        const HEAP_SIZE: usize = 0x10_0000; // 1 M
+        static HEAP: GlobalAllocator = GlobalAllocator::new();
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            let layout = Layout::from_size_align(HEAP_SIZE, PAGE_SIZE).unwrap();
+            let start = unsafe { std::alloc::alloc(layout) } as usize;
+            HEAP.init(start, HEAP_SIZE);
+        });
+        &HEAP
+    }
+}
@@ -0,0 +1,228 @@
+//! Per-CPU magazine caches of small blocks, enabled by the `percpu-cache`
+//! feature.
+//!
+//! Small allocations are rounded up to a power-of-two size class, and served
+//! from a per-CPU magazine (a stack of free blocks) of that class without
+//! taking the byte allocator lock. An empty magazine is refilled, and a full
+//! one flushed, by [`BATCH`] blocks at a time under a single lock. The caches
+//! of all CPUs are drained back to the byte allocator by [`drain_all`], e.g.
+//! before heap chunks are reclaimed, so that the cached blocks do not keep
+//! the chunks in use.
+//!
+//! Each cache is guarded by a lock of its own, which is only contended while
+//! another CPU drains it. The cache lock is taken before the byte allocator
+//! lock.
+
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use allocator::AllocResult;
+use kspin::SpinNoIrq;
+
+use crate::GlobalAllocator;
+
+/// The number of size classes, from 8 bytes to 2 KiB.
+const NUM_CLASSES: usize = 9;
+const MIN_CLASS_SHIFT: usize = 3;
+
+/// The capacity of a magazine.
+const MAG_SIZE: usize = 32;
+/// The number of blocks moved by a refill or a flush.
+const BATCH: usize = MAG_SIZE / 2;
+
+struct Magazine {
+    count: usize,
+    blocks: [usize; MAG_SIZE],
+}
+
+struct CpuCache {
+    mags: [Magazine; NUM_CLASSES],
+}
+
+#[percpu::def_percpu]
+static CPU_CACHE: SpinNoIrq<CpuCache> = SpinNoIrq::new(CpuCache {
+    mags: [const {
+        Magazine {
+            count: 0,
+            blocks: [0; MAG_SIZE],
+        }
+    }; NUM_CLASSES],
+});
+
+/// Runs `f` on the locked cache of the current CPU.
+///
+/// The cache stays correct even if the task migrates before locking it, as
+/// it is only accessed under its lock.
+fn with_cpu_cache<T>(f: impl FnOnce(&mut CpuCache) -> T) -> T {
+    f(&mut unsafe { CPU_CACHE.current_ref_raw() }.lock())
+}
+
+/// Returns the size class of `layout`, or `None` if it is too large to be
+/// cached.
+pub(crate) fn size_class(layout: Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align()).max(1 << MIN_CLASS_SHIFT);
+    let class = size.next_power_of_two().trailing_zeros() as usize - MIN_CLASS_SHIFT;
+    (class < NUM_CLASSES).then_some(class)
+}
+
+/// Returns the layout of the blocks in the size class.
+fn class_layout(class: usize) -> Layout {
+    let size = 1 << (class + MIN_CLASS_SHIFT);
+    Layout::from_size_align(size, size).unwrap()
+}
+
+/// Allocates a block of the size class.
+pub(crate) fn alloc(ga: &GlobalAllocator, class: usize) -> AllocResult<NonNull<u8>> {
+    let layout = class_layout(class);
+    let cached = with_cpu_cache(|cache| {
+        let mag = &mut cache.mags[class];
+        if mag.count == 0 {
+            let mut balloc = ga.balloc.lock();
+            while mag.count < BATCH {
+                let Ok(ptr) = balloc.alloc(layout) else {
+                    break;
+                };
+                mag.blocks[mag.count] = ptr.as_ptr() as usize;
+                mag.count += 1;
+            }
+        }
+        if mag.count == 0 {
+            return None;
+        }
+        mag.count -= 1;
+        Some(NonNull::new(mag.blocks[mag.count] as *mut u8).unwrap())
+    });
+    match cached {
+        Some(ptr) => Ok(ptr),
+        // The byte allocator needs to be expanded, which may drain the caches.
+        None => ga.alloc_from_heap(layout),
+    }
+}
+
+/// Frees a block of the size class.
+pub(crate) fn dealloc(ga: &GlobalAllocator, class: usize, pos: NonNull<u8>) {
+    with_cpu_cache(|cache| {
+        let mag = &mut cache.mags[class];
+        if mag.count == MAG_SIZE {
+            flush(ga, class, mag, BATCH);
+        }
+        mag.blocks[mag.count] = pos.as_ptr() as usize;
+        mag.count += 1;
+    })
+}
+
+/// Gives `count` blocks of the magazine back to the byte allocator.
+fn flush(ga: &GlobalAllocator, class: usize, mag: &mut Magazine, count: usize) {
+    let layout = class_layout(class);
+    let mut balloc = ga.balloc.lock();
+    for _ in 0..count.min(mag.count) {
+        mag.count -= 1;
+        let ptr = NonNull::new(mag.blocks[mag.count] as *mut u8).unwrap();
+        balloc.dealloc(ptr, layout);
+    }
+}
+
+/// Gives all blocks cached by the current CPU back to the byte allocator.
+pub(crate) fn drain(ga: &GlobalAllocator) {
+    with_cpu_cache(|cache| drain_cache(ga, cache))
+}
+
+/// Gives all blocks cached by every CPU back to the byte allocator.
+pub(crate) fn drain_all(ga: &GlobalAllocator) {
+    for cpu_id in 0..percpu::percpu_area_num() {
+        let cache = unsafe { CPU_CACHE.remote_ref_raw(cpu_id) };
+        drain_cache(ga, &mut cache.lock());
+    }
+}
+
+fn drain_cache(ga: &GlobalAllocator, cache: &mut CpuCache) {
+    for (class, mag) in cache.mags.iter_mut().enumerate() {
+        if mag.count > 0 {
+            flush(ga, class, mag, MAG_SIZE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{heap, SERIAL};
+
+    fn cached(class: usize) -> usize {
+        with_cpu_cache(|cache| cache.mags[class].count)
+    }
+
+    #[test]
+    fn test_size_class() {
+        let class = |size, align| size_class(Layout::from_size_align(size, align).unwrap());
+        assert_eq!(class(0, 1), Some(0));
+        assert_eq!(class(8, 8), Some(0));
+        assert_eq!(class(9, 1), Some(1));
+        assert_eq!(class(16, 64), Some(3));
+        assert_eq!(class(2048, 8), Some(8));
+        assert_eq!(class(2049, 8), None);
+        assert_eq!(class(8, 4096), None);
+        assert_eq!(class_layout(2), Layout::from_size_align(32, 32).unwrap());
+    }
+
+    #[test]
+    fn test_refill_and_flush() {
+        let _guard = SERIAL.lock().unwrap();
+        let ga = heap();
+        drain_all(ga);
+        let used = ga.used_bytes();
+
+        // an empty magazine is refilled by a batch
+        let mut blocks = vec![alloc(ga, 2).unwrap()];
+        assert_eq!(cached(2), BATCH - 1);
+        assert_eq!(ga.used_bytes(), used + BATCH * 32);
+        for _ in 1..BATCH {
+            blocks.push(alloc(ga, 2).unwrap());
+        }
+        assert_eq!(cached(2), 0);
+        assert_eq!(ga.used_bytes(), used + BATCH * 32);
+        blocks.push(alloc(ga, 2).unwrap());
+        assert_eq!(cached(2), BATCH - 1);
+        assert_eq!(ga.used_bytes(), used + 2 * BATCH * 32);
+        let mut sorted: Vec<_> = blocks.iter().map(|p| p.as_ptr() as usize).collect();
+        sorted.sort_unstable();
+        sorted.dedup();
+        assert_eq!(sorted.len(), BATCH + 1);
+        assert!(sorted.iter().all(|addr| addr % 32 == 0));
+
+        // the magazine fills up without giving anything back
+        for ptr in blocks {
+            dealloc(ga, 2, ptr);
+        }
+        assert_eq!(cached(2), MAG_SIZE);
+        assert_eq!(ga.used_bytes(), used + 2 * BATCH * 32);
+        // a full one is flushed by a batch
+        let ptr = ga.alloc_from_heap(class_layout(2)).unwrap();
+        dealloc(ga, 2, ptr);
+        assert_eq!(cached(2), MAG_SIZE - BATCH + 1);
+        assert_eq!(ga.used_bytes(), used + (BATCH + 1) * 32);
+
+        drain(ga);
+        assert_eq!(cached(2), 0);
+        assert_eq!(ga.used_bytes(), used);
+    }
+
+    #[test]
+    fn test_drain_all() {
+        let _guard = SERIAL.lock().unwrap();
+        let ga = heap();
+        drain_all(ga);
+        let used = ga.used_bytes();
+
+        for class in [0, 3, NUM_CLASSES - 1] {
+            let ptr = alloc(ga, class).unwrap();
+            assert_eq!(ptr.as_ptr() as usize % class_layout(class).align(), 0);
+            dealloc(ga, class, ptr);
+            assert_ne!(cached(class), 0);
+        }
+        assert!(ga.used_bytes() > used);
+        drain_all(ga);
+        assert!((0..NUM_CLASSES).all(|class| cached(class) == 0));
+        assert_eq!(ga.used_bytes(), used);
+    }
+}
@@ -0,0 +1,583 @@
+//! Allocation statistics, enabled by the `alloc-stats` feature.
+//!
+//! Every allocation through [`GlobalAllocator::alloc`] is counted by its size
+//! class, and live allocations are recorded in a fixed-size table together
+//! with the tag set by [`set_tag`]. This allows to list the allocations
+//! created after a [`Checkpoint`] that are still outstanding, e.g. to find
+//! leaks between test phases.
+//!
+//! The record table does not allocate, so it has a fixed capacity of
+//! [`MAX_RECORDS`]. Allocations that do not fit are only counted in
+//! [`AllocStats::untracked`], and are not charged to any tag.
+//!
+//! [`GlobalAllocator::alloc`]: crate::GlobalAllocator::alloc
+
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::fmt;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use allocator::AllocResult;
+use kspin::SpinNoIrq;
+
+use crate::{global_allocator, PAGE_SIZE};
+
+/// The number of size classes. Class `i` holds the allocations of at most
+/// `8 << i` bytes, except the last one, which holds all larger allocations.
+pub const NUM_SIZE_CLASSES: usize = 11;
+
+/// The maximum number of live allocations that can be recorded.
+pub const MAX_RECORDS: usize = 4096;
+
+/// The maximum number of distinct tags.
+pub const MAX_TAGS: usize = 32;
+
+/// The tag of allocations made while no tag is set.
+pub const UNTAGGED: &str = "untagged";
+
+const MIN_CLASS_SHIFT: u32 = 3;
+
+/// Allocation counters of the global allocator.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllocStats {
+    /// The number of successful allocations in each size class.
+    pub class_allocs: [usize; NUM_SIZE_CLASSES],
+    /// The number of deallocations in each size class.
+    pub class_deallocs: [usize; NUM_SIZE_CLASSES],
+    /// The total number of successful allocations.
+    pub allocs: usize,
+    /// The total number of deallocations.
+    pub deallocs: usize,
+    /// The number of failed allocations.
+    pub failed: usize,
+    /// The number of bytes currently allocated.
+    pub used_bytes: usize,
+    /// The maximum of `used_bytes` ever reached.
+    pub peak_bytes: usize,
+    /// The number of live allocations missing from the record table because
+    /// it was full.
+    pub untracked: usize,
+}
+
+impl AllocStats {
+    /// Returns the upper bound of the `class`-th size class, or `None` for the
+    /// last class, which is unbounded.
+    pub const fn class_size(class: usize) -> Option<usize> {
+        if class + 1 < NUM_SIZE_CLASSES {
+            Some(1 << (class as u32 + MIN_CLASS_SHIFT))
+        } else {
+            None
+        }
+    }
+
+    fn size_class(size: usize) -> usize {
+        let shift = size.max(1).next_power_of_two().trailing_zeros();
+        (shift.saturating_sub(MIN_CLASS_SHIFT) as usize).min(NUM_SIZE_CLASSES - 1)
+    }
+}
+
+/// Byte totals of the recorded allocations with the same tag.
+#[derive(Debug, Clone, Copy)]
+pub struct TagStats {
+    /// The tag name.
+    pub name: &'static str,
+    /// The number of live allocations.
+    pub count: usize,
+    /// The number of bytes in live allocations.
+    pub bytes: usize,
+}
+
+/// A live allocation, returned by [`outstanding_since`].
+#[derive(Debug, Clone, Copy)]
+pub struct AllocRecord {
+    /// The start address.
+    pub addr: usize,
+    /// The size in bytes.
+    pub size: usize,
+    /// The tag set when it was allocated.
+    pub tag: &'static str,
+    seq: usize,
+}
+
+/// A point in the allocation history, created by [`checkpoint`].
+#[derive(Debug, Clone, Copy)]
+pub struct Checkpoint {
+    seq: usize,
+    /// The counters at the time of the checkpoint.
+    pub stats: AllocStats,
+}
+
+/// Restores the previous tag when dropped. Returned by [`set_tag`].
+pub struct TagGuard {
+    prev: usize,
+}
+
+impl Drop for TagGuard {
+    fn drop(&mut self) {
+        CURRENT_TAG.store(self.prev, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Record {
+    addr: usize,
+    size: usize,
+    seq: usize,
+    tag: usize,
+}
+
+struct StatsInner {
+    stats: AllocStats,
+    records: [Option<Record>; MAX_RECORDS],
+    /// Tag slots. Slot 0 is [`UNTAGGED`].
+    tags: [Option<TagStats>; MAX_TAGS],
+}
+
+/// The index of the current tag in `StatsInner::tags`.
+static CURRENT_TAG: AtomicUsize = AtomicUsize::new(0);
+
+static STATS: SpinNoIrq<StatsInner> = SpinNoIrq::new(StatsInner::new());
+
+impl StatsInner {
+    const fn new() -> Self {
+        let mut tags = [None; MAX_TAGS];
+        tags[0] = Some(TagStats {
+            name: UNTAGGED,
+            count: 0,
+            bytes: 0,
+        });
+        Self {
+            stats: AllocStats {
+                class_allocs: [0; NUM_SIZE_CLASSES],
+                class_deallocs: [0; NUM_SIZE_CLASSES],
+                allocs: 0,
+                deallocs: 0,
+                failed: 0,
+                used_bytes: 0,
+                peak_bytes: 0,
+                untracked: 0,
+            },
+            records: [None; MAX_RECORDS],
+            tags,
+        }
+    }
+
+    const fn home_slot(addr: usize) -> usize {
+        (addr / 8) % MAX_RECORDS
+    }
+
+    /// Inserts a record into the open-addressing table. Returns `false` if
+    /// the table is full.
+    fn insert(&mut self, rec: Record) -> bool {
+        let home = Self::home_slot(rec.addr);
+        for i in 0..MAX_RECORDS {
+            let slot = &mut self.records[(home + i) % MAX_RECORDS];
+            if slot.is_none() {
+                *slot = Some(rec);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Removes the record of `addr`, shifting back the following records in
+    /// its probe sequence so that no tombstones are needed.
+    fn remove(&mut self, addr: usize) -> Option<Record> {
+        let home = Self::home_slot(addr);
+        let mut hole = (0..MAX_RECORDS)
+            .map(|i| (home + i) % MAX_RECORDS)
+            .take_while(|&i| self.records[i].is_some())
+            .find(|&i| self.records[i].is_some_and(|r| r.addr == addr))?;
+        let rec = self.records[hole].take();
+        let mut next = hole;
+        loop {
+            next = (next + 1) % MAX_RECORDS;
+            let Some(r) = self.records[next] else {
+                break;
+            };
+            // The record can fill the hole unless its home slot lies
+            // cyclically in `(hole, next]`.
+            let home = Self::home_slot(r.addr);
+            let stays = if hole <= next {
+                hole < home && home <= next
+            } else {
+                hole < home || home <= next
+            };
+            if !stays {
+                self.records[hole] = self.records[next].take();
+                hole = next;
+            }
+        }
+        rec
+    }
+
+    fn tag_mut(&mut self, tag: usize) -> &mut TagStats {
+        self.tags[tag].as_mut().unwrap()
+    }
+}
+
+pub(crate) fn record_alloc(layout: Layout, res: &AllocResult<NonNull<u8>>) {
+    let mut inner = STATS.lock();
+    let stats = &mut inner.stats;
+    let Ok(ptr) = res else {
+        stats.failed += 1;
+        return;
+    };
+    let size = layout.size();
+    stats.class_allocs[AllocStats::size_class(size)] += 1;
+    stats.allocs += 1;
+    stats.used_bytes += size;
+    stats.peak_bytes = stats.peak_bytes.max(stats.used_bytes);
+
+    let rec = Record {
+        addr: ptr.as_ptr() as usize,
+        size,
+        seq: stats.allocs,
+        tag: CURRENT_TAG.load(Ordering::Relaxed),
+    };
+    if inner.insert(rec) {
+        let tag = inner.tag_mut(rec.tag);
+        tag.count += 1;
+        tag.bytes += size;
+    } else {
+        inner.stats.untracked += 1;
+    }
+}
+
+pub(crate) fn record_dealloc(pos: NonNull<u8>, layout: Layout) {
+    let mut inner = STATS.lock();
+    let size = layout.size();
+    let stats = &mut inner.stats;
+    stats.class_deallocs[AllocStats::size_class(size)] += 1;
+    stats.deallocs += 1;
+    stats.used_bytes = stats.used_bytes.saturating_sub(size);
+
+    match inner.remove(pos.as_ptr() as usize) {
+        Some(rec) => {
+            let tag = inner.tag_mut(rec.tag);
+            tag.count -= 1;
+            tag.bytes -= rec.size;
+        }
+        None => inner.stats.untracked = inner.stats.untracked.saturating_sub(1),
+    }
+}
+
+/// Returns the current allocation counters.
+pub fn stats() -> AllocStats {
+    STATS.lock().stats
+}
+
+/// Returns the byte totals of all tags used so far.
+pub fn tag_stats() -> Vec<TagStats> {
+    // The vector must not grow while the lock is held, as that would
+    // allocate and record into the locked table.
+    let mut tags = Vec::with_capacity(MAX_TAGS);
+    tags.extend(STATS.lock().tags.iter().flatten().copied());
+    tags
+}
+
+/// Charges the following allocations to `tag`, until the returned guard is
+/// dropped.
+///
+/// The tag is global rather than per task, so allocations by other tasks in
+/// the meantime are charged to it as well. If all [`MAX_TAGS`] tag slots are
+/// in use, new tags fall back to [`UNTAGGED`].
+pub fn set_tag(tag: &'static str) -> TagGuard {
+    let idx = {
+        let mut inner = STATS.lock();
+        let existing = inner
+            .tags
+            .iter()
+            .position(|t| t.is_some_and(|t| t.name == tag));
+        existing
+            .or_else(|| {
+                let free = inner.tags.iter().position(|t| t.is_none())?;
+                inner.tags[free] = Some(TagStats {
+                    name: tag,
+                    count: 0,
+                    bytes: 0,
+                });
+                Some(free)
+            })
+            .unwrap_or(0)
+    };
+    TagGuard {
+        prev: CURRENT_TAG.swap(idx, Ordering::Relaxed),
+    }
+}
+
+/// Creates a checkpoint of the allocation history.
+pub fn checkpoint() -> Checkpoint {
+    let inner = STATS.lock();
+    Checkpoint {
+        seq: inner.stats.allocs,
+        stats: inner.stats,
+    }
+}
+
+/// Returns the recorded allocations made after `checkpoint` that have not
+/// been freed yet, in allocation order.
+pub fn outstanding_since(checkpoint: &Checkpoint) -> Vec<AllocRecord> {
+    let is_new = |r: &&Record| r.seq > checkpoint.seq;
+    let count = STATS.lock().records.iter().flatten().filter(is_new).count();
+
+    // Reserve room for allocations made in the meantime as well, so that the
+    // vector does not grow while the lock is held.
+    let mut out = Vec::with_capacity(count + 16);
+    {
+        let inner = STATS.lock();
+        for r in inner.records.iter().flatten().filter(is_new) {
+            if out.len() == out.capacity() {
+                break;
+            }
+            out.push(AllocRecord {
+                addr: r.addr,
+                size: r.size,
+                tag: inner.tags[r.tag].unwrap().name,
+                seq: r.seq,
+            });
+        }
+    }
+    out.sort_unstable_by_key(|r| r.seq);
+    out
+}
+
+/// Writes a summary of the memory usage in the style of `/proc/meminfo`.
+pub fn dump_meminfo(out: &mut dyn fmt::Write) -> fmt::Result {
+    let (stats, tags) = {
+        let inner = STATS.lock();
+        (inner.stats, inner.tags)
+    };
+    let ga = global_allocator();
+    let kb = |bytes: usize| bytes / 1024;
+    let pages_kb = |pages: usize| kb(pages * PAGE_SIZE);
+
+    for (key, value) in [
+        ("PagesUsed", pages_kb(ga.used_pages())),
+        ("PagesFree", pages_kb(ga.available_pages())),
+        ("HeapUsed", kb(ga.used_bytes())),
+        ("HeapFree", kb(ga.available_bytes())),
+        ("HeapPeak", kb(stats.peak_bytes)),
+    ] {
+        writeln!(out, "{:<16}{:>10} kB", alloc::format!("{key}:"), value)?;
+    }
+    #[cfg(feature = "page-buddy")]
+    {
+        // Free blocks of each order, like `/proc/buddyinfo`.
+        write!(out, "{:<16}", "BuddyFree:")?;
+        for count in ga.page_frag_stats().free_blocks {
+            write!(out, " {count}")?;
+        }
+        writeln!(out)?;
+    }
+    for (key, value) in [
+        ("Allocs", stats.allocs),
+        ("Frees", stats.deallocs),
+        ("FailedAllocs", stats.failed),
+        ("Untracked", stats.untracked),
+    ] {
+        writeln!(out, "{:<16}{:>10}", alloc::format!("{key}:"), value)?;
+    }
+
+    writeln!(
+        out,
+        "\n{:<16}{:>10}{:>10}{:>10}",
+        "SizeClass", "Allocs", "Frees", "Live"
+    )?;
+    for class in 0..NUM_SIZE_CLASSES {
+        let name = match AllocStats::class_size(class) {
+            Some(size) => alloc::format!("<={size}"),
+            None => alloc::format!(">{}", AllocStats::class_size(class - 1).unwrap()),
+        };
+        let (allocs, frees) = (stats.class_allocs[class], stats.class_deallocs[class]);
+        writeln!(
+            out,
+            "{:<16}{:>10}{:>10}{:>10}",
+            name,
+            allocs,
+            frees,
+            allocs.saturating_sub(frees)
+        )?;
+    }
+
+    writeln!(out, "\n{:<16}{:>10}{:>13}", "Tag", "Count", "Bytes")?;
+    for tag in tags.iter().flatten() {
+        writeln!(out, "{:<16}{:>10}{:>13}", tag.name, tag.count, tag.bytes)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::SERIAL;
+    use allocator::AllocError;
+
+    /// Returns an address whose home slot is `slot`, different for each
+    /// `round`.
+    const fn addr(slot: usize, round: usize) -> usize {
+        (round * MAX_RECORDS + slot) * 8
+    }
+
+    fn record(addr: usize) -> Record {
+        Record {
+            addr,
+            size: 8,
+            seq: 0,
+            tag: 0,
+        }
+    }
+
+    fn slot_of(inner: &StatsInner, addr: usize) -> Option<usize> {
+        inner
+            .records
+            .iter()
+            .position(|r| r.is_some_and(|r| r.addr == addr))
+    }
+
+    #[test]
+    fn test_size_class() {
+        assert_eq!(AllocStats::size_class(0), 0);
+        assert_eq!(AllocStats::size_class(8), 0);
+        assert_eq!(AllocStats::size_class(9), 1);
+        assert_eq!(AllocStats::size_class(4096), 9);
+        assert_eq!(AllocStats::size_class(8192), 10);
+        assert_eq!(AllocStats::size_class(usize::MAX / 2), 10);
+        assert_eq!(AllocStats::class_size(0), Some(8));
+        assert_eq!(AllocStats::class_size(9), Some(4096));
+        assert_eq!(AllocStats::class_size(10), None);
+    }
+
+    #[test]
+    fn test_remove_shifts_back() {
+        let mut inner = Box::new(StatsInner::new());
+        let (a, b, c) = (addr(5, 0), addr(5, 1), addr(5, 2));
+        let d = addr(6, 0);
+        for r in [a, b, c, d] {
+            assert!(inner.insert(record(r)));
+        }
+        assert_eq!(
+            [a, b, c, d].map(|r| slot_of(&inner, r)),
+            [5, 6, 7, 8].map(Some)
+        );
+
+        // the records after the hole are moved back, as none is at its home
+        assert_eq!(inner.remove(b).map(|r| r.addr), Some(b));
+        assert_eq!([a, c, d].map(|r| slot_of(&inner, r)), [5, 6, 7].map(Some));
+        assert!(inner.records[8].is_none());
+        // `d` stops at its home slot
+        assert_eq!(inner.remove(a).map(|r| r.addr), Some(a));
+        assert_eq!([c, d].map(|r| slot_of(&inner, r)), [5, 6].map(Some));
+        assert!(inner.remove(a).is_none());
+        assert!(inner.remove(addr(6, 1)).is_none());
+
+        // a record at its home slot stays when a hole is made before it
+        let e = addr(4, 0);
+        assert!(inner.insert(record(e)));
+        assert!(inner.insert(record(addr(4, 1))));
+        assert_eq!(slot_of(&inner, addr(4, 1)), Some(7));
+        assert_eq!(inner.remove(e).map(|r| r.addr), Some(e));
+        assert_eq!(slot_of(&inner, c), Some(5));
+        assert_eq!(slot_of(&inner, d), Some(6));
+        assert_eq!(slot_of(&inner, addr(4, 1)), Some(4));
+    }
+
+    #[test]
+    fn test_remove_wraps_around() {
+        let mut inner = Box::new(StatsInner::new());
+        let last = MAX_RECORDS - 1;
+        let (x, y, z) = (addr(last, 0), addr(last, 1), addr(last, 2));
+        let w = addr(0, 0);
+        for r in [x, y, z, w] {
+            assert!(inner.insert(record(r)));
+        }
+        assert_eq!(
+            [x, y, z, w].map(|r| slot_of(&inner, r)),
+            [last, 0, 1, 2].map(Some)
+        );
+        assert_eq!(inner.remove(x).map(|r| r.addr), Some(x));
+        assert_eq!(
+            [y, z, w].map(|r| slot_of(&inner, r)),
+            [last, 0, 1].map(Some)
+        );
+        assert_eq!(inner.remove(y).map(|r| r.addr), Some(y));
+        assert_eq!([z, w].map(|r| slot_of(&inner, r)), [last, 0].map(Some));
+    }
+
+    #[test]
+    fn test_full_table() {
+        let mut inner = Box::new(StatsInner::new());
+        for round in 0..MAX_RECORDS {
+            assert!(inner.insert(record(addr(0, round))));
+        }
+        assert!(!inner.insert(record(addr(1, 0))));
+        assert!(inner.remove(addr(1, 0)).is_none());
+
+        assert!(inner.remove(addr(0, 100)).is_some());
+        assert!(inner.insert(record(addr(1, 0))));
+        assert!(inner.remove(addr(1, 0)).is_some());
+        for round in (0..MAX_RECORDS).rev().filter(|&r| r != 100) {
+            assert_eq!(
+                inner.remove(addr(0, round)).map(|r| r.addr),
+                Some(addr(0, round))
+            );
+        }
+        assert!(inner.records.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_outstanding_since() {
+        let _guard = SERIAL.lock().unwrap();
+        let ptr = |round| NonNull::new(addr(7, round) as *mut u8).unwrap();
+        let small = Layout::from_size_align(24, 8).unwrap();
+        let large = Layout::from_size_align(100, 8).unwrap();
+
+        let start = checkpoint();
+        record_alloc(small, &Ok(ptr(1)));
+        {
+            let _tag = set_tag("test_outstanding");
+            record_alloc(large, &Ok(ptr(2)));
+            record_alloc(small, &Err(AllocError::NoMemory));
+        }
+        let middle = checkpoint();
+        record_alloc(small, &Ok(ptr(3)));
+        record_dealloc(ptr(1), small);
+
+        let outstanding = outstanding_since(&start);
+        let summary: Vec<_> = outstanding
+            .iter()
+            .map(|r| (r.addr, r.size, r.tag))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (addr(7, 2), 100, "test_outstanding"),
+                (addr(7, 3), 24, UNTAGGED),
+            ]
+        );
+        let outstanding = outstanding_since(&middle);
+        assert_eq!(outstanding.len(), 1);
+        assert_eq!(outstanding[0].addr, addr(7, 3));
+
+        let now = stats();
+        assert_eq!(now.allocs - start.stats.allocs, 3);
+        assert_eq!(now.deallocs - start.stats.deallocs, 1);
+        assert_eq!(now.failed - start.stats.failed, 1);
+        assert_eq!(now.used_bytes - start.stats.used_bytes, 124);
+        assert_eq!(now.class_allocs[2] - start.stats.class_allocs[2], 2);
+        assert_eq!(now.class_allocs[4] - start.stats.class_allocs[4], 1);
+        assert_eq!(now.class_deallocs[2] - start.stats.class_deallocs[2], 1);
+        let tag = |name| tag_stats().into_iter().find(|t| t.name == name).unwrap();
+        assert_eq!(
+            (tag("test_outstanding").count, tag("test_outstanding").bytes),
+            (1, 100)
+        );
+
+        record_dealloc(ptr(2), large);
+        record_dealloc(ptr(3), small);
+        assert!(outstanding_since(&start).is_empty());
+        assert_eq!(tag("test_outstanding").count, 0);
+        assert_eq!(stats().used_bytes, start.stats.used_bytes);
+    }
+}
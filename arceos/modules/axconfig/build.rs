@@ -101,6 +101,16 @@ fn gen_config_rs(config_path: &Path) -> Result<Vec<u8>> {
         toml_edit::value(std::env::var("AX_SMP").unwrap_or("1".into())),
         Some("# Number of CPUs"),
     );
+    let aslr = match std::env::var("AX_ASLR").as_deref() {
+        Ok("y") | Ok("1") => "1",
+        _ => "0",
+    };
+    add_config(
+        &mut config,
+        "aslr",
+        toml_edit::value(aslr),
+        Some("# Whether to randomize the layout of user address spaces (0: off, 1: on)"),
+    );
 
     // Generate config.rs
     let mut output = Vec::new();
@@ -168,5 +178,6 @@ fn main() -> Result<()> {
     println!("cargo:rerun-if-changed={}", config_path.display());
     println!("cargo:rerun-if-env-changed=AX_PLATFORM");
     println!("cargo:rerun-if-env-changed=AX_SMP");
+    println!("cargo:rerun-if-env-changed=AX_ASLR");
     Ok(())
 }
@@ -32,6 +32,7 @@ fn main() {
     // Generate cfgs like `net_dev="virtio-net"`. if `dyn` is not enabled, only one device is
     // selected for each device category. If no device is selected, `dummy` is selected.
     let is_dyn = has_feature("dyn");
+    let mut any_dev = false;
     for (dev_kind, feat_list) in [
         ("net", NET_DEV_FEATURES),
         ("block", BLOCK_DEV_FEATURES),
@@ -54,8 +55,14 @@ fn main() {
         if !is_dyn && !selected {
             enable_cfg(&format!("{dev_kind}_dev"), "dummy");
         }
+        any_dev |= selected;
+    }
+    // Generate the cfg `any_dev` if any device is selected, i.e. any device can be probed.
+    if any_dev {
+        println!("cargo:rustc-cfg=any_dev");
     }
 
+    println!("cargo::rustc-check-cfg=cfg(any_dev)");
     println!(
         "cargo::rustc-check-cfg=cfg(bus, values({}))",
         make_cfg_values(&["pci", "mmio"])
@@ -1,3 +1,5 @@
+#[cfg(any_dev)]
+use crate::DeviceBus;
 use crate::{prelude::*, AllDevices};
 use axdriver_pci::{
     BarInfo, Cam, Command, DeviceFunction, HeaderType, MemoryBarType, PciRangeAllocator, PciRoot,
@@ -107,7 +109,16 @@ impl AllDevices {
                                 bdf,
                                 dev.device_name(),
                             );
-                            self.add_device(dev);
+                            let bus = DeviceBus::Pci {
+                                bus: bdf.bus,
+                                device: bdf.device,
+                                function: bdf.function,
+                                vendor_id: dev_info.vendor_id,
+                                device_id: dev_info.device_id,
+                                class: dev_info.class,
+                                subclass: dev_info.subclass,
+                            };
+                            self.add_device(dev, bus);
                             continue; // skip to the next device
                         }
                     }),
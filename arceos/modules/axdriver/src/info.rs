@@ -0,0 +1,95 @@
+//! The inventory of probed devices, for presenting the device tree (e.g. in
+//! sysfs) after the devices have been handed to the subsystems.
+
+use alloc::{format, string::String, vec::Vec};
+
+use axdriver_base::DeviceType;
+use lazyinit::LazyInit;
+
+static DEVICES: LazyInit<Vec<DeviceInfo>> = LazyInit::new();
+
+/// Where a device is found.
+#[derive(Debug, Clone)]
+pub enum DeviceBus {
+    /// Not on a bus, e.g. a RAM disk or an on-board controller.
+    Platform,
+    /// A memory-mapped device, at the physical address range.
+    Mmio {
+        /// The base physical address.
+        base: usize,
+        /// The size of the register region.
+        size: usize,
+    },
+    /// A PCI device function.
+    Pci {
+        /// The bus number.
+        bus: u8,
+        /// The device number.
+        device: u8,
+        /// The function number.
+        function: u8,
+        /// The vendor ID.
+        vendor_id: u16,
+        /// The device ID.
+        device_id: u16,
+        /// The base class code.
+        class: u8,
+        /// The subclass code.
+        subclass: u8,
+    },
+}
+
+/// Information of a probed device.
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    /// The name of the driver, e.g. `virtio-blk`.
+    pub driver: String,
+    /// The device category.
+    pub device_type: DeviceType,
+    /// The index among the devices of the same category, in probe order.
+    pub index: usize,
+    /// Where the device is found.
+    pub bus: DeviceBus,
+    /// The number of blocks and the block size, for block devices.
+    pub block: Option<(u64, usize)>,
+    /// The MAC address, for network devices.
+    pub mac: Option<[u8; 6]>,
+}
+
+impl DeviceInfo {
+    /// Returns the conventional name of the device, such as `vda` for the
+    /// first block device, `eth0` for the first NIC and `fb0` for the first
+    /// display.
+    pub fn name(&self) -> String {
+        match self.device_type {
+            DeviceType::Block => block_device_name(self.index),
+            DeviceType::Net => format!("eth{}", self.index),
+            DeviceType::Display => format!("fb{}", self.index),
+            _ => format!("{}{}", self.driver, self.index),
+        }
+    }
+}
+
+/// Returns the name of the block device with the given index, i.e. `vda`,
+/// `vdb`, ..., `vdz`, `vdaa`, and so on.
+pub fn block_device_name(index: usize) -> String {
+    let mut suffix = Vec::new();
+    let mut n = index + 1;
+    while n > 0 {
+        n -= 1;
+        suffix.push(b'a' + (n % 26) as u8);
+        n /= 26;
+    }
+    suffix.reverse();
+    format!("vd{}", core::str::from_utf8(&suffix).unwrap())
+}
+
+/// Returns all devices probed by [`init_drivers`](crate::init_drivers), in
+/// probe order. It is empty before the probing.
+pub fn devices() -> &'static [DeviceInfo] {
+    DEVICES.get().map_or(&[], |devs| devs.as_slice())
+}
+
+pub(crate) fn init(devices: Vec<DeviceInfo>) {
+    DEVICES.init_once(devices);
+}
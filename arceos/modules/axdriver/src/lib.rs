@@ -5,7 +5,8 @@
 //! All detected devices are composed into a large struct [`AllDevices`]
 //! and returned by the [`init_drivers`] function. The upperlayer subsystems
 //! (e.g., the network stack) may unpack the struct to get the specified device
-//! driver they want.
+//! driver they want. The information of the probed devices, such as their
+//! buses and IDs, remains available from [`devices`] afterwards.
 //!
 //! For each device category (i.e., net, block, display, etc.), an unified type
 //! is used to represent all devices in that category. Currently, there are 3
@@ -61,7 +62,6 @@
 #[macro_use]
 extern crate log;
 
-#[cfg(feature = "dyn")]
 extern crate alloc;
 
 #[macro_use]
@@ -70,6 +70,7 @@ mod macros;
 mod bus;
 mod drivers;
 mod dummy;
+mod info;
 mod structs;
 
 #[cfg(feature = "virtio")]
@@ -80,6 +81,7 @@ mod ixgbe;
 
 pub mod prelude;
 
+pub use self::info::{block_device_name, devices, DeviceBus, DeviceInfo};
 #[allow(unused_imports)]
 use self::prelude::*;
 pub use self::structs::{AxDeviceContainer, AxDeviceEnum};
@@ -103,6 +105,8 @@ pub struct AllDevices {
     /// All graphics device drivers.
     #[cfg(feature = "display")]
     pub display: AxDeviceContainer<AxDisplayDevice>,
+    /// Information of the devices above, see [`devices`].
+    infos: alloc::vec::Vec<DeviceInfo>,
 }
 
 impl AllDevices {
@@ -126,23 +130,51 @@ impl AllDevices {
                     dev.device_type(),
                     dev.device_name(),
                 );
-                self.add_device(dev);
+                self.add_device(dev, DeviceBus::Platform);
             }
         });
 
         self.probe_bus_devices();
     }
 
-    /// Adds one device into the corresponding container, according to its device category.
-    #[allow(dead_code)]
-    fn add_device(&mut self, dev: AxDeviceEnum) {
-        match dev {
+    /// Adds one device into the corresponding container, according to its device category,
+    /// and records its information if it is kept.
+    #[cfg(any_dev)]
+    fn add_device(&mut self, dev: AxDeviceEnum, bus: DeviceBus) {
+        let mut info = DeviceInfo {
+            driver: alloc::string::String::from(dev.device_name()),
+            device_type: dev.device_type(),
+            index: 0,
+            bus,
+            block: None,
+            mac: None,
+        };
+        let (index, len) = match dev {
             #[cfg(feature = "net")]
-            AxDeviceEnum::Net(dev) => self.net.push(dev),
+            AxDeviceEnum::Net(dev) => {
+                info.mac = Some(dev.mac_address().0);
+                let index = self.net.len();
+                self.net.push(dev);
+                (index, self.net.len())
+            }
             #[cfg(feature = "block")]
-            AxDeviceEnum::Block(dev) => self.block.push(dev),
+            AxDeviceEnum::Block(dev) => {
+                info.block = Some((dev.num_blocks(), dev.block_size()));
+                let index = self.block.len();
+                self.block.push(dev);
+                (index, self.block.len())
+            }
             #[cfg(feature = "display")]
-            AxDeviceEnum::Display(dev) => self.display.push(dev),
+            AxDeviceEnum::Display(dev) => {
+                let index = self.display.len();
+                self.display.push(dev);
+                (index, self.display.len())
+            }
+        };
+        // The static device model keeps only the first device of each category.
+        if len > index {
+            info.index = index;
+            self.infos.push(info);
         }
     }
 }
@@ -180,5 +212,6 @@ pub fn init_drivers() -> AllDevices {
         }
     }
 
+    info::init(core::mem::take(&mut all_devs.infos));
     all_devs
 }
@@ -0,0 +1,18 @@
+use std::path::PathBuf;
+
+/// Generates the expression of the archive embedded as the initramfs, from
+/// the path in the `AX_INITRAMFS` environment variable. The archive is empty
+/// if it is not set, e.g. when building with all features for `clippy`.
+fn main() {
+    println!("cargo:rerun-if-env-changed=AX_INITRAMFS");
+    let archive = match std::env::var("AX_INITRAMFS") {
+        Ok(path) if !path.is_empty() => {
+            let path = std::fs::canonicalize(&path).unwrap_or_else(|_| path.into());
+            println!("cargo:rerun-if-changed={}", path.display());
+            format!("include_bytes!({:?})", path)
+        }
+        _ => "&[]".into(),
+    };
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    std::fs::write(out_dir.join("initramfs.rs"), archive + "\n").unwrap();
+}
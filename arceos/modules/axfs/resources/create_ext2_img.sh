@@ -0,0 +1,25 @@
+#!/bin/bash
+
+# Creates the ext2 image of the tests from a staging directory, which needs
+# neither root nor a loop device.
+
+CUR_DIR=`dirname $0`
+
+create_ext2_img() {
+	local name=$1
+	local blkcount=$2
+	local staging=$(mktemp -d)
+	for i in $(seq 1 1000); do
+	  echo "Rust is cool!" >>"$staging/long.txt"
+	done
+	echo "Rust is cool!" >>"$staging/short.txt"
+	mkdir -p "$staging/very/long/path"
+	echo "Rust is cool!" >>"$staging/very/long/path/test.txt"
+	mkdir -p "$staging/very-long-dir-name"
+	echo "Rust is cool!" >>"$staging/very-long-dir-name/very-long-file-name.txt"
+	rm -f "$name"
+	mkfs.ext2 -q -b 1024 -L "Test!" -d "$staging" "$name" $blkcount
+	rm -rf "$staging"
+}
+
+create_ext2_img "$CUR_DIR/ext2.img" 4096
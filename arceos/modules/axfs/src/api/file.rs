@@ -1,7 +1,7 @@
 use axio::{prelude::*, Result, SeekFrom};
-use core::fmt;
+use core::{fmt, time::Duration};
 
-use crate::fops;
+use crate::fops::{self, LockType};
 
 /// A structure representing a type of file with accessors for each file type.
 /// It is returned by [`Metadata::file_type`] method.
@@ -16,7 +16,7 @@ pub struct File {
 }
 
 /// Metadata information about a file.
-pub struct Metadata(fops::FileAttr);
+pub struct Metadata(fops::FileAttr, Option<fops::FileMeta>);
 
 /// Options and flags which can be used to configure how a file is opened.
 #[derive(Clone, Debug)]
@@ -90,6 +90,12 @@ impl Metadata {
         self.0.is_file()
     }
 
+    /// Returns `true` if this metadata is for a symbolic link, which is only
+    /// possible when it is from [`symlink_metadata`](super::symlink_metadata).
+    pub const fn is_symlink(&self) -> bool {
+        matches!(self.0.file_type(), FileType::SymLink)
+    }
+
     /// Returns the size of the file, in bytes, this metadata is for.
     #[allow(clippy::len_without_is_empty)]
     pub const fn len(&self) -> u64 {
@@ -110,6 +116,47 @@ impl Metadata {
     pub const fn blocks(&self) -> u64 {
         self.0.blocks()
     }
+
+    /// Returns the last access time, since the UNIX epoch.
+    ///
+    /// It fails with [`Unsupported`](axio::Error::Unsupported) if the
+    /// filesystem does not keep the timestamps, and so do
+    /// [`Metadata::modified`] and [`Metadata::changed`].
+    pub fn accessed(&self) -> Result<Duration> {
+        self.file_meta().map(|meta| meta.atime)
+    }
+
+    /// Returns the last modification time, since the UNIX epoch.
+    pub fn modified(&self) -> Result<Duration> {
+        self.file_meta().map(|meta| meta.mtime)
+    }
+
+    /// Returns the last time the content or the metadata was changed, since
+    /// the UNIX epoch.
+    pub fn changed(&self) -> Result<Duration> {
+        self.file_meta().map(|meta| meta.ctime)
+    }
+
+    fn file_meta(&self) -> Result<&fops::FileMeta> {
+        self.1.as_ref().ok_or(axio::Error::Unsupported)
+    }
+}
+
+impl Metadata {
+    pub(super) const fn new(attr: fops::FileAttr, meta: Option<fops::FileMeta>) -> Self {
+        Self(attr, meta)
+    }
+
+    /// Returns the underlying attributes of the node.
+    pub const fn raw_metadata(&self) -> &fops::FileAttr {
+        &self.0
+    }
+
+    /// Returns the ownership and timestamps of the node, or `None` if they are
+    /// not kept by the filesystem.
+    pub const fn raw_meta(&self) -> Option<&fops::FileMeta> {
+        self.1.as_ref()
+    }
 }
 
 impl fmt::Debug for Metadata {
@@ -160,7 +207,48 @@ impl File {
 
     /// Queries metadata about the underlying file.
     pub fn metadata(&self) -> Result<Metadata> {
-        self.inner.get_attr().map(Metadata)
+        let attr = self.inner.get_attr()?;
+        Ok(Metadata(attr, self.inner.get_meta()))
+    }
+
+    /// Changes the permissions of the underlying file.
+    pub fn set_permissions(&self, perm: Permissions) -> Result<()> {
+        self.inner.set_perm(perm)
+    }
+
+    /// Changes the access and modification times of the underlying file.
+    /// `None` leaves the time unchanged.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> Result<()> {
+        self.inner.set_times(atime, mtime)
+    }
+
+    /// Acquires an exclusive advisory lock on the file, waiting for the
+    /// locks of the other opened files to be released.
+    pub fn lock(&self) -> Result<()> {
+        self.inner.lock_handle().flock(LockType::Exclusive, true)
+    }
+
+    /// Acquires a shared advisory lock on the file, waiting for an exclusive
+    /// lock of another opened file to be released.
+    pub fn lock_shared(&self) -> Result<()> {
+        self.inner.lock_handle().flock(LockType::Shared, true)
+    }
+
+    /// Tries to acquire an exclusive advisory lock on the file. Returns
+    /// `false` if another opened file holds a lock on it.
+    pub fn try_lock(&self) -> Result<bool> {
+        try_flock(self, LockType::Exclusive)
+    }
+
+    /// Tries to acquire a shared advisory lock on the file. Returns `false`
+    /// if another opened file holds an exclusive lock on it.
+    pub fn try_lock_shared(&self) -> Result<bool> {
+        try_flock(self, LockType::Shared)
+    }
+
+    /// Releases the advisory lock on the file held by this file.
+    pub fn unlock(&self) -> Result<()> {
+        self.inner.lock_handle().flock(LockType::Unlock, false)
     }
 }
 
@@ -185,3 +273,11 @@ impl Seek for File {
         self.inner.seek(pos)
     }
 }
+
+fn try_flock(file: &File, ty: LockType) -> Result<bool> {
+    match file.inner.lock_handle().flock(ty, false) {
+        Ok(()) => Ok(true),
+        Err(axio::Error::WouldBlock) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
@@ -6,8 +6,20 @@ mod file;
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
 pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
 
-use alloc::{string::String, vec::Vec};
+use alloc::{string::String, sync::Arc, vec::Vec};
+use axerrno::AxError;
+use axfs_vfs::{VfsNodeOps, VfsOps};
 use axio::{self as io, prelude::*};
+use core::time::Duration;
+
+use crate::fops::FsStat;
+
+/// The error returned when more than 40 symbolic links are followed while
+/// resolving a path (`ELOOP`), as there is no dedicated error kind for it.
+///
+/// The path operations return [`AxError::BadState`] for nothing else, so the
+/// POSIX API maps it to `ELOOP` for all of them.
+pub const TOO_MANY_LINKS: AxError = AxError::BadState;
 
 /// Returns an iterator over the entries within a directory.
 pub fn read_dir(path: &str) -> io::Result<ReadDir> {
@@ -59,6 +71,67 @@ pub fn metadata(path: &str) -> io::Result<Metadata> {
     File::open(path)?.metadata()
 }
 
+/// Queries the metadata about a file without following symbolic links.
+pub fn symlink_metadata(path: &str) -> io::Result<Metadata> {
+    let node = crate::root::lookup_no_follow(None, path)?;
+    Ok(Metadata::new(node.get_attr()?, crate::fs::node_meta(&node)))
+}
+
+/// Changes the permissions found on a file or a directory.
+///
+/// It is only supported by the filesystems that keep the permissions, i.e.
+/// ramfs, and so are [`chown`] and [`set_times`].
+pub fn set_permissions(path: &str, perm: Permissions) -> io::Result<()> {
+    crate::fs::set_node_perm(&crate::root::lookup(None, path)?, perm)
+}
+
+/// Changes the owner and the group of a file or a directory. `None` leaves
+/// it unchanged.
+pub fn chown(path: &str, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    crate::fs::set_node_owner(&crate::root::lookup(None, path)?, uid, gid)
+}
+
+/// Like [`chown`], but does not follow the symbolic link at `path`.
+pub fn lchown(path: &str, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    crate::fs::set_node_owner(&crate::root::lookup_no_follow(None, path)?, uid, gid)
+}
+
+/// Changes the access and modification times of a file or a directory, as
+/// durations since the UNIX epoch. `None` leaves the time unchanged.
+pub fn set_times(path: &str, atime: Option<Duration>, mtime: Option<Duration>) -> io::Result<()> {
+    crate::fs::set_node_times(&crate::root::lookup(None, path)?, atime, mtime)
+}
+
+/// Returns the space usage of the filesystem that `path` is in.
+///
+/// It is only supported by ramfs, whose size is limited by the `size=` mount
+/// option, see [`mount_fs_with_options`].
+pub fn statfs(path: &str) -> io::Result<FsStat> {
+    crate::fs::fs_stat(&crate::root::lookup(None, path)?)
+}
+
+/// Creates a new symbolic link at `link` pointing to `target`.
+///
+/// `target` is stored as is, and resolved relative to the directory of the
+/// link when followed. It may be in another mounted filesystem.
+pub fn symlink(target: &str, link: &str) -> io::Result<()> {
+    crate::root::symlink(None, target, link)
+}
+
+/// Reads the target of a symbolic link.
+pub fn read_link(path: &str) -> io::Result<String> {
+    crate::root::read_link(None, path)
+}
+
+/// Creates a new hard link at `link` to the `original` file, which is not
+/// followed if it is a symbolic link. It is supported by ramfs and ext2.
+///
+/// It fails with [`io::Error::Unsupported`] if they are in different
+/// filesystems, or the filesystem does not support hard links.
+pub fn hard_link(original: &str, link: &str) -> io::Result<()> {
+    crate::root::link(None, original, link)
+}
+
 /// Creates a new, empty directory at the provided path.
 pub fn create_dir(path: &str) -> io::Result<()> {
     DirBuilder::new().create(path)
@@ -87,3 +160,117 @@ pub fn remove_file(path: &str) -> io::Result<()> {
 pub fn rename(old: &str, new: &str) -> io::Result<()> {
     crate::root::rename(old, new)
 }
+
+/// Mounts the filesystem `fs` on the directory `path`.
+///
+/// The directory must exist, and can be in another mounted filesystem. It
+/// fails with [`io::Error::NotFound`] otherwise.
+pub fn mount(path: &str, fs: Arc<dyn VfsOps>) -> io::Result<()> {
+    crate::root::mount(path, fs, "none", "unknown")
+}
+
+/// Creates a filesystem of the type `fstype` from the device `source`, and
+/// mounts it on the existing directory `path`.
+///
+/// The supported types are `tmpfs` (or `ramfs`), `devfs` (or `devtmpfs`),
+/// `proc` and `sysfs`, depending on the enabled features. `source` is ignored
+/// by these virtual filesystems.
+///
+/// The filesystems on disks are `vfat` (or `fat`) and `ext4` (or `ext2`,
+/// `ext3`), or `auto` to detect it. `source` is then a name in
+/// [`block_devices`], optionally prefixed by `/dev/`, or `PARTUUID=<uuid>` or
+/// `PARTLABEL=<label>` of a partition. It fails with
+/// [`io::Error::ResourceBusy`] if the device is already in use by a mounted
+/// filesystem, including one on the same disk or partition.
+pub fn mount_fs(source: &str, path: &str, fstype: &str) -> io::Result<()> {
+    crate::mounts::mount_fs(source, path, fstype, "")
+}
+
+/// Like [`mount_fs`], with the comma-separated mount `options`.
+///
+/// Only `size=` of `tmpfs` is supported, which limits the size of its files
+/// in bytes, with an optional suffix `k`, `m` or `g`, or as a percentage of
+/// the physical memory such as `size=50%`. Writes beyond it fail with
+/// [`io::Error::StorageFull`]. The other options are ignored.
+pub fn mount_fs_with_options(
+    source: &str,
+    path: &str,
+    fstype: &str,
+    options: &str,
+) -> io::Result<()> {
+    crate::mounts::mount_fs(source, path, fstype, options)
+}
+
+/// Mounts the filesystems listed in the `fstab` file at `path`, which is
+/// done for `/etc/fstab` at boot.
+///
+/// Each line is `<source> <target> <fstype> [<options> ...]`, where the
+/// entries of `/` and those with the `noauto` option are skipped, and the
+/// other options are passed to [`mount_fs_with_options`]. Lines starting with `#` are comments. Failed
+/// entries are logged and skipped.
+pub fn mount_fstab(path: &str) -> io::Result<()> {
+    crate::mounts::mount_fstab(path)
+}
+
+/// Returns the names of all block devices and their partitions, such as
+/// `vda`, `vda1` and `vdb`, to be mounted by [`mount_fs`].
+pub fn block_devices() -> Vec<String> {
+    crate::dev::all_disks()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Unmounts the filesystem mounted on `path`.
+///
+/// It fails with [`io::Error::ResourceBusy`] if other filesystems are mounted
+/// under it, or the current directory is in it.
+pub fn umount(path: &str) -> io::Result<()> {
+    crate::root::umount(path, false)
+}
+
+/// Unmounts the filesystem mounted on `path` even if it is busy, together
+/// with all filesystems mounted under it.
+pub fn umount_lazy(path: &str) -> io::Result<()> {
+    crate::root::umount(path, true)
+}
+
+/// Publishes the device node `node` as `/dev/<name>`.
+///
+/// It fails with [`io::Error::AlreadyExists`] if the name is taken, or
+/// [`io::Error::InvalidInput`] if it is not a valid file name.
+#[cfg(feature = "devfs")]
+pub fn register_device(name: &str, node: axfs_vfs::VfsNodeRef) -> io::Result<()> {
+    crate::fs::devfs::register(name, node)
+}
+
+/// Removes the device node published as `/dev/<name>`.
+#[cfg(feature = "devfs")]
+pub fn unregister_device(name: &str) -> io::Result<()> {
+    crate::fs::devfs::unregister(name)
+}
+
+/// Writes the changed filesystem metadata and all cached disk blocks back to
+/// the devices.
+pub fn sync() -> io::Result<()> {
+    #[cfg(all(feature = "ext4", not(feature = "myfs")))]
+    crate::fs::ext4::sync_all()?;
+    crate::dev::sync_all().map_err(|_| io::Error::Io)
+}
+
+/// Sets the capacity of the block cache of each disk, in 512-byte blocks.
+///
+/// The least recently used blocks are evicted if a cache is shrunk.
+pub fn set_block_cache_capacity(blocks: usize) -> io::Result<()> {
+    crate::dev::set_cache_capacity(blocks).map_err(|_| io::Error::Io)
+}
+
+/// Returns the capacity of the block cache of each disk, in 512-byte blocks.
+pub fn block_cache_capacity() -> usize {
+    crate::dev::cache_capacity()
+}
+
+/// Returns the total size of the disk blocks in the block caches, in bytes.
+pub fn block_cache_bytes() -> usize {
+    crate::dev::cached_bytes()
+}
@@ -0,0 +1,434 @@
+//! An LRU cache of disk blocks, shared by all filesystems on a device.
+//!
+//! Writes only modify the cached blocks, which are written back to the device
+//! when evicted or synced. On a miss right after the previous missed block,
+//! the following blocks are read ahead with a single device request.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axdriver::prelude::*;
+use axsync::Mutex;
+
+use super::BLOCK_SIZE;
+
+/// The default capacity of a cache, in blocks.
+const DEFAULT_CAPACITY: usize = 1024;
+/// The maximum number of blocks read ahead at once.
+const MAX_READAHEAD: usize = 32;
+
+const NIL: usize = usize::MAX;
+
+/// The capacity of the caches of newly opened devices.
+static CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_CAPACITY);
+/// All live caches, to be synced or resized together.
+static CACHES: Mutex<Vec<Weak<Mutex<BlockCache>>>> = Mutex::new(Vec::new());
+
+struct Slot {
+    block_id: u64,
+    dirty: bool,
+    /// The previous (more recently used) slot in the LRU list.
+    prev: usize,
+    /// The next (less recently used) slot in the LRU list.
+    next: usize,
+    data: Box<[u8; BLOCK_SIZE]>,
+}
+
+/// An LRU block cache in front of a block device.
+pub struct BlockCache {
+    dev: AxBlockDevice,
+    capacity: usize,
+    slots: Vec<Slot>,
+    /// The slot of each cached block.
+    map: BTreeMap<u64, usize>,
+    /// The most recently used slot.
+    head: usize,
+    /// The least recently used slot.
+    tail: usize,
+    /// The block of the last miss, to detect sequential reads.
+    last_miss: Option<u64>,
+}
+
+impl BlockCache {
+    /// Creates a cache over the device, and registers it so that it is
+    /// synced by [`sync_all`].
+    pub fn new(dev: AxBlockDevice) -> Arc<Mutex<Self>> {
+        let cache = Arc::new(Mutex::new(Self {
+            dev,
+            capacity: CAPACITY.load(Ordering::Relaxed),
+            slots: Vec::new(),
+            map: BTreeMap::new(),
+            head: NIL,
+            tail: NIL,
+            last_miss: None,
+        }));
+        let mut caches = CACHES.lock();
+        caches.retain(|c| c.strong_count() > 0);
+        caches.push(Arc::downgrade(&cache));
+        cache
+    }
+
+    /// Returns the number of blocks of the device.
+    pub fn num_blocks(&self) -> u64 {
+        self.dev.num_blocks()
+    }
+
+    /// Copies the block into `buf`.
+    pub fn read_block(&mut self, block_id: u64, buf: &mut [u8; BLOCK_SIZE]) -> DevResult {
+        let slot = self.get(block_id, true)?;
+        buf.copy_from_slice(&self.slots[slot].data[..]);
+        Ok(())
+    }
+
+    /// Runs `f` to modify the block in the cache, which is written back
+    /// later. If `whole` is set, `f` overwrites the whole block, so it is not
+    /// read from the device on a miss.
+    pub fn modify_block(
+        &mut self,
+        block_id: u64,
+        whole: bool,
+        f: impl FnOnce(&mut [u8; BLOCK_SIZE]),
+    ) -> DevResult {
+        let slot = self.get(block_id, !whole)?;
+        let slot = &mut self.slots[slot];
+        f(&mut slot.data);
+        slot.dirty = true;
+        Ok(())
+    }
+
+    /// Writes all dirty blocks back to the device in block order, and
+    /// flushes the device.
+    pub fn sync(&mut self) -> DevResult {
+        let dirty: Vec<usize> = self
+            .map
+            .values()
+            .copied()
+            .filter(|&slot| self.slots[slot].dirty)
+            .collect();
+        for slot in dirty {
+            self.write_back(slot)?;
+        }
+        self.dev.flush()
+    }
+
+    /// Changes the capacity, in blocks, writing back the evicted blocks.
+    pub fn set_capacity(&mut self, capacity: usize) -> DevResult {
+        let capacity = capacity.max(1);
+        while self.slots.len() > capacity {
+            let slot = self.tail;
+            self.write_back(slot)?;
+            self.unlink(slot);
+            self.map.remove(&self.slots[slot].block_id);
+            self.slots.swap_remove(slot);
+            self.relocate(self.slots.len(), slot);
+        }
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    /// Returns the slot of the block, loading it if `load` is set, and marks
+    /// it as the most recently used.
+    fn get(&mut self, block_id: u64, load: bool) -> DevResult<usize> {
+        if let Some(&slot) = self.map.get(&block_id) {
+            self.unlink(slot);
+            self.push_front(slot);
+            return Ok(slot);
+        }
+        if load {
+            let sequential = self.last_miss.is_some_and(|b| b + 1 == block_id);
+            self.last_miss = Some(block_id);
+            if sequential {
+                self.read_ahead(block_id)?;
+                if let Some(&slot) = self.map.get(&block_id) {
+                    return Ok(slot);
+                }
+            }
+        }
+        let slot = self.alloc_slot(block_id)?;
+        if load {
+            let data = &mut self.slots[slot].data;
+            if let Err(e) = self.dev.read_block(block_id, &mut data[..]) {
+                self.discard(slot);
+                return Err(e);
+            }
+        }
+        Ok(slot)
+    }
+
+    /// Reads the block and the following ones, up to the first cached block,
+    /// with one device request. The missed block ends up most recently used.
+    fn read_ahead(&mut self, block_id: u64) -> DevResult {
+        let max = MAX_READAHEAD
+            .min(self.capacity / 2)
+            .min((self.num_blocks().saturating_sub(block_id)) as usize);
+        let count = (0..max as u64)
+            .take_while(|i| !self.map.contains_key(&(block_id + i)))
+            .count();
+        if count < 2 {
+            return Ok(());
+        }
+        let mut buf = vec![0; count * BLOCK_SIZE];
+        self.dev.read_block(block_id, &mut buf)?;
+        for (i, data) in buf.chunks_exact(BLOCK_SIZE).enumerate().rev() {
+            let slot = self.alloc_slot(block_id + i as u64)?;
+            self.slots[slot].data.copy_from_slice(data);
+        }
+        self.last_miss = Some(block_id + count as u64 - 1);
+        Ok(())
+    }
+
+    /// Assigns a slot to the block, evicting the least recently used block
+    /// if the cache is full. The slot becomes the most recently used.
+    fn alloc_slot(&mut self, block_id: u64) -> DevResult<usize> {
+        let slot = if self.slots.len() < self.capacity {
+            self.slots.push(Slot {
+                block_id,
+                dirty: false,
+                prev: NIL,
+                next: NIL,
+                data: Box::new([0; BLOCK_SIZE]),
+            });
+            self.slots.len() - 1
+        } else {
+            let slot = self.tail;
+            self.write_back(slot)?;
+            self.unlink(slot);
+            self.map.remove(&self.slots[slot].block_id);
+            self.slots[slot].block_id = block_id;
+            slot
+        };
+        self.map.insert(block_id, slot);
+        self.push_front(slot);
+        Ok(slot)
+    }
+
+    /// Leaves the slot unused, to be reused first.
+    fn discard(&mut self, slot: usize) {
+        self.unlink(slot);
+        self.map.remove(&self.slots[slot].block_id);
+        self.slots[slot].block_id = u64::MAX;
+        self.push_back(slot);
+    }
+
+    fn write_back(&mut self, slot: usize) -> DevResult {
+        let slot = &mut self.slots[slot];
+        if slot.dirty {
+            self.dev.write_block(slot.block_id, &slot.data[..])?;
+            slot.dirty = false;
+        }
+        Ok(())
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let Slot { prev, next, .. } = self.slots[slot];
+        match prev {
+            NIL => self.head = next,
+            prev => self.slots[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.slots[next].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, slot: usize) {
+        self.slots[slot].prev = NIL;
+        self.slots[slot].next = self.head;
+        match self.head {
+            NIL => self.tail = slot,
+            head => self.slots[head].prev = slot,
+        }
+        self.head = slot;
+    }
+
+    fn push_back(&mut self, slot: usize) {
+        self.slots[slot].next = NIL;
+        self.slots[slot].prev = self.tail;
+        match self.tail {
+            NIL => self.head = slot,
+            tail => self.slots[tail].next = slot,
+        }
+        self.tail = slot;
+    }
+
+    /// Fixes the links after the slot at `from` is moved to `to`.
+    fn relocate(&mut self, from: usize, to: usize) {
+        if from == to {
+            return;
+        }
+        let Slot {
+            prev,
+            next,
+            block_id,
+            ..
+        } = self.slots[to];
+        match prev {
+            NIL => self.head = to,
+            prev => self.slots[prev].next = to,
+        }
+        match next {
+            NIL => self.tail = to,
+            next => self.slots[next].prev = to,
+        }
+        if let Some(slot) = self.map.get_mut(&block_id) {
+            *slot = to;
+        }
+    }
+}
+
+impl Drop for BlockCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.sync() {
+            warn!("failed to write back the block cache: {:?}", e);
+        }
+    }
+}
+
+/// Syncs all block caches.
+pub fn sync_all() -> DevResult {
+    for cache in live_caches() {
+        cache.lock().sync()?;
+    }
+    Ok(())
+}
+
+/// Sets the capacity of all block caches, including those created later.
+pub fn set_capacity(blocks: usize) -> DevResult {
+    CAPACITY.store(blocks.max(1), Ordering::Relaxed);
+    for cache in live_caches() {
+        cache.lock().set_capacity(blocks)?;
+    }
+    Ok(())
+}
+
+/// Returns the capacity of the caches of newly opened devices, in blocks.
+pub fn capacity() -> usize {
+    CAPACITY.load(Ordering::Relaxed)
+}
+
+/// Returns the total size of the blocks in all caches, in bytes.
+pub fn cached_bytes() -> usize {
+    live_caches()
+        .iter()
+        .map(|cache| cache.lock().slots.len() * BLOCK_SIZE)
+        .sum()
+}
+
+fn live_caches() -> Vec<Arc<Mutex<BlockCache>>> {
+    CACHES.lock().iter().filter_map(Weak::upgrade).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axdriver_block::ramdisk::RamDisk;
+
+    /// Creates a cache over a disk whose blocks are filled with their IDs.
+    fn new_cache(num_blocks: usize, capacity: usize) -> BlockCache {
+        let mut data = vec![0; num_blocks * BLOCK_SIZE];
+        for (i, block) in data.chunks_exact_mut(BLOCK_SIZE).enumerate() {
+            block.fill(i as u8);
+        }
+        BlockCache {
+            dev: RamDisk::from(&data[..]),
+            capacity,
+            slots: Vec::new(),
+            map: BTreeMap::new(),
+            head: NIL,
+            tail: NIL,
+            last_miss: None,
+        }
+    }
+
+    fn cached(cache: &BlockCache) -> Vec<u64> {
+        cache.map.keys().copied().collect()
+    }
+
+    fn read(cache: &mut BlockCache, block_id: u64) -> u8 {
+        let mut buf = [0; BLOCK_SIZE];
+        cache.read_block(block_id, &mut buf).unwrap();
+        assert!(buf.iter().all(|&b| b == buf[0]));
+        buf[0]
+    }
+
+    fn read_dev(cache: &mut BlockCache, block_id: u64) -> u8 {
+        let mut buf = [0; BLOCK_SIZE];
+        cache.dev.read_block(block_id, &mut buf).unwrap();
+        buf[0]
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut cache = new_cache(16, 3);
+        for block_id in [0, 5, 10] {
+            assert_eq!(read(&mut cache, block_id), block_id as u8);
+        }
+        // the block 5 becomes the least recently used
+        assert_eq!(read(&mut cache, 0), 0);
+        assert_eq!(read(&mut cache, 15), 15);
+        assert_eq!(cached(&cache), [0, 10, 15]);
+        assert_eq!(read(&mut cache, 5), 5);
+        assert_eq!(cached(&cache), [0, 5, 15]);
+
+        cache.set_capacity(1).unwrap();
+        assert_eq!(cached(&cache), [5]);
+        assert_eq!(cache.slots.len(), 1);
+        assert_eq!(read(&mut cache, 12), 12);
+        assert_eq!(cached(&cache), [12]);
+    }
+
+    #[test]
+    fn test_write_back() {
+        let mut cache = new_cache(16, 2);
+        cache.modify_block(1, true, |data| data.fill(0xaa)).unwrap();
+        cache
+            .modify_block(2, false, |data| data[..8].fill(0xbb))
+            .unwrap();
+        assert_eq!(read(&mut cache, 1), 0xaa);
+        assert_eq!(read_dev(&mut cache, 1), 1);
+
+        // evicts the dirty block 2, keeping the rest of it
+        assert_eq!(read(&mut cache, 8), 8);
+        let mut buf = [0; BLOCK_SIZE];
+        cache.dev.read_block(2, &mut buf).unwrap();
+        assert!(buf[..8].iter().all(|&b| b == 0xbb));
+        assert!(buf[8..].iter().all(|&b| b == 2));
+        assert_eq!(read_dev(&mut cache, 1), 1);
+
+        cache.sync().unwrap();
+        assert_eq!(read_dev(&mut cache, 1), 0xaa);
+        assert!(cache.slots.iter().all(|slot| !slot.dirty));
+    }
+
+    #[test]
+    fn test_readahead() {
+        let mut cache = new_cache(16, 8);
+        assert_eq!(read(&mut cache, 3), 3);
+        assert_eq!(cached(&cache), [3]);
+        // a sequential miss reads ahead half of the capacity
+        assert_eq!(read(&mut cache, 4), 4);
+        assert_eq!(cached(&cache), [3, 4, 5, 6, 7]);
+        // the missed block is the most recently used
+        assert_eq!(cache.slots[cache.head].block_id, 4);
+        for block_id in 5..8 {
+            assert_eq!(read(&mut cache, block_id), block_id as u8);
+        }
+
+        // up to the first cached block
+        let mut cache = new_cache(16, 8);
+        read(&mut cache, 12);
+        read(&mut cache, 8);
+        read(&mut cache, 9);
+        assert_eq!(cached(&cache), [8, 9, 10, 11, 12]);
+        assert_eq!(read(&mut cache, 11), 11);
+
+        // up to the end of the disk
+        let mut cache = new_cache(16, 8);
+        read(&mut cache, 13);
+        read(&mut cache, 14);
+        assert_eq!(cached(&cache), [13, 14, 15]);
+        assert_eq!(read(&mut cache, 15), 15);
+    }
+}
@@ -0,0 +1,157 @@
+mod cache;
+mod partition;
+mod registry;
+
+#[cfg(test)]
+mod tests;
+
+use alloc::sync::Arc;
+
+use axdriver::prelude::*;
+use axsync::Mutex;
+
+use self::cache::BlockCache;
+pub use self::cache::{cached_bytes, capacity as cache_capacity};
+pub use self::cache::{set_capacity as set_cache_capacity, sync_all};
+pub use self::partition::{read_partitions, Partition};
+pub use self::registry::{all_disks, find_disk, is_in_use, register_disks, select_root};
+
+const BLOCK_SIZE: usize = 512;
+
+/// A disk device with a cursor, or a partition of it.
+///
+/// All accesses go through the block cache of the device, see [`sync`] to
+/// write the changes back. Clones share the device and its cache, each with
+/// its own cursor.
+///
+/// [`sync`]: Disk::sync
+#[derive(Clone)]
+pub struct Disk {
+    block_id: u64,
+    offset: usize,
+    /// The first block of the disk on the device.
+    start_block: u64,
+    num_blocks: u64,
+    cache: Arc<Mutex<BlockCache>>,
+}
+
+impl Disk {
+    /// Create a new disk.
+    pub fn new(dev: AxBlockDevice) -> Self {
+        assert_eq!(BLOCK_SIZE, dev.block_size());
+        let cache = BlockCache::new(dev);
+        let num_blocks = cache.lock().num_blocks();
+        Self {
+            block_id: 0,
+            offset: 0,
+            start_block: 0,
+            num_blocks,
+            cache,
+        }
+    }
+
+    /// Returns the partition `part` of the disk as a disk, whose positions
+    /// start from the partition and end at its size.
+    pub fn partition(&self, part: &Partition) -> Self {
+        Self {
+            block_id: 0,
+            offset: 0,
+            start_block: self.start_block + part.start_block,
+            num_blocks: part.num_blocks,
+            cache: self.cache.clone(),
+        }
+    }
+
+    /// Get the size of the disk.
+    pub fn size(&self) -> u64 {
+        self.num_blocks * BLOCK_SIZE as u64
+    }
+
+    /// Get the position of the cursor.
+    pub fn position(&self) -> u64 {
+        self.block_id * BLOCK_SIZE as u64 + self.offset as u64
+    }
+
+    /// Set the position of the cursor.
+    pub fn set_position(&mut self, pos: u64) {
+        self.block_id = pos / BLOCK_SIZE as u64;
+        self.offset = pos as usize % BLOCK_SIZE;
+    }
+
+    /// Read within one block, returns the number of bytes read, which is 0
+    /// at the end of the disk.
+    pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
+        if self.block_id >= self.num_blocks {
+            return Ok(0);
+        }
+        let mut data = [0u8; BLOCK_SIZE];
+        let start = self.offset;
+        let count = buf.len().min(BLOCK_SIZE - self.offset);
+
+        let block_id = self.start_block + self.block_id;
+        self.cache.lock().read_block(block_id, &mut data)?;
+        buf[..count].copy_from_slice(&data[start..start + count]);
+        self.advance(count);
+        Ok(count)
+    }
+
+    /// Write within one block, returns the number of bytes written, which is
+    /// 0 at the end of the disk.
+    pub fn write_one(&mut self, buf: &[u8]) -> DevResult<usize> {
+        if self.block_id >= self.num_blocks {
+            return Ok(0);
+        }
+        let start = self.offset;
+        let count = buf.len().min(BLOCK_SIZE - self.offset);
+
+        let whole = count == BLOCK_SIZE;
+        let block_id = self.start_block + self.block_id;
+        self.cache.lock().modify_block(block_id, whole, |data| {
+            data[start..start + count].copy_from_slice(&buf[..count]);
+        })?;
+        self.advance(count);
+        Ok(count)
+    }
+
+    /// Reads the whole buffer from the position `pos`. It fails with
+    /// [`DevError::InvalidParam`] if the end of the disk is reached.
+    pub fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> DevResult {
+        self.set_position(pos);
+        let mut read = 0;
+        while read < buf.len() {
+            match self.read_one(&mut buf[read..])? {
+                0 => return Err(DevError::InvalidParam),
+                n => read += n,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the whole buffer at the position `pos`. It fails with
+    /// [`DevError::InvalidParam`] if the end of the disk is reached.
+    #[cfg_attr(not(feature = "devfs"), allow(dead_code))]
+    pub fn write_all_at(&mut self, pos: u64, buf: &[u8]) -> DevResult {
+        self.set_position(pos);
+        let mut written = 0;
+        while written < buf.len() {
+            match self.write_one(&buf[written..])? {
+                0 => return Err(DevError::InvalidParam),
+                n => written += n,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the cached changes of the disk back to the device.
+    pub fn sync(&mut self) -> DevResult {
+        self.cache.lock().sync()
+    }
+
+    fn advance(&mut self, count: usize) {
+        self.offset += count;
+        if self.offset >= BLOCK_SIZE {
+            self.block_id += 1;
+            self.offset -= BLOCK_SIZE;
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Partition tables of disks, MBR and GPT.
+
+use alloc::{format, string::String, vec::Vec};
+
+use super::{Disk, BLOCK_SIZE};
+
+/// The offset of the partition entries in the MBR.
+const MBR_ENTRIES: usize = 446;
+/// The partition type of the protective MBR of a GPT disk.
+const MBR_TYPE_GPT: u8 = 0xee;
+/// The partition types of extended partitions, with the logical partitions
+/// in a chain of extended boot records (EBR).
+const MBR_TYPES_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+/// The number of the first logical partition.
+const MBR_FIRST_LOGICAL: usize = 5;
+/// The signature of the GPT header.
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+/// The maximum number of GPT entries, 128 as usual.
+const GPT_MAX_ENTRIES: u32 = 128;
+
+/// A partition of a disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    /// The partition number, starting from 1, e.g. 2 for `vda2`.
+    pub number: usize,
+    /// The first block of the partition.
+    pub start_block: u64,
+    /// The number of blocks in the partition.
+    pub num_blocks: u64,
+    /// The name of a GPT partition, i.e. `PARTLABEL` of Linux.
+    pub label: Option<String>,
+    /// The unique ID, i.e. `PARTUUID` of Linux: the GUID of a GPT partition,
+    /// or the disk signature followed by the number for MBR, e.g.
+    /// `1234abcd-02`.
+    pub uuid: String,
+}
+
+/// Reads the partitions of the disk, from the GPT if the MBR is protective,
+/// or from the MBR otherwise: the primary partitions, followed by the logical
+/// partitions in the first extended partition, numbered from 5 as Linux does.
+///
+/// It returns nothing if the disk has no valid partition table, e.g. it is a
+/// FAT volume as a whole, whose boot sector has the same signature as an MBR.
+pub fn read_partitions(disk: &Disk) -> Vec<Partition> {
+    let mut sector = [0; BLOCK_SIZE];
+    if disk.clone().read_exact_at(0, &mut sector).is_err() || !is_mbr(&sector) {
+        return Vec::new();
+    }
+
+    let entries = sector[MBR_ENTRIES..MBR_ENTRIES + 64].chunks_exact(16);
+    if entries.clone().any(|entry| entry[4] == MBR_TYPE_GPT) {
+        return read_gpt(disk).unwrap_or_else(|| {
+            warn!("invalid GPT on the disk");
+            Vec::new()
+        });
+    }
+
+    let signature = u32::from_le_bytes(sector[440..444].try_into().unwrap());
+    let mut parts = Vec::new();
+    let mut extended = None;
+    for (i, entry) in entries.enumerate() {
+        let part = mbr_partition(entry, signature, i + 1, 0);
+        // type 0 marks an unused entry
+        if entry[4] == 0 || part.num_blocks == 0 {
+            continue;
+        }
+        // an extended partition is not a volume, but holds the logical ones
+        if MBR_TYPES_EXTENDED.contains(&entry[4]) {
+            if is_valid(disk, &part) {
+                extended.get_or_insert(part.start_block);
+            }
+            continue;
+        }
+        if is_valid(disk, &part) {
+            parts.push(part);
+        }
+    }
+    if let Some(start_block) = extended {
+        read_logical(disk, signature, start_block, &mut parts);
+    }
+    parts
+}
+
+/// Reads the logical partitions in the extended partition at `ext_start`,
+/// following the chain of EBRs until an invalid one. Each EBR has the
+/// logical partition relative to itself, and the next EBR relative to the
+/// extended partition.
+fn read_logical(disk: &Disk, signature: u32, ext_start: u64, parts: &mut Vec<Partition>) {
+    let mut disk = disk.clone();
+    let mut sector = [0; BLOCK_SIZE];
+    let mut ebr = ext_start;
+    let mut number = MBR_FIRST_LOGICAL;
+    loop {
+        let read = ebr
+            .checked_mul(BLOCK_SIZE as u64)
+            .map(|pos| disk.read_exact_at(pos, &mut sector));
+        if !matches!(read, Some(Ok(()))) || sector[510..] != [0x55, 0xaa] {
+            warn!("invalid EBR at block {}", ebr);
+            return;
+        }
+        let entry = &sector[MBR_ENTRIES..MBR_ENTRIES + 16];
+        if entry[4] != 0 {
+            let part = mbr_partition(entry, signature, number, ebr);
+            if is_valid(&disk, &part) {
+                parts.push(part);
+            }
+            number += 1;
+        }
+        // the EBRs are in ascending order, which also stops at loops
+        let link = &sector[MBR_ENTRIES + 16..MBR_ENTRIES + 32];
+        let next = u32::from_le_bytes(link[8..12].try_into().unwrap()) as u64;
+        match ext_start.checked_add(next) {
+            Some(next_ebr) if link[4] != 0 && next_ebr > ebr => ebr = next_ebr,
+            _ => return,
+        }
+    }
+}
+
+/// Parses a partition entry of an MBR or EBR, whose start is relative to the
+/// block `base`.
+fn mbr_partition(entry: &[u8], signature: u32, number: usize, base: u64) -> Partition {
+    let start = u32::from_le_bytes(entry[8..12].try_into().unwrap()) as u64;
+    Partition {
+        number,
+        // both are at most 32 bits
+        start_block: base + start,
+        num_blocks: u32::from_le_bytes(entry[12..16].try_into().unwrap()) as u64,
+        label: None,
+        uuid: format!("{:08x}-{:02x}", signature, number),
+    }
+}
+
+/// Reads the partitions in the primary GPT, or `None` if its header or an
+/// entry is invalid. The checksums are not verified.
+fn read_gpt(disk: &Disk) -> Option<Vec<Partition>> {
+    let mut disk = disk.clone();
+    let mut header = [0; BLOCK_SIZE];
+    disk.read_exact_at(BLOCK_SIZE as u64, &mut header).ok()?;
+    if &header[..8] != GPT_SIGNATURE {
+        return None;
+    }
+    let entries_lba = u64::from_le_bytes(header[72..80].try_into().unwrap());
+    let num_entries = u32::from_le_bytes(header[80..84].try_into().unwrap());
+    let entry_size = u32::from_le_bytes(header[84..88].try_into().unwrap()) as u64;
+    if entry_size < 128 || num_entries > GPT_MAX_ENTRIES {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    let mut entry = [0; 128];
+    for i in 0..num_entries as u64 {
+        let pos = entries_lba
+            .checked_mul(BLOCK_SIZE as u64)?
+            .checked_add(i.checked_mul(entry_size)?)?;
+        disk.read_exact_at(pos, &mut entry).ok()?;
+        // a zero type GUID marks an unused entry
+        if entry[..16].iter().all(|&b| b == 0) {
+            continue;
+        }
+        let first_lba = u64::from_le_bytes(entry[32..40].try_into().unwrap());
+        let last_lba = u64::from_le_bytes(entry[40..48].try_into().unwrap());
+        let name: Vec<u16> = entry[56..128]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|&c| c != 0)
+            .collect();
+        let part = Partition {
+            number: i as usize + 1,
+            start_block: first_lba,
+            num_blocks: last_lba.checked_add(1)?.saturating_sub(first_lba),
+            label: Some(String::from_utf16_lossy(&name)).filter(|name| !name.is_empty()),
+            uuid: format_guid(entry[16..32].try_into().unwrap()),
+        };
+        if is_valid(&disk, &part) {
+            parts.push(part);
+        }
+    }
+    Some(parts)
+}
+
+fn is_valid(disk: &Disk, part: &Partition) -> bool {
+    let disk_blocks = disk.size() / BLOCK_SIZE as u64;
+    let valid = part.start_block != 0
+        && part.num_blocks != 0
+        && part
+            .start_block
+            .checked_add(part.num_blocks)
+            .is_some_and(|end| end <= disk_blocks);
+    if !valid {
+        warn!("ignore invalid partition {:?}", part);
+    }
+    valid
+}
+
+fn is_mbr(sector: &[u8; BLOCK_SIZE]) -> bool {
+    if sector[510..] != [0x55, 0xaa] {
+        return false;
+    }
+    // A FAT boot sector starts with a jump, followed by the BIOS parameter
+    // block with the bytes per sector.
+    let bytes_per_sector = u16::from_le_bytes([sector[11], sector[12]]);
+    if matches!(sector[0], 0xeb | 0xe9) && bytes_per_sector.is_power_of_two() {
+        return false;
+    }
+    // The boot indicator of each entry is either 0 or 0x80.
+    sector[MBR_ENTRIES..MBR_ENTRIES + 64]
+        .chunks_exact(16)
+        .all(|entry| entry[0] & 0x7f == 0)
+}
+
+/// Formats a GUID, whose first three fields are little-endian.
+fn format_guid(guid: &[u8; 16]) -> String {
+    let mut s = format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-",
+        u32::from_le_bytes(guid[..4].try_into().unwrap()),
+        u16::from_le_bytes([guid[4], guid[5]]),
+        u16::from_le_bytes([guid[6], guid[7]]),
+        guid[8],
+        guid[9],
+    );
+    for b in &guid[10..] {
+        s += &format!("{:02x}", b);
+    }
+    s
+}
@@ -0,0 +1,120 @@
+//! The registry of all block devices, i.e. the disks and their partitions by
+//! name, such as `vda`, `vda1` and `vdb`.
+
+use alloc::{format, string::String, vec::Vec};
+
+use axsync::Mutex;
+
+use super::{read_partitions, Disk, Partition};
+
+/// A disk or a partition registered by its name.
+struct Device {
+    name: String,
+    disk: Disk,
+    /// The partition it is, or `None` for a whole disk.
+    part: Option<Partition>,
+}
+
+static DEVICES: Mutex<Vec<Device>> = Mutex::new(Vec::new());
+/// The name of the device of the root filesystem.
+static ROOT_DEVICE: Mutex<String> = Mutex::new(String::new());
+
+/// Registers the disks as `vda`, `vdb` and so on in order, followed by each
+/// of their partitions, e.g. `vda1`.
+pub fn register_disks(disks: Vec<Disk>) {
+    let mut devices = DEVICES.lock();
+    for disk in disks {
+        let name = axdriver::block_device_name(devices.iter().filter(|d| d.part.is_none()).count());
+        let parts = read_partitions(&disk);
+        devices.push(Device {
+            name: name.clone(),
+            disk: disk.clone(),
+            part: None,
+        });
+        for part in parts {
+            devices.push(Device {
+                name: format!("{}{}", name, part.number),
+                disk: disk.partition(&part),
+                part: Some(part),
+            });
+        }
+    }
+}
+
+/// Returns the names and disks of all registered devices, in order.
+pub fn all_disks() -> Vec<(String, Disk)> {
+    let devices = DEVICES.lock();
+    devices
+        .iter()
+        .map(|dev| (dev.name.clone(), dev.disk.clone()))
+        .collect()
+}
+
+/// Finds a registered device by `spec`, which is one of:
+///
+/// - a device name, such as `vdb` or `vda2`, optionally prefixed by `/dev/`;
+/// - `PARTUUID=<uuid>`, the unique ID of a partition, case-insensitive;
+/// - `PARTLABEL=<label>`, the name of a GPT partition.
+///
+/// Returns the name of the device and the disk for it.
+pub fn find_disk(spec: &str) -> Option<(String, Disk)> {
+    let spec = spec.strip_prefix("/dev/").unwrap_or(spec);
+    let devices = DEVICES.lock();
+    let found = devices.iter().find(|dev| {
+        let part = dev.part.as_ref();
+        if let Some(uuid) = spec.strip_prefix("PARTUUID=") {
+            part.is_some_and(|part| part.uuid.eq_ignore_ascii_case(uuid))
+        } else if let Some(label) = spec.strip_prefix("PARTLABEL=") {
+            part.is_some_and(|part| part.label.as_deref() == Some(label))
+        } else {
+            dev.name == spec
+        }
+    })?;
+    Some((found.name.clone(), found.disk.clone()))
+}
+
+/// Selects the device for the root filesystem by `spec`, which is either
+/// one accepted by [`find_disk`], or a partition number of the first disk,
+/// such as `2`.
+///
+/// An empty `spec` selects the first partition of the first disk, or the
+/// disk as a whole if it has no partition table. The selected device is
+/// recorded as in use, see [`is_in_use`].
+pub fn select_root(spec: &str) -> Option<(String, Disk)> {
+    let spec = spec.trim();
+    let found = if spec.is_empty() {
+        // the partitions follow their disk
+        let devices = DEVICES.lock();
+        devices
+            .get(1)
+            .filter(|dev| dev.part.is_some())
+            .or_else(|| devices.first())
+            .map(|dev| (dev.name.clone(), dev.disk.clone()))
+    } else if spec.bytes().all(|b| b.is_ascii_digit()) {
+        find_disk(&format!("{}{}", axdriver::block_device_name(0), spec))
+    } else {
+        find_disk(spec)
+    };
+    if let Some((name, _)) = &found {
+        *ROOT_DEVICE.lock() = name.clone();
+    }
+    found
+}
+
+/// Returns whether the device named `name` is in use by the root filesystem
+/// or a mounted one, i.e. `sources` of the mount table. A disk is also in
+/// use if any of its partitions is, and vice versa.
+pub fn is_in_use<'a>(name: &str, sources: impl IntoIterator<Item = &'a str>) -> bool {
+    let is_mounted = |source: &str| overlaps(source.strip_prefix("/dev/").unwrap_or(source), name);
+    is_mounted(&ROOT_DEVICE.lock()) || sources.into_iter().any(is_mounted)
+}
+
+/// Returns whether the devices `a` and `b` are the same, or one is a
+/// partition of the other.
+fn overlaps(a: &str, b: &str) -> bool {
+    let is_part_of = |part: &str, disk: &str| {
+        part.strip_prefix(disk)
+            .is_some_and(|number| number.bytes().all(|b| b.is_ascii_digit()))
+    };
+    !a.is_empty() && !b.is_empty() && (is_part_of(a, b) || is_part_of(b, a))
+}
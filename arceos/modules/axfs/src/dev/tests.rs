@@ -0,0 +1,189 @@
+use std::sync::{Mutex, Once};
+
+use axdriver_block::ramdisk::RamDisk;
+
+use super::*;
+
+static INIT: Once = Once::new();
+static SERIAL: Mutex<()> = Mutex::new(());
+
+const GUID: [u8; 16] = [
+    0x78, 0x56, 0x34, 0x12, 0xbc, 0x9a, 0xf0, 0xde, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef,
+];
+
+fn new_disk(num_blocks: usize, init: impl FnOnce(&mut [u8])) -> Disk {
+    let mut data = vec![0; num_blocks * BLOCK_SIZE];
+    init(&mut data);
+    Disk::new(RamDisk::from(&data))
+}
+
+fn put_u32(data: &mut [u8], pos: usize, value: u32) {
+    data[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(data: &mut [u8], pos: usize, value: u64) {
+    data[pos..pos + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Fills the entry `index` of the MBR or EBR at the block `sector`.
+fn mbr_entry(data: &mut [u8], sector: usize, index: usize, ty: u8, start: u32, len: u32) {
+    let pos = sector * BLOCK_SIZE + 446 + index * 16;
+    data[pos + 4] = ty;
+    put_u32(data, pos + 8, start);
+    put_u32(data, pos + 12, len);
+    data[sector * BLOCK_SIZE + 510..sector * BLOCK_SIZE + 512].copy_from_slice(&[0x55, 0xaa]);
+}
+
+/// A disk of 200 blocks with two primary partitions, an extended partition
+/// with two logical ones, and an invalid partition out of the disk.
+fn mbr_disk() -> Disk {
+    new_disk(200, |data| {
+        put_u32(data, 440, 0x1234abcd);
+        mbr_entry(data, 0, 0, 0x83, 10, 20);
+        mbr_entry(data, 0, 1, 0x0c, 30, 10);
+        mbr_entry(data, 0, 2, 0x05, 100, 90);
+        mbr_entry(data, 0, 3, 0x83, 180, 100);
+        // EBRs at blocks 100 and 120, the last one linking back to the first
+        mbr_entry(data, 100, 0, 0x83, 2, 10);
+        mbr_entry(data, 100, 1, 0x05, 20, 40);
+        mbr_entry(data, 120, 0, 0x83, 2, 30);
+        mbr_entry(data, 120, 1, 0x05, 0, 20);
+    })
+}
+
+/// A disk of 100 blocks with a GPT of 4 entries at block 2, of which the
+/// first and the third are used.
+fn gpt_disk(init: impl FnOnce(&mut [u8])) -> Disk {
+    new_disk(100, |data| {
+        mbr_entry(data, 0, 0, 0xee, 1, 99);
+        let header = &mut data[BLOCK_SIZE..];
+        header[..8].copy_from_slice(b"EFI PART");
+        put_u64(header, 72, 2);
+        put_u32(header, 80, 4);
+        put_u32(header, 84, 128);
+
+        let entries = &mut data[2 * BLOCK_SIZE..];
+        entries[..16].fill(0xaf);
+        entries[16..32].copy_from_slice(&GUID);
+        put_u64(entries, 32, 34);
+        put_u64(entries, 40, 43);
+        for (i, c) in "root".encode_utf16().enumerate() {
+            entries[56 + i * 2..58 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+        let entry = &mut entries[256..];
+        entry[..16].fill(0xaf);
+        entry[16] = 0x42;
+        put_u64(entry, 32, 50);
+        put_u64(entry, 40, 59);
+        init(data);
+    })
+}
+
+fn part(number: usize, start_block: u64, num_blocks: u64, uuid: &str) -> Partition {
+    Partition {
+        number,
+        start_block,
+        num_blocks,
+        label: None,
+        uuid: uuid.into(),
+    }
+}
+
+fn serial() -> std::sync::MutexGuard<'static, ()> {
+    let guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    INIT.call_once(axtask::init_scheduler); // call this to use `axsync::Mutex`.
+    guard
+}
+
+#[test]
+fn test_mbr() {
+    let _guard = serial();
+    let parts = read_partitions(&mbr_disk());
+    assert_eq!(
+        parts,
+        [
+            part(1, 10, 20, "1234abcd-01"),
+            part(2, 30, 10, "1234abcd-02"),
+            part(5, 102, 10, "1234abcd-05"),
+            part(6, 122, 30, "1234abcd-06"),
+        ]
+    );
+
+    // a FAT volume as a whole
+    let disk = new_disk(16, |data| {
+        data[0] = 0xeb;
+        data[11..13].copy_from_slice(&512u16.to_le_bytes());
+        data[510..512].copy_from_slice(&[0x55, 0xaa]);
+    });
+    assert!(read_partitions(&disk).is_empty());
+
+    // no signature
+    assert!(read_partitions(&new_disk(16, |_| {})).is_empty());
+}
+
+#[test]
+fn test_gpt() {
+    let _guard = serial();
+    let parts = read_partitions(&gpt_disk(|_| {}));
+    let uuid = "12345678-9abc-def0-0123-456789abcdef";
+    let mut root = part(1, 34, 10, uuid);
+    root.label = Some("root".into());
+    let data = part(3, 50, 10, "00000042-0000-0000-0000-000000000000");
+    assert_eq!(parts, [root, data]);
+
+    // the positions overflow
+    let disk = gpt_disk(|data| put_u64(&mut data[BLOCK_SIZE..], 72, u64::MAX / 256));
+    assert!(read_partitions(&disk).is_empty());
+    let disk = gpt_disk(|data| put_u64(&mut data[2 * BLOCK_SIZE..], 40, u64::MAX));
+    assert!(read_partitions(&disk).is_empty());
+    let disk = gpt_disk(|data| {
+        put_u64(&mut data[2 * BLOCK_SIZE..], 32, u64::MAX - 4);
+        put_u64(&mut data[2 * BLOCK_SIZE..], 40, u64::MAX - 1);
+    });
+    assert_eq!(read_partitions(&disk).len(), 1);
+    // too small entries
+    let disk = gpt_disk(|data| put_u32(&mut data[BLOCK_SIZE..], 84, 64));
+    assert!(read_partitions(&disk).is_empty());
+}
+
+#[test]
+fn test_registry() {
+    let _guard = serial();
+    let whole = new_disk(16, |_| {});
+    register_disks(vec![gpt_disk(|_| {}), mbr_disk(), whole]);
+    let names: Vec<String> = all_disks().into_iter().map(|(name, _)| name).collect();
+    let expected = [
+        "vda", "vda1", "vda3", "vdb", "vdb1", "vdb2", "vdb5", "vdb6", "vdc",
+    ];
+    assert_eq!(names, expected);
+
+    let find = |spec| find_disk(spec).map(|(name, disk)| (name, disk.size()));
+    let uuid = "PARTUUID=12345678-9ABC-DEF0-0123-456789ABCDEF";
+    assert_eq!(find(uuid), Some(("vda1".into(), 10 * BLOCK_SIZE as u64)));
+    assert_eq!(find("PARTLABEL=root").unwrap().0, "vda1");
+    assert_eq!(find("PARTLABEL=ROOT"), None);
+    assert_eq!(find("PARTUUID=1234abcd-05").unwrap().0, "vdb5");
+    assert_eq!(find("/dev/vdb6").unwrap().0, "vdb6");
+    assert_eq!(find("vdc").unwrap().0, "vdc");
+    assert_eq!(find("vdd"), None);
+
+    // the partitions read the blocks of the disk from their start
+    let (_, mut disk) = find_disk("vdb5").unwrap();
+    disk.write_all_at(0, b"logical").unwrap();
+    let (_, mut vdb) = find_disk("vdb").unwrap();
+    let mut buf = [0; 7];
+    vdb.read_exact_at(102 * BLOCK_SIZE as u64, &mut buf)
+        .unwrap();
+    assert_eq!(&buf, b"logical");
+
+    assert_eq!(select_root("").unwrap().0, "vda1");
+    assert!(is_in_use("vda", []));
+    assert!(!is_in_use("vdb", []));
+    assert_eq!(select_root("3").unwrap().0, "vda3");
+    assert_eq!(select_root("PARTUUID=1234abcd-06").unwrap().0, "vdb6");
+    assert!(select_root("PARTLABEL=home").is_none());
+    assert!(is_in_use("vdb", []));
+    assert!(!is_in_use("vda", []));
+    assert!(is_in_use("vda3", ["/dev/vda"]));
+    assert!(!is_in_use("vdc", ["vdb5"]));
+}
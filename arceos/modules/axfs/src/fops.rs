@@ -1,10 +1,16 @@
 //! Low-level filesystem operations.
 
+use alloc::sync::Arc;
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axfs_vfs::{VfsError, VfsNodeRef};
 use axio::SeekFrom;
 use cap_access::{Cap, WithCap};
 use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::lock::{self, Kind, Lock, LockKey};
+use crate::root::MountRef;
 
 #[cfg(feature = "myfs")]
 pub use crate::dev::Disk;
@@ -20,11 +26,87 @@ pub type FileAttr = axfs_vfs::VfsNodeAttr;
 /// Alias of [`axfs_vfs::VfsNodePerm`].
 pub type FilePerm = axfs_vfs::VfsNodePerm;
 
+/// The ownership and timestamps of a file, which are only kept by some
+/// filesystems (e.g. ramfs).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMeta {
+    /// The user ID of the owner.
+    pub uid: u32,
+    /// The group ID of the owner.
+    pub gid: u32,
+    /// The time of the last access, since the UNIX epoch.
+    pub atime: Duration,
+    /// The time of the last modification of the content.
+    pub mtime: Duration,
+    /// The time of the last change of the content or the metadata.
+    pub ctime: Duration,
+}
+
+/// The space usage of a filesystem, see [`crate::api::statfs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsStat {
+    /// The size of a block, in bytes.
+    pub block_size: u64,
+    /// The total number of blocks, or 0 if the size is unlimited.
+    pub blocks: u64,
+    /// The number of blocks in use.
+    pub used_blocks: u64,
+    /// The number of free blocks, or 0 if the size is unlimited.
+    pub free_blocks: u64,
+}
+
+/// The type of an advisory lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockType {
+    /// A shared lock, which others can hold at the same time.
+    Shared,
+    /// An exclusive lock.
+    Exclusive,
+    /// No lock, to release the held ones.
+    Unlock,
+}
+
+/// A record lock on a range of bytes of a file, see
+/// [`LockHandle::set_record_lock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordLock {
+    /// The type of the lock.
+    pub ty: LockType,
+    /// The offset of the first byte.
+    pub start: u64,
+    /// The number of bytes, or 0 for all the bytes from `start` on, even
+    /// beyond the end of the file.
+    pub len: u64,
+    /// The ID of the task holding the lock, only set by
+    /// [`LockHandle::get_record_lock`].
+    pub owner: u64,
+}
+
+/// The advisory locks of an opened file, which can be waited for without
+/// holding the [`File`].
+///
+/// The locks are only checked by the lock operations, not by reads and
+/// writes. The `flock` locks are owned by the opened file and released when
+/// it is closed, and the record locks are owned by the task and released
+/// when it closes an opened file of the same file that has been locked
+/// through, so that the files opened internally (e.g. by `stat`) keep them.
+#[derive(Clone)]
+pub struct LockHandle {
+    key: Arc<LockKey>,
+    /// The ID of the opened file.
+    id: u64,
+    /// Whether the file has been locked through this opened file.
+    used: Arc<AtomicBool>,
+}
+
 /// An opened file object, with open permissions and a cursor.
 pub struct File {
     node: WithCap<VfsNodeRef>,
     is_append: bool,
     offset: u64,
+    locks: LockHandle,
+    /// Keeps the filesystem from being unmounted.
+    mount: Option<MountRef>,
 }
 
 /// An opened directory object, with open permissions and a cursor for
@@ -32,6 +114,9 @@ pub struct File {
 pub struct Directory {
     node: WithCap<VfsNodeRef>,
     entry_idx: usize,
+    /// Keeps the filesystem from being unmounted, and is shared with the
+    /// files and directories opened relative to it.
+    mount: Option<MountRef>,
 }
 
 /// Options and flags which can be used to configure how a file is opened.
@@ -122,8 +207,8 @@ impl File {
             return ax_err!(InvalidInput);
         }
 
-        let node_option = crate::root::lookup(dir, path);
-        let node = if opts.create || opts.create_new {
+        let node_option = crate::root::lookup_to_open(dir, path);
+        let (node, mount) = if opts.create || opts.create_new {
             match node_option {
                 Ok(node) => {
                     // already exists
@@ -133,7 +218,7 @@ impl File {
                     node
                 }
                 // not exists, create new
-                Err(VfsError::NotFound) => crate::root::create_file(dir, path)?,
+                Err(VfsError::NotFound) => crate::root::create_file_to_open(dir, path)?,
                 Err(e) => return Err(e),
             }
         } else {
@@ -157,9 +242,11 @@ impl File {
             node.truncate(0)?;
         }
         Ok(Self {
+            locks: LockHandle::new(&node, dir, path),
             node: WithCap::new(node, access_cap),
             is_append: opts.append,
             offset: 0,
+            mount,
         })
     }
 
@@ -242,10 +329,178 @@ impl File {
         Ok(new_offset)
     }
 
+    /// Sets the cursor to the first data at or after `offset`, skipping the
+    /// holes of a sparse file. Returns the new position, or `None` if there
+    /// is no more data, which leaves the cursor unchanged.
+    pub fn seek_data(&mut self, offset: u64) -> AxResult<Option<u64>> {
+        let pos = crate::fs::seek_data(self.access_node(Cap::empty())?, offset)?;
+        self.offset = pos.unwrap_or(self.offset);
+        Ok(pos)
+    }
+
+    /// Sets the cursor to the first hole at or after `offset`, which is the
+    /// end of the file if there is no other hole. Returns the new position,
+    /// or `None` if `offset` is past the end, which leaves the cursor
+    /// unchanged.
+    pub fn seek_hole(&mut self, offset: u64) -> AxResult<Option<u64>> {
+        let pos = crate::fs::seek_hole(self.access_node(Cap::empty())?, offset)?;
+        self.offset = pos.unwrap_or(self.offset);
+        Ok(pos)
+    }
+
     /// Gets the file attributes.
     pub fn get_attr(&self) -> AxResult<FileAttr> {
         self.access_node(Cap::empty())?.get_attr()
     }
+
+    /// Gets the ownership and timestamps of the file, or `None` if they are
+    /// not kept by the filesystem.
+    pub fn get_meta(&self) -> Option<FileMeta> {
+        crate::fs::node_meta(self.access_node(Cap::empty()).ok()?)
+    }
+
+    /// Changes the permission bits of the file.
+    pub fn set_perm(&self, perm: FilePerm) -> AxResult {
+        crate::fs::set_node_perm(self.access_node(Cap::empty())?, perm)
+    }
+
+    /// Changes the owner and the group of the file. `None` leaves it as is.
+    pub fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) -> AxResult {
+        crate::fs::set_node_owner(self.access_node(Cap::empty())?, uid, gid)
+    }
+
+    /// Changes the access and modification times of the file. `None` leaves
+    /// it as is.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+        crate::fs::set_node_times(self.access_node(Cap::empty())?, atime, mtime)
+    }
+
+    /// Gets the space usage of the filesystem that the file is in.
+    pub fn fs_stat(&self) -> AxResult<FsStat> {
+        crate::fs::fs_stat(self.access_node(Cap::empty())?)
+    }
+
+    /// Returns the handle of the advisory locks of the file.
+    pub fn lock_handle(&self) -> LockHandle {
+        self.locks.clone()
+    }
+}
+
+impl LockHandle {
+    fn new(node: &VfsNodeRef, dir: Option<&VfsNodeRef>, path: &str) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        // the nodes of some filesystems are created at each lookup, so their
+        // files are told apart by the absolute paths, or not at all if
+        // opened relative to a directory
+        let path = match dir {
+            None => crate::root::absolute_path(path).ok(),
+            Some(_) => None,
+        };
+        let key = crate::fs::lock_key(node)
+            .or_else(|| path.map(LockKey::Path))
+            .unwrap_or_else(|| LockKey::Node(Arc::as_ptr(node) as *const () as usize));
+        Self {
+            key: Arc::new(key),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            used: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Releases the locks of the closed file.
+    fn release(&self) {
+        if self.used.load(Ordering::Acquire) {
+            lock::release(&self.key, self.id);
+        }
+    }
+
+    /// Applies or removes the `flock` lock of the opened file, on the whole
+    /// file. A held lock is converted to the new type.
+    ///
+    /// If the lock conflicts with the ones of other opened files, it waits
+    /// for them to be released if `wait` is set, or fails with
+    /// [`WouldBlock`](AxError::WouldBlock) otherwise. A converted lock is
+    /// released before waiting.
+    pub fn flock(&self, ty: LockType, wait: bool) -> AxResult {
+        let exclusive = match ty {
+            LockType::Shared => false,
+            LockType::Exclusive => true,
+            LockType::Unlock => {
+                lock::unlock(&self.key, Kind::Flock, self.id, 0, u64::MAX);
+                return Ok(());
+            }
+        };
+        let lock = Lock {
+            owner: self.id,
+            exclusive,
+            start: 0,
+            end: u64::MAX,
+        };
+        self.used.store(true, Ordering::Release);
+        lock::lock(&self.key, Kind::Flock, lock, wait)
+    }
+
+    /// Applies or removes the record locks of the current task on the range
+    /// of `lock`, whose owner is ignored. The locks of the task on the range
+    /// are replaced, and the parts of them out of the range are kept.
+    ///
+    /// If the lock conflicts with the ones of other tasks, it waits for them
+    /// to be released if `wait` is set, or fails with
+    /// [`WouldBlock`](AxError::WouldBlock) otherwise. It fails with
+    /// [`ResourceBusy`](AxError::ResourceBusy) if waiting would deadlock.
+    pub fn set_record_lock(&self, lock: &RecordLock, wait: bool) -> AxResult {
+        let owner = lock::current_task();
+        let (start, end) = record_range(lock)?;
+        let exclusive = match lock.ty {
+            LockType::Shared => false,
+            LockType::Exclusive => true,
+            LockType::Unlock => {
+                lock::unlock(&self.key, Kind::Record, owner, start, end);
+                return Ok(());
+            }
+        };
+        let lock = Lock {
+            owner,
+            exclusive,
+            start,
+            end,
+        };
+        self.used.store(true, Ordering::Release);
+        lock::lock(&self.key, Kind::Record, lock, wait)
+    }
+
+    /// Returns a record lock of another task that prevents the current task
+    /// from applying `lock`, or `None` if it can be applied.
+    pub fn get_record_lock(&self, lock: &RecordLock) -> AxResult<Option<RecordLock>> {
+        let (start, end) = record_range(lock)?;
+        let exclusive = match lock.ty {
+            LockType::Shared => false,
+            LockType::Exclusive => true,
+            LockType::Unlock => return ax_err!(InvalidInput),
+        };
+        let lock = Lock {
+            owner: lock::current_task(),
+            exclusive,
+            start,
+            end,
+        };
+        Ok(lock::find_conflict(&self.key, &lock).map(|l| {
+            let ty = match l.exclusive {
+                true => LockType::Exclusive,
+                false => LockType::Shared,
+            };
+            let len = if l.end == u64::MAX {
+                0
+            } else {
+                l.end - l.start
+            };
+            RecordLock {
+                ty,
+                start: l.start,
+                len,
+                owner: l.owner,
+            }
+        }))
+    }
 }
 
 impl Directory {
@@ -262,7 +517,7 @@ impl Directory {
             return ax_err!(InvalidInput);
         }
 
-        let node = crate::root::lookup(dir, path)?;
+        let (node, mount) = crate::root::lookup_to_open(dir, path)?;
         let attr = node.get_attr()?;
         if !attr.is_dir() {
             return ax_err!(NotADirectory);
@@ -276,6 +531,7 @@ impl Directory {
         Ok(Self {
             node: WithCap::new(node, access_cap),
             entry_idx: 0,
+            mount,
         })
     }
 
@@ -296,13 +552,17 @@ impl Directory {
     /// Opens a directory at the path relative to this directory. Returns a
     /// [`Directory`] object.
     pub fn open_dir_at(&self, path: &str, opts: &OpenOptions) -> AxResult<Self> {
-        Self::_open_dir_at(self.access_at(path)?, path, opts)
+        let mut dir = Self::_open_dir_at(self.access_at(path)?, path, opts)?;
+        dir.mount = dir.mount.or_else(|| self.mount.clone());
+        Ok(dir)
     }
 
     /// Opens a file at the path relative to this directory. Returns a [`File`]
     /// object.
     pub fn open_file_at(&self, path: &str, opts: &OpenOptions) -> AxResult<File> {
-        File::_open_at(self.access_at(path)?, path, opts)
+        let mut file = File::_open_at(self.access_at(path)?, path, opts)?;
+        file.mount = file.mount.or_else(|| self.mount.clone());
+        Ok(file)
     }
 
     /// Creates an empty file at the path relative to this directory.
@@ -349,6 +609,7 @@ impl Directory {
 
 impl Drop for File {
     fn drop(&mut self) {
+        self.locks.release();
         unsafe { self.node.access_unchecked().release().ok() };
     }
 }
@@ -397,6 +658,16 @@ impl From<&OpenOptions> for Cap {
     }
 }
 
+/// Returns the range of bytes of a record lock, from the start to the end
+/// (exclusive).
+fn record_range(lock: &RecordLock) -> AxResult<(u64, u64)> {
+    if lock.len == 0 {
+        return Ok((lock.start, u64::MAX));
+    }
+    let end = lock.start.checked_add(lock.len);
+    Ok((lock.start, end.ok_or(AxError::InvalidInput)?))
+}
+
 fn perm_to_cap(perm: FilePerm) -> Cap {
     let mut cap = Cap::empty();
     if perm.owner_readable() {
@@ -0,0 +1,295 @@
+//! The device filesystem, which lists the device nodes registered by
+//! [`register`], such as `null`, `vda` and `console`.
+//!
+//! All instances list the same nodes, like `devtmpfs` of Linux, so a device
+//! registered at runtime appears in each mounted devfs.
+
+use alloc::collections::{btree_map::Entry, BTreeMap};
+use alloc::{string::String, sync::Arc};
+
+use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef};
+use axfs_vfs::{VfsNodeType, VfsResult};
+use axsync::Mutex;
+
+pub use axfs_devfs::{NullDev, ZeroDev};
+
+use super::pseudo::{PseudoDir, PseudoFileSystem};
+use crate::dev::{all_disks, Disk};
+
+static DEVICES: Mutex<BTreeMap<String, VfsNodeRef>> = Mutex::new(BTreeMap::new());
+
+/// Creates a devfs instance.
+pub fn new_devfs() -> PseudoFileSystem {
+    PseudoFileSystem::new(PseudoDir::new_dynamic(None, |_| {
+        let devices = DEVICES.lock();
+        devices
+            .iter()
+            .map(|(name, node)| (name.clone(), node.clone()))
+            .collect()
+    }))
+}
+
+/// Registers the device node `node` as `name` in all devfs instances.
+pub fn register(name: &str, node: VfsNodeRef) -> VfsResult {
+    if matches!(name, "" | "." | "..") || name.contains('/') {
+        return Err(VfsError::InvalidInput);
+    }
+    match DEVICES.lock().entry(name.into()) {
+        Entry::Occupied(_) => Err(VfsError::AlreadyExists),
+        Entry::Vacant(entry) => {
+            entry.insert(node);
+            Ok(())
+        }
+    }
+}
+
+/// Removes the device node registered as `name`.
+pub fn unregister(name: &str) -> VfsResult {
+    match DEVICES.lock().remove(name) {
+        Some(_) => Ok(()),
+        None => Err(VfsError::NotFound),
+    }
+}
+
+/// Registers the standard device nodes, and a node for each registered disk
+/// and partition, named as `vda`, `vda1`, `vdb` and so on.
+pub(crate) fn init() {
+    let console = Arc::new(ConsoleDev);
+    let random = Arc::new(RandomDev);
+    let mut devices = DEVICES.lock();
+    devices.insert("null".into(), Arc::new(NullDev));
+    devices.insert("zero".into(), Arc::new(ZeroDev));
+    devices.insert("full".into(), Arc::new(FullDev));
+    devices.insert("random".into(), random.clone());
+    devices.insert("urandom".into(), random);
+    devices.insert("console".into(), console.clone());
+    devices.insert("ttyS0".into(), console);
+
+    for (name, disk) in all_disks() {
+        devices.insert(name, BlockDev::new(disk));
+    }
+
+    #[cfg(feature = "axdisplay")]
+    if axdriver::devices()
+        .iter()
+        .any(|dev| dev.device_type == axdriver::prelude::DeviceType::Display)
+    {
+        devices.insert("fb0".into(), Arc::new(FbDev));
+    }
+}
+
+fn char_dev_attr(size: u64) -> VfsNodeAttr {
+    VfsNodeAttr::new(
+        VfsNodePerm::default_file(),
+        VfsNodeType::CharDevice,
+        size,
+        0,
+    )
+}
+
+/// A device that reads as zeros and is always full when written, i.e.
+/// `/dev/full`.
+pub struct FullDev;
+
+impl VfsNodeOps for FullDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(char_dev_attr(0))
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::StorageFull)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// A device that reads as pseudo-random bytes, i.e. `/dev/random` and
+/// `/dev/urandom`. Writes are discarded.
+///
+/// **It is not cryptographically secure.** The bytes come from
+/// [`axhal::misc::random`], a Lehmer generator seeded from the timer ticks,
+/// so they are predictable and must not be used for keys or nonces.
+pub struct RandomDev;
+
+impl VfsNodeOps for RandomDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(char_dev_attr(0))
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        for chunk in buf.chunks_mut(16) {
+            let bytes = axhal::misc::random().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+        Ok(buf.len())
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// The console on the UART, i.e. `/dev/console` and `/dev/ttyS0`.
+///
+/// Reads wait until some input is available.
+pub struct ConsoleDev;
+
+impl VfsNodeOps for ConsoleDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(char_dev_attr(0))
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let mut read_len = 0;
+            while read_len < buf.len() {
+                match axhal::console::getchar() {
+                    Some(c) => buf[read_len] = if c == b'\r' { b'\n' } else { c },
+                    None => break,
+                }
+                read_len += 1;
+            }
+            if read_len > 0 {
+                return Ok(read_len);
+            }
+            #[cfg(feature = "axtask")]
+            axtask::yield_now();
+            #[cfg(not(feature = "axtask"))]
+            core::hint::spin_loop();
+        }
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        axhal::console::write_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// A disk or a partition of it, accessed through the block cache.
+pub struct BlockDev {
+    disk: Mutex<Disk>,
+    size: u64,
+}
+
+impl BlockDev {
+    /// Creates a device for the disk.
+    pub fn new(disk: Disk) -> Arc<Self> {
+        Arc::new(Self {
+            size: disk.size(),
+            disk: Mutex::new(disk),
+        })
+    }
+}
+
+impl VfsNodeOps for BlockDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::BlockDevice,
+            self.size,
+            self.size / 512,
+        ))
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.disk.lock().sync().map_err(|_| VfsError::Io)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(()) // the size is fixed
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let len = self.size.saturating_sub(offset).min(buf.len() as u64) as usize;
+        self.disk
+            .lock()
+            .read_exact_at(offset, &mut buf[..len])
+            .map_err(|_| VfsError::Io)?;
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let len = self.size.saturating_sub(offset).min(buf.len() as u64) as usize;
+        if len == 0 && !buf.is_empty() {
+            return Err(VfsError::StorageFull);
+        }
+        self.disk
+            .lock()
+            .write_all_at(offset, &buf[..len])
+            .map_err(|_| VfsError::Io)?;
+        Ok(len)
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// The framebuffer of the main display, i.e. `/dev/fb0`. Writes are shown
+/// on the screen immediately.
+#[cfg(feature = "axdisplay")]
+pub struct FbDev;
+
+#[cfg(feature = "axdisplay")]
+impl FbDev {
+    fn framebuffer() -> &'static mut [u8] {
+        let info = axdisplay::framebuffer_info();
+        // SAFETY: the framebuffer is mapped for the lifetime of the kernel.
+        unsafe { core::slice::from_raw_parts_mut(info.fb_base_vaddr as *mut u8, info.fb_size) }
+    }
+}
+
+#[cfg(feature = "axdisplay")]
+impl VfsNodeOps for FbDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(char_dev_attr(axdisplay::framebuffer_info().fb_size as u64))
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let fb = Self::framebuffer();
+        let start = fb.len().min(offset as usize);
+        let len = buf.len().min(fb.len() - start);
+        buf[..len].copy_from_slice(&fb[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let fb = Self::framebuffer();
+        let start = fb.len().min(offset as usize);
+        let len = buf.len().min(fb.len() - start);
+        if len == 0 && !buf.is_empty() {
+            return Err(VfsError::StorageFull);
+        }
+        fb[start..start + len].copy_from_slice(&buf[..len]);
+        axdisplay::framebuffer_flush();
+        Ok(len)
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
@@ -0,0 +1,474 @@
+//! On-disk structures of the ext2/3/4 filesystems.
+//!
+//! All structures are kept as raw little-endian bytes, so that the fields not
+//! known here are preserved when they are written back.
+
+use alloc::vec::Vec;
+
+use axfs_vfs::VfsNodeType;
+
+pub const SUPERBLOCK_OFFSET: u64 = 1024;
+pub const SUPERBLOCK_SIZE: usize = 1024;
+pub const EXT_MAGIC: u16 = 0xef53;
+
+pub const ROOT_INO: u32 = 2;
+
+pub const INCOMPAT_FILETYPE: u32 = 0x2;
+pub const INCOMPAT_RECOVER: u32 = 0x4;
+pub const INCOMPAT_EXTENTS: u32 = 0x40;
+pub const INCOMPAT_64BIT: u32 = 0x80;
+pub const INCOMPAT_MMP: u32 = 0x100;
+pub const INCOMPAT_FLEX_BG: u32 = 0x200;
+pub const INCOMPAT_CSUM_SEED: u32 = 0x2000;
+pub const INCOMPAT_LARGEDIR: u32 = 0x4000;
+
+pub const RO_COMPAT_SPARSE_SUPER: u32 = 0x1;
+pub const RO_COMPAT_LARGE_FILE: u32 = 0x2;
+pub const RO_COMPAT_HUGE_FILE: u32 = 0x8;
+
+/// The incompatible features that can be read.
+pub const INCOMPAT_READ: u32 = INCOMPAT_FILETYPE
+    | INCOMPAT_RECOVER
+    | INCOMPAT_EXTENTS
+    | INCOMPAT_64BIT
+    | INCOMPAT_MMP
+    | INCOMPAT_FLEX_BG
+    | INCOMPAT_CSUM_SEED
+    | INCOMPAT_LARGEDIR;
+/// The incompatible features that can be written.
+pub const INCOMPAT_WRITE: u32 = INCOMPAT_FILETYPE;
+/// The read-only compatible features that can be written.
+pub const RO_COMPAT_WRITE: u32 = RO_COMPAT_SPARSE_SUPER | RO_COMPAT_LARGE_FILE;
+
+pub const INODE_FLAG_INDEX: u32 = 0x1000;
+pub const INODE_FLAG_HUGE_FILE: u32 = 0x40000;
+pub const INODE_FLAG_EXTENTS: u32 = 0x80000;
+pub const INODE_FLAG_INLINE_DATA: u32 = 0x1000_0000;
+
+pub const EXTENT_MAGIC: u16 = 0xf30a;
+
+/// The number of block pointers in an inode.
+pub const N_BLOCKS: usize = 15;
+/// The number of direct block pointers in an inode.
+pub const N_DIRECT: usize = 12;
+
+const S_IFMT: u16 = 0o170000;
+
+pub fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+pub fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+pub fn write_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn lo_hi(lo: u32, hi: u32) -> u64 {
+    (hi as u64) << 32 | lo as u64
+}
+
+/// The superblock, at byte 1024 of the volume.
+pub struct Superblock {
+    raw: Vec<u8>,
+}
+
+impl Superblock {
+    pub fn new(raw: Vec<u8>) -> Self {
+        Self { raw }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn magic(&self) -> u16 {
+        read_u16(&self.raw, 56)
+    }
+
+    pub fn inodes_count(&self) -> u32 {
+        read_u32(&self.raw, 0)
+    }
+
+    pub fn blocks_count(&self) -> u64 {
+        let hi = if self.is_64bit() {
+            read_u32(&self.raw, 0x150)
+        } else {
+            0
+        };
+        lo_hi(read_u32(&self.raw, 4), hi)
+    }
+
+    pub fn free_blocks_count(&self) -> u64 {
+        let hi = if self.is_64bit() {
+            read_u32(&self.raw, 0x158)
+        } else {
+            0
+        };
+        lo_hi(read_u32(&self.raw, 12), hi)
+    }
+
+    pub fn set_free_blocks_count(&mut self, count: u64) {
+        write_u32(&mut self.raw, 12, count as u32);
+        if self.is_64bit() {
+            write_u32(&mut self.raw, 0x158, (count >> 32) as u32);
+        }
+    }
+
+    pub fn free_inodes_count(&self) -> u32 {
+        read_u32(&self.raw, 16)
+    }
+
+    pub fn set_free_inodes_count(&mut self, count: u32) {
+        write_u32(&mut self.raw, 16, count);
+    }
+
+    pub fn first_data_block(&self) -> u32 {
+        read_u32(&self.raw, 20)
+    }
+
+    pub fn log_block_size(&self) -> u32 {
+        read_u32(&self.raw, 24)
+    }
+
+    pub fn block_size(&self) -> usize {
+        1024 << self.log_block_size()
+    }
+
+    pub fn blocks_per_group(&self) -> u32 {
+        read_u32(&self.raw, 32)
+    }
+
+    pub fn inodes_per_group(&self) -> u32 {
+        read_u32(&self.raw, 40)
+    }
+
+    /// The time of the last write, in seconds since the epoch.
+    pub fn write_time(&self) -> u32 {
+        read_u32(&self.raw, 48)
+    }
+
+    pub fn rev_level(&self) -> u32 {
+        read_u32(&self.raw, 76)
+    }
+
+    /// Returns the first inode number that is not reserved.
+    pub fn first_ino(&self) -> u32 {
+        match self.rev_level() {
+            0 => 11,
+            _ => read_u32(&self.raw, 84),
+        }
+    }
+
+    pub fn inode_size(&self) -> usize {
+        match self.rev_level() {
+            0 => 128,
+            _ => read_u16(&self.raw, 88) as usize,
+        }
+    }
+
+    pub fn feature_incompat(&self) -> u32 {
+        read_u32(&self.raw, 96)
+    }
+
+    pub fn feature_ro_compat(&self) -> u32 {
+        read_u32(&self.raw, 100)
+    }
+
+    pub fn set_feature_ro_compat(&mut self, features: u32) {
+        write_u32(&mut self.raw, 100, features);
+    }
+
+    pub fn is_64bit(&self) -> bool {
+        self.feature_incompat() & INCOMPAT_64BIT != 0
+    }
+
+    /// Returns the size of a group descriptor.
+    pub fn desc_size(&self) -> usize {
+        if self.is_64bit() {
+            read_u16(&self.raw, 0xfe) as usize
+        } else {
+            32
+        }
+    }
+
+    /// Returns the size of the extra fields of new inodes.
+    pub fn want_extra_isize(&self) -> u16 {
+        read_u16(&self.raw, 0x15e)
+    }
+}
+
+/// A block group descriptor.
+pub struct GroupDesc {
+    raw: Vec<u8>,
+}
+
+impl GroupDesc {
+    pub fn new(raw: Vec<u8>) -> Self {
+        Self { raw }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    fn get(&self, lo: usize, hi: usize) -> u64 {
+        let hi = if self.raw.len() >= 64 {
+            read_u32(&self.raw, hi)
+        } else {
+            0
+        };
+        lo_hi(read_u32(&self.raw, lo), hi)
+    }
+
+    fn get16(&self, lo: usize, hi: usize) -> u32 {
+        let hi = if self.raw.len() >= 64 {
+            read_u16(&self.raw, hi)
+        } else {
+            0
+        };
+        (hi as u32) << 16 | read_u16(&self.raw, lo) as u32
+    }
+
+    fn set16(&mut self, lo: usize, hi: usize, value: u32) {
+        write_u16(&mut self.raw, lo, value as u16);
+        if self.raw.len() >= 64 {
+            write_u16(&mut self.raw, hi, (value >> 16) as u16);
+        }
+    }
+
+    pub fn block_bitmap(&self) -> u64 {
+        self.get(0x0, 0x20)
+    }
+
+    pub fn inode_bitmap(&self) -> u64 {
+        self.get(0x4, 0x24)
+    }
+
+    pub fn inode_table(&self) -> u64 {
+        self.get(0x8, 0x28)
+    }
+
+    pub fn free_blocks_count(&self) -> u32 {
+        self.get16(0xc, 0x2c)
+    }
+
+    pub fn set_free_blocks_count(&mut self, count: u32) {
+        self.set16(0xc, 0x2c, count)
+    }
+
+    pub fn free_inodes_count(&self) -> u32 {
+        self.get16(0xe, 0x2e)
+    }
+
+    pub fn set_free_inodes_count(&mut self, count: u32) {
+        self.set16(0xe, 0x2e, count)
+    }
+
+    pub fn used_dirs_count(&self) -> u32 {
+        self.get16(0x10, 0x30)
+    }
+
+    pub fn set_used_dirs_count(&mut self, count: u32) {
+        self.set16(0x10, 0x30, count)
+    }
+}
+
+/// An inode.
+#[derive(Clone)]
+pub struct Inode {
+    raw: Vec<u8>,
+}
+
+impl Inode {
+    pub fn new(raw: Vec<u8>) -> Self {
+        Self { raw }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn mode(&self) -> u16 {
+        read_u16(&self.raw, 0)
+    }
+
+    pub fn set_mode(&mut self, mode: u16) {
+        write_u16(&mut self.raw, 0, mode)
+    }
+
+    pub fn node_type(&self) -> VfsNodeType {
+        match self.mode() & S_IFMT {
+            0o010000 => VfsNodeType::Fifo,
+            0o020000 => VfsNodeType::CharDevice,
+            0o040000 => VfsNodeType::Dir,
+            0o060000 => VfsNodeType::BlockDevice,
+            0o120000 => VfsNodeType::SymLink,
+            0o140000 => VfsNodeType::Socket,
+            _ => VfsNodeType::File,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.node_type() == VfsNodeType::Dir
+    }
+
+    pub fn size(&self) -> u64 {
+        lo_hi(read_u32(&self.raw, 4), read_u32(&self.raw, 108))
+    }
+
+    pub fn set_size(&mut self, size: u64) {
+        write_u32(&mut self.raw, 4, size as u32);
+        write_u32(&mut self.raw, 108, (size >> 32) as u32);
+    }
+
+    pub fn set_dtime(&mut self, time: u32) {
+        write_u32(&mut self.raw, 20, time)
+    }
+
+    pub fn links_count(&self) -> u16 {
+        read_u16(&self.raw, 26)
+    }
+
+    pub fn set_links_count(&mut self, count: u16) {
+        write_u16(&mut self.raw, 26, count)
+    }
+
+    /// Returns the number of 512-byte sectors used, including metadata blocks.
+    pub fn sectors(&self, huge_file: bool, block_size: usize) -> u64 {
+        if !huge_file {
+            return read_u32(&self.raw, 28) as u64;
+        }
+        let count = lo_hi(read_u32(&self.raw, 28), read_u16(&self.raw, 116) as u32);
+        if self.flags() & INODE_FLAG_HUGE_FILE != 0 {
+            // Counted in filesystem blocks instead.
+            count * (block_size / 512) as u64
+        } else {
+            count
+        }
+    }
+
+    /// Sets the number of 512-byte sectors used. Only for file systems
+    /// without the `huge_file` feature.
+    pub fn set_sectors(&mut self, count: u64) {
+        write_u32(&mut self.raw, 28, count as u32)
+    }
+
+    pub fn flags(&self) -> u32 {
+        read_u32(&self.raw, 32)
+    }
+
+    pub fn set_flags(&mut self, flags: u32) {
+        write_u32(&mut self.raw, 32, flags)
+    }
+
+    /// Returns the 60 bytes of block pointers, or the extent tree root, or
+    /// the target of a fast symbolic link.
+    pub fn block_area(&self) -> &[u8] {
+        &self.raw[40..40 + N_BLOCKS * 4]
+    }
+
+    pub fn block_area_mut(&mut self) -> &mut [u8] {
+        &mut self.raw[40..40 + N_BLOCKS * 4]
+    }
+
+    pub fn block(&self, idx: usize) -> u32 {
+        read_u32(&self.raw, 40 + idx * 4)
+    }
+
+    pub fn set_block(&mut self, idx: usize, block: u32) {
+        write_u32(&mut self.raw, 40 + idx * 4, block)
+    }
+
+    pub fn set_extra_isize(&mut self, size: u16) {
+        if self.raw.len() > 128 {
+            write_u16(&mut self.raw, 128, size)
+        }
+    }
+}
+
+/// Returns the type code of directory entries for the node type.
+pub fn dirent_type(ty: VfsNodeType) -> u8 {
+    match ty {
+        VfsNodeType::File => 1,
+        VfsNodeType::Dir => 2,
+        VfsNodeType::CharDevice => 3,
+        VfsNodeType::BlockDevice => 4,
+        VfsNodeType::Fifo => 5,
+        VfsNodeType::Socket => 6,
+        VfsNodeType::SymLink => 7,
+    }
+}
+
+/// Returns the mode bits of the node type.
+pub fn type_mode(ty: VfsNodeType) -> u16 {
+    (ty as u16) << 12
+}
+
+/// A directory entry in a directory block.
+pub struct DirEntry<'a> {
+    pub inode: u32,
+    pub rec_len: usize,
+    pub name: &'a [u8],
+}
+
+/// The size of the fixed part of a directory entry.
+pub const DIRENT_HEADER_SIZE: usize = 8;
+
+/// Returns the minimal record length of a directory entry with the name.
+pub fn dirent_len(name_len: usize) -> usize {
+    (DIRENT_HEADER_SIZE + name_len).next_multiple_of(4)
+}
+
+/// Parses the directory entry at `offset` of a directory block.
+pub fn parse_dirent(block: &[u8], offset: usize, has_filetype: bool) -> Option<DirEntry<'_>> {
+    if offset + DIRENT_HEADER_SIZE > block.len() {
+        return None;
+    }
+    let rec_len = read_u16(block, offset + 4) as usize;
+    let name_len = if has_filetype {
+        block[offset + 6] as usize
+    } else {
+        read_u16(block, offset + 6) as usize
+    };
+    if rec_len < DIRENT_HEADER_SIZE
+        || offset + rec_len > block.len()
+        || DIRENT_HEADER_SIZE + name_len > rec_len
+    {
+        return None;
+    }
+    let name_start = offset + DIRENT_HEADER_SIZE;
+    Some(DirEntry {
+        inode: read_u32(block, offset),
+        rec_len,
+        name: &block[name_start..name_start + name_len],
+    })
+}
+
+/// Writes a directory entry at `offset` of a directory block.
+pub fn write_dirent(
+    block: &mut [u8],
+    offset: usize,
+    inode: u32,
+    rec_len: usize,
+    name: &[u8],
+    file_type: Option<u8>,
+) {
+    write_u32(block, offset, inode);
+    write_u16(block, offset + 4, rec_len as u16);
+    match file_type {
+        Some(ty) => {
+            block[offset + 6] = name.len() as u8;
+            block[offset + 7] = ty;
+        }
+        None => write_u16(block, offset + 6, name.len() as u16),
+    }
+    let name_start = offset + DIRENT_HEADER_SIZE;
+    block[name_start..name_start + name.len()].copy_from_slice(name);
+}
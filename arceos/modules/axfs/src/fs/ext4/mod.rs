@@ -0,0 +1,281 @@
+//! The ext2/3/4 filesystems.
+//!
+//! Volumes with only the ext2 features (plus `sparse_super`, `large_file`,
+//! and an unused ext3 journal) are readable and writable. Volumes with ext4
+//! features, such as extents, 64-bit block numbers, flexible block groups or
+//! metadata checksums, are mounted read-only.
+
+mod layout;
+mod volume;
+
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
+use axsync::Mutex;
+
+use self::layout::ROOT_INO;
+use self::volume::Volume;
+use crate::dev::Disk;
+
+/// All opened filesystems, to be synced together.
+static FILESYSTEMS: Mutex<Vec<Weak<ExtFs>>> = Mutex::new(Vec::new());
+
+/// The state shared by the filesystem and its nodes.
+struct ExtFs {
+    volume: Mutex<Volume>,
+    /// The node in the parent filesystem that this one is mounted on.
+    mount_point: Mutex<Option<VfsNodeRef>>,
+}
+
+/// An ext2/3/4 filesystem on a disk.
+pub struct Ext4FileSystem {
+    fs: Arc<ExtFs>,
+}
+
+/// A file, directory or other node of an ext filesystem.
+pub struct ExtNode {
+    fs: Arc<ExtFs>,
+    ino: u32,
+}
+
+impl Ext4FileSystem {
+    /// Returns whether the disk holds an ext filesystem, by the magic number
+    /// in its superblock.
+    pub fn probe(disk: &mut Disk) -> bool {
+        Volume::probe(disk)
+    }
+
+    /// Opens the ext filesystem on the disk.
+    pub fn new(disk: Disk) -> VfsResult<Self> {
+        let volume = Volume::open(disk)?;
+        let fs = Arc::new(ExtFs {
+            volume: Mutex::new(volume),
+            mount_point: Mutex::new(None),
+        });
+        let mut filesystems = FILESYSTEMS.lock();
+        filesystems.retain(|fs| fs.strong_count() > 0);
+        filesystems.push(Arc::downgrade(&fs));
+        Ok(Self { fs })
+    }
+}
+
+/// Writes the changed metadata of all opened ext filesystems back to their
+/// disks.
+pub(crate) fn sync_all() -> VfsResult {
+    let filesystems: Vec<_> = FILESYSTEMS
+        .lock()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    for fs in filesystems {
+        fs.volume.lock().sync()?;
+    }
+    Ok(())
+}
+
+impl VfsOps for Ext4FileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        *self.fs.mount_point.lock() = Some(mount_point);
+        Ok(())
+    }
+
+    fn umount(&self) -> VfsResult {
+        self.fs.mount_point.lock().take();
+        self.fs.volume.lock().sync()
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        ExtNode::new(self.fs.clone(), ROOT_INO)
+    }
+}
+
+impl ExtNode {
+    fn new(fs: Arc<ExtFs>, ino: u32) -> Arc<Self> {
+        Arc::new(Self { fs, ino })
+    }
+
+    /// Looks up `name` in this directory, following `..` of
+    /// the root directory to the mount point.
+    fn lookup_one(self: Arc<Self>, name: &str) -> VfsResult<VfsNodeRef> {
+        match name {
+            "" | "." => Ok(self),
+            ".." if self.ino == ROOT_INO => {
+                let mount_point = self.fs.mount_point.lock().clone();
+                match mount_point {
+                    Some(mount_point) => mount_point.parent().ok_or(VfsError::NotFound),
+                    None => Ok(self),
+                }
+            }
+            _ => {
+                let mut volume = self.fs.volume.lock();
+                let dir = volume.read_inode(self.ino)?;
+                if !dir.is_dir() {
+                    return Err(VfsError::NotADirectory);
+                }
+                let ino = volume.lookup(&dir, name)?.ok_or(VfsError::NotFound)?;
+                Ok(Self::new(self.fs.clone(), ino))
+            }
+        }
+    }
+
+    /// Resolves the parent directory of `path`, and returns it with the last
+    /// component of `path`.
+    fn lookup_parent<'a>(self: Arc<Self>, path: &'a str) -> VfsResult<(VfsNodeRef, &'a str)> {
+        let path = path.trim_matches('/');
+        let (parent, name) = match path.rsplit_once('/') {
+            Some((parent, name)) => (self.lookup(parent)?, name),
+            None => (self as VfsNodeRef, path),
+        };
+        Ok((parent, name))
+    }
+
+    /// Adds a hard link of `name` in this directory to `node`, which must be
+    /// in the same filesystem.
+    pub(crate) fn link(&self, name: &str, node: &VfsNodeRef) -> VfsResult {
+        let ino = self.same_fs_ino(node).map_err(|_| VfsError::Unsupported)?;
+        self.fs.volume.lock().link(self.ino, name, ino)
+    }
+
+    /// Returns the filesystem and the inode number of this node, which
+    /// identify the file among the nodes created at each lookup.
+    pub(crate) fn inode_id(&self) -> (usize, u64) {
+        (Arc::as_ptr(&self.fs) as usize, self.ino as u64)
+    }
+
+    /// Returns the inode number of a node of the same filesystem.
+    fn same_fs_ino(&self, node: &VfsNodeRef) -> VfsResult<u32> {
+        match node.as_any().downcast_ref::<Self>() {
+            Some(node) if Arc::ptr_eq(&node.fs, &self.fs) => Ok(node.ino),
+            _ => Err(VfsError::InvalidInput),
+        }
+    }
+}
+
+impl VfsNodeOps for ExtNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut volume = self.fs.volume.lock();
+        let inode = volume.read_inode(self.ino)?;
+        let mut perm = VfsNodePerm::from_bits_truncate(inode.mode() & 0o777);
+        if !volume.is_writable() {
+            perm.remove(
+                VfsNodePerm::OWNER_WRITE | VfsNodePerm::GROUP_WRITE | VfsNodePerm::OTHER_WRITE,
+            );
+        }
+        Ok(VfsNodeAttr::new(
+            perm,
+            inode.node_type(),
+            inode.size(),
+            volume.inode_sectors(&inode),
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let mut volume = self.fs.volume.lock();
+        let inode = volume.read_inode(self.ino)?;
+        if inode.is_dir() {
+            return Err(VfsError::IsADirectory);
+        }
+        volume.read_data(&inode, offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let mut volume = self.fs.volume.lock();
+        if volume.read_inode(self.ino)?.is_dir() {
+            return Err(VfsError::IsADirectory);
+        }
+        volume.write_data(self.ino, offset, buf)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.fs.volume.lock().sync()
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        let mut volume = self.fs.volume.lock();
+        if volume.read_inode(self.ino)?.is_dir() {
+            return Err(VfsError::IsADirectory);
+        }
+        volume.truncate(self.ino, size)
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        Self::new(self.fs.clone(), self.ino).lookup_one("..").ok()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        debug!("lookup at ext4: {}", path);
+        let path = path.trim_matches('/');
+        let (name, rest) = match path.split_once('/') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (path, None),
+        };
+        let node = self.lookup_one(name)?;
+        match rest {
+            Some(rest) => node.lookup(rest),
+            None => Ok(node),
+        }
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {:?} at ext4: {}", ty, path);
+        let this = Self::new(self.fs.clone(), self.ino);
+        let (parent, name) = this.lookup_parent(path)?;
+        if name.is_empty() || name == "." || name == ".." {
+            return Ok(());
+        }
+        let dir_ino = self.same_fs_ino(&parent)?;
+        let mut volume = self.fs.volume.lock();
+        let dir = volume.read_inode(dir_ino)?;
+        if !dir.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        if volume.lookup(&dir, name)?.is_some() {
+            return Ok(()); // already exists
+        }
+        volume.create(dir_ino, name, ty).map(|_| ())
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        debug!("remove at ext4: {}", path);
+        let this = Self::new(self.fs.clone(), self.ino);
+        let (parent, name) = this.lookup_parent(path)?;
+        let dir_ino = self.same_fs_ino(&parent)?;
+        self.fs.volume.lock().unlink(dir_ino, name)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let mut volume = self.fs.volume.lock();
+        let dir = volume.read_inode(self.ino)?;
+        if !dir.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        let mut entries = Vec::new();
+        volume.list(&dir, start_idx, dirents.len(), |name, ino, ty| {
+            entries.push((String::from_utf8_lossy(name).into_owned(), ino, ty));
+        })?;
+        for ((name, ino, ty), out) in entries.iter().zip(dirents.iter_mut()) {
+            *out = VfsDirEntry::new(name, volume.entry_type(*ino, *ty)?);
+        }
+        Ok(entries.len())
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("rename at ext4: {} -> {}", src_path, dst_path);
+        let this = Self::new(self.fs.clone(), self.ino);
+        let (src_dir, src_name) = this.clone().lookup_parent(src_path)?;
+        let (dst_dir, dst_name) = this.lookup_parent(dst_path)?;
+        let src_dir = self.same_fs_ino(&src_dir)?;
+        let dst_dir = self.same_fs_ino(&dst_dir)?;
+        self.fs
+            .volume
+            .lock()
+            .rename(src_dir, src_name, dst_dir, dst_name)
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
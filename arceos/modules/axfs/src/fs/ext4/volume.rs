@@ -0,0 +1,1123 @@
+//! Block, inode and directory management of an ext volume.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::{vec, vec::Vec};
+
+use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+
+use super::layout::*;
+use crate::dev::Disk;
+
+/// The maximum number of changed bitmaps kept before writing them back.
+const MAX_DIRTY_BITMAPS: usize = 16;
+
+/// An opened ext volume with its superblock and group descriptors.
+///
+/// The allocation bitmaps, group descriptors and superblock changed by the
+/// allocations are kept in memory, and written back by [`Volume::sync`] or
+/// when the volume is dropped.
+pub struct Volume {
+    disk: Disk,
+    sb: Superblock,
+    groups: Vec<GroupDesc>,
+    /// The block of the first group descriptor.
+    gdt_block: u64,
+    block_size: usize,
+    writable: bool,
+    /// The changed bitmaps, by their blocks.
+    bitmaps: BTreeMap<u64, Vec<u8>>,
+    /// The changed group descriptors.
+    dirty_groups: BTreeSet<usize>,
+    sb_dirty: bool,
+}
+
+impl Volume {
+    /// Returns whether the disk holds an ext volume.
+    pub fn probe(disk: &mut Disk) -> bool {
+        let mut magic = [0; 2];
+        read_bytes(disk, SUPERBLOCK_OFFSET + 56, &mut magic).is_ok()
+            && u16::from_le_bytes(magic) == EXT_MAGIC
+    }
+
+    pub fn open(mut disk: Disk) -> VfsResult<Self> {
+        let mut raw = vec![0; SUPERBLOCK_SIZE];
+        read_bytes(&mut disk, SUPERBLOCK_OFFSET, &mut raw)?;
+        let sb = Superblock::new(raw);
+        if sb.magic() != EXT_MAGIC {
+            return Err(VfsError::InvalidData);
+        }
+        let incompat = sb.feature_incompat();
+        if incompat & !INCOMPAT_READ != 0 {
+            warn!("unsupported ext features: {:#x}", incompat & !INCOMPAT_READ);
+            return Err(VfsError::Unsupported);
+        }
+        let ro_compat = sb.feature_ro_compat();
+        let writable = incompat & !INCOMPAT_WRITE == 0 && ro_compat & !RO_COMPAT_WRITE == 0;
+        if incompat & INCOMPAT_RECOVER != 0 {
+            warn!("ext journal needs recovery, the data may be inconsistent");
+        }
+        if !writable {
+            info!("ext volume is read-only due to its features");
+        }
+
+        let num_groups = check_layout(&sb, disk.size())?;
+        let block_size = sb.block_size();
+        let desc_size = sb.desc_size();
+        let gdt_block = sb.first_data_block() as u64 + 1;
+        let mut gdt = vec![0; num_groups * desc_size];
+        read_bytes(&mut disk, gdt_block * block_size as u64, &mut gdt)?;
+        let groups = gdt
+            .chunks_exact(desc_size)
+            .map(|raw| GroupDesc::new(raw.to_vec()))
+            .collect();
+        Ok(Self {
+            disk,
+            sb,
+            groups,
+            gdt_block,
+            block_size,
+            writable,
+            bitmaps: BTreeMap::new(),
+            dirty_groups: BTreeSet::new(),
+            sb_dirty: false,
+        })
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    fn has_filetype(&self) -> bool {
+        self.sb.feature_incompat() & INCOMPAT_FILETYPE != 0
+    }
+
+    fn check_writable(&self) -> VfsResult {
+        if self.writable {
+            Ok(())
+        } else {
+            Err(VfsError::PermissionDenied)
+        }
+    }
+
+    /// Writes the changed metadata and the cached blocks back to the disk.
+    pub fn sync(&mut self) -> VfsResult {
+        self.write_metadata()?;
+        self.disk.sync().map_err(|_| VfsError::Io)
+    }
+
+    /// Writes the changed bitmaps, group descriptors and superblock to the
+    /// disk cache.
+    fn write_metadata(&mut self) -> VfsResult {
+        while let Some((block, bitmap)) = self.bitmaps.pop_first() {
+            self.write_block(block, &bitmap)?;
+        }
+        while let Some(group) = self.dirty_groups.pop_first() {
+            self.write_group(group)?;
+        }
+        if self.sb_dirty {
+            self.write_superblock()?;
+            self.sb_dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Returns the bitmap in the block, which is kept in memory as changed
+    /// until the metadata is written back.
+    fn bitmap(&mut self, block: u64) -> VfsResult<&mut Vec<u8>> {
+        if !self.bitmaps.contains_key(&block) {
+            if self.bitmaps.len() >= MAX_DIRTY_BITMAPS {
+                self.write_metadata()?;
+            }
+            let bitmap = self.read_block(block)?;
+            self.bitmaps.insert(block, bitmap);
+        }
+        Ok(self.bitmaps.get_mut(&block).unwrap())
+    }
+
+    /// Marks the group descriptor and the superblock as changed.
+    fn mark_dirty(&mut self, group: usize) {
+        self.dirty_groups.insert(group);
+        self.sb_dirty = true;
+    }
+
+    pub fn read_block(&mut self, block: u64) -> VfsResult<Vec<u8>> {
+        let mut buf = vec![0; self.block_size];
+        read_bytes(&mut self.disk, block * self.block_size as u64, &mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn write_block(&mut self, block: u64, buf: &[u8]) -> VfsResult {
+        debug_assert_eq!(buf.len(), self.block_size);
+        write_bytes(&mut self.disk, block * self.block_size as u64, buf)
+    }
+
+    fn write_superblock(&mut self) -> VfsResult {
+        write_bytes(&mut self.disk, SUPERBLOCK_OFFSET, self.sb.as_bytes())
+    }
+
+    fn write_group(&mut self, group: usize) -> VfsResult {
+        let desc_size = self.sb.desc_size();
+        let pos = self.gdt_block * self.block_size as u64 + (group * desc_size) as u64;
+        write_bytes(&mut self.disk, pos, self.groups[group].as_bytes())
+    }
+
+    /// Returns the byte position of the inode on the disk.
+    fn inode_pos(&self, ino: u32) -> VfsResult<u64> {
+        if ino == 0 || ino > self.sb.inodes_count() {
+            return Err(VfsError::InvalidData);
+        }
+        let ipg = self.sb.inodes_per_group();
+        let group = ((ino - 1) / ipg) as usize;
+        let index = ((ino - 1) % ipg) as u64;
+        let table = self.groups[group].inode_table();
+        Ok(table * self.block_size as u64 + index * self.sb.inode_size() as u64)
+    }
+
+    pub fn read_inode(&mut self, ino: u32) -> VfsResult<Inode> {
+        let mut raw = vec![0; self.sb.inode_size()];
+        let pos = self.inode_pos(ino)?;
+        read_bytes(&mut self.disk, pos, &mut raw)?;
+        Ok(Inode::new(raw))
+    }
+
+    pub fn write_inode(&mut self, ino: u32, inode: &Inode) -> VfsResult {
+        let pos = self.inode_pos(ino)?;
+        write_bytes(&mut self.disk, pos, inode.as_bytes())
+    }
+
+    /// Returns the number of 512-byte sectors used by the inode.
+    pub fn inode_sectors(&self, inode: &Inode) -> u64 {
+        let huge_file = self.sb.feature_ro_compat() & RO_COMPAT_HUGE_FILE != 0;
+        inode.sectors(huge_file, self.block_size)
+    }
+
+    /// Returns the number of block pointers in an indirect block.
+    fn ptrs_per_block(&self) -> u64 {
+        self.block_size as u64 / 4
+    }
+
+    /// Returns the maximum file size with indirect block mapping.
+    fn max_indirect_size(&self) -> u64 {
+        let per = self.ptrs_per_block();
+        let blocks = N_DIRECT as u64 + per + per * per + per * per * per;
+        blocks.saturating_mul(self.block_size as u64)
+    }
+
+    /// Maps a logical block of the inode to a disk block, or `None` if it is
+    /// a hole.
+    pub fn map_block(&mut self, inode: &Inode, lblock: u64) -> VfsResult<Option<u64>> {
+        if inode.flags() & INODE_FLAG_EXTENTS != 0 {
+            return self.map_extent(inode.block_area(), lblock);
+        }
+        let Some((slot, path)) = self.indirect_path(lblock) else {
+            return Ok(None);
+        };
+        let mut block = inode.block(slot) as u64;
+        for idx in path {
+            if block == 0 {
+                break;
+            }
+            block = read_u32(&self.read_block(block)?, idx * 4) as u64;
+        }
+        Ok((block != 0).then_some(block))
+    }
+
+    fn map_extent(&mut self, root: &[u8], lblock: u64) -> VfsResult<Option<u64>> {
+        let lblock = u32::try_from(lblock).map_err(|_| VfsError::InvalidInput)?;
+        let mut node = root.to_vec();
+        loop {
+            if read_u16(&node, 0) != EXTENT_MAGIC {
+                return Err(VfsError::InvalidData);
+            }
+            let entries = read_u16(&node, 2) as usize;
+            let depth = read_u16(&node, 6);
+            let entry = |i: usize| &node[12 + i * 12..24 + i * 12];
+            if entries * 12 + 12 > node.len() {
+                return Err(VfsError::InvalidData);
+            }
+            if depth == 0 {
+                for e in (0..entries).map(entry) {
+                    let start = read_u32(e, 0);
+                    let mut len = read_u16(e, 4) as u32;
+                    let uninit = len > 32768;
+                    if uninit {
+                        len -= 32768;
+                    }
+                    if lblock >= start && lblock - start < len {
+                        if uninit {
+                            return Ok(None); // reads as zeros
+                        }
+                        let block = (read_u16(e, 6) as u64) << 32 | read_u32(e, 8) as u64;
+                        return Ok(Some(block + (lblock - start) as u64));
+                    }
+                }
+                return Ok(None);
+            }
+            let child = (0..entries)
+                .map(entry)
+                .take_while(|e| read_u32(e, 0) <= lblock)
+                .last()
+                .map(|e| (read_u16(e, 8) as u64) << 32 | read_u32(e, 4) as u64);
+            match child {
+                Some(block) => node = self.read_block(block)?,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Returns the slot in the inode and the indices in the indirect blocks
+    /// to reach the logical block, or `None` if it is out of range.
+    fn indirect_path(&self, lblock: u64) -> Option<(usize, Vec<usize>)> {
+        let per = self.ptrs_per_block();
+        if lblock < N_DIRECT as u64 {
+            return Some((lblock as usize, Vec::new()));
+        }
+        let mut rest = lblock - N_DIRECT as u64;
+        let mut span = 1;
+        for depth in 1..=3 {
+            span *= per;
+            if rest < span {
+                let mut path = Vec::with_capacity(depth);
+                let mut sub = span;
+                for _ in 0..depth {
+                    sub /= per;
+                    path.push((rest / sub % per) as usize);
+                }
+                return Some((N_DIRECT + depth - 1, path));
+            }
+            rest -= span;
+        }
+        None
+    }
+
+    /// Maps a logical block of the inode to a disk block, allocating it and
+    /// the indirect blocks on the way if missing.
+    fn map_block_alloc(&mut self, ino: u32, inode: &mut Inode, lblock: u64) -> VfsResult<u64> {
+        let (slot, path) = self.indirect_path(lblock).ok_or(VfsError::InvalidInput)?;
+        let goal = self.inode_group(ino);
+        let mut block = inode.block(slot) as u64;
+        if block == 0 {
+            block = self.alloc_block(goal, inode)?;
+            inode.set_block(slot, block as u32);
+        }
+        for idx in path {
+            let mut buf = self.read_block(block)?;
+            let next = read_u32(&buf, idx * 4) as u64;
+            block = if next == 0 {
+                let new = self.alloc_block(goal, inode)?;
+                write_u32(&mut buf, idx * 4, new as u32);
+                self.write_block(block, &buf)?;
+                new
+            } else {
+                next
+            };
+        }
+        Ok(block)
+    }
+
+    fn inode_group(&self, ino: u32) -> usize {
+        ((ino - 1) / self.sb.inodes_per_group()) as usize
+    }
+
+    /// Allocates a zeroed block, preferably in the group `goal`, and accounts
+    /// it to the inode.
+    fn alloc_block(&mut self, goal: usize, inode: &mut Inode) -> VfsResult<u64> {
+        let bpg = self.sb.blocks_per_group() as u64;
+        let first = self.sb.first_data_block() as u64;
+        let num_groups = self.groups.len();
+        for group in (0..num_groups).map(|i| (goal + i) % num_groups) {
+            if self.groups[group].free_blocks_count() == 0 {
+                continue;
+            }
+            let group_start = first + group as u64 * bpg;
+            let count = bpg.min(self.sb.blocks_count() - group_start) as usize;
+            let bitmap_block = self.groups[group].block_bitmap();
+            let bitmap = self.bitmap(bitmap_block)?;
+            let Some(bit) = find_zero_bit(bitmap, count) else {
+                continue;
+            };
+            bitmap[bit / 8] |= 1 << (bit % 8);
+
+            let desc = &mut self.groups[group];
+            desc.set_free_blocks_count(desc.free_blocks_count() - 1);
+            let free = self.sb.free_blocks_count();
+            self.sb.set_free_blocks_count(free - 1);
+            self.mark_dirty(group);
+
+            let block = group_start + bit as u64;
+            self.write_block(block, &vec![0; self.block_size])?;
+            inode.set_sectors(inode.sectors(false, 0) + (self.block_size / 512) as u64);
+            return Ok(block);
+        }
+        Err(VfsError::StorageFull)
+    }
+
+    /// Frees a block accounted to the inode.
+    fn free_block(&mut self, block: u64, inode: &mut Inode) -> VfsResult {
+        let bpg = self.sb.blocks_per_group() as u64;
+        let rel = block
+            .checked_sub(self.sb.first_data_block() as u64)
+            .ok_or(VfsError::InvalidData)?;
+        let group = (rel / bpg) as usize;
+        let bit = (rel % bpg) as usize;
+        let bitmap_block = self
+            .groups
+            .get(group)
+            .ok_or(VfsError::InvalidData)?
+            .block_bitmap();
+        let bitmap = self.bitmap(bitmap_block)?;
+        if bitmap[bit / 8] & (1 << (bit % 8)) == 0 {
+            warn!("ext: freeing free block {}", block);
+            return Err(VfsError::InvalidData);
+        }
+        bitmap[bit / 8] &= !(1 << (bit % 8));
+
+        let desc = &mut self.groups[group];
+        desc.set_free_blocks_count(desc.free_blocks_count() + 1);
+        let free = self.sb.free_blocks_count();
+        self.sb.set_free_blocks_count(free + 1);
+        self.mark_dirty(group);
+
+        let sectors = inode.sectors(false, 0);
+        inode.set_sectors(sectors.saturating_sub((self.block_size / 512) as u64));
+        Ok(())
+    }
+
+    /// Allocates an inode, preferably in the group `goal`.
+    fn alloc_inode(&mut self, goal: usize, is_dir: bool) -> VfsResult<u32> {
+        let ipg = self.sb.inodes_per_group();
+        let num_groups = self.groups.len();
+        for group in (0..num_groups).map(|i| (goal + i) % num_groups) {
+            if self.groups[group].free_inodes_count() == 0 {
+                continue;
+            }
+            let bitmap_block = self.groups[group].inode_bitmap();
+            // Skip the reserved inodes.
+            let first = self.sb.first_ino().saturating_sub(group as u32 * ipg + 1);
+            let bitmap = self.bitmap(bitmap_block)?;
+            let Some(bit) =
+                (first as usize..ipg as usize).find(|&i| bitmap[i / 8] & (1 << (i % 8)) == 0)
+            else {
+                continue;
+            };
+            bitmap[bit / 8] |= 1 << (bit % 8);
+
+            let desc = &mut self.groups[group];
+            desc.set_free_inodes_count(desc.free_inodes_count() - 1);
+            if is_dir {
+                desc.set_used_dirs_count(desc.used_dirs_count() + 1);
+            }
+            let free = self.sb.free_inodes_count();
+            self.sb.set_free_inodes_count(free - 1);
+            self.mark_dirty(group);
+            return Ok(group as u32 * ipg + bit as u32 + 1);
+        }
+        Err(VfsError::StorageFull)
+    }
+
+    fn free_inode(&mut self, ino: u32, is_dir: bool) -> VfsResult {
+        let ipg = self.sb.inodes_per_group();
+        let group = self.inode_group(ino);
+        let bit = ((ino - 1) % ipg) as usize;
+        let bitmap_block = self.groups[group].inode_bitmap();
+        let bitmap = self.bitmap(bitmap_block)?;
+        bitmap[bit / 8] &= !(1 << (bit % 8));
+
+        let desc = &mut self.groups[group];
+        desc.set_free_inodes_count(desc.free_inodes_count() + 1);
+        if is_dir {
+            desc.set_used_dirs_count(desc.used_dirs_count().saturating_sub(1));
+        }
+        let free = self.sb.free_inodes_count();
+        self.sb.set_free_inodes_count(free + 1);
+        self.mark_dirty(group);
+        Ok(())
+    }
+
+    /// Reads the data of the inode at `offset`.
+    pub fn read_data(&mut self, inode: &Inode, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if inode.flags() & INODE_FLAG_INLINE_DATA != 0 {
+            return Err(VfsError::Unsupported);
+        }
+        let size = inode.size();
+        if inode.node_type() == VfsNodeType::SymLink && size < 60 && self.inode_sectors(inode) == 0
+        {
+            // A fast symbolic link, stored in the block pointers.
+            let target = &inode.block_area()[..size as usize];
+            let start = (offset as usize).min(target.len());
+            let len = buf.len().min(target.len() - start);
+            buf[..len].copy_from_slice(&target[start..start + len]);
+            return Ok(len);
+        }
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+        let bs = self.block_size as u64;
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let in_block = (pos % bs) as usize;
+            let count = (len - done).min(self.block_size - in_block);
+            let dst = &mut buf[done..done + count];
+            match self.map_block(inode, pos / bs)? {
+                Some(block) => {
+                    let data = self.read_block(block)?;
+                    dst.copy_from_slice(&data[in_block..in_block + count]);
+                }
+                None => dst.fill(0),
+            }
+            done += count;
+        }
+        Ok(len)
+    }
+
+    /// Writes the data of the inode at `offset`, and updates the inode.
+    pub fn write_data(&mut self, ino: u32, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.check_writable()?;
+        let mut inode = self.read_inode(ino)?;
+        if inode.node_type() == VfsNodeType::SymLink {
+            // The target of a symbolic link is written once.
+            if offset != 0 || inode.size() != 0 {
+                return Err(VfsError::InvalidInput);
+            } else if buf.len() < 60 {
+                // A fast symbolic link, stored in the block pointers.
+                inode.block_area_mut()[..buf.len()].copy_from_slice(buf);
+                inode.set_size(buf.len() as u64);
+                self.write_inode(ino, &inode)?;
+                return Ok(buf.len());
+            }
+        }
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .filter(|&end| end <= self.max_indirect_size())
+            .ok_or(VfsError::InvalidInput)?;
+        let bs = self.block_size as u64;
+        let mut done = 0;
+        let result = loop {
+            if done == buf.len() {
+                break Ok(());
+            }
+            let pos = offset + done as u64;
+            let in_block = (pos % bs) as usize;
+            let count = (buf.len() - done).min(self.block_size - in_block);
+            let block = match self.map_block_alloc(ino, &mut inode, pos / bs) {
+                Ok(block) => block,
+                Err(e) => break Err(e),
+            };
+            let src = &buf[done..done + count];
+            let res = if count == self.block_size {
+                self.write_block(block, src)
+            } else {
+                self.read_block(block).and_then(|mut data| {
+                    data[in_block..in_block + count].copy_from_slice(src);
+                    self.write_block(block, &data)
+                })
+            };
+            if let Err(e) = res {
+                break Err(e);
+            }
+            done += count;
+        };
+        if offset + done as u64 > inode.size() {
+            inode.set_size(offset + done as u64);
+            if end > i32::MAX as u64 {
+                self.set_large_file()?;
+            }
+        }
+        self.write_inode(ino, &inode)?;
+        match result {
+            Err(e) if done == 0 => Err(e),
+            _ => Ok(done),
+        }
+    }
+
+    fn set_large_file(&mut self) -> VfsResult {
+        let features = self.sb.feature_ro_compat();
+        if features & RO_COMPAT_LARGE_FILE == 0 {
+            self.sb
+                .set_feature_ro_compat(features | RO_COMPAT_LARGE_FILE);
+            self.sb_dirty = true;
+        }
+        Ok(())
+    }
+
+    /// Truncates or extends the data of the inode to `size`.
+    pub fn truncate(&mut self, ino: u32, size: u64) -> VfsResult {
+        self.check_writable()?;
+        if size > self.max_indirect_size() {
+            return Err(VfsError::InvalidInput);
+        }
+        let mut inode = self.read_inode(ino)?;
+        let old_size = inode.size();
+        if size < old_size {
+            let keep = size.div_ceil(self.block_size as u64);
+            self.free_blocks_from(&mut inode, keep)?;
+            // Zero the tail of the last block, which may be read again after
+            // extending the file.
+            let tail = (size % self.block_size as u64) as usize;
+            if tail != 0 {
+                if let Some(block) = self.map_block(&inode, size / self.block_size as u64)? {
+                    let mut data = self.read_block(block)?;
+                    data[tail..].fill(0);
+                    self.write_block(block, &data)?;
+                }
+            }
+        } else if size > i32::MAX as u64 {
+            self.set_large_file()?;
+        }
+        inode.set_size(size);
+        self.write_inode(ino, &inode)
+    }
+
+    /// Frees all blocks of the inode from the logical block `keep`.
+    fn free_blocks_from(&mut self, inode: &mut Inode, keep: u64) -> VfsResult {
+        if inode.flags() & INODE_FLAG_EXTENTS != 0 {
+            return Err(VfsError::Unsupported);
+        }
+        for slot in keep.min(N_DIRECT as u64) as usize..N_DIRECT {
+            let block = inode.block(slot) as u64;
+            if block != 0 {
+                self.free_block(block, inode)?;
+                inode.set_block(slot, 0);
+            }
+        }
+        let per = self.ptrs_per_block();
+        let mut first = N_DIRECT as u64;
+        let mut span = 1;
+        for depth in 1..=3 {
+            span *= per;
+            let slot = N_DIRECT + depth - 1;
+            let block = inode.block(slot) as u64;
+            if block != 0 && first + span > keep && self.prune(block, depth, first, keep, inode)? {
+                inode.set_block(slot, 0);
+            }
+            first += span;
+        }
+        Ok(())
+    }
+
+    /// Frees the blocks from the logical block `keep` in the subtree of the
+    /// indirect block at `depth`, which maps from the logical block `first`.
+    /// Returns whether the whole subtree is freed.
+    fn prune(
+        &mut self,
+        block: u64,
+        depth: usize,
+        first: u64,
+        keep: u64,
+        inode: &mut Inode,
+    ) -> VfsResult<bool> {
+        if depth > 0 {
+            let per = self.ptrs_per_block();
+            let span = per.pow(depth as u32 - 1);
+            let mut buf = self.read_block(block)?;
+            let mut dirty = false;
+            for i in 0..per as usize {
+                let child = read_u32(&buf, i * 4) as u64;
+                let child_first = first + i as u64 * span;
+                if child != 0
+                    && child_first + span > keep
+                    && self.prune(child, depth - 1, child_first, keep, inode)?
+                {
+                    write_u32(&mut buf, i * 4, 0);
+                    dirty = true;
+                }
+            }
+            if first < keep {
+                if dirty {
+                    self.write_block(block, &buf)?;
+                }
+                return Ok(false);
+            }
+        } else if first < keep {
+            return Ok(false);
+        }
+        self.free_block(block, inode)?;
+        Ok(true)
+    }
+
+    /// Iterates over the entries of the directory, until `f` returns `Some`.
+    fn find_dirent<T>(
+        &mut self,
+        dir: &Inode,
+        mut f: impl FnMut(&DirEntry, u8) -> Option<T>,
+    ) -> VfsResult<Option<T>> {
+        let has_filetype = self.has_filetype();
+        let num_blocks = dir.size().div_ceil(self.block_size as u64);
+        for lblock in 0..num_blocks {
+            let Some(block) = self.map_block(dir, lblock)? else {
+                continue;
+            };
+            let data = self.read_block(block)?;
+            let mut offset = 0;
+            while let Some(entry) = parse_dirent(&data, offset, has_filetype) {
+                if entry.inode != 0 {
+                    let ty = if has_filetype { data[offset + 7] } else { 0 };
+                    if let Some(res) = f(&entry, ty) {
+                        return Ok(Some(res));
+                    }
+                }
+                offset += entry.rec_len;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Looks up the inode number of `name` in the directory.
+    pub fn lookup(&mut self, dir: &Inode, name: &str) -> VfsResult<Option<u32>> {
+        self.find_dirent(dir, |entry, _| {
+            (entry.name == name.as_bytes()).then_some(entry.inode)
+        })
+    }
+
+    /// Lists the entries of the directory from the index `start`, up to
+    /// `max` entries.
+    pub fn list(
+        &mut self,
+        dir: &Inode,
+        start: usize,
+        max: usize,
+        mut f: impl FnMut(&[u8], u32, u8),
+    ) -> VfsResult<usize> {
+        let mut idx = 0;
+        let mut count = 0;
+        self.find_dirent(dir, |entry, ty| {
+            if idx >= start {
+                if count == max {
+                    return Some(());
+                }
+                f(entry.name, entry.inode, ty);
+                count += 1;
+            }
+            idx += 1;
+            None
+        })?;
+        Ok(count)
+    }
+
+    /// Returns the node type of the entry with the type code in the
+    /// directory entry, reading the inode if the code is absent.
+    pub fn entry_type(&mut self, ino: u32, ty: u8) -> VfsResult<VfsNodeType> {
+        Ok(match ty {
+            1 => VfsNodeType::File,
+            2 => VfsNodeType::Dir,
+            3 => VfsNodeType::CharDevice,
+            4 => VfsNodeType::BlockDevice,
+            5 => VfsNodeType::Fifo,
+            6 => VfsNodeType::Socket,
+            7 => VfsNodeType::SymLink,
+            _ => self.read_inode(ino)?.node_type(),
+        })
+    }
+
+    /// Adds an entry to the directory.
+    fn add_dirent(&mut self, dir_ino: u32, name: &str, ino: u32, ty: VfsNodeType) -> VfsResult {
+        if name.is_empty() || name.len() > 255 {
+            return Err(VfsError::InvalidInput);
+        }
+        let has_filetype = self.has_filetype();
+        let file_type = has_filetype.then(|| dirent_type(ty));
+        let needed = dirent_len(name.len());
+        let mut dir = self.read_inode(dir_ino)?;
+        // The hashed index is not maintained.
+        dir.set_flags(dir.flags() & !INODE_FLAG_INDEX);
+        let num_blocks = dir.size().div_ceil(self.block_size as u64);
+        for lblock in 0..num_blocks {
+            let Some(block) = self.map_block(&dir, lblock)? else {
+                continue;
+            };
+            let mut data = self.read_block(block)?;
+            let mut offset = 0;
+            while let Some(entry) = parse_dirent(&data, offset, has_filetype) {
+                let rec_len = entry.rec_len;
+                let used = if entry.inode == 0 {
+                    0
+                } else {
+                    dirent_len(entry.name.len())
+                };
+                if rec_len - used >= needed {
+                    if used > 0 {
+                        write_u16(&mut data, offset + 4, used as u16);
+                    }
+                    let new_off = offset + used;
+                    write_dirent(
+                        &mut data,
+                        new_off,
+                        ino,
+                        rec_len - used,
+                        name.as_bytes(),
+                        file_type,
+                    );
+                    self.write_block(block, &data)?;
+                    return self.write_inode(dir_ino, &dir);
+                }
+                offset += rec_len;
+            }
+        }
+        // Append a new block.
+        let block = self.map_block_alloc(dir_ino, &mut dir, num_blocks)?;
+        let mut data = vec![0; self.block_size];
+        write_dirent(
+            &mut data,
+            0,
+            ino,
+            self.block_size,
+            name.as_bytes(),
+            file_type,
+        );
+        self.write_block(block, &data)?;
+        dir.set_size((num_blocks + 1) * self.block_size as u64);
+        self.write_inode(dir_ino, &dir)
+    }
+
+    /// Removes the entry of `name` from the directory, and returns its inode
+    /// number.
+    fn remove_dirent(&mut self, dir_ino: u32, name: &str) -> VfsResult<u32> {
+        let has_filetype = self.has_filetype();
+        let mut dir = self.read_inode(dir_ino)?;
+        let num_blocks = dir.size().div_ceil(self.block_size as u64);
+        for lblock in 0..num_blocks {
+            let Some(block) = self.map_block(&dir, lblock)? else {
+                continue;
+            };
+            let mut data = self.read_block(block)?;
+            let mut offset = 0;
+            let mut prev = None;
+            while let Some(entry) = parse_dirent(&data, offset, has_filetype) {
+                let rec_len = entry.rec_len;
+                if entry.inode != 0 && entry.name == name.as_bytes() {
+                    let ino = entry.inode;
+                    match prev {
+                        // Merge into the previous entry.
+                        Some(prev) => {
+                            let prev_len = read_u16(&data, prev + 4) as usize;
+                            write_u16(&mut data, prev + 4, (prev_len + rec_len) as u16);
+                        }
+                        None => write_u32(&mut data, offset, 0),
+                    }
+                    self.write_block(block, &data)?;
+                    dir.set_flags(dir.flags() & !INODE_FLAG_INDEX);
+                    self.write_inode(dir_ino, &dir)?;
+                    return Ok(ino);
+                }
+                prev = Some(offset);
+                offset += rec_len;
+            }
+        }
+        Err(VfsError::NotFound)
+    }
+
+    /// Creates a node of the type in the directory, and returns its inode
+    /// number.
+    pub fn create(&mut self, dir_ino: u32, name: &str, ty: VfsNodeType) -> VfsResult<u32> {
+        self.check_writable()?;
+        let is_dir = ty == VfsNodeType::Dir;
+        let perm = match ty {
+            VfsNodeType::Dir => 0o755,
+            VfsNodeType::SymLink => 0o777,
+            _ => 0o644,
+        };
+        let ino = self.alloc_inode(self.inode_group(dir_ino), is_dir)?;
+        let mut inode = Inode::new(vec![0; self.sb.inode_size()]);
+        inode.set_mode(type_mode(ty) | perm);
+        inode.set_links_count(1);
+        inode.set_extra_isize(self.sb.want_extra_isize());
+        self.write_inode(ino, &inode)?;
+
+        let res = self.add_dirent(dir_ino, name, ino, ty).and_then(|_| {
+            if !is_dir {
+                return Ok(());
+            }
+            self.add_dirent(ino, ".", ino, ty)?;
+            self.add_dirent(ino, "..", dir_ino, ty)?;
+            let mut inode = self.read_inode(ino)?;
+            inode.set_links_count(2);
+            self.write_inode(ino, &inode)?;
+            let mut dir = self.read_inode(dir_ino)?;
+            dir.set_links_count(dir.links_count() + 1);
+            self.write_inode(dir_ino, &dir)
+        });
+        if res.is_err() {
+            self.remove_dirent(dir_ino, name).ok();
+            self.release_inode(ino).ok();
+        }
+        res.map(|_| ino)
+    }
+
+    /// Frees the data and the inode itself.
+    fn release_inode(&mut self, ino: u32) -> VfsResult {
+        let mut inode = self.read_inode(ino)?;
+        let is_dir = inode.is_dir();
+        if !(inode.node_type() == VfsNodeType::SymLink && self.inode_sectors(&inode) == 0) {
+            self.free_blocks_from(&mut inode, 0)?;
+        }
+        inode.set_links_count(0);
+        inode.set_size(0);
+        // There is no clock here. A deletion time not above the inode count
+        // would be taken as a link of the orphan list.
+        let dtime = self.sb.write_time().max(self.sb.inodes_count() + 1);
+        inode.set_dtime(dtime);
+        self.write_inode(ino, &inode)?;
+        self.free_inode(ino, is_dir)
+    }
+
+    /// Returns whether the directory has no entries other than `.` and `..`.
+    fn is_empty_dir(&mut self, dir: &Inode) -> VfsResult<bool> {
+        let found = self.find_dirent(dir, |entry, _| {
+            (entry.name != b"." && entry.name != b"..").then_some(())
+        })?;
+        Ok(found.is_none())
+    }
+
+    /// Adds an entry of `name` to the directory for the existing inode, which
+    /// must not be a directory.
+    pub fn link(&mut self, dir_ino: u32, name: &str, ino: u32) -> VfsResult {
+        self.check_writable()?;
+        let dir = self.read_inode(dir_ino)?;
+        if !dir.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        if self.lookup(&dir, name)?.is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+        let mut inode = self.read_inode(ino)?;
+        if inode.is_dir() {
+            return Err(VfsError::PermissionDenied);
+        }
+        self.add_dirent(dir_ino, name, ino, inode.node_type())?;
+        inode.set_links_count(inode.links_count() + 1);
+        self.write_inode(ino, &inode)
+    }
+
+    /// Removes the entry of `name` from the directory, and frees its inode if
+    /// it has no more links.
+    pub fn unlink(&mut self, dir_ino: u32, name: &str) -> VfsResult {
+        self.check_writable()?;
+        if name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        let dir = self.read_inode(dir_ino)?;
+        let ino = self.lookup(&dir, name)?.ok_or(VfsError::NotFound)?;
+        let mut inode = self.read_inode(ino)?;
+        if inode.is_dir() {
+            if !self.is_empty_dir(&inode)? {
+                return Err(VfsError::DirectoryNotEmpty);
+            }
+            self.remove_dirent(dir_ino, name)?;
+            let mut dir = self.read_inode(dir_ino)?;
+            dir.set_links_count(dir.links_count().saturating_sub(1));
+            self.write_inode(dir_ino, &dir)?;
+            return self.release_inode(ino);
+        }
+        self.remove_dirent(dir_ino, name)?;
+        let links = inode.links_count().saturating_sub(1);
+        if links == 0 {
+            self.release_inode(ino)
+        } else {
+            inode.set_links_count(links);
+            self.write_inode(ino, &inode)
+        }
+    }
+
+    /// Moves the entry of `src_name` in `src_dir` to `dst_name` in `dst_dir`,
+    /// replacing an existing file.
+    pub fn rename(
+        &mut self,
+        src_dir: u32,
+        src_name: &str,
+        dst_dir: u32,
+        dst_name: &str,
+    ) -> VfsResult {
+        self.check_writable()?;
+        if [src_name, dst_name].iter().any(|n| *n == "." || *n == "..") {
+            return Err(VfsError::InvalidInput);
+        }
+        let src_dir_inode = self.read_inode(src_dir)?;
+        let ino = self
+            .lookup(&src_dir_inode, src_name)?
+            .ok_or(VfsError::NotFound)?;
+        let inode = self.read_inode(ino)?;
+        if inode.is_dir() && self.is_ancestor(ino, dst_dir)? {
+            return Err(VfsError::InvalidInput);
+        }
+        let dst_dir_inode = self.read_inode(dst_dir)?;
+        if let Some(old) = self.lookup(&dst_dir_inode, dst_name)? {
+            if old == ino {
+                return Ok(());
+            }
+            if self.read_inode(old)?.is_dir() {
+                return Err(VfsError::AlreadyExists);
+            }
+            self.unlink(dst_dir, dst_name)?;
+        }
+        self.add_dirent(dst_dir, dst_name, ino, inode.node_type())?;
+        self.remove_dirent(src_dir, src_name)?;
+        if inode.is_dir() && src_dir != dst_dir {
+            // Point `..` to the new parent.
+            self.remove_dirent(ino, "..")?;
+            self.add_dirent(ino, "..", dst_dir, VfsNodeType::Dir)?;
+            let mut src = self.read_inode(src_dir)?;
+            src.set_links_count(src.links_count().saturating_sub(1));
+            self.write_inode(src_dir, &src)?;
+            let mut dst = self.read_inode(dst_dir)?;
+            dst.set_links_count(dst.links_count() + 1);
+            self.write_inode(dst_dir, &dst)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the directory `ino` is `dir` or one of its ancestors.
+    fn is_ancestor(&mut self, ino: u32, mut dir: u32) -> VfsResult<bool> {
+        loop {
+            if dir == ino {
+                return Ok(true);
+            } else if dir == ROOT_INO {
+                return Ok(false);
+            }
+            let inode = self.read_inode(dir)?;
+            dir = self.lookup(&inode, "..")?.ok_or(VfsError::InvalidData)?;
+        }
+    }
+}
+
+impl Drop for Volume {
+    fn drop(&mut self) {
+        if let Err(e) = self.write_metadata() {
+            warn!("ext: failed to write back the metadata: {:?}", e);
+        }
+    }
+}
+
+/// Checks the fields of the superblock that the layout is computed from, so
+/// that a corrupted volume fails to open instead of panicking later. Returns
+/// the number of block groups.
+fn check_layout(sb: &Superblock, disk_size: u64) -> VfsResult<usize> {
+    // 1K to 64K
+    if sb.log_block_size() > 6 {
+        warn!("invalid ext block size: 1024 << {}", sb.log_block_size());
+        return Err(VfsError::InvalidData);
+    }
+    let block_size = sb.block_size();
+    let (bpg, ipg) = (sb.blocks_per_group() as u64, sb.inodes_per_group());
+    let inode_size = sb.inode_size();
+    let desc_size = sb.desc_size();
+    if bpg == 0
+        || ipg == 0
+        || sb.first_data_block() as u64 >= sb.blocks_count()
+        || !inode_size.is_power_of_two()
+        || !(128..=block_size).contains(&inode_size)
+        || !(32..=block_size).contains(&desc_size)
+    {
+        warn!("invalid ext superblock");
+        return Err(VfsError::InvalidData);
+    }
+    let num_groups = (sb.blocks_count() - sb.first_data_block() as u64).div_ceil(bpg);
+    let gdt_start = (sb.first_data_block() as u64 + 1) * block_size as u64;
+    let gdt_end = num_groups
+        .checked_mul(desc_size as u64)
+        .and_then(|size| size.checked_add(gdt_start));
+    if gdt_end.map_or(true, |end| end > disk_size)
+        || num_groups.saturating_mul(ipg as u64) < sb.inodes_count() as u64
+    {
+        warn!("invalid ext block groups");
+        return Err(VfsError::InvalidData);
+    }
+    Ok(num_groups as usize)
+}
+
+fn find_zero_bit(bitmap: &[u8], count: usize) -> Option<usize> {
+    let byte = bitmap
+        .iter()
+        .take(count.div_ceil(8))
+        .position(|&b| b != 0xff)?;
+    let bit = byte * 8 + bitmap[byte].trailing_ones() as usize;
+    (bit < count).then_some(bit)
+}
+
+fn read_bytes(disk: &mut Disk, pos: u64, mut buf: &mut [u8]) -> VfsResult {
+    disk.set_position(pos);
+    while !buf.is_empty() {
+        match disk.read_one(buf) {
+            Ok(0) => return Err(VfsError::UnexpectedEof),
+            Ok(n) => buf = &mut buf[n..],
+            Err(_) => return Err(VfsError::Io),
+        }
+    }
+    Ok(())
+}
+
+fn write_bytes(disk: &mut Disk, pos: u64, mut buf: &[u8]) -> VfsResult {
+    disk.set_position(pos);
+    while !buf.is_empty() {
+        match disk.write_one(buf) {
+            Ok(0) => return Err(VfsError::WriteZero),
+            Ok(n) => buf = &buf[n..],
+            Err(_) => return Err(VfsError::Io),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISK_SIZE: u64 = 8 << 20;
+
+    /// A superblock of a group of 8192 blocks of 1K, with 2048 inodes.
+    fn superblock(init: impl FnOnce(&mut [u8])) -> Superblock {
+        let mut raw = vec![0; SUPERBLOCK_SIZE];
+        write_u32(&mut raw, 0, 2048);
+        write_u32(&mut raw, 4, 8192);
+        write_u32(&mut raw, 20, 1);
+        write_u32(&mut raw, 32, 8192);
+        write_u32(&mut raw, 40, 2048);
+        init(&mut raw);
+        Superblock::new(raw)
+    }
+
+    fn check(init: impl FnOnce(&mut [u8])) -> VfsResult<usize> {
+        check_layout(&superblock(init), DISK_SIZE)
+    }
+
+    #[test]
+    fn test_check_layout() {
+        assert_eq!(check(|_| {}), Ok(1));
+        assert_eq!(check(|raw| write_u32(raw, 32, 1000)), Ok(9));
+        // 64K blocks
+        let sb = superblock(|raw| {
+            write_u32(raw, 4, 64);
+            write_u32(raw, 20, 0);
+            write_u32(raw, 24, 6);
+        });
+        assert_eq!(check_layout(&sb, DISK_SIZE), Ok(1));
+        // 64-bit group descriptors
+        let set_desc_size = |size| {
+            move |raw: &mut [u8]| {
+                write_u32(raw, 96, INCOMPAT_64BIT);
+                write_u16(raw, 0xfe, size);
+            }
+        };
+        assert_eq!(check(set_desc_size(64)), Ok(1));
+        assert_eq!(check(set_desc_size(0)), Err(VfsError::InvalidData));
+
+        let invalid: [fn(&mut [u8]); 8] = [
+            |raw| write_u32(raw, 24, 7),
+            |raw| write_u32(raw, 24, 40),
+            |raw| write_u32(raw, 32, 0),
+            |raw| write_u32(raw, 40, 0),
+            |raw| write_u32(raw, 4, 1),
+            |raw| write_u32(raw, 0, 2049),
+            |raw| {
+                write_u32(raw, 76, 1);
+                write_u16(raw, 88, 0);
+            },
+            // the group descriptors out of the disk
+            |raw| write_u32(raw, 4, u32::MAX),
+        ];
+        for init in invalid {
+            assert_eq!(check(init), Err(VfsError::InvalidData));
+        }
+    }
+}
@@ -48,6 +48,23 @@ impl FatFileSystem {
         }
     }
 
+    /// Opens the FAT filesystem on the disk to be mounted at runtime, which
+    /// is never formatted.
+    ///
+    /// The filesystem is never freed, even after unmounted, as its nodes
+    /// borrow it for `'static`.
+    pub fn open(disk: Disk) -> VfsResult<Arc<Self>> {
+        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new()).map_err(as_vfs_err)?;
+        let fs = Arc::new(Self {
+            inner,
+            root_dir: UnsafeCell::new(None),
+        });
+        // SAFETY: the leaked reference keeps it alive forever.
+        let leaked: &'static Self = unsafe { &*Arc::into_raw(fs.clone()) };
+        leaked.init();
+        Ok(fs)
+    }
+
     pub fn init(&'static self) {
         // must be called before later operations
         unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir())) }
@@ -85,6 +102,10 @@ impl VfsNodeOps for FileWrapper<'static> {
         file.write(buf).map_err(as_vfs_err)
     }
 
+    fn fsync(&self) -> VfsResult {
+        self.0.lock().flush().map_err(as_vfs_err)
+    }
+
     fn truncate(&self, size: u64) -> VfsResult {
         let mut file = self.0.lock();
         file.seek(SeekFrom::Start(size)).map_err(as_vfs_err)?; // TODO: more efficient
@@ -243,7 +264,7 @@ impl Write for Disk {
         Ok(write_len)
     }
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(())
+        self.sync().map_err(|_| ())
     }
 }
 
@@ -1,13 +1,182 @@
 cfg_if::cfg_if! {
     if #[cfg(feature = "myfs")] {
         pub mod myfs;
-    } else if #[cfg(feature = "fatfs")] {
+    } else {
+        #[cfg(feature = "fatfs")]
         pub mod fatfs;
+        #[cfg(feature = "ext4")]
+        pub mod ext4;
     }
 }
 
 #[cfg(feature = "devfs")]
-pub use axfs_devfs as devfs;
+pub mod devfs;
 
 #[cfg(feature = "ramfs")]
 pub use axfs_ramfs as ramfs;
+
+#[cfg(feature = "overlayfs")]
+pub mod overlay;
+#[cfg(feature = "procfs")]
+pub mod procfs;
+#[cfg(any(feature = "devfs", feature = "procfs", feature = "sysfs"))]
+#[allow(dead_code)] // parts are only used with some of the features
+pub mod pseudo;
+#[cfg(feature = "sysfs")]
+pub mod sysfs;
+
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeRef, VfsResult};
+use core::time::Duration;
+
+use crate::fops::{FileMeta, FsStat};
+use crate::lock::LockKey;
+
+/// Adds a hard link of `name` in the directory `dir` to `node`, for the
+/// filesystems that support it. Both must be in the same filesystem.
+#[allow(unused_variables)]
+pub(crate) fn link_node(dir: &VfsNodeRef, name: &str, node: &VfsNodeRef) -> VfsResult {
+    #[cfg(feature = "ramfs")]
+    if let Some(dir) = dir.as_any().downcast_ref::<ramfs::DirNode>() {
+        return dir.link_node(name, node.clone());
+    }
+    #[cfg(all(feature = "ext4", not(feature = "myfs")))]
+    if let Some(dir) = dir.as_any().downcast_ref::<ext4::ExtNode>() {
+        return dir.link(name, node);
+    }
+    Err(VfsError::Unsupported)
+}
+
+/// Returns the node that `node` of an overlay stands for, which is copied up
+/// to the upper layer first if `copy_up` is set, or `node` itself if it is in
+/// another filesystem.
+#[allow(unused_variables)]
+fn real_node(node: &VfsNodeRef, copy_up: bool) -> VfsResult<VfsNodeRef> {
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = node.as_any().downcast_ref::<overlay::OverlayNode>() {
+        return if copy_up { node.copy_up() } else { node.real() };
+    }
+    Ok(node.clone())
+}
+
+/// Returns the ownership and timestamps of `node`, or `None` if they are not
+/// kept by its filesystem.
+#[allow(unused_variables)]
+pub(crate) fn node_meta(node: &VfsNodeRef) -> Option<FileMeta> {
+    let node = &real_node(node, false).ok()?;
+    #[cfg(feature = "ramfs")]
+    if let Some(meta) = ramfs::node_meta(node.as_ref()) {
+        let meta = meta.get();
+        return Some(FileMeta {
+            uid: meta.uid,
+            gid: meta.gid,
+            atime: meta.atime,
+            mtime: meta.mtime,
+            ctime: meta.ctime,
+        });
+    }
+    None
+}
+
+/// Changes the permission bits of `node`, for the filesystems that keep them.
+#[allow(unused_variables)]
+pub(crate) fn set_node_perm(node: &VfsNodeRef, perm: VfsNodePerm) -> VfsResult {
+    let node = &real_node(node, true)?;
+    #[cfg(feature = "ramfs")]
+    if let Some(meta) = ramfs::node_meta(node.as_ref()) {
+        meta.set_perm(perm);
+        return Ok(());
+    }
+    Err(VfsError::Unsupported)
+}
+
+/// Changes the owner and the group of `node`, for the filesystems that keep
+/// them.
+#[allow(unused_variables)]
+pub(crate) fn set_node_owner(node: &VfsNodeRef, uid: Option<u32>, gid: Option<u32>) -> VfsResult {
+    let node = &real_node(node, true)?;
+    #[cfg(feature = "ramfs")]
+    if let Some(meta) = ramfs::node_meta(node.as_ref()) {
+        meta.set_owner(uid, gid);
+        return Ok(());
+    }
+    Err(VfsError::Unsupported)
+}
+
+/// Changes the access and modification times of `node`, for the filesystems
+/// that keep them.
+#[allow(unused_variables)]
+pub(crate) fn set_node_times(
+    node: &VfsNodeRef,
+    atime: Option<Duration>,
+    mtime: Option<Duration>,
+) -> VfsResult {
+    let node = &real_node(node, true)?;
+    #[cfg(feature = "ramfs")]
+    if let Some(meta) = ramfs::node_meta(node.as_ref()) {
+        meta.set_times(atime, mtime);
+        return Ok(());
+    }
+    Err(VfsError::Unsupported)
+}
+
+/// Returns the offset of the first data in `node` at or after `offset`, or
+/// `None` if there is none. Files without holes are all data.
+pub(crate) fn seek_data(node: &VfsNodeRef, offset: u64) -> VfsResult<Option<u64>> {
+    let node = &real_node(node, false)?;
+    #[cfg(feature = "ramfs")]
+    if let Some(file) = node.as_any().downcast_ref::<ramfs::FileNode>() {
+        return Ok(file.seek_data(offset));
+    }
+    let size = node.get_attr()?.size();
+    Ok(Some(offset).filter(|_| offset < size))
+}
+
+/// Returns the offset of the first hole in `node` at or after `offset`, or
+/// `None` if `offset` is past the end. Files without holes only have the one
+/// at the end.
+pub(crate) fn seek_hole(node: &VfsNodeRef, offset: u64) -> VfsResult<Option<u64>> {
+    let node = &real_node(node, false)?;
+    #[cfg(feature = "ramfs")]
+    if let Some(file) = node.as_any().downcast_ref::<ramfs::FileNode>() {
+        return Ok(file.seek_hole(offset));
+    }
+    let size = node.get_attr()?.size();
+    Ok(Some(size).filter(|_| offset < size))
+}
+
+/// Returns the space usage of the filesystem that `node` is in, for the
+/// filesystems that report it. An overlay reports its upper layer.
+#[allow(unused_variables)]
+pub(crate) fn fs_stat(node: &VfsNodeRef) -> VfsResult<FsStat> {
+    #[cfg(feature = "overlayfs")]
+    if let Some(node) = node.as_any().downcast_ref::<overlay::OverlayNode>() {
+        return fs_stat(&node.upper_root());
+    }
+    #[cfg(feature = "ramfs")]
+    if let Some(usage) = ramfs::node_usage(node.as_ref()) {
+        let block_size = ramfs::PAGE_SIZE as u64;
+        return Ok(FsStat {
+            block_size,
+            blocks: usage.limit.unwrap_or(0) / block_size,
+            used_blocks: usage.used / block_size,
+            free_blocks: usage.free().unwrap_or(0) / block_size,
+        });
+    }
+    Err(VfsError::Unsupported)
+}
+
+/// Returns the key of the locks of the file `node`, or `None` if its
+/// filesystem creates a node at each lookup without inode numbers.
+#[allow(unused_variables)]
+pub(crate) fn lock_key(node: &VfsNodeRef) -> Option<LockKey> {
+    #[cfg(feature = "ramfs")]
+    if ramfs::node_meta(node.as_ref()).is_some() {
+        return Some(LockKey::Node(alloc::sync::Arc::as_ptr(node) as *const () as usize));
+    }
+    #[cfg(all(feature = "ext4", not(feature = "myfs")))]
+    if let Some(node) = node.as_any().downcast_ref::<ext4::ExtNode>() {
+        let (fs, ino) = node.inode_id();
+        return Some(LockKey::Inode(fs, ino));
+    }
+    None
+}
@@ -0,0 +1,552 @@
+//! An overlay filesystem, which merges a writable upper directory over a
+//! read-only lower one, like overlayfs of Linux.
+//!
+//! The lower layer is never modified. A file or directory in it is copied up
+//! to the upper layer before it is written or its metadata is changed, and
+//! the parent directories are copied up first. A deleted entry of the lower
+//! layer is hidden by a whiteout, an empty file named `.wh.<name>` in the
+//! same directory of the upper layer. A directory that replaces a deleted
+//! one is marked opaque by a file named `.wh..wh..opq` in it, which hides the
+//! entries of the lower directory. Names starting with `.wh.` are reserved.
+//!
+//! As the lower layer cannot be changed, renaming a directory that merges a
+//! lower one copies up its whole tree first, and marks it opaque.
+
+use alloc::collections::BTreeSet;
+use alloc::sync::{Arc, Weak};
+use alloc::{format, string::String, vec::Vec};
+
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsOps, VfsResult};
+use axsync::Mutex;
+
+/// The prefix of the names of whiteouts.
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// The name of the marker of an opaque directory.
+const OPAQUE: &str = ".wh..wh..opq";
+
+/// An overlay filesystem that implements [`axfs_vfs::VfsOps`].
+pub struct OverlayFileSystem {
+    root: Arc<OverlayNode>,
+    /// Keeps the filesystems of the layers alive.
+    _layers: Vec<Arc<dyn VfsOps>>,
+}
+
+impl OverlayFileSystem {
+    /// Creates an overlay of the root directories of the filesystems.
+    pub fn new(lower: Arc<dyn VfsOps>, upper: Arc<dyn VfsOps>) -> Self {
+        let root = OverlayNode::new_root(lower.root_dir(), upper.root_dir());
+        Self {
+            root,
+            _layers: alloc::vec![lower, upper],
+        }
+    }
+
+    /// Creates an overlay of the directories, which may be in any mounted
+    /// filesystems.
+    ///
+    /// It fails with [`VfsError::NotADirectory`] if either is not a
+    /// directory.
+    pub fn from_dirs(lower: VfsNodeRef, upper: VfsNodeRef) -> VfsResult<Self> {
+        if !lower.get_attr()?.is_dir() || !upper.get_attr()?.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        Ok(Self {
+            root: OverlayNode::new_root(lower, upper),
+            _layers: Vec::new(),
+        })
+    }
+}
+
+impl VfsOps for OverlayFileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        *self.root.layers.parent.lock() = mount_point.parent();
+        Ok(())
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+/// The state shared by the nodes of an overlay.
+struct Layers {
+    upper: VfsNodeRef,
+    /// The parent of the mount point.
+    parent: Mutex<Option<VfsNodeRef>>,
+    /// Serializes the changes of the upper layer by copy-ups and whiteouts.
+    lock: Mutex<()>,
+}
+
+/// A file or directory of an overlay, which stands for the node of the upper
+/// layer if there is one, or the node of the lower layer otherwise. A
+/// directory in both layers is merged.
+pub struct OverlayNode {
+    this: Weak<OverlayNode>,
+    layers: Arc<Layers>,
+    parent: Option<Arc<OverlayNode>>,
+    /// The path from the root of the overlay, empty for the root.
+    path: String,
+    lower: Option<VfsNodeRef>,
+    upper: Mutex<Option<VfsNodeRef>>,
+}
+
+impl OverlayNode {
+    fn new_root(lower: VfsNodeRef, upper: VfsNodeRef) -> Arc<Self> {
+        let layers = Arc::new(Layers {
+            upper: upper.clone(),
+            parent: Mutex::new(None),
+            lock: Mutex::new(()),
+        });
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            layers,
+            parent: None,
+            path: String::new(),
+            lower: Some(lower),
+            upper: Mutex::new(Some(upper)),
+        })
+    }
+
+    fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or_default()
+    }
+
+    /// Returns the node in the upper layer, which may have been copied up
+    /// through another node of the same path.
+    fn upper(&self) -> Option<VfsNodeRef> {
+        let mut upper = self.upper.lock();
+        if upper.is_none() {
+            *upper = self.layers.upper.clone().lookup(&self.path).ok();
+        }
+        upper.clone()
+    }
+
+    /// Returns the lower directory to merge, or `None` if it is hidden by
+    /// the upper one.
+    fn lower_dir(&self) -> VfsResult<Option<VfsNodeRef>> {
+        match self.upper() {
+            Some(upper) if find(&upper, OPAQUE)?.is_some() => Ok(None),
+            _ => Ok(self.lower.clone()),
+        }
+    }
+
+    /// Returns the node of the upper or the lower layer that it stands for.
+    pub(crate) fn real(&self) -> VfsResult<VfsNodeRef> {
+        self.upper()
+            .or_else(|| self.lower.clone())
+            .ok_or(VfsError::NotFound)
+    }
+
+    /// Returns the root directory of the upper layer.
+    pub(crate) fn upper_root(&self) -> VfsNodeRef {
+        self.layers.upper.clone()
+    }
+
+    fn is_dir(&self) -> VfsResult<bool> {
+        Ok(self.real()?.get_attr()?.is_dir())
+    }
+
+    /// Copies the node up to the upper layer if it is only in the lower one,
+    /// and returns the node in the upper layer. The content of a directory
+    /// is not copied, as it is merged.
+    pub(crate) fn copy_up(&self) -> VfsResult<VfsNodeRef> {
+        if let Some(upper) = self.upper() {
+            return Ok(upper);
+        }
+        // the root is always in the upper layer
+        let upper_dir = self.parent.as_ref().ok_or(VfsError::NotFound)?.copy_up()?;
+        let lower = self.lower.as_ref().ok_or(VfsError::NotFound)?;
+        let _guard = self.layers.lock.lock();
+        if let Some(upper) = self.upper() {
+            return Ok(upper);
+        }
+        debug!("copy up at overlay: {}", self.path);
+        let name = self.name();
+        let attr = lower.get_attr()?;
+        upper_dir.create(name, attr.file_type())?;
+        let upper = upper_dir.clone().lookup(name)?;
+        if !attr.is_dir() {
+            if let Err(e) = copy_content(lower, &upper) {
+                upper_dir.remove(name).ok();
+                return Err(e);
+            }
+        }
+        // only kept by some filesystems
+        super::set_node_perm(&upper, attr.perm()).ok();
+        *self.upper.lock() = Some(upper.clone());
+        Ok(upper)
+    }
+
+    /// Copies the directory up with all its entries, recursively, and marks
+    /// it opaque, so that it no longer merges the lower directory.
+    fn copy_up_tree(&self) -> VfsResult<VfsNodeRef> {
+        let upper = self.copy_up()?;
+        for (name, _) in self.entries()? {
+            let child = self.child(&name)?;
+            if child.lower.is_some() && child.is_dir()? {
+                child.copy_up_tree()?;
+            } else {
+                child.copy_up()?;
+            }
+        }
+        let _guard = self.layers.lock.lock();
+        // the whiteouts are not needed in an opaque directory
+        for (name, _) in list(&upper)? {
+            if name.starts_with(WHITEOUT_PREFIX) && name != OPAQUE {
+                upper.remove(&name)?;
+            }
+        }
+        make_opaque(&upper)?;
+        Ok(upper)
+    }
+
+    /// Looks up the entry `name` in this directory.
+    fn child(&self, name: &str) -> VfsResult<Arc<OverlayNode>> {
+        if name.starts_with(WHITEOUT_PREFIX) {
+            return Err(VfsError::NotFound);
+        }
+        let upper = match self.upper() {
+            Some(dir) => find(&dir, name)?,
+            None => None,
+        };
+        let lower = match (&upper, self.lower_dir()?) {
+            (None, Some(dir)) if !self.is_whiteout(name)? => find(&dir, name)?,
+            (Some(upper), Some(dir)) if upper.get_attr()?.is_dir() => {
+                find(&dir, name)?.filter(|lower| lower.get_attr().is_ok_and(|a| a.is_dir()))
+            }
+            _ => None,
+        };
+        if upper.is_none() && lower.is_none() {
+            return Err(VfsError::NotFound);
+        }
+        Ok(Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            layers: self.layers.clone(),
+            parent: self.this.upgrade(),
+            path: join(&self.path, name),
+            lower,
+            upper: Mutex::new(upper),
+        }))
+    }
+
+    fn is_whiteout(&self, name: &str) -> VfsResult<bool> {
+        match self.upper() {
+            Some(dir) => Ok(find(&dir, &whiteout(name))?.is_some()),
+            None => Ok(false),
+        }
+    }
+
+    /// Returns whether the entry `name` of the lower directory shows through,
+    /// so that it has to be hidden by a whiteout when removed.
+    fn in_lower(&self, name: &str) -> VfsResult<bool> {
+        match self.lower_dir()? {
+            Some(dir) if !self.is_whiteout(name)? => Ok(find(&dir, name)?.is_some()),
+            _ => Ok(false),
+        }
+    }
+
+    /// Returns the merged entries of this directory, except `.` and `..`.
+    fn entries(&self) -> VfsResult<Vec<(String, VfsNodeType)>> {
+        let mut entries = Vec::new();
+        let mut names: BTreeSet<String> = BTreeSet::new();
+        if let Some(upper) = self.upper() {
+            for (name, ty) in list(&upper)? {
+                // the whiteouts hide the entries of the lower directory
+                names.insert(name.strip_prefix(WHITEOUT_PREFIX).unwrap_or(&name).into());
+                if !name.starts_with(WHITEOUT_PREFIX) {
+                    entries.push((name, ty));
+                }
+            }
+        }
+        if let Some(lower) = self.lower_dir()? {
+            for (name, ty) in list(&lower)? {
+                if !names.contains(&name) {
+                    entries.push((name, ty));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Looks up the parent directory of the last component of `path`.
+    /// Returns the directory and the name.
+    fn lookup_parent<'a>(&self, path: &'a str) -> VfsResult<(VfsNodeRef, &'a str)> {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        match path.trim_end_matches('/').rsplit_once('/') {
+            Some((parent, name)) => Ok((this.lookup(parent)?, name)),
+            None => Ok((this as VfsNodeRef, path.trim_end_matches('/'))),
+        }
+    }
+
+    fn create_in(&self, name: &str, ty: VfsNodeType) -> VfsResult {
+        match self.child(name) {
+            Ok(_) => return Err(VfsError::AlreadyExists),
+            Err(VfsError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        let upper_dir = self.copy_up()?;
+        let _guard = self.layers.lock.lock();
+        upper_dir.create(name, ty)?;
+        if self.is_whiteout(name)? {
+            // not to merge the deleted directory
+            if ty == VfsNodeType::Dir {
+                upper_dir
+                    .clone()
+                    .lookup(name)?
+                    .create(OPAQUE, VfsNodeType::File)?;
+            }
+            upper_dir.remove(&whiteout(name))?;
+        }
+        Ok(())
+    }
+
+    fn remove_in(&self, name: &str) -> VfsResult {
+        let node = self.child(name)?;
+        if node.is_dir()? && !node.entries()?.is_empty() {
+            return Err(VfsError::DirectoryNotEmpty);
+        }
+        let in_lower = self.in_lower(name)?;
+        let upper_dir = if in_lower {
+            self.copy_up()?
+        } else {
+            self.upper().ok_or(VfsError::NotFound)?
+        };
+        let _guard = self.layers.lock.lock();
+        if let Some(upper) = node.upper() {
+            if upper.get_attr()?.is_dir() {
+                // the whiteouts and the opaque marker left in it
+                for (name, _) in list(&upper)? {
+                    upper.remove(&name)?;
+                }
+            }
+            upper_dir.remove(name)?;
+        }
+        if in_lower {
+            upper_dir.create(&whiteout(name), VfsNodeType::File)?;
+        }
+        Ok(())
+    }
+}
+
+impl VfsNodeOps for OverlayNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.real()?.get_attr()
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        match &self.parent {
+            Some(parent) => Some(parent.clone()),
+            None => self.layers.parent.lock().clone(),
+        }
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node: VfsNodeRef = match name {
+            "" | "." => self.clone(),
+            ".." => self.parent().ok_or(VfsError::NotFound)?,
+            _ if !self.is_dir()? => return Err(VfsError::NotADirectory),
+            _ => self.child(name)?,
+        };
+        match rest {
+            Some(rest) => node.lookup(rest),
+            None => Ok(node),
+        }
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {:?} at overlay: {}", ty, path);
+        let (dir, name) = self.lookup_parent(path)?;
+        if name.is_empty() || name == "." || name == ".." {
+            return Ok(()); // already exists
+        } else if name.starts_with(WHITEOUT_PREFIX) {
+            return Err(VfsError::InvalidInput);
+        }
+        match dir.as_any().downcast_ref::<OverlayNode>() {
+            Some(dir) => dir.create_in(name, ty),
+            None => dir.create(name, ty), // out of the overlay by `..`
+        }
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        debug!("remove at overlay: {}", path);
+        let (dir, name) = self.lookup_parent(path)?;
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        match dir.as_any().downcast_ref::<OverlayNode>() {
+            Some(dir) => dir.remove_in(name),
+            None => dir.remove(name),
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        if !self.is_dir()? {
+            return Err(VfsError::NotADirectory);
+        }
+        let mut entries = alloc::vec![
+            (".".into(), VfsNodeType::Dir),
+            ("..".into(), VfsNodeType::Dir)
+        ];
+        entries.extend(self.entries()?);
+        let entries = entries.iter().skip(start_idx);
+        let mut count = 0;
+        for ((name, ty), ent) in entries.zip(dirents.iter_mut()) {
+            *ent = VfsDirEntry::new(name, *ty);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("rename at overlay: {} -> {}", src_path, dst_path);
+        let (src_dir, src_name) = self.lookup_parent(src_path)?;
+        let (dst_dir, dst_name) = self.lookup_parent(dst_path)?;
+        let (Some(src_dir), Some(dst_dir)) = (
+            src_dir.as_any().downcast_ref::<OverlayNode>(),
+            dst_dir.as_any().downcast_ref::<OverlayNode>(),
+        ) else {
+            return Err(VfsError::InvalidInput);
+        };
+        if [src_name, dst_name]
+            .iter()
+            .any(|name| matches!(*name, "" | "." | "..") || name.starts_with(WHITEOUT_PREFIX))
+        {
+            return Err(VfsError::InvalidInput);
+        }
+        let node = src_dir.child(src_name)?;
+        let is_dir = node.is_dir()?;
+        let src_in_lower = src_dir.in_lower(src_name)?;
+        match dst_dir.child(dst_name) {
+            Ok(_) => dst_dir.remove_in(dst_name)?,
+            Err(VfsError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+
+        if is_dir && node.lower.is_some() {
+            // the entries of the lower directory cannot be moved
+            node.copy_up_tree()?;
+        } else {
+            node.copy_up()?;
+        }
+        let dst_upper = dst_dir.copy_up()?;
+        let src_upper = src_dir.upper().ok_or(VfsError::NotFound)?;
+        let _guard = self.layers.lock.lock();
+        if Arc::ptr_eq(&src_upper, &dst_upper) {
+            src_upper.rename(src_name, dst_name)?;
+        } else {
+            let dst_path = join(&dst_dir.path, dst_name);
+            self.layers.upper.rename(&node.path, &dst_path)?;
+        }
+        if find(&dst_upper, &whiteout(dst_name))?.is_some() {
+            if is_dir {
+                make_opaque(&dst_upper.clone().lookup(dst_name)?)?;
+            }
+            dst_upper.remove(&whiteout(dst_name))?;
+        }
+        if src_in_lower {
+            src_upper.create(&whiteout(src_name), VfsNodeType::File)?;
+        }
+        Ok(())
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.real()?.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.copy_up()?.write_at(offset, buf)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        match self.upper() {
+            Some(upper) => upper.fsync(),
+            None => Ok(()), // nothing written
+        }
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        self.copy_up()?.truncate(size)
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
+
+fn whiteout(name: &str) -> String {
+    format!("{}{}", WHITEOUT_PREFIX, name)
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.into()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Looks up the entry `name` in the directory `dir`, or `None` if it does
+/// not exist.
+fn find(dir: &VfsNodeRef, name: &str) -> VfsResult<Option<VfsNodeRef>> {
+    match dir.clone().lookup(name) {
+        Ok(node) => Ok(Some(node)),
+        Err(VfsError::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Marks the directory `dir` of the upper layer opaque, if it is not yet.
+fn make_opaque(dir: &VfsNodeRef) -> VfsResult {
+    if find(dir, OPAQUE)?.is_none() {
+        dir.create(OPAQUE, VfsNodeType::File)?;
+    }
+    Ok(())
+}
+
+/// Lists the entries of the directory `dir`, except `.` and `..`.
+fn list(dir: &VfsNodeRef) -> VfsResult<Vec<(String, VfsNodeType)>> {
+    const EMPTY: VfsDirEntry = VfsDirEntry::default();
+    let mut dirents = [EMPTY; 16];
+    let mut entries = Vec::new();
+    loop {
+        let n = dir.read_dir(entries.len(), &mut dirents)?;
+        if n == 0 {
+            break;
+        }
+        for ent in &dirents[..n] {
+            let name = String::from_utf8_lossy(ent.name_as_bytes()).into_owned();
+            entries.push((name, ent.entry_type()));
+        }
+    }
+    entries.retain(|(name, _)| name != "." && name != "..");
+    Ok(entries)
+}
+
+/// Copies the content of the file `src` to `dst`.
+fn copy_content(src: &VfsNodeRef, dst: &VfsNodeRef) -> VfsResult {
+    let mut buf = [0; 512];
+    let mut offset = 0;
+    loop {
+        let n = src.read_at(offset, &mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        let mut written = 0;
+        while written < n {
+            match dst.write_at(offset + written as u64, &buf[written..n])? {
+                0 => return Err(VfsError::WriteZero),
+                len => written += len,
+            }
+        }
+        offset += n as u64;
+    }
+}
+
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    let trimmed_path = path.trim_start_matches('/');
+    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
+        (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
+    })
+}
@@ -0,0 +1,241 @@
+//! The process filesystem, which renders the kernel state when its files are
+//! read, in the formats of `/proc` of Linux.
+//!
+//! Only the fields that the kernel keeps track of are meaningful; the others
+//! are reported as zeros.
+
+use alloc::{format, string::String};
+use core::fmt::Write;
+
+use axfs_vfs::{VfsError, VfsResult};
+
+use super::pseudo::{log_level_file, PseudoDir, PseudoFile, PseudoFileSystem};
+
+/// Creates a procfs instance.
+pub fn new_procfs() -> PseudoFileSystem {
+    #[cfg(feature = "axtask")]
+    let root = PseudoDir::new_dynamic(None, task::list_tasks);
+    #[cfg(not(feature = "axtask"))]
+    let root = PseudoDir::new(None);
+
+    root.add("mounts", PseudoFile::new(|| Ok(mounts())));
+    root.add("uptime", PseudoFile::new(|| Ok(uptime())));
+    root.add("cpuinfo", PseudoFile::new(|| Ok(cpuinfo())));
+    #[cfg(feature = "axalloc")]
+    root.add("meminfo", PseudoFile::new(|| Ok(meminfo())));
+    #[cfg(feature = "irq")]
+    root.add("interrupts", PseudoFile::new(|| Ok(interrupts())));
+    #[cfg(feature = "axnet")]
+    root.mkdir("net")
+        .add("dev", PseudoFile::new(|| Ok(net_dev())));
+    #[cfg(feature = "axtask")]
+    root.add("self", task::self_link());
+
+    add_sysctls(&root.mkdir("sys"));
+    PseudoFileSystem::new(root)
+}
+
+/// Adds the tunables under `/proc/sys`.
+fn add_sysctls(sys: &PseudoDir) {
+    sys.mkdir("kernel").add("log_level", log_level_file());
+
+    let vm = sys.mkdir("vm");
+    vm.add("overcommit_memory", PseudoFile::new(|| Ok("0\n".into())));
+    vm.add(
+        "block_cache_blocks",
+        PseudoFile::new_rw(
+            || Ok(format!("{}\n", crate::dev::cache_capacity())),
+            |value| {
+                let blocks = parse_positive(value)?;
+                crate::dev::set_cache_capacity(blocks).map_err(|_| VfsError::Io)
+            },
+        ),
+    );
+
+    let core = sys.mkdir("net").mkdir("core");
+    #[cfg(feature = "axnet")]
+    let somaxconn = PseudoFile::new_rw(
+        || Ok(format!("{}\n", axnet::listen_queue_size())),
+        |value| {
+            axnet::set_listen_queue_size(parse_positive(value)?);
+            Ok(())
+        },
+    );
+    #[cfg(not(feature = "axnet"))]
+    let somaxconn = PseudoFile::new(|| Ok("4096\n".into()));
+    core.add("somaxconn", somaxconn);
+}
+
+fn parse_positive(value: &str) -> VfsResult<usize> {
+    match value.parse() {
+        Ok(0) | Err(_) => Err(VfsError::InvalidInput),
+        Ok(n) => Ok(n),
+    }
+}
+
+fn mounts() -> String {
+    let mut s = String::new();
+    for mp in crate::root::mount_table() {
+        writeln!(s, "{} {} {} rw 0 0", mp.source, mp.path, mp.fstype).ok();
+    }
+    s
+}
+
+fn uptime() -> String {
+    let now = axhal::time::monotonic_time();
+    // The idle time is not tracked.
+    format!("{}.{:02} 0.00\n", now.as_secs(), now.subsec_millis() / 10)
+}
+
+fn cpuinfo() -> String {
+    let mut s = String::new();
+    for cpu in 0..axconfig::SMP {
+        writeln!(s, "processor\t: {}", cpu).ok();
+        writeln!(s, "arch\t\t: {}", axconfig::ARCH).ok();
+        writeln!(s, "platform\t: {}", axconfig::PLATFORM).ok();
+        writeln!(s, "timer freq\t: {} Hz", axconfig::TIMER_FREQUENCY).ok();
+        writeln!(s).ok();
+    }
+    s
+}
+
+#[cfg(feature = "axalloc")]
+fn meminfo() -> String {
+    let alloc = axalloc::global_allocator();
+    let kb = |pages: usize| pages * axhal::mem::PAGE_SIZE_4K / 1024;
+    let free = kb(alloc.available_pages());
+    let fields = [
+        ("MemTotal", kb(alloc.used_pages() + alloc.available_pages())),
+        ("MemFree", free),
+        ("MemAvailable", free + alloc.available_bytes() / 1024),
+        ("Buffers", crate::dev::cached_bytes() / 1024),
+        ("Cached", 0),
+        ("SwapTotal", 0),
+        ("SwapFree", 0),
+        ("Slab", alloc.used_bytes() / 1024),
+    ];
+    let mut s = String::new();
+    for (name, value) in fields {
+        writeln!(s, "{:<15} {:>8} kB", format!("{}:", name), value).ok();
+    }
+    s
+}
+
+#[cfg(feature = "irq")]
+fn interrupts() -> String {
+    use axhal::irq::{irq_count, MAX_IRQ_COUNT};
+
+    let mut s = String::from("    ");
+    for cpu in 0..axconfig::SMP {
+        write!(s, " {:>10}", format!("CPU{}", cpu)).ok();
+    }
+    writeln!(s).ok();
+    for irq in 0..MAX_IRQ_COUNT {
+        let counts: alloc::vec::Vec<usize> =
+            (0..axconfig::SMP).map(|cpu| irq_count(cpu, irq)).collect();
+        if counts.iter().all(|&n| n == 0) {
+            continue;
+        }
+        write!(s, "{:>3}:", irq).ok();
+        for n in counts {
+            write!(s, " {:>10}", n).ok();
+        }
+        writeln!(s).ok();
+    }
+    s
+}
+
+#[cfg(feature = "axnet")]
+fn net_dev() -> String {
+    let mut s = String::from(
+        "Inter-|   Receive                                                \
+         |  Transmit\n \
+         face |bytes    packets errs drop fifo frame compressed multicast\
+         |bytes    packets errs drop fifo colls carrier compressed\n",
+    );
+    for dev in axnet::interface_stats() {
+        writeln!(
+            s,
+            "{:>6}:{:>8} {:>7} {:>4}    0    0     0          0         0 \
+             {:>8} {:>7} {:>4}    0    0     0       0          0",
+            dev.name,
+            dev.rx_bytes,
+            dev.rx_packets,
+            dev.rx_errors,
+            dev.tx_bytes,
+            dev.tx_packets,
+            dev.tx_errors,
+        )
+        .ok();
+    }
+    s
+}
+
+#[cfg(feature = "axtask")]
+mod task {
+    use super::*;
+    use alloc::{sync::Arc, vec::Vec};
+    use axfs_vfs::VfsNodeRef;
+    use axtask::{AxTaskRef, TaskState};
+
+    /// Lists a directory for each task, named by its ID.
+    pub fn list_tasks(root: &PseudoDir) -> Vec<(String, VfsNodeRef)> {
+        axtask::all_tasks()
+            .iter()
+            .map(|task| {
+                let id = task.id().as_u64();
+                let dir = root.new_subdir();
+                dir.add("status", PseudoFile::new(move || status(&find(id)?)));
+                dir.add("stat", PseudoFile::new(move || stat(&find(id)?)));
+                (format!("{}", id), dir as VfsNodeRef)
+            })
+            .collect()
+    }
+
+    /// Creates the `self` link to the directory of the current task.
+    pub fn self_link() -> Arc<PseudoFile> {
+        PseudoFile::new_symlink(|| Ok(format!("{}", axtask::current().id().as_u64())))
+    }
+
+    /// Finds the task, which may have been released after being listed.
+    fn find(id: u64) -> VfsResult<AxTaskRef> {
+        axtask::find_task(id).ok_or(VfsError::NotFound)
+    }
+
+    fn state_of(task: &AxTaskRef) -> (char, &'static str) {
+        match task.state() {
+            TaskState::Running | TaskState::Ready => ('R', "running"),
+            TaskState::Blocked => ('S', "sleeping"),
+            TaskState::Exited => ('Z', "zombie"),
+        }
+    }
+
+    fn status(task: &AxTaskRef) -> VfsResult<String> {
+        let id = task.id().as_u64();
+        let (state, desc) = state_of(task);
+        let mut s = String::new();
+        writeln!(s, "Name:\t{}", task.name()).ok();
+        writeln!(s, "State:\t{} ({})", state, desc).ok();
+        writeln!(s, "Tgid:\t{}", id).ok();
+        writeln!(s, "Pid:\t{}", id).ok();
+        writeln!(s, "PPid:\t0").ok();
+        writeln!(s, "Threads:\t1").ok();
+        Ok(s)
+    }
+
+    fn stat(task: &AxTaskRef) -> VfsResult<String> {
+        let id = task.id().as_u64();
+        let (state, _) = state_of(task);
+        // pid (comm) state ppid pgrp session tty_nr tpgid, then flags to
+        // cstime, priority nice num_threads itrealvalue starttime vsize rss,
+        // and the remaining 28 fields.
+        let mut s = format!("{} ({}) {} 0 {} {} 0 -1", id, task.name(), state, id, id);
+        s += " 0 0 0 0 0 0 0 0 0";
+        s += " 20 0 1 0 0 0 0";
+        for _ in 0..28 {
+            s += " 0";
+        }
+        s += "\n";
+        Ok(s)
+    }
+}
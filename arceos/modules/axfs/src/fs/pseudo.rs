@@ -0,0 +1,319 @@
+//! Building blocks of synthetic filesystems, such as procfs and sysfs.
+//!
+//! Their files store nothing: the content is rendered from the kernel state
+//! each time it is read, and writes are passed to a callback. Directories may
+//! also list entries generated on the fly, e.g. one per task.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef};
+use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
+use axsync::Mutex;
+
+type ReadFn = Box<dyn Fn() -> VfsResult<String> + Send + Sync>;
+type WriteFn = Box<dyn Fn(&str) -> VfsResult + Send + Sync>;
+type ListFn = Box<dyn Fn(&PseudoDir) -> Vec<(String, VfsNodeRef)> + Send + Sync>;
+
+/// A synthetic filesystem that implements [`axfs_vfs::VfsOps`].
+pub struct PseudoFileSystem {
+    /// Keeps the parent of the mount point alive.
+    parent: Mutex<Option<VfsNodeRef>>,
+    root: Arc<PseudoDir>,
+}
+
+impl PseudoFileSystem {
+    /// Creates a filesystem with the given root directory, which should have
+    /// no parent.
+    pub fn new(root: Arc<PseudoDir>) -> Self {
+        Self {
+            parent: Mutex::new(None),
+            root,
+        }
+    }
+}
+
+impl VfsOps for PseudoFileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        let parent = mount_point.parent();
+        self.root.set_parent(parent.as_ref());
+        *self.parent.lock() = parent;
+        Ok(())
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+/// A directory of a synthetic filesystem.
+///
+/// It holds the entries added to it, followed by those listed by its
+/// generator if it has one. Entries cannot be created or removed by users.
+pub struct PseudoDir {
+    this: Weak<PseudoDir>,
+    parent: Mutex<Weak<dyn VfsNodeOps>>,
+    children: Mutex<BTreeMap<String, VfsNodeRef>>,
+    list: Option<ListFn>,
+}
+
+impl PseudoDir {
+    /// Creates an empty directory.
+    pub fn new(parent: Option<Weak<dyn VfsNodeOps>>) -> Arc<Self> {
+        Self::new_inner(parent, None)
+    }
+
+    /// Creates a directory whose entries are listed by `list` whenever they
+    /// are looked up or read. `list` gets the directory itself, to create
+    /// subdirectories with [`PseudoDir::new_subdir`].
+    pub fn new_dynamic<F>(parent: Option<Weak<dyn VfsNodeOps>>, list: F) -> Arc<Self>
+    where
+        F: Fn(&PseudoDir) -> Vec<(String, VfsNodeRef)> + Send + Sync + 'static,
+    {
+        Self::new_inner(parent, Some(Box::new(list)))
+    }
+
+    fn new_inner(parent: Option<Weak<dyn VfsNodeOps>>, list: Option<ListFn>) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            parent: Mutex::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
+            children: Mutex::new(BTreeMap::new()),
+            list,
+        })
+    }
+
+    fn set_parent(&self, parent: Option<&VfsNodeRef>) {
+        *self.parent.lock() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
+    }
+
+    /// Creates an empty directory whose parent is this one, without adding
+    /// it as an entry.
+    pub fn new_subdir(&self) -> Arc<PseudoDir> {
+        Self::new(Some(self.this.clone()))
+    }
+
+    /// Creates an empty subdirectory with the given name, or returns the
+    /// existing one.
+    pub fn mkdir(&self, name: &str) -> Arc<PseudoDir> {
+        let mut children = self.children.lock();
+        let existing = children.get(name).and_then(|node| {
+            let dir = node.as_any().downcast_ref::<PseudoDir>()?;
+            dir.this.upgrade()
+        });
+        existing.unwrap_or_else(|| {
+            let dir = self.new_subdir();
+            children.insert(name.into(), dir.clone());
+            dir
+        })
+    }
+
+    /// Adds a node with the given name, replacing the existing one.
+    pub fn add(&self, name: &str, node: VfsNodeRef) {
+        self.children.lock().insert(name.into(), node);
+    }
+
+    fn entries(&self) -> Vec<(String, VfsNodeRef)> {
+        let mut entries: Vec<_> = self
+            .children
+            .lock()
+            .iter()
+            .map(|(name, node)| (name.clone(), node.clone()))
+            .collect();
+        if let Some(list) = &self.list {
+            entries.extend(list(self));
+        }
+        entries
+    }
+
+    fn child(&self, name: &str) -> Option<VfsNodeRef> {
+        if let Some(node) = self.children.lock().get(name) {
+            return Some(node.clone());
+        }
+        let list = self.list.as_ref()?;
+        list(self)
+            .into_iter()
+            .find_map(|(n, node)| (n == name).then_some(node))
+    }
+}
+
+impl VfsNodeOps for PseudoDir {
+    axfs_vfs::impl_vfs_dir_default! {}
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o555),
+            VfsNodeType::Dir,
+            0,
+            0,
+        ))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent.lock().upgrade()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node = match name {
+            "" | "." => Ok(self.clone() as VfsNodeRef),
+            ".." => self.parent().ok_or(VfsError::NotFound),
+            _ => self.child(name).ok_or(VfsError::NotFound),
+        }?;
+
+        if let Some(rest) = rest {
+            node.lookup(rest)
+        } else {
+            Ok(node)
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let entries = self.entries();
+        let mut entries = entries.iter().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    if let Some((name, node)) = entries.next() {
+                        let ty = node.get_attr().map_or(VfsNodeType::File, |a| a.file_type());
+                        *ent = VfsDirEntry::new(name, ty);
+                    } else {
+                        return Ok(i);
+                    }
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        match split_path(path) {
+            ("" | "." | "..", None) => Ok(()), // already exists
+            (name, Some(rest)) => match self.this.upgrade().unwrap().lookup(name)? {
+                node if node.get_attr()?.is_dir() => node.create(rest, ty),
+                _ => Err(VfsError::NotADirectory),
+            },
+            (name, None) if self.child(name).is_some() => Err(VfsError::AlreadyExists),
+            _ => Err(VfsError::PermissionDenied),
+        }
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        match split_path(path) {
+            (name, Some(rest)) => self.this.upgrade().unwrap().lookup(name)?.remove(rest),
+            _ => Err(VfsError::PermissionDenied),
+        }
+    }
+}
+
+/// A file or symbolic link of a synthetic filesystem, whose content is
+/// rendered when read.
+pub struct PseudoFile {
+    ty: VfsNodeType,
+    read: ReadFn,
+    write: Option<WriteFn>,
+}
+
+impl PseudoFile {
+    /// Creates a read-only file whose content is rendered by `read`.
+    pub fn new<R>(read: R) -> Arc<Self>
+    where
+        R: Fn() -> VfsResult<String> + Send + Sync + 'static,
+    {
+        Self::new_inner(VfsNodeType::File, Box::new(read), None)
+    }
+
+    /// Creates a writable file. Each write passes the written text, with
+    /// surrounding whitespace trimmed, to `write`.
+    pub fn new_rw<R, W>(read: R, write: W) -> Arc<Self>
+    where
+        R: Fn() -> VfsResult<String> + Send + Sync + 'static,
+        W: Fn(&str) -> VfsResult + Send + Sync + 'static,
+    {
+        Self::new_inner(VfsNodeType::File, Box::new(read), Some(Box::new(write)))
+    }
+
+    /// Creates a symbolic link whose target is rendered by `target`.
+    pub fn new_symlink<R>(target: R) -> Arc<Self>
+    where
+        R: Fn() -> VfsResult<String> + Send + Sync + 'static,
+    {
+        Self::new_inner(VfsNodeType::SymLink, Box::new(target), None)
+    }
+
+    fn new_inner(ty: VfsNodeType, read: ReadFn, write: Option<WriteFn>) -> Arc<Self> {
+        Arc::new(Self { ty, read, write })
+    }
+}
+
+impl VfsNodeOps for PseudoFile {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = match (self.ty, &self.write) {
+            (VfsNodeType::SymLink, _) => 0o777,
+            (_, Some(_)) => 0o644,
+            (_, None) => 0o444,
+        };
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(perm),
+            self.ty,
+            (self.read)()?.len() as _,
+            0,
+        ))
+    }
+
+    fn fsync(&self) -> VfsResult {
+        Ok(()) // nothing to write back
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        // Allows opening with `O_TRUNC` before writing.
+        match self.write {
+            Some(_) => Ok(()),
+            None => Err(VfsError::PermissionDenied),
+        }
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = (self.read)()?;
+        let content = content.as_bytes();
+        let start = content.len().min(offset as usize);
+        let end = content.len().min(offset as usize + buf.len());
+        let src = &content[start..end];
+        buf[..src.len()].copy_from_slice(src);
+        Ok(src.len())
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let write = self.write.as_ref().ok_or(VfsError::PermissionDenied)?;
+        let text = core::str::from_utf8(buf).map_err(|_| VfsError::InvalidInput)?;
+        write(text.trim())?;
+        Ok(buf.len())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// Creates a writable file of the maximum log level, such as `info`, which is
+/// shared by procfs and sysfs.
+pub fn log_level_file() -> Arc<PseudoFile> {
+    PseudoFile::new_rw(
+        || {
+            let level = log::max_level().as_str().to_ascii_lowercase();
+            Ok(alloc::format!("{}\n", level))
+        },
+        |value| {
+            let level = value.parse().map_err(|_| VfsError::InvalidInput)?;
+            log::set_max_level(level);
+            Ok(())
+        },
+    )
+}
+
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    let trimmed_path = path.trim_start_matches('/');
+    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
+        (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
+    })
+}
@@ -0,0 +1,194 @@
+//! The system filesystem, which presents the devices probed by [`axdriver`]
+//! in the layout of `/sys` of Linux.
+//!
+//! Each device has a directory under `/sys/devices`, linked from its bus in
+//! `/sys/bus` and from its class, e.g. `/sys/block/vda` for block devices and
+//! `/sys/class/net/eth0` for NICs.
+
+use alloc::{format, string::String, sync::Arc};
+
+use axdriver::{prelude::DeviceType, DeviceBus, DeviceInfo};
+
+use super::pseudo::{log_level_file, PseudoDir, PseudoFile, PseudoFileSystem};
+
+/// Creates a sysfs instance.
+pub fn new_sysfs() -> PseudoFileSystem {
+    let root = PseudoDir::new(None);
+    for dev in axdriver::devices() {
+        add_device(&root, dev);
+    }
+
+    let kernel = root.mkdir("kernel");
+    kernel.add("log_level", log_level_file());
+    kernel
+        .mkdir("mm")
+        .mkdir("transparent_hugepage")
+        .add("enabled", text("always madvise [never]"));
+    root.mkdir("devices")
+        .mkdir("system")
+        .mkdir("clocksource")
+        .mkdir("clocksource0")
+        .add("current_clocksource", text(clocksource()));
+    PseudoFileSystem::new(root)
+}
+
+/// Adds the directory of the device, and the links to it from its bus and
+/// class.
+fn add_device(root: &PseudoDir, dev: &DeviceInfo) {
+    let (bus, id) = match dev.bus {
+        DeviceBus::Pci {
+            bus,
+            device,
+            function,
+            ..
+        } => (
+            "pci",
+            format!("0000:{:02x}:{:02x}.{:x}", bus, device, function),
+        ),
+        DeviceBus::Mmio { base, .. } => ("platform", format!("{:x}.virtio_mmio", base)),
+        DeviceBus::Platform => ("platform", format!("{}.{}", dev.driver, dev.index)),
+    };
+    let parent = if bus == "pci" {
+        "pci0000:00"
+    } else {
+        "platform"
+    };
+    let dev_path = format!("devices/{}/{}", parent, id);
+
+    // /sys/devices/<parent>/<id>
+    let dir = root.mkdir("devices").mkdir(parent).mkdir(&id);
+    let driver_path = format!("bus/{}/drivers/{}", bus, dev.driver);
+    dir.add("driver", link(3, &driver_path));
+    if let DeviceBus::Pci {
+        vendor_id,
+        device_id,
+        class,
+        subclass,
+        ..
+    } = dev.bus
+    {
+        dir.add("vendor", text(format!("{:#06x}", vendor_id)));
+        dir.add("device", text(format!("{:#06x}", device_id)));
+        dir.add(
+            "class",
+            text(format!(
+                "{:#08x}",
+                (class as u32) << 16 | (subclass as u32) << 8
+            )),
+        );
+    }
+
+    // /sys/bus/<bus>/devices/<id> and /sys/bus/<bus>/drivers/<driver>/<id>
+    let bus_dir = root.mkdir("bus").mkdir(bus);
+    bus_dir.mkdir("devices").add(&id, link(3, &dev_path));
+    let drivers = bus_dir.mkdir("drivers");
+    drivers.mkdir(&dev.driver).add(&id, link(4, &dev_path));
+
+    let name = dev.name();
+    match dev.device_type {
+        DeviceType::Block => {
+            // /sys/block/<name>, linked from /sys/class/block/<name>
+            let block = root.mkdir("block").mkdir(&name);
+            block.add("device", link(2, &dev_path));
+            let (num_blocks, block_size) = dev.block.unwrap_or_default();
+            block.add("size", text(num_blocks * block_size as u64 / 512));
+            block.add("ro", text(0));
+            block.add("removable", text(0));
+            let queue = block.mkdir("queue");
+            queue.add("logical_block_size", text(block_size));
+            queue.add("hw_sector_size", text(block_size));
+            let class = root.mkdir("class").mkdir("block");
+            class.add(&name, link(2, &format!("block/{}", name)));
+        }
+        DeviceType::Net => {
+            let net = root.mkdir("class").mkdir("net").mkdir(&name);
+            net.add("device", link(3, &dev_path));
+            add_net_attrs(&net, name, dev.mac.unwrap_or_default());
+        }
+        DeviceType::Display => {
+            let fb = root.mkdir("class").mkdir("graphics").mkdir(&name);
+            fb.add("device", link(3, &dev_path));
+        }
+        _ => {}
+    }
+}
+
+/// Adds the attributes of a NIC, whose link is up if the network stack uses
+/// it.
+fn add_net_attrs(dir: &PseudoDir, name: String, mac: [u8; 6]) {
+    let mac = mac.map(|b| format!("{:02x}", b)).join(":");
+    dir.add("address", text(mac));
+    dir.add("mtu", text(1500));
+    let iface = name.clone();
+    dir.add(
+        "operstate",
+        PseudoFile::new(move || Ok(format!("{}\n", if is_up(&iface) { "up" } else { "down" }))),
+    );
+    let iface = name.clone();
+    dir.add(
+        "carrier",
+        PseudoFile::new(move || Ok(format!("{}\n", is_up(&iface) as u8))),
+    );
+
+    #[cfg(feature = "axnet")]
+    {
+        let stats = dir.mkdir("statistics");
+        type Counter = fn(&axnet::InterfaceStats) -> u64;
+        let counters: [(&str, Counter); 6] = [
+            ("rx_bytes", |s| s.rx_bytes),
+            ("rx_packets", |s| s.rx_packets),
+            ("rx_errors", |s| s.rx_errors),
+            ("tx_bytes", |s| s.tx_bytes),
+            ("tx_packets", |s| s.tx_packets),
+            ("tx_errors", |s| s.tx_errors),
+        ];
+        for (file, counter) in counters {
+            let iface = name.clone();
+            stats.add(
+                file,
+                PseudoFile::new(move || {
+                    let value = axnet::interface_stats()
+                        .iter()
+                        .find(|s| s.name == iface)
+                        .map_or(0, counter);
+                    Ok(format!("{}\n", value))
+                }),
+            );
+        }
+    }
+}
+
+#[cfg(feature = "axnet")]
+fn is_up(iface: &str) -> bool {
+    axnet::interface_stats().iter().any(|s| s.name == iface)
+}
+
+#[cfg(not(feature = "axnet"))]
+fn is_up(_iface: &str) -> bool {
+    false
+}
+
+fn clocksource() -> &'static str {
+    if cfg!(target_arch = "x86_64") {
+        "tsc"
+    } else if cfg!(target_arch = "aarch64") {
+        "arch_sys_counter"
+    } else if cfg!(any(target_arch = "riscv32", target_arch = "riscv64")) {
+        "riscv_clocksource"
+    } else {
+        "unknown"
+    }
+}
+
+/// Creates a read-only file of the value, ended with a newline.
+fn text<T: core::fmt::Display>(value: T) -> Arc<PseudoFile> {
+    let content = format!("{}\n", value);
+    PseudoFile::new(move || Ok(content.clone()))
+}
+
+/// Creates a symbolic link to `path` relative to the sysfs root, from a
+/// directory `depth` levels under the root.
+fn link(depth: usize, path: &str) -> Arc<PseudoFile> {
+    let target = "../".repeat(depth) + path;
+    PseudoFile::new_symlink(move || Ok(target.clone()))
+}
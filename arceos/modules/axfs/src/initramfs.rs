@@ -0,0 +1,349 @@
+//! The initial RAM filesystem, unpacked from a cpio archive of the `newc`
+//! format embedded in the kernel image.
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
+use core::time::Duration;
+
+use crate::{fs, mounts};
+
+/// The archive at the path of the `AX_INITRAMFS` environment variable at
+/// build time, or an empty one if it is not set.
+static ARCHIVE: &[u8] = include!(concat!(env!("OUT_DIR"), "/initramfs.rs"));
+
+/// The magic number of the `newc` format, without checksums.
+const MAGIC: &[u8; 6] = b"070701";
+/// The magic number of the `crc` format, whose checksums are not verified.
+const MAGIC_CRC: &[u8; 6] = b"070702";
+/// The size of the header, the magic number followed by 13 fields of 8 hex
+/// digits.
+const HEADER_LEN: usize = 110;
+/// The name of the last entry.
+const TRAILER: &str = "TRAILER!!!";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+/// An entry of the archive.
+struct Entry<'a> {
+    ino: u32,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    nlink: u32,
+    mtime: u32,
+    name: &'a str,
+    data: &'a [u8],
+}
+
+/// Reads the entries of an archive in order, until the trailer.
+struct Reader<'a> {
+    archive: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    const fn new(archive: &'a [u8]) -> Self {
+        Self { archive, pos: 0 }
+    }
+
+    /// Returns the next entry, or `None` after the trailer. It fails with
+    /// [`VfsError::InvalidData`] if the archive is malformed or truncated.
+    fn next_entry(&mut self) -> VfsResult<Option<Entry<'a>>> {
+        let header = self.take(HEADER_LEN)?;
+        if &header[..6] != MAGIC && &header[..6] != MAGIC_CRC {
+            return Err(VfsError::InvalidData);
+        }
+        let field = |i: usize| -> VfsResult<u32> {
+            let hex = &header[6 + i * 8..14 + i * 8];
+            let hex = core::str::from_utf8(hex).map_err(|_| VfsError::InvalidData)?;
+            u32::from_str_radix(hex, 16).map_err(|_| VfsError::InvalidData)
+        };
+        let (ino, mode, uid, gid) = (field(0)?, field(1)?, field(2)?, field(3)?);
+        let (nlink, mtime, size, name_len) = (field(4)?, field(5)?, field(6)?, field(11)?);
+
+        // the name ends with a NUL, then the name and the data are padded to
+        // multiples of 4 bytes
+        let name = self.take(name_len as usize)?;
+        let name = name.strip_suffix(&[0]).ok_or(VfsError::InvalidData)?;
+        let name = core::str::from_utf8(name).map_err(|_| VfsError::InvalidData)?;
+        self.align()?;
+        let data = self.take(size as usize)?;
+        self.align()?;
+        if name == TRAILER {
+            return Ok(None);
+        }
+        Ok(Some(Entry {
+            ino,
+            mode,
+            uid,
+            gid,
+            nlink,
+            mtime,
+            name,
+            data,
+        }))
+    }
+
+    fn take(&mut self, len: usize) -> VfsResult<&'a [u8]> {
+        let end = self.pos.checked_add(len).ok_or(VfsError::InvalidData)?;
+        let bytes = self
+            .archive
+            .get(self.pos..end)
+            .ok_or(VfsError::InvalidData)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn align(&mut self) -> VfsResult {
+        self.take(self.pos.next_multiple_of(4) - self.pos)?;
+        Ok(())
+    }
+}
+
+/// Creates a RAM filesystem with the files of the embedded archive.
+///
+/// The unpacking stops at the first malformed entry, keeping the files
+/// before it.
+pub(crate) fn new_initramfs() -> Arc<dyn VfsOps> {
+    let ramfs = mounts::ramfs();
+    match unpack(&ramfs.root_dir(), ARCHIVE) {
+        Ok(count) => info!("  unpacked {} entries of the initramfs", count),
+        Err(e) => warn!("failed to unpack the initramfs: {:?}", e),
+    }
+    ramfs
+}
+
+/// Unpacks the archive into the directory `root`, keeping the ownership,
+/// permissions and modification times if the filesystem supports them.
+/// Returns the number of the entries unpacked.
+///
+/// Directories, regular files, symbolic links and hard links are supported,
+/// while device nodes, FIFOs and sockets are skipped. An empty archive has no
+/// entries.
+fn unpack(root: &VfsNodeRef, archive: &[u8]) -> VfsResult<usize> {
+    if archive.is_empty() {
+        return Ok(0);
+    }
+    // the first path of each file with hard links
+    let mut links: BTreeMap<u32, String> = BTreeMap::new();
+    let mut dir_times = Vec::new();
+    let mut reader = Reader::new(archive);
+    let mut count = 0;
+    while let Some(entry) = reader.next_entry()? {
+        let path = entry.name.trim_start_matches("./").trim_start_matches('/');
+        if path.is_empty() || path == "." {
+            continue;
+        }
+        let ty = match entry.mode & S_IFMT {
+            S_IFDIR => VfsNodeType::Dir,
+            S_IFREG => VfsNodeType::File,
+            S_IFLNK => VfsNodeType::SymLink,
+            _ => {
+                warn!("skip the special file {:?} in the initramfs", path);
+                continue;
+            }
+        };
+        debug!("unpack {:?} at initramfs: {}", ty, path);
+
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((parent, name)) => (root.clone().lookup(parent)?, name),
+            None => (root.clone(), path),
+        };
+        // the data of hard links is in the last one
+        let first = links.get(&entry.ino).filter(|_| ty == VfsNodeType::File);
+        let node = match first {
+            Some(first) => {
+                let node = root.clone().lookup(first)?;
+                fs::link_node(&dir, name, &node)?;
+                node
+            }
+            None => {
+                match dir.create(name, ty) {
+                    Err(VfsError::AlreadyExists) if ty == VfsNodeType::Dir => {}
+                    res => res?,
+                }
+                dir.clone().lookup(name)?
+            }
+        };
+        if ty == VfsNodeType::File && entry.nlink > 1 {
+            links.entry(entry.ino).or_insert_with(|| path.into());
+        }
+        let mut written = 0;
+        while written < entry.data.len() {
+            match node.write_at(written as u64, &entry.data[written..])? {
+                0 => return Err(VfsError::WriteZero),
+                n => written += n,
+            }
+        }
+
+        let perm = VfsNodePerm::from_bits_truncate((entry.mode & 0o777) as u16);
+        unsupported_ok(fs::set_node_perm(&node, perm))?;
+        unsupported_ok(fs::set_node_owner(&node, Some(entry.uid), Some(entry.gid)))?;
+        // the directories are changed by the entries in them later
+        let mtime = Duration::from_secs(entry.mtime as u64);
+        if ty == VfsNodeType::Dir {
+            dir_times.push((node, mtime));
+        } else {
+            unsupported_ok(fs::set_node_times(&node, Some(mtime), Some(mtime)))?;
+        }
+        count += 1;
+    }
+    for (dir, mtime) in dir_times {
+        unsupported_ok(fs::set_node_times(&dir, Some(mtime), Some(mtime)))?;
+    }
+    Ok(count)
+}
+
+/// Ignores the attributes not kept by the filesystem.
+fn unsupported_ok(res: VfsResult) -> VfsResult {
+    match res {
+        Err(VfsError::Unsupported) => Ok(()),
+        res => res,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{format, vec};
+
+    const FILE: u32 = S_IFREG | 0o644;
+    const DIR: u32 = S_IFDIR | 0o755;
+
+    /// Appends an entry of the `newc` format to the archive.
+    fn push(archive: &mut Vec<u8>, ino: u32, mode: u32, nlink: u32, name: &str, data: &[u8]) {
+        let len = data.len() as u32;
+        let fields = [ino, mode, 5, 6, nlink, 1_700_000_000, len, 0, 0, 0, 0];
+        archive.extend_from_slice(MAGIC);
+        for field in fields.into_iter().chain([name.len() as u32 + 1, 0]) {
+            archive.extend_from_slice(format!("{:08x}", field).as_bytes());
+        }
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+        archive.extend_from_slice(data);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+    }
+
+    fn with_trailer(mut archive: Vec<u8>) -> Vec<u8> {
+        push(&mut archive, 0, 0, 1, TRAILER, b"");
+        archive
+    }
+
+    fn read(root: &VfsNodeRef, path: &str) -> Vec<u8> {
+        let node = root.clone().lookup(path).unwrap();
+        let mut buf = vec![0; node.get_attr().unwrap().size() as usize];
+        let len = node.read_at(0, &mut buf).unwrap();
+        buf.truncate(len);
+        buf
+    }
+
+    #[test]
+    fn test_header() {
+        let mut archive = Vec::new();
+        push(&mut archive, 42, FILE, 1, "etc/hosts", b"127.0.0.1");
+        let archive = with_trailer(archive);
+        let mut reader = Reader::new(&archive);
+        let entry = reader.next_entry().unwrap().unwrap();
+        assert_eq!((entry.ino, entry.mode, entry.nlink), (42, FILE, 1));
+        assert_eq!((entry.uid, entry.gid, entry.mtime), (5, 6, 1_700_000_000));
+        assert_eq!((entry.name, entry.data), ("etc/hosts", &b"127.0.0.1"[..]));
+        assert!(reader.next_entry().unwrap().is_none());
+
+        // the checksums of the `crc` format are ignored
+        let mut crc = archive.clone();
+        crc[..6].copy_from_slice(MAGIC_CRC);
+        assert!(Reader::new(&crc).next_entry().unwrap().is_some());
+        let mut bad = archive;
+        bad[5] = b'7';
+        assert_eq!(
+            Reader::new(&bad).next_entry().err(),
+            Some(VfsError::InvalidData)
+        );
+    }
+
+    #[test]
+    fn test_alignment() {
+        // names and data of every length modulo 4
+        let mut archive = Vec::new();
+        for i in 1..=8 {
+            let name = "n".repeat(i);
+            push(&mut archive, i as u32, FILE, 1, &name, &vec![i as u8; i]);
+        }
+        let archive = with_trailer(archive);
+        assert_eq!(archive.len() % 4, 0);
+        let mut reader = Reader::new(&archive);
+        for i in 1..=8 {
+            let entry = reader.next_entry().unwrap().unwrap();
+            assert_eq!(entry.name.len(), i);
+            assert_eq!(entry.data, vec![i as u8; i]);
+        }
+        assert!(reader.next_entry().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_trailer() {
+        // the entries after the trailer are ignored
+        let mut archive = with_trailer(Vec::new());
+        push(&mut archive, 1, FILE, 1, "after", b"x");
+        let root = fs::ramfs::RamFileSystem::new().root_dir();
+        assert_eq!(unpack(&root, &archive), Ok(0));
+        assert!(root.lookup("after").is_err());
+
+        // no trailer
+        let mut archive = Vec::new();
+        push(&mut archive, 1, FILE, 1, "file", b"x");
+        let root = fs::ramfs::RamFileSystem::new().root_dir();
+        assert_eq!(unpack(&root, &archive), Err(VfsError::InvalidData));
+        assert_eq!(unpack(&root, &[]), Ok(0));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut archive = Vec::new();
+        push(&mut archive, 1, FILE, 1, "file", b"data");
+        let archive = with_trailer(archive);
+        for len in 1..archive.len() {
+            let root = fs::ramfs::RamFileSystem::new().root_dir();
+            assert_eq!(unpack(&root, &archive[..len]), Err(VfsError::InvalidData));
+        }
+        // a size beyond the end
+        let mut archive = Vec::new();
+        push(&mut archive, 1, FILE, 1, "file", b"data");
+        archive[6 + 6 * 8..6 + 7 * 8].copy_from_slice(b"ffffffff");
+        let mut reader = Reader::new(&archive);
+        assert_eq!(reader.next_entry().err(), Some(VfsError::InvalidData));
+    }
+
+    #[test]
+    fn test_unpack() {
+        let mut archive = Vec::new();
+        push(&mut archive, 1, DIR, 2, ".", b"");
+        push(&mut archive, 2, S_IFDIR | 0o700, 2, "sbin", b"");
+        push(&mut archive, 3, FILE | 0o755, 1, "sbin/init", b"init");
+        push(&mut archive, 4, S_IFLNK | 0o777, 1, "sbin/sh", b"init");
+        push(&mut archive, 5, DIR, 2, "./etc", b"");
+        push(&mut archive, 6, FILE, 2, "etc/a", b"");
+        push(&mut archive, 6, FILE, 2, "etc/b", b"shared");
+        push(&mut archive, 7, 0o020000 | 0o600, 1, "etc/console", b"");
+        let archive = with_trailer(archive);
+
+        let root = fs::ramfs::RamFileSystem::new().root_dir();
+        assert_eq!(unpack(&root, &archive), Ok(6));
+        assert_eq!(read(&root, "sbin/init"), b"init");
+        assert_eq!(read(&root, "etc/a"), b"shared");
+        assert_eq!(read(&root, "etc/b"), b"shared");
+        let link = root.clone().lookup("sbin/sh").unwrap();
+        assert_eq!(link.get_attr().unwrap().file_type(), VfsNodeType::SymLink);
+        assert_eq!(read(&root, "sbin/sh"), b"init");
+        let sbin = root.clone().lookup("sbin").unwrap();
+        assert_eq!(sbin.get_attr().unwrap().perm().bits(), 0o700);
+        let meta = fs::node_meta(&sbin).unwrap();
+        assert_eq!((meta.uid, meta.gid), (5, 6));
+        assert_eq!(meta.mtime, Duration::from_secs(1_700_000_000));
+        assert!(root.lookup("etc/console").is_err());
+    }
+}
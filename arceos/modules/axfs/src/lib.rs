@@ -6,10 +6,37 @@
 //!
 //! - `fatfs`: Use [FAT] as the main filesystem and mount it on `/`. This feature
 //!    is **enabled** by default.
-//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`. This feature is
-//!    **enabled** by default.
-//! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`. This feature is
-//!    **enabled** by default.
+//! - `ext4`: Detect [ext2/3/4] on the disk by its superblock, and use it as the
+//!    main filesystem instead of FAT. ext2 volumes are writable, while volumes
+//!    with ext4 features (e.g. extents) are read-only. This feature is
+//!    **disabled** by default.
+//! - `devfs`: Mount a device filesystem on `/dev`, with the standard devices,
+//!    the console, a node for each disk and partition (e.g. `vda1`), and those
+//!    registered by [`api::register_device`]. `fb0` is added by the
+//!    `axdisplay` feature. `random` and `urandom` are not cryptographically
+//!    secure. This feature is **enabled** by default.
+//! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`, limited to half
+//!    of the physical memory. Its files are sparse, and it keeps their
+//!    ownership, permissions and timestamps, which can be changed by
+//!    [`api::set_permissions`], [`api::chown`] and [`api::set_times`]. This
+//!    feature is **enabled** by default.
+//! - `overlayfs`: Overlay the main filesystem with a RAM filesystem, so that
+//!    the disk is never modified and the changes are lost on reboot. Other
+//!    overlays can be mounted with the `overlay` type. This feature is
+//!    **disabled** by default.
+//! - `initramfs`: Unpack the cpio archive of the `newc` format, at the path
+//!    of the `AX_INITRAMFS` environment variable at build time, into a RAM
+//!    filesystem, which is the root filesystem if there is no disk, or the
+//!    upper layer of the overlay on the disk with the `overlayfs` feature.
+//!    This feature is **disabled** by default.
+//! - `procfs`: Mount a synthetic filesystem on `/proc`, whose files render
+//!    the kernel state when read, such as `meminfo`, `mounts` and a directory
+//!    per task. The entries for memory, tasks, interrupts and networking are
+//!    enabled by the `axalloc`, `axtask`, `irq` and `axnet` features
+//!    respectively. This feature is **enabled** by default.
+//! - `sysfs`: Mount a synthetic filesystem on `/sys`, which presents the
+//!    devices probed by [`axdriver`] under `block`, `bus`, `class` and
+//!    `devices`. This feature is **enabled** by default.
 //! - `myfs`: Allow users to define their custom filesystems to override the
 //!    default. In this case, [`MyFileSystemIf`] is required to be implemented
 //!    to create and initialize other filesystems. This feature is **disabled** by
@@ -17,6 +44,7 @@
 //!    both are enabled.
 //!
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
+//! [ext2/3/4]: https://en.wikipedia.org/wiki/Extended_file_system
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
 
 #![cfg_attr(all(not(test), not(doc)), no_std)]
@@ -28,6 +56,9 @@ extern crate alloc;
 
 mod dev;
 mod fs;
+#[cfg(feature = "initramfs")]
+mod initramfs;
+mod lock;
 mod mounts;
 mod root;
 
@@ -36,11 +67,59 @@ pub mod fops;
 
 use axdriver::{prelude::*, AxDeviceContainer};
 
+/// The disk or partition of the root filesystem, see [`init_filesystems`].
+const ROOT: &str = match option_env!("AX_ROOT") {
+    Some(root) => root,
+    None => "",
+};
+
 /// Initializes filesystems by block devices.
+///
+/// All block devices and their partitions are registered by name, such as
+/// `vda`, `vda1` and `vdb`, which can be mounted by [`api::mount_fs`].
+///
+/// The root filesystem is on the disk or partition selected by the `AX_ROOT`
+/// environment variable at build time, which is one of:
+///
+/// - a device name, such as `vda` or `vda2`, optionally prefixed by `/dev/`;
+/// - a partition number of the first disk, such as `2`;
+/// - `PARTUUID=<uuid>`, the unique ID of a partition, case-insensitive;
+/// - `PARTLABEL=<label>`, the name of a GPT partition.
+///
+/// By default, it is the first partition of the first disk, or the disk as a
+/// whole if it has no MBR or GPT partition table.
+///
+/// Without any block device, the root filesystem is the initramfs with the
+/// `initramfs` feature.
+///
+/// Then the filesystems listed in `/etc/fstab` of the root filesystem are
+/// mounted, see [`api::mount_fstab`].
 pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
     info!("Initialize filesystems...");
 
-    let dev = blk_devs.take_one().expect("No block device found!");
-    info!("  use block device 0: {:?}", dev.device_name());
-    self::root::init_rootfs(self::dev::Disk::new(dev));
+    let mut disks = alloc::vec::Vec::new();
+    while let Some(dev) = blk_devs.take_one() {
+        info!("  block device {}: {:?}", disks.len(), dev.device_name());
+        disks.push(self::dev::Disk::new(dev));
+    }
+    let has_disk = !disks.is_empty();
+    #[cfg(not(feature = "initramfs"))]
+    assert!(has_disk, "No block device found!");
+    self::dev::register_disks(disks);
+    let disk = has_disk.then(|| {
+        let (name, disk) = self::dev::select_root(ROOT)
+            .unwrap_or_else(|| panic!("root device {:?} not found", ROOT));
+        info!("  use {} as the root device", name);
+        disk
+    });
+
+    #[cfg(feature = "devfs")]
+    self::fs::devfs::init();
+    self::root::init_rootfs(disk);
+
+    if let Err(e) = self::api::mount_fstab("/etc/fstab") {
+        if e != axerrno::AxError::NotFound {
+            warn!("failed to read /etc/fstab: {:?}", e);
+        }
+    }
 }
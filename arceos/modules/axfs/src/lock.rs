@@ -0,0 +1,481 @@
+//! Advisory file locks: whole-file locks of `flock` and byte-range record
+//! locks of `fcntl`, which do not interact with each other.
+//!
+//! The locks of a file are kept while they are held or waited for, in a table
+//! keyed by [`LockKey`]. The `flock` locks are owned by the opened files, and
+//! the record locks by the tasks, since there is only one process. A task
+//! waiting for a lock sleeps on the wait queue of the file, which is notified
+//! whenever a lock of the file is released or changed.
+
+use alloc::{collections::BTreeMap, collections::BTreeSet, string::String, vec::Vec};
+use axerrno::{ax_err, AxResult};
+use axsync::spin::{SpinNoIrq, SpinNoIrqGuard};
+
+/// Identifies a file among the opened ones.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum LockKey {
+    /// A node that is the same for every lookup of the file, by its address.
+    Node(usize),
+    /// An inode, by the address of the filesystem and the inode number.
+    Inode(usize, u64),
+    /// A file of a filesystem that creates a node at each lookup, by its
+    /// absolute path.
+    Path(String),
+}
+
+/// The kind of a lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    /// A whole-file lock of `flock`, owned by an opened file.
+    Flock,
+    /// A record lock of `fcntl`, owned by a task.
+    Record,
+}
+
+/// A held lock, on the bytes from `start` to `end` (exclusive) of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Lock {
+    pub owner: u64,
+    pub exclusive: bool,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Lock {
+    fn conflicts(&self, other: &Lock) -> bool {
+        self.owner != other.owner
+            && (self.exclusive || other.exclusive)
+            && self.start < other.end
+            && other.start < self.end
+    }
+}
+
+/// The tasks waiting for the locks of a file, which is never waited for
+/// without multitasking.
+#[derive(Clone)]
+struct Queue {
+    #[cfg(feature = "axtask")]
+    inner: alloc::sync::Arc<axtask::WaitQueue>,
+}
+
+impl Queue {
+    fn new() -> Self {
+        Self {
+            #[cfg(feature = "axtask")]
+            inner: alloc::sync::Arc::new(axtask::WaitQueue::new()),
+        }
+    }
+
+    fn notify_all(&self) {
+        #[cfg(feature = "axtask")]
+        self.inner.notify_all(false);
+    }
+
+    fn wait_until(&self, condition: impl Fn() -> bool) {
+        #[cfg(feature = "axtask")]
+        self.inner.wait_until(condition);
+        #[cfg(not(feature = "axtask"))]
+        while !condition() {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// The locks of a file.
+struct FileLocks {
+    flocks: Vec<Lock>,
+    records: Vec<Lock>,
+    /// The number of tasks waiting for the locks.
+    waiters: usize,
+    queue: Queue,
+}
+
+impl FileLocks {
+    fn new() -> Self {
+        Self {
+            flocks: Vec::new(),
+            records: Vec::new(),
+            waiters: 0,
+            queue: Queue::new(),
+        }
+    }
+
+    fn held(&self, kind: Kind) -> &Vec<Lock> {
+        match kind {
+            Kind::Flock => &self.flocks,
+            Kind::Record => &self.records,
+        }
+    }
+
+    fn held_mut(&mut self, kind: Kind) -> &mut Vec<Lock> {
+        match kind {
+            Kind::Flock => &mut self.flocks,
+            Kind::Record => &mut self.records,
+        }
+    }
+
+    fn is_unused(&self) -> bool {
+        self.flocks.is_empty() && self.records.is_empty() && self.waiters == 0
+    }
+}
+
+struct LockTable {
+    files: BTreeMap<LockKey, FileLocks>,
+    /// The record locks that the tasks are waiting for, to detect deadlocks.
+    waiting: BTreeMap<u64, (LockKey, Lock)>,
+}
+
+impl LockTable {
+    /// Returns the owners of the locks conflicting with `lock`.
+    fn blockers(&self, key: &LockKey, kind: Kind, lock: &Lock) -> Vec<u64> {
+        self.files.get(key).map_or_else(Vec::new, |locks| {
+            let held = locks.held(kind).iter();
+            held.filter(|l| l.conflicts(lock))
+                .map(|l| l.owner)
+                .collect()
+        })
+    }
+
+    /// Returns whether `owner` waiting for the `blockers` would deadlock, as
+    /// one of them is waiting for `owner`, directly or through others.
+    fn would_deadlock(&self, owner: u64, blockers: Vec<u64>) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut stack = blockers;
+        while let Some(blocker) = stack.pop() {
+            if blocker == owner {
+                return true;
+            }
+            if !visited.insert(blocker) {
+                continue;
+            }
+            if let Some((key, lock)) = self.waiting.get(&blocker) {
+                stack.extend(self.blockers(key, Kind::Record, lock));
+            }
+        }
+        false
+    }
+}
+
+/// A spinlock, as the wait conditions are checked with the run queue locked.
+static LOCKS: SpinNoIrq<LockTable> = SpinNoIrq::new(LockTable {
+    files: BTreeMap::new(),
+    waiting: BTreeMap::new(),
+});
+
+/// Returns the owner of the record locks, the current task.
+pub(crate) fn current_task() -> u64 {
+    #[cfg(feature = "axtask")]
+    return axtask::current().id().as_u64();
+    #[cfg(not(feature = "axtask"))]
+    return 0;
+}
+
+/// Acquires `lock` on the file, replacing the locks of its owner on its
+/// range. It waits for the conflicting locks to be released if `wait` is set,
+/// or fails with [`WouldBlock`] otherwise.
+///
+/// It fails with [`ResourceBusy`] if waiting would deadlock, i.e. the owner
+/// of a conflicting record lock is waiting for a lock of this owner, directly
+/// or through others. The `flock` locks are not checked like Linux, but
+/// waiting always fails without multitasking, as no other task can release
+/// the locks.
+///
+/// [`WouldBlock`]: axerrno::AxError::WouldBlock
+/// [`ResourceBusy`]: axerrno::AxError::ResourceBusy
+pub(crate) fn lock(key: &LockKey, kind: Kind, lock: Lock, wait: bool) -> AxResult {
+    let mut table = LOCKS.lock();
+    loop {
+        let blockers = table.blockers(key, kind, &lock);
+        if blockers.is_empty() {
+            let locks = table
+                .files
+                .entry(key.clone())
+                .or_insert_with(FileLocks::new);
+            replace_range(locks.held_mut(kind), &lock, Some(lock));
+            // a converted lock may let others in
+            let queue = locks.queue.clone();
+            drop(table);
+            queue.notify_all();
+            return Ok(());
+        }
+        if !wait {
+            return ax_err!(WouldBlock);
+        }
+        let deadlock = kind == Kind::Record && table.would_deadlock(lock.owner, blockers);
+        if deadlock || cfg!(not(feature = "axtask")) {
+            if table.files.get(key).is_some_and(FileLocks::is_unused) {
+                table.files.remove(key); // left by a previous wait
+            }
+            return ax_err!(ResourceBusy);
+        }
+        if kind == Kind::Flock {
+            // like Linux, a converted lock is released before waiting, so
+            // that two tasks converting their shared locks do not deadlock
+            if let Some(locks) = table.files.get_mut(key) {
+                locks.flocks.retain(|l| l.owner != lock.owner);
+            }
+        }
+        table = wait_for(table, key, kind, &lock);
+    }
+}
+
+/// Releases the locks of `owner` on the range from `start` to `end`.
+pub(crate) fn unlock(key: &LockKey, kind: Kind, owner: u64, start: u64, end: u64) {
+    let range = Lock {
+        owner,
+        exclusive: false,
+        start,
+        end,
+    };
+    update(key, |locks| {
+        replace_range(locks.held_mut(kind), &range, None)
+    });
+}
+
+/// Releases the `flock` lock of the opened file `file`, and the record locks
+/// of the current task on the file, as the file is closed.
+pub(crate) fn release(key: &LockKey, file: u64) {
+    let task = current_task();
+    update(key, |locks| {
+        locks.flocks.retain(|l| l.owner != file);
+        locks.records.retain(|l| l.owner != task);
+    });
+}
+
+/// Returns the first record lock of another task conflicting with `lock`.
+pub(crate) fn find_conflict(key: &LockKey, lock: &Lock) -> Option<Lock> {
+    let table = LOCKS.lock();
+    let locks = table.files.get(key)?;
+    locks.records.iter().find(|l| l.conflicts(lock)).copied()
+}
+
+/// Replaces the locks of the owner of `range` on its range with `new`, keeping
+/// the parts of them out of the range.
+fn replace_range(held: &mut Vec<Lock>, range: &Lock, new: Option<Lock>) {
+    let mut kept = Vec::with_capacity(held.len() + 2);
+    for l in held.drain(..) {
+        if l.owner != range.owner || l.end <= range.start || range.end <= l.start {
+            kept.push(l);
+            continue;
+        }
+        if l.start < range.start {
+            kept.push(Lock {
+                end: range.start,
+                ..l
+            });
+        }
+        if range.end < l.end {
+            kept.push(Lock {
+                start: range.end,
+                ..l
+            });
+        }
+    }
+    kept.extend(new);
+    *held = kept;
+}
+
+/// Changes the locks of the file by `f`, and wakes up the waiting tasks.
+fn update(key: &LockKey, f: impl FnOnce(&mut FileLocks)) {
+    let mut table = LOCKS.lock();
+    let Some(locks) = table.files.get_mut(key) else {
+        return;
+    };
+    f(locks);
+    let queue = locks.queue.clone();
+    if locks.is_unused() {
+        table.files.remove(key);
+    }
+    drop(table);
+    queue.notify_all();
+}
+
+/// Sleeps until `lock` does not conflict with the locks of the file, and
+/// returns the table locked again.
+fn wait_for<'a>(
+    mut table: SpinNoIrqGuard<'a, LockTable>,
+    key: &LockKey,
+    kind: Kind,
+    lock: &Lock,
+) -> SpinNoIrqGuard<'a, LockTable> {
+    let locks = table
+        .files
+        .entry(key.clone())
+        .or_insert_with(FileLocks::new);
+    locks.waiters += 1;
+    let queue = locks.queue.clone();
+    if kind == Kind::Record {
+        table.waiting.insert(lock.owner, (key.clone(), *lock));
+    }
+    drop(table);
+    queue.notify_all(); // for a released converted lock
+
+    queue.wait_until(|| LOCKS.lock().blockers(key, kind, lock).is_empty());
+
+    let mut table = LOCKS.lock();
+    if kind == Kind::Record {
+        table.waiting.remove(&lock.owner);
+    }
+    if let Some(locks) = table.files.get_mut(key) {
+        locks.waiters -= 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axerrno::AxError;
+
+    fn record(owner: u64, exclusive: bool, start: u64, end: u64) -> Lock {
+        Lock {
+            owner,
+            exclusive,
+            start,
+            end,
+        }
+    }
+
+    fn records(key: &LockKey) -> Vec<Lock> {
+        let table = LOCKS.lock();
+        let mut held = table
+            .files
+            .get(key)
+            .map_or_else(Vec::new, |l| l.records.clone());
+        held.sort_by_key(|l| (l.owner, l.start));
+        held
+    }
+
+    #[test]
+    fn test_replace_range() {
+        let mut held = vec![record(1, true, 0, 100), record(2, false, 0, 100)];
+        // split by an unlock in the middle
+        replace_range(&mut held, &record(1, false, 10, 20), None);
+        assert_eq!(
+            held,
+            [
+                record(1, true, 0, 10),
+                record(1, true, 20, 100),
+                record(2, false, 0, 100),
+            ]
+        );
+        // a lock over the ends replaces the parts in it
+        let shared = record(1, false, 5, 30);
+        replace_range(&mut held, &shared, Some(shared));
+        assert_eq!(
+            held,
+            [
+                record(1, true, 0, 5),
+                record(1, true, 30, 100),
+                record(2, false, 0, 100),
+                shared,
+            ]
+        );
+        // the whole range
+        replace_range(&mut held, &record(1, false, 0, u64::MAX), None);
+        assert_eq!(held, [record(2, false, 0, 100)]);
+    }
+
+    #[test]
+    fn test_record_locks() {
+        let key = LockKey::Path("/test_record_locks".into());
+        assert_eq!(
+            lock(&key, Kind::Record, record(11, false, 0, 100), false),
+            Ok(())
+        );
+        assert_eq!(
+            lock(&key, Kind::Record, record(12, false, 50, 150), false),
+            Ok(())
+        );
+        // upgrading a part conflicts with the shared lock of the other owner
+        assert_eq!(
+            lock(&key, Kind::Record, record(11, true, 40, 60), false),
+            Err(AxError::WouldBlock)
+        );
+        assert_eq!(
+            lock(&key, Kind::Record, record(11, true, 10, 20), false),
+            Ok(())
+        );
+        assert_eq!(
+            records(&key),
+            [
+                record(11, false, 0, 10),
+                record(11, true, 10, 20),
+                record(11, false, 20, 100),
+                record(12, false, 50, 150),
+            ]
+        );
+
+        // F_GETLK
+        assert_eq!(find_conflict(&key, &record(12, false, 20, 50)), None);
+        assert_eq!(
+            find_conflict(&key, &record(12, true, 0, 50)),
+            Some(record(11, false, 0, 10))
+        );
+        assert_eq!(
+            find_conflict(&key, &record(13, false, 15, 16)),
+            Some(record(11, true, 10, 20))
+        );
+        assert_eq!(find_conflict(&key, &record(11, true, 0, 50)), None);
+
+        unlock(&key, Kind::Record, 12, 0, u64::MAX);
+        assert_eq!(
+            lock(&key, Kind::Record, record(11, true, 40, 60), false),
+            Ok(())
+        );
+        // the flock locks are independent
+        assert_eq!(
+            lock(&key, Kind::Flock, record(12, true, 0, u64::MAX), false),
+            Ok(())
+        );
+        unlock(&key, Kind::Flock, 12, 0, u64::MAX);
+        unlock(&key, Kind::Record, 11, 0, u64::MAX);
+        assert!(!LOCKS.lock().files.contains_key(&key));
+    }
+
+    #[test]
+    fn test_deadlock() {
+        let key = LockKey::Path("/test_deadlock".into());
+        assert_eq!(
+            lock(&key, Kind::Record, record(21, true, 0, 10), false),
+            Ok(())
+        );
+        assert_eq!(
+            lock(&key, Kind::Record, record(22, true, 20, 30), false),
+            Ok(())
+        );
+        assert!(!LOCKS.lock().would_deadlock(21, vec![22]));
+
+        // 22 waits for the lock of 21, so 21 waiting for 22 would deadlock
+        let wanted = record(22, true, 5, 6);
+        LOCKS.lock().waiting.insert(22, (key.clone(), wanted));
+        assert!(LOCKS.lock().would_deadlock(21, vec![22]));
+        assert!(!LOCKS.lock().would_deadlock(23, vec![22]));
+        assert_eq!(
+            lock(&key, Kind::Record, record(21, true, 25, 26), true),
+            Err(AxError::ResourceBusy)
+        );
+        // through another owner: 22 waits for 23, which waits for 21
+        assert_eq!(
+            lock(&key, Kind::Record, record(23, true, 40, 50), false),
+            Ok(())
+        );
+        let mut table = LOCKS.lock();
+        table
+            .waiting
+            .insert(22, (key.clone(), record(22, true, 45, 46)));
+        table
+            .waiting
+            .insert(23, (key.clone(), record(23, true, 5, 6)));
+        drop(table);
+        assert_eq!(
+            lock(&key, Kind::Record, record(21, true, 25, 26), true),
+            Err(AxError::ResourceBusy)
+        );
+
+        LOCKS.lock().waiting.clear();
+        for owner in 21..=23 {
+            unlock(&key, Kind::Record, owner, 0, u64::MAX);
+        }
+    }
+}
@@ -1,80 +1,185 @@
-use alloc::sync::Arc;
-use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
+use alloc::{format, string::String, sync::Arc};
+use axerrno::{ax_err, ax_err_type, AxResult};
+use axfs_vfs::VfsOps;
 
 use crate::fs;
 
-#[cfg(feature = "devfs")]
-pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
-    let null = fs::devfs::NullDev;
-    let zero = fs::devfs::ZeroDev;
-    let bar = fs::devfs::ZeroDev;
-    let devfs = fs::devfs::DeviceFileSystem::new();
-    let foo_dir = devfs.mkdir("foo");
-    devfs.add("null", Arc::new(null));
-    devfs.add("zero", Arc::new(zero));
-    foo_dir.add("bar", Arc::new(bar));
-    Arc::new(devfs)
+/// The filesystem types on disks, where `auto` detects it by the superblock.
+const DISK_FSTYPES: &[&str] = &["auto", "vfat", "fat", "ext2", "ext3", "ext4"];
+
+/// Creates a filesystem of the type `fstype` from `source`, and mounts it on
+/// the existing directory `path`.
+///
+/// `source` is the name of the disk or partition for the filesystems on
+/// disks, see [`crate::dev::find_disk`], which is recorded as `/dev/<name>`
+/// in the mount table. It is ignored by the virtual filesystems.
+///
+/// `options` are comma-separated, of which only `size=` of tmpfs, and
+/// `lowerdir=` and `upperdir=` of overlay are supported, see
+/// [`ramfs_with_options`] and [`overlay_with_options`]. The others are
+/// ignored.
+pub(crate) fn mount_fs(source: &str, path: &str, fstype: &str, options: &str) -> AxResult {
+    if DISK_FSTYPES.contains(&fstype) {
+        let (name, fs, fstype) = disk_fs(source, fstype)?;
+        crate::root::mount(path, fs, &format!("/dev/{}", name), fstype)
+    } else {
+        let fs = new_fs(source, fstype, options)?;
+        crate::root::mount(path, fs, source, fstype)
+    }
 }
 
-#[cfg(feature = "ramfs")]
-pub(crate) fn ramfs() -> Arc<fs::ramfs::RamFileSystem> {
-    Arc::new(fs::ramfs::RamFileSystem::new())
+/// Creates a virtual filesystem of the type `fstype` to be mounted at
+/// runtime.
+///
+/// `source` is the device to mount, which is ignored by the virtual
+/// filesystems.
+#[allow(unused_variables)]
+fn new_fs(source: &str, fstype: &str, options: &str) -> AxResult<Arc<dyn VfsOps>> {
+    debug!("create {} filesystem from {:?}", fstype, source);
+    match fstype {
+        #[cfg(feature = "devfs")]
+        "devfs" | "devtmpfs" => Ok(devfs()),
+        #[cfg(feature = "ramfs")]
+        "ramfs" | "tmpfs" => Ok(ramfs_with_options(options)?),
+        #[cfg(feature = "overlayfs")]
+        "overlay" => Ok(overlay_with_options(options)?),
+        #[cfg(feature = "procfs")]
+        "proc" => Ok(procfs()),
+        #[cfg(feature = "sysfs")]
+        "sysfs" => Ok(sysfs()),
+        _ => ax_err!(Unsupported, "unknown filesystem type"),
+    }
 }
 
-#[cfg(feature = "procfs")]
-pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
-    let procfs = fs::ramfs::RamFileSystem::new();
-    let proc_root = procfs.root_dir();
+/// Opens the filesystem of the type `fstype` on the registered disk or
+/// partition `source`. Returns the name of the device, the filesystem and
+/// its type.
+///
+/// It fails with [`ResourceBusy`] if the device, one of its partitions or
+/// the disk of it is in use by the root or another mounted filesystem.
+///
+/// [`ResourceBusy`]: axerrno::AxError::ResourceBusy
+#[allow(unused_mut, unused_variables)]
+fn disk_fs<'a>(source: &str, fstype: &'a str) -> AxResult<(String, Arc<dyn VfsOps>, &'a str)> {
+    debug!("open {} filesystem on {:?}", fstype, source);
+    let (name, mut disk) =
+        crate::dev::find_disk(source).ok_or_else(|| ax_err_type!(NotFound, "no such device"))?;
+    let mounts = crate::root::mount_table();
+    if crate::dev::is_in_use(&name, mounts.iter().map(|mp| mp.source.as_str())) {
+        return ax_err!(ResourceBusy, "device is in use");
+    }
 
-    // Create /proc/sys/net/core/somaxconn
-    proc_root.create("sys", VfsNodeType::Dir)?;
-    proc_root.create("sys/net", VfsNodeType::Dir)?;
-    proc_root.create("sys/net/core", VfsNodeType::Dir)?;
-    proc_root.create("sys/net/core/somaxconn", VfsNodeType::File)?;
-    let file_somaxconn = proc_root.clone().lookup("./sys/net/core/somaxconn")?;
-    file_somaxconn.write_at(0, b"4096\n")?;
+    #[cfg(all(feature = "ext4", not(feature = "myfs")))]
+    if matches!(fstype, "auto" | "ext2" | "ext3" | "ext4")
+        && fs::ext4::Ext4FileSystem::probe(&mut disk)
+    {
+        let fs = fs::ext4::Ext4FileSystem::new(disk)?;
+        return Ok((name, Arc::new(fs), "ext4"));
+    }
+    #[cfg(all(feature = "fatfs", not(feature = "myfs")))]
+    if matches!(fstype, "auto" | "vfat" | "fat") {
+        return Ok((name, fs::fatfs::FatFileSystem::open(disk)?, "vfat"));
+    }
+    ax_err!(InvalidData, "no supported filesystem found on the device")
+}
 
-    // Create /proc/sys/vm/overcommit_memory
-    proc_root.create("sys/vm", VfsNodeType::Dir)?;
-    proc_root.create("sys/vm/overcommit_memory", VfsNodeType::File)?;
-    let file_over = proc_root.clone().lookup("./sys/vm/overcommit_memory")?;
-    file_over.write_at(0, b"0\n")?;
+/// Mounts the filesystems listed in the `fstab` file at `path`, whose lines
+/// are `<source> <target> <fstype> [<options> ...]`. Empty lines and those
+/// starting with `#` are skipped, and so are the entries of `/` and those
+/// with the `noauto` option. The other options are passed to [`mount_fs`].
+///
+/// Failed entries are logged and skipped.
+pub(crate) fn mount_fstab(path: &str) -> AxResult {
+    let fstab = crate::api::read_to_string(path)?;
+    for line in fstab.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: alloc::vec::Vec<_> = line.split_whitespace().collect();
+        let [source, target, fstype, rest @ ..] = fields.as_slice() else {
+            warn!("invalid fstab entry: {:?}", line);
+            continue;
+        };
+        let options = rest.first().copied().unwrap_or_default();
+        if *target == "/" || options.split(',').any(|opt| opt == "noauto") {
+            continue;
+        }
+        match mount_fs(source, target, fstype, options) {
+            Ok(()) => info!("  mount {} on {} ({})", source, target, fstype),
+            Err(e) => warn!("failed to mount {} on {}: {:?}", source, target, e),
+        }
+    }
+    Ok(())
+}
 
-    // Create /proc/self/stat
-    proc_root.create("self", VfsNodeType::Dir)?;
-    proc_root.create("self/stat", VfsNodeType::File)?;
+#[cfg(feature = "devfs")]
+pub(crate) fn devfs() -> Arc<fs::pseudo::PseudoFileSystem> {
+    Arc::new(fs::devfs::new_devfs())
+}
 
-    Ok(Arc::new(procfs))
+/// Creates the RAM filesystem of `/tmp`, limited to half of the physical
+/// memory like tmpfs of Linux.
+#[cfg(feature = "ramfs")]
+pub(crate) fn ramfs() -> Arc<fs::ramfs::RamFileSystem> {
+    ramfs_with_options("size=50%").unwrap()
 }
 
-#[cfg(feature = "sysfs")]
-pub(crate) fn sysfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
-    let sysfs = fs::ramfs::RamFileSystem::new();
-    let sys_root = sysfs.root_dir();
+/// Creates a RAM filesystem with the mount `options`, where `size=<bytes>`
+/// limits the size of its files. The size may have a suffix `k`, `m` or `g`,
+/// or be a percentage of the physical memory such as `50%`. It is unlimited
+/// if absent or zero.
+#[cfg(feature = "ramfs")]
+pub(crate) fn ramfs_with_options(options: &str) -> AxResult<Arc<fs::ramfs::RamFileSystem>> {
+    fs::ramfs::set_clock(axhal::time::wall_time);
+    let size = options.split(',').find_map(|opt| opt.strip_prefix("size="));
+    let Some(size) = size else {
+        return Ok(Arc::new(fs::ramfs::RamFileSystem::new()));
+    };
+    let (digits, unit) = match size.find(|c: char| !c.is_ascii_digit()) {
+        Some(pos) => size.split_at(pos),
+        None => (size, ""),
+    };
+    let num: u64 = digits
+        .parse()
+        .map_err(|_| ax_err_type!(InvalidInput, "invalid tmpfs size"))?;
+    let bytes = match unit {
+        "" => Some(num),
+        "k" | "K" => num.checked_mul(1 << 10),
+        "m" | "M" => num.checked_mul(1 << 20),
+        "g" | "G" => num.checked_mul(1 << 30),
+        "%" => num
+            .checked_mul(axconfig::PHYS_MEMORY_SIZE as u64)
+            .map(|bytes| bytes / 100),
+        _ => None,
+    };
+    match bytes.ok_or_else(|| ax_err_type!(InvalidInput, "invalid tmpfs size"))? {
+        0 => Ok(Arc::new(fs::ramfs::RamFileSystem::new())),
+        bytes => Ok(Arc::new(fs::ramfs::RamFileSystem::with_limit(bytes))),
+    }
+}
 
-    // Create /sys/kernel/mm/transparent_hugepage/enabled
-    sys_root.create("kernel", VfsNodeType::Dir)?;
-    sys_root.create("kernel/mm", VfsNodeType::Dir)?;
-    sys_root.create("kernel/mm/transparent_hugepage", VfsNodeType::Dir)?;
-    sys_root.create("kernel/mm/transparent_hugepage/enabled", VfsNodeType::File)?;
-    let file_hp = sys_root
-        .clone()
-        .lookup("./kernel/mm/transparent_hugepage/enabled")?;
-    file_hp.write_at(0, b"always [madvise] never\n")?;
+/// Creates an overlay with the mount `options` `lowerdir=<path>` and
+/// `upperdir=<path>`, where the changes to the lower directory are written
+/// to the upper one.
+#[cfg(feature = "overlayfs")]
+pub(crate) fn overlay_with_options(options: &str) -> AxResult<Arc<fs::overlay::OverlayFileSystem>> {
+    let dir = |key: &str| {
+        let path = options.split(',').find_map(|opt| opt.strip_prefix(key));
+        let path = path.ok_or_else(|| ax_err_type!(InvalidInput, "missing overlay directory"))?;
+        crate::root::lookup(None, path)
+    };
+    let (lower, upper) = (dir("lowerdir=")?, dir("upperdir=")?);
+    Ok(Arc::new(fs::overlay::OverlayFileSystem::from_dirs(
+        lower, upper,
+    )?))
+}
 
-    // Create /sys/devices/system/clocksource/clocksource0/current_clocksource
-    sys_root.create("devices", VfsNodeType::Dir)?;
-    sys_root.create("devices/system", VfsNodeType::Dir)?;
-    sys_root.create("devices/system/clocksource", VfsNodeType::Dir)?;
-    sys_root.create("devices/system/clocksource/clocksource0", VfsNodeType::Dir)?;
-    sys_root.create(
-        "devices/system/clocksource/clocksource0/current_clocksource",
-        VfsNodeType::File,
-    )?;
-    let file_cc = sys_root
-        .clone()
-        .lookup("devices/system/clocksource/clocksource0/current_clocksource")?;
-    file_cc.write_at(0, b"tsc\n")?;
+#[cfg(feature = "procfs")]
+pub(crate) fn procfs() -> Arc<fs::pseudo::PseudoFileSystem> {
+    Arc::new(fs::procfs::new_procfs())
+}
 
-    Ok(Arc::new(sysfs))
+#[cfg(feature = "sysfs")]
+pub(crate) fn sysfs() -> Arc<fs::pseudo::PseudoFileSystem> {
+    Arc::new(fs::sysfs::new_sysfs())
 }
@@ -1,8 +1,13 @@
 //! Root directory of the filesystem
 //!
-//! TODO: it doesn't work very well if the mount points have containment relationships.
+//! Filesystems can be mounted on any directory at runtime, including
+//! directories of other mounted filesystems. A path is resolved by the
+//! filesystem mounted on its longest prefix.
+//!
+//! Symbolic links are followed here rather than by the filesystems, so that
+//! a link may point into another mounted filesystem.
 
-use alloc::{string::String, sync::Arc, vec::Vec};
+use alloc::{format, string::String, sync::Arc, vec, vec::Vec};
 use axerrno::{ax_err, AxError, AxResult};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
 use axsync::Mutex;
@@ -14,60 +19,187 @@ static CURRENT_DIR_PATH: Mutex<String> = Mutex::new(String::new());
 static CURRENT_DIR: LazyInit<Mutex<VfsNodeRef>> = LazyInit::new();
 
 struct MountPoint {
-    path: &'static str,
+    /// The canonical absolute path of the mount point, without the trailing
+    /// `/`.
+    path: String,
     fs: Arc<dyn VfsOps>,
+    /// The device or other source it is mounted from, as given to `mount`.
+    source: String,
+    fstype: String,
+    /// Shared with the [`MountRef`]s of the opened files and directories.
+    users: Arc<()>,
+}
+
+/// A reference to a filesystem mounted at runtime, held by the files and
+/// directories opened in it, so that it is not unmounted while they are open.
+#[derive(Clone)]
+pub(crate) struct MountRef {
+    _users: Arc<()>,
+}
+
+/// A line of the mount table.
+pub(crate) struct MountInfo {
+    pub source: String,
+    pub path: String,
+    pub fstype: String,
 }
 
 struct RootDirectory {
     main_fs: Arc<dyn VfsOps>,
-    mounts: Vec<MountPoint>,
+    main_fstype: &'static str,
+    mounts: Mutex<Vec<MountPoint>>,
 }
 
 static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
 
+/// The maximum number of symbolic links followed in one path resolution.
+const MAX_SYMLINKS: usize = 40;
+/// The maximum length of the target of a symbolic link.
+const MAX_LINK_LEN: usize = 4095;
+
 impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path, fs }
+    pub fn new(path: String, fs: Arc<dyn VfsOps>, source: &str, fstype: &str) -> Self {
+        Self {
+            path,
+            fs,
+            source: source.into(),
+            fstype: fstype.into(),
+            users: Arc::new(()),
+        }
     }
-}
 
-impl Drop for MountPoint {
-    fn drop(&mut self) {
-        self.fs.umount().ok();
+    /// Returns whether files or directories are opened in the filesystem.
+    fn is_in_use(&self) -> bool {
+        Arc::strong_count(&self.users) > 1
     }
 }
 
+/// Returns whether the canonical `path` is `mount_path` or a path under it.
+fn is_under(path: &str, mount_path: &str) -> bool {
+    path.strip_prefix(mount_path)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
 impl RootDirectory {
-    pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
+    pub const fn new(main_fs: Arc<dyn VfsOps>, main_fstype: &'static str) -> Self {
         Self {
             main_fs,
-            mounts: Vec::new(),
+            main_fstype,
+            mounts: Mutex::new(Vec::new()),
         }
     }
 
-    pub fn mount(&mut self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
+    /// Mounts `fs` on the directory `path`.
+    ///
+    /// If `create` is set, the directory is created in the filesystem that
+    /// `path` currently resolves to if it does not exist, which is only done
+    /// for the mounts at boot. Otherwise it fails with [`AxError::NotFound`].
+    ///
+    /// `source` and `fstype` are only recorded for the mount table.
+    pub fn mount(
+        &self,
+        path: &str,
+        fs: Arc<dyn VfsOps>,
+        source: &str,
+        fstype: &str,
+        create: bool,
+    ) -> AxResult {
+        if !path.starts_with('/') {
+            return ax_err!(InvalidInput, "mount path must start with '/'");
+        }
+        let path = axfs_vfs::path::canonicalize(path);
         if path == "/" {
             return ax_err!(InvalidInput, "cannot mount root filesystem");
         }
-        if !path.starts_with('/') {
-            return ax_err!(InvalidInput, "mount path must start with '/'");
+        if self.contains(&path) {
+            return ax_err!(ResourceBusy, "mount point already exists");
         }
-        if self.mounts.iter().any(|mp| mp.path == path) {
-            return ax_err!(InvalidInput, "mount point already exists");
+        let node = self.lookup_mounted_fs(&path, |parent_fs, rest_path| {
+            let root = parent_fs.root_dir();
+            match root.clone().lookup(rest_path) {
+                Err(AxError::NotFound) if create => {
+                    root.create(rest_path, FileType::Dir)?;
+                    root.lookup(rest_path)
+                }
+                res => res,
+            }
+        })?;
+        if !node.get_attr()?.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        fs.mount(&path, node)?;
+        self.mounts
+            .lock()
+            .push(MountPoint::new(path, fs, source, fstype));
+        Ok(())
+    }
+
+    /// Unmounts the filesystem mounted on `path`.
+    ///
+    /// It fails with [`AxError::ResourceBusy`] if other filesystems are
+    /// mounted under it, files or directories are opened in it, or the
+    /// current directory is in it, unless `lazy` is set. In that case, the
+    /// filesystems mounted under it are unmounted as well.
+    pub fn umount(&self, path: &str, lazy: bool) -> AxResult {
+        let path = axfs_vfs::path::canonicalize(path);
+        let mut mounts = self.mounts.lock();
+        let Some(target) = mounts.iter().find(|mp| mp.path == path) else {
+            return ax_err!(InvalidInput, "not a mount point");
+        };
+        if !lazy {
+            let nested = mounts
+                .iter()
+                .any(|mp| mp.path != path && is_under(&mp.path, &path));
+            let cwd = CURRENT_DIR_PATH.lock();
+            if nested || target.is_in_use() || is_under(cwd.trim_end_matches('/'), &path) {
+                return ax_err!(ResourceBusy);
+            }
+        }
+        let (mut removed, kept): (Vec<_>, Vec<_>) = core::mem::take(&mut *mounts)
+            .into_iter()
+            .partition(|mp| is_under(&mp.path, &path));
+        *mounts = kept;
+        drop(mounts);
+
+        // Unmount the innermost filesystems first.
+        removed.sort_by_key(|mp| core::cmp::Reverse(mp.path.len()));
+        for mp in removed {
+            mp.fs.umount()?;
         }
-        // create the mount point in the main filesystem if it does not exist
-        self.main_fs.root_dir().create(path, FileType::Dir)?;
-        fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
-        self.mounts.push(MountPoint::new(path, fs));
         Ok(())
     }
 
-    pub fn _umount(&mut self, path: &str) {
-        self.mounts.retain(|mp| mp.path != path);
+    /// Returns the root filesystem and the mounted ones, in mount order.
+    pub fn mount_table(&self) -> Vec<MountInfo> {
+        let root = MountInfo {
+            source: "/dev/root".into(),
+            path: "/".into(),
+            fstype: self.main_fstype.into(),
+        };
+        let mounts = self.mounts.lock();
+        let mounted = mounts.iter().map(|mp| MountInfo {
+            source: mp.source.clone(),
+            path: mp.path.clone(),
+            fstype: mp.fstype.clone(),
+        });
+        core::iter::once(root).chain(mounted).collect()
     }
 
     pub fn contains(&self, path: &str) -> bool {
-        self.mounts.iter().any(|mp| mp.path == path)
+        self.mounts.lock().iter().any(|mp| mp.path == path)
+    }
+
+    /// Returns a reference to the filesystem mounted on the longest prefix
+    /// of the canonical `path`, or `None` if it is in the main filesystem.
+    fn mount_ref(&self, path: &str) -> Option<MountRef> {
+        let mounts = self.mounts.lock();
+        let mp = mounts
+            .iter()
+            .filter(|mp| is_under(path, &mp.path))
+            .max_by_key(|mp| mp.path.len())?;
+        Some(MountRef {
+            _users: mp.users.clone(),
+        })
     }
 
     fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
@@ -75,29 +207,26 @@ impl RootDirectory {
         F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
     {
         debug!("lookup at root: {}", path);
-        let path = path.trim_matches('/');
-        if let Some(rest) = path.strip_prefix("./") {
-            return self.lookup_mounted_fs(rest, f);
-        }
-
-        let mut idx = 0;
-        let mut max_len = 0;
+        // Drop empty and `.` components. `..` is kept and resolved by the
+        // filesystems, whose roots are linked to their mount points.
+        let path = path
+            .split('/')
+            .filter(|c| !c.is_empty() && *c != ".")
+            .fold(String::new(), |path, c| path + "/" + c);
 
         // Find the filesystem that has the longest mounted path match
         // TODO: more efficient, e.g. trie
-        for (i, mp) in self.mounts.iter().enumerate() {
-            // skip the first '/'
-            if path.starts_with(&mp.path[1..]) && mp.path.len() - 1 > max_len {
-                max_len = mp.path.len() - 1;
-                idx = i;
-            }
-        }
-
-        if max_len == 0 {
-            f(self.main_fs.clone(), path) // not matched any mount point
-        } else {
-            f(self.mounts[idx].fs.clone(), &path[max_len..]) // matched at `idx`
-        }
+        let (fs, prefix_len) = self
+            .mounts
+            .lock()
+            .iter()
+            .filter(|mp| is_under(&path, &mp.path))
+            .max_by_key(|mp| mp.path.len())
+            .map_or((self.main_fs.clone(), 0), |mp| {
+                (mp.fs.clone(), mp.path.len())
+            });
+        // The lock is released here, as `f` may look up other paths.
+        f(fs, path[prefix_len..].trim_start_matches('/'))
     }
 }
 
@@ -166,47 +295,94 @@ impl VfsNodeOps for RootDirectory {
     }
 }
 
-pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
-    cfg_if::cfg_if! {
-        if #[cfg(feature = "myfs")] { // override the default filesystem
-            let main_fs = fs::myfs::new_myfs(disk);
-        } else if #[cfg(feature = "fatfs")] {
-            static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
-            FAT_FS.init_once(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
-            FAT_FS.init();
-            let main_fs = FAT_FS.clone();
-        }
-    }
+pub(crate) fn init_rootfs(disk: Option<crate::dev::Disk>) {
+    let (main_fs, main_fstype) = match disk {
+        Some(disk) => disk_root_fs(disk),
+        #[cfg(feature = "initramfs")]
+        None => (crate::initramfs::new_initramfs(), "rootfs"),
+        #[cfg(not(feature = "initramfs"))]
+        None => unreachable!(),
+    };
 
-    let mut root_dir = RootDirectory::new(main_fs);
+    let root_dir = RootDirectory::new(main_fs, main_fstype);
 
     #[cfg(feature = "devfs")]
     root_dir
-        .mount("/dev", mounts::devfs())
+        .mount("/dev", mounts::devfs(), "devtmpfs", "devtmpfs", true)
         .expect("failed to mount devfs at /dev");
 
     #[cfg(feature = "ramfs")]
     root_dir
-        .mount("/tmp", mounts::ramfs())
+        .mount("/tmp", mounts::ramfs(), "tmpfs", "tmpfs", true)
         .expect("failed to mount ramfs at /tmp");
 
-    // Mount another ramfs as procfs
+    // Mount the synthetic procfs, rendering the kernel state when read
     #[cfg(feature = "procfs")]
-    root_dir // should not fail
-        .mount("/proc", mounts::procfs().unwrap())
-        .expect("fail to mount procfs at /proc");
+    root_dir
+        .mount("/proc", mounts::procfs(), "proc", "proc", true)
+        .expect("failed to mount procfs at /proc");
 
-    // Mount another ramfs as sysfs
+    // Mount the synthetic sysfs, presenting the probed devices
     #[cfg(feature = "sysfs")]
-    root_dir // should not fail
-        .mount("/sys", mounts::sysfs().unwrap())
-        .expect("fail to mount sysfs at /sys");
+    root_dir
+        .mount("/sys", mounts::sysfs(), "sysfs", "sysfs", true)
+        .expect("failed to mount sysfs at /sys");
 
     ROOT_DIR.init_once(Arc::new(root_dir));
     CURRENT_DIR.init_once(Mutex::new(ROOT_DIR.clone()));
     *CURRENT_DIR_PATH.lock() = "/".into();
 }
 
+/// Creates the root filesystem on the disk, overlaid with a RAM filesystem
+/// with the `overlayfs` feature. Returns the filesystem and its type name.
+fn disk_root_fs(disk: crate::dev::Disk) -> (Arc<dyn VfsOps>, &'static str) {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "myfs")] { // override the default filesystem
+            let (main_fs, main_fstype) = (fs::myfs::new_myfs(disk), "myfs");
+        } else {
+            let (main_fs, main_fstype) = new_main_fs(disk);
+        }
+    }
+    // Keep the disk intact, and write the changes to the memory
+    #[cfg(feature = "overlayfs")]
+    let (main_fs, main_fstype) = {
+        #[cfg(feature = "initramfs")]
+        let upper = crate::initramfs::new_initramfs();
+        #[cfg(not(feature = "initramfs"))]
+        let upper = mounts::ramfs();
+        let overlay: Arc<dyn VfsOps> =
+            Arc::new(fs::overlay::OverlayFileSystem::new(main_fs, upper));
+        (overlay, "overlay")
+    };
+    (main_fs, main_fstype)
+}
+
+/// Creates the main filesystem by the superblock on the disk. FAT goes last,
+/// as it formats the disk with the `use-ramdisk` feature. Returns the
+/// filesystem and its type name.
+#[cfg(not(feature = "myfs"))]
+fn new_main_fs(disk: crate::dev::Disk) -> (Arc<dyn VfsOps>, &'static str) {
+    #[cfg(feature = "ext4")]
+    let mut disk = disk;
+    #[cfg(feature = "ext4")]
+    if fs::ext4::Ext4FileSystem::probe(&mut disk) {
+        info!("  use ext filesystem");
+        let ext_fs =
+            fs::ext4::Ext4FileSystem::new(disk).expect("failed to open the ext filesystem");
+        return (Arc::new(ext_fs), "ext4");
+    }
+
+    #[cfg(feature = "fatfs")]
+    {
+        static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
+        FAT_FS.init_once(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
+        FAT_FS.init();
+        (FAT_FS.clone(), "vfat")
+    }
+    #[cfg(not(feature = "fatfs"))]
+    panic!("no supported filesystem found on the disk");
+}
+
 fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
     if path.starts_with('/') {
         ROOT_DIR.clone()
@@ -224,46 +400,209 @@ pub(crate) fn absolute_path(path: &str) -> AxResult<String> {
     }
 }
 
-pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+fn is_symlink(node: &VfsNodeRef) -> AxResult<bool> {
+    Ok(node.get_attr()?.file_type() == VfsNodeType::SymLink)
+}
+
+/// Reads the target of the symbolic link `node`.
+fn read_link_node(node: &VfsNodeRef) -> AxResult<String> {
+    let size = node.get_attr()?.size() as usize;
+    if size > MAX_LINK_LEN {
+        return ax_err!(InvalidData);
+    }
+    let mut buf = vec![0; size];
+    let len = node.read_at(0, &mut buf)?;
+    buf.truncate(len);
+    String::from_utf8(buf).map_err(|_| AxError::InvalidData)
+}
+
+/// Resolves the symbolic links in `path`, except the last component unless
+/// `follow` is set or `path` ends with `/`.
+///
+/// Returns a directory and the path relative to it without symbolic links.
+/// `..` after a followed link goes to the parent of the link target, also
+/// across mount points. It fails with [`crate::api::TOO_MANY_LINKS`] if more
+/// than [`MAX_SYMLINKS`] links are followed.
+fn resolve(dir: Option<&VfsNodeRef>, path: &str, follow: bool) -> AxResult<(VfsNodeRef, String)> {
+    let follow = follow || path.ends_with('/');
+    let mut start = parent_node_of(dir, path);
+    // A lookup by the filesystems fails at any symbolic link in the middle,
+    // as it is not a directory, so a path they resolve has no links except
+    // the last component.
+    if !path.split('/').any(|c| c == "..") {
+        match start.clone().lookup(path) {
+            Ok(node) if !(follow && is_symlink(&node)?) => return Ok((start, path.into())),
+            Err(AxError::NotFound) if !path.contains('/') => return Ok((start, path.into())),
+            _ => {}
+        }
+    }
+
+    let mut resolved = String::new();
+    let mut pending: Vec<String> = path.split('/').rev().map(String::from).collect();
+    let mut links = 0;
+    while let Some(name) = pending.pop() {
+        let is_last = pending.iter().all(|c| c.is_empty() || c == ".");
+        match name.as_str() {
+            "" | "." => continue,
+            ".." => {
+                // All links before are resolved, so it is the parent of the
+                // resolved path.
+                match resolved.rfind('/') {
+                    Some(pos) if !resolved[pos + 1..].starts_with("..") => resolved.truncate(pos),
+                    _ if Arc::ptr_eq(&start, &(ROOT_DIR.clone() as VfsNodeRef)) => {}
+                    _ => resolved += "/..",
+                }
+                continue;
+            }
+            _ => {}
+        }
+        let candidate = format!("{}/{}", resolved, name);
+        if is_last && !follow {
+            resolved = candidate;
+            continue;
+        }
+        match start.clone().lookup(&candidate) {
+            Ok(node) if is_symlink(&node)? => {
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(crate::api::TOO_MANY_LINKS);
+                }
+                let target = read_link_node(&node)?;
+                if target.is_empty() {
+                    return ax_err!(NotFound);
+                }
+                if target.starts_with('/') {
+                    start = ROOT_DIR.clone();
+                    resolved.clear();
+                }
+                pending.extend(target.split('/').rev().map(String::from));
+            }
+            Ok(_) => resolved = candidate,
+            Err(AxError::NotFound) if is_last => resolved = candidate,
+            Err(e) => return Err(e),
+        }
+    }
+    if path.ends_with('/') {
+        resolved.push('/');
+    }
+    Ok((start, resolved))
+}
+
+fn lookup_resolved(dir: Option<&VfsNodeRef>, path: &str, follow: bool) -> AxResult<VfsNodeRef> {
+    lookup_at(dir, path, follow).map(|(node, _, _)| node)
+}
+
+/// Looks up `path` like [`lookup_resolved`], and also returns the directory
+/// and the relative path it is resolved from.
+fn lookup_at(
+    dir: Option<&VfsNodeRef>,
+    path: &str,
+    follow: bool,
+) -> AxResult<(VfsNodeRef, VfsNodeRef, String)> {
     if path.is_empty() {
         return ax_err!(NotFound);
     }
-    let node = parent_node_of(dir, path).lookup(path)?;
+    let (start, rel_path) = resolve(dir, path, follow)?;
+    let node = start.clone().lookup(&rel_path)?;
     if path.ends_with('/') && !node.get_attr()?.is_dir() {
         ax_err!(NotADirectory)
     } else {
-        Ok(node)
+        Ok((node, start, rel_path))
     }
 }
 
+/// Returns the canonical absolute path of the path resolved from `start`, if
+/// `start` is the root or the current directory.
+fn absolute_path_from(start: &VfsNodeRef, path: &str) -> Option<String> {
+    let abs_path = if Arc::ptr_eq(start, &(ROOT_DIR.clone() as VfsNodeRef)) {
+        path.into()
+    } else if Arc::ptr_eq(start, &CURRENT_DIR.lock()) {
+        CURRENT_DIR_PATH.lock().clone() + path
+    } else {
+        return None;
+    };
+    Some(axfs_vfs::path::canonicalize(&abs_path))
+}
+
+/// Returns the filesystem of the path resolved from `start`, if `start` is the
+/// root or the current directory.
+fn mounted_fs_of(start: &VfsNodeRef, path: &str) -> Option<Arc<dyn VfsOps>> {
+    let abs_path = absolute_path_from(start, path)?;
+    ROOT_DIR.lookup_mounted_fs(&abs_path, |fs, _| Ok(fs)).ok()
+}
+
+/// Returns a reference to the filesystem mounted at runtime that the path
+/// resolved from `start` is in, if `start` is the root or the current
+/// directory.
+fn mount_ref_of(start: &VfsNodeRef, path: &str) -> Option<MountRef> {
+    ROOT_DIR.mount_ref(&absolute_path_from(start, path)?)
+}
+
+/// Looks up `path`, following symbolic links.
+pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+    lookup_resolved(dir, path, true)
+}
+
+/// Looks up `path`, without following the symbolic link of the last
+/// component.
+pub(crate) fn lookup_no_follow(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+    lookup_resolved(dir, path, false)
+}
+
+/// Looks up `path` to open it, following symbolic links. Also returns a
+/// reference to the filesystem mounted at runtime that it is in, which is
+/// `None` for a relative path from another directory than the current one.
+pub(crate) fn lookup_to_open(
+    dir: Option<&VfsNodeRef>,
+    path: &str,
+) -> AxResult<(VfsNodeRef, Option<MountRef>)> {
+    let (node, start, rel_path) = lookup_at(dir, path, true)?;
+    Ok((node, mount_ref_of(&start, &rel_path)))
+}
+
 pub(crate) fn create_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+    create_file_to_open(dir, path).map(|(node, _)| node)
+}
+
+/// Creates a file at `path` like [`create_file`] to open it, and also returns
+/// a reference to the filesystem like [`lookup_to_open`].
+pub(crate) fn create_file_to_open(
+    dir: Option<&VfsNodeRef>,
+    path: &str,
+) -> AxResult<(VfsNodeRef, Option<MountRef>)> {
     if path.is_empty() {
         return ax_err!(NotFound);
     } else if path.ends_with('/') {
         return ax_err!(NotADirectory);
     }
-    let parent = parent_node_of(dir, path);
-    parent.create(path, VfsNodeType::File)?;
-    parent.lookup(path)
+    // A dangling link creates its target.
+    let (parent, path) = resolve(dir, path, true)?;
+    parent.create(&path, VfsNodeType::File)?;
+    let mount = mount_ref_of(&parent, &path);
+    Ok((parent.lookup(&path)?, mount))
 }
 
 pub(crate) fn create_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
-    match lookup(dir, path) {
+    match lookup_no_follow(dir, path) {
         Ok(_) => ax_err!(AlreadyExists),
-        Err(AxError::NotFound) => parent_node_of(dir, path).create(path, VfsNodeType::Dir),
+        Err(AxError::NotFound) => {
+            let (parent, path) = resolve(dir, path, false)?;
+            parent.create(&path, VfsNodeType::Dir)
+        }
         Err(e) => Err(e),
     }
 }
 
 pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
-    let node = lookup(dir, path)?;
+    let node = lookup_no_follow(dir, path)?;
     let attr = node.get_attr()?;
     if attr.is_dir() {
         ax_err!(IsADirectory)
     } else if !attr.perm().owner_writable() {
         ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        let (parent, path) = resolve(dir, path, false)?;
+        parent.remove(&path)
     }
 }
 
@@ -285,15 +624,98 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
         return ax_err!(PermissionDenied);
     }
 
-    let node = lookup(dir, path)?;
+    let node = lookup_no_follow(dir, path.trim_end_matches('/'))?;
     let attr = node.get_attr()?;
     if !attr.is_dir() {
         ax_err!(NotADirectory)
     } else if !attr.perm().owner_writable() {
         ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        let (parent, path) = resolve(dir, path.trim_end_matches('/'), false)?;
+        parent.remove(&path)
+    }
+}
+
+/// Creates a symbolic link at `path` pointing to `target`, which is not
+/// resolved until the link is followed.
+pub(crate) fn symlink(dir: Option<&VfsNodeRef>, target: &str, path: &str) -> AxResult {
+    if target.is_empty() || path.is_empty() {
+        return ax_err!(NotFound);
+    } else if target.len() > MAX_LINK_LEN {
+        return ax_err!(InvalidInput);
+    } else if path.ends_with('/') {
+        return ax_err!(NotADirectory);
+    }
+    match lookup_no_follow(dir, path) {
+        Ok(_) => return ax_err!(AlreadyExists),
+        Err(AxError::NotFound) => {}
+        Err(e) => return Err(e),
+    }
+    let (parent, path) = resolve(dir, path, false)?;
+    parent.create(&path, VfsNodeType::SymLink)?;
+    let node = parent.clone().lookup(&path)?;
+    if let Err(e) = node.write_at(0, target.as_bytes()) {
+        parent.remove(&path).ok();
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Returns the target of the symbolic link at `path`.
+pub(crate) fn read_link(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<String> {
+    let node = lookup_no_follow(dir, path)?;
+    if !is_symlink(&node)? {
+        return ax_err!(InvalidInput);
+    }
+    read_link_node(&node)
+}
+
+/// Creates a hard link at `new` to the node at `old`, which must be in the
+/// same filesystem. `old` is not followed if it is a symbolic link.
+pub(crate) fn link(dir: Option<&VfsNodeRef>, old: &str, new: &str) -> AxResult {
+    if new.is_empty() {
+        return ax_err!(NotFound);
+    } else if new.ends_with('/') {
+        return ax_err!(NotADirectory);
+    }
+    let node = lookup_no_follow(dir, old)?;
+    if node.get_attr()?.is_dir() {
+        return ax_err!(PermissionDenied);
+    }
+    match lookup_no_follow(dir, new) {
+        Ok(_) => return ax_err!(AlreadyExists),
+        Err(AxError::NotFound) => {}
+        Err(e) => return Err(e),
     }
+    let (old_start, old) = resolve(dir, old, false)?;
+    let (start, new) = resolve(dir, new, false)?;
+    let old_fs = mounted_fs_of(&old_start, &old);
+    let new_fs = mounted_fs_of(&start, &new);
+    if let (Some(old_fs), Some(new_fs)) = (old_fs, new_fs) {
+        if !Arc::ptr_eq(&old_fs, &new_fs) {
+            return ax_err!(Unsupported, "cannot link across filesystems");
+        }
+    }
+    let (parent, name) = match new.trim_start_matches('/').rsplit_once('/') {
+        Some((parent, name)) => (start.lookup(parent)?, name),
+        None if Arc::ptr_eq(&start, &(ROOT_DIR.clone() as VfsNodeRef)) => {
+            (ROOT_DIR.main_fs.root_dir(), new.trim_start_matches('/'))
+        }
+        None => (start, new.trim_start_matches('/')),
+    };
+    fs::link_node(&parent, name, &node)
+}
+
+pub(crate) fn mount(path: &str, fs: Arc<dyn VfsOps>, source: &str, fstype: &str) -> AxResult {
+    ROOT_DIR.mount(&absolute_path(path)?, fs, source, fstype, false)
+}
+
+pub(crate) fn umount(path: &str, lazy: bool) -> AxResult {
+    ROOT_DIR.umount(&absolute_path(path)?, lazy)
+}
+
+pub(crate) fn mount_table() -> Vec<MountInfo> {
+    ROOT_DIR.mount_table()
 }
 
 pub(crate) fn current_dir() -> AxResult<String> {
@@ -311,6 +733,9 @@ pub(crate) fn set_current_dir(path: &str) -> AxResult {
         return Ok(());
     }
 
+    // Keep the path without symbolic links, as the absolute path is
+    // resolved from the root directory.
+    let (_, resolved) = resolve(None, &abs_path, true)?;
     let node = lookup(None, &abs_path)?;
     let attr = node.get_attr()?;
     if !attr.is_dir() {
@@ -318,16 +743,26 @@ pub(crate) fn set_current_dir(path: &str) -> AxResult {
     } else if !attr.perm().owner_executable() {
         ax_err!(PermissionDenied)
     } else {
+        let mut path = axfs_vfs::path::canonicalize(&resolved);
+        if !path.ends_with('/') {
+            path += "/";
+        }
         *CURRENT_DIR.lock() = node;
-        *CURRENT_DIR_PATH.lock() = abs_path;
+        *CURRENT_DIR_PATH.lock() = path;
         Ok(())
     }
 }
 
 pub(crate) fn rename(old: &str, new: &str) -> AxResult {
-    if parent_node_of(None, new).lookup(new).is_ok() {
+    if lookup_no_follow(None, new).is_ok() {
         warn!("dst file already exist, now remove it");
         remove_file(None, new)?;
     }
-    parent_node_of(None, old).rename(old, new)
+    let (src_start, old) = resolve(None, old, false)?;
+    let (dst_start, new) = resolve(None, new, false)?;
+    if !Arc::ptr_eq(&src_start, &dst_start) {
+        // Only after an absolute symbolic link on one side.
+        return ax_err!(InvalidInput);
+    }
+    src_start.rename(&old, &new)
 }
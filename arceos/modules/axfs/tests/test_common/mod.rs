@@ -1,8 +1,10 @@
 use axfs::api as fs;
 use axio as io;
 
-use fs::{File, FileType, OpenOptions};
+use core::time::Duration;
+use fs::{File, FileType, OpenOptions, Permissions};
 use io::{prelude::*, Error, Result};
+use std::sync::Arc;
 
 macro_rules! assert_err {
     ($expr: expr) => {
@@ -113,6 +115,11 @@ fn test_create_file_dir() -> Result<()> {
     assert_eq!(fs::read_to_string(fname)?, contents);
     assert_err!(File::create_new(fname), AlreadyExists);
 
+    // write the cached blocks back, and read them again
+    File::options().write(true).open(fname)?.flush()?;
+    fs::sync()?;
+    assert_eq!(fs::read_to_string(fname)?, contents);
+
     // create a directory and test existence
     let dirname = "///././/very//.//long/./new-dir";
     println!("test create dir {:?}:", dirname);
@@ -192,12 +199,47 @@ fn test_devfs_ramfs() -> Result<()> {
     assert!(file.write_all(&buf).is_ok());
     assert_eq!(buf, [0; N]);
 
+    // register /dev/foo/bar
+    let foo = axfs_devfs::DeviceFileSystem::new().mkdir("foo");
+    foo.add("bar", Arc::new(axfs_devfs::ZeroDev));
+    fs::register_device("foo", foo.clone())?;
+    assert_err!(fs::register_device("foo", foo), AlreadyExists);
+
     // list /dev
     let dirents = fs::read_dir("/dev")?
         .map(|e| e.unwrap().file_name())
         .collect::<Vec<_>>();
     assert!(dirents.contains(&"null".into()));
     assert!(dirents.contains(&"zero".into()));
+    assert!(dirents.contains(&"foo".into()));
+    for name in ["full", "random", "urandom", "console", "ttyS0", "vda"] {
+        assert!(dirents.contains(&name.into()));
+    }
+
+    // read and write /dev/full
+    let mut file = File::options().read(true).write(true).open("/dev/full")?;
+    buf = [1; N];
+    assert_eq!(file.read(&mut buf)?, N);
+    assert_eq!(buf, [0; N]);
+    assert_err!(file.write(&buf), StorageFull);
+
+    // read /dev/urandom
+    let mut file = File::open("/dev/urandom")?;
+    let mut rand = [0; 64];
+    file.read_exact(&mut rand)?;
+    assert_ne!(rand, [0; 64]);
+
+    // read and write the root disk /dev/vda
+    let md = fs::metadata("/dev/vda")?;
+    assert_eq!(md.file_type(), FileType::BlockDevice);
+    let mut disk = File::options().read(true).write(true).open("/dev/vda")?;
+    let mut sector = [0; 512];
+    disk.seek(io::SeekFrom::Start(md.len() - 512))?;
+    disk.read_exact(&mut sector)?;
+    disk.seek(io::SeekFrom::Start(md.len() - 512))?;
+    disk.write_all(&sector)?;
+    assert_eq!(disk.read(&mut sector)?, 0);
+    assert_err!(disk.write(&sector), StorageFull);
 
     // stat /dev
     let dname = "/dev";
@@ -216,6 +258,10 @@ fn test_devfs_ramfs() -> Result<()> {
     assert_eq!(md.file_type(), FileType::CharDevice);
     assert!(!md.is_dir());
 
+    // stat /dev/console
+    let md = fs::metadata("/dev/console")?;
+    assert_eq!(md.file_type(), FileType::CharDevice);
+
     // error cases
     assert_err!(fs::metadata("/dev/null/"), NotADirectory);
     assert_err!(fs::create_dir("dev"), AlreadyExists);
@@ -252,6 +298,359 @@ fn test_devfs_ramfs() -> Result<()> {
     Ok(())
 }
 
+fn test_mount_umount() -> Result<()> {
+    println!("test mount and umount:");
+
+    // mount a tmpfs in /tmp, which is a mount point itself
+    assert_err!(fs::mount_fs("", "/tmp/mnt", "tmpfs"), NotFound);
+    fs::create_dir("/tmp/mnt")?;
+    fs::mount_fs("", "/tmp/mnt", "tmpfs")?;
+    assert_eq!(fs::write("/tmp/mnt/test.txt", "test"), Ok(()));
+    assert_eq!(fs::read("tmp/mnt/.././/mnt/test.txt"), Ok("test".into()));
+    assert_err!(fs::metadata("/tmp/test.txt"), NotFound);
+
+    // nested mount
+    fs::create_dir("/tmp/mnt/inner")?;
+    fs::mount_fs("", "/tmp//mnt/./inner/", "tmpfs")?;
+    assert_eq!(fs::read_dir("/tmp/mnt/inner")?.count(), 0);
+    assert_eq!(fs::write("/tmp/mnt/inner/test.txt", "inner"), Ok(()));
+    assert_eq!(fs::read("/tmp/mnt/test.txt"), Ok("test".into()));
+    assert_eq!(fs::read("/tmp/mnt/inner/test.txt"), Ok("inner".into()));
+
+    // error cases
+    assert_err!(fs::mount_fs("", "/tmp/mnt", "tmpfs"), ResourceBusy);
+    assert_err!(
+        fs::mount_fs("", "/tmp/mnt/test.txt", "tmpfs"),
+        NotADirectory
+    );
+    assert_err!(fs::mount_fs("", "/tmp/other", "nosuchfs"), Unsupported);
+    assert_err!(fs::mount_fs("", "/", "tmpfs"), InvalidInput);
+    assert_eq!(fs::block_devices(), ["vda"]);
+    assert_err!(fs::mount_fs("vda", "/tmp/other", "auto"), ResourceBusy);
+    assert_err!(fs::mount_fs("/dev/vdz", "/tmp/other", "vfat"), NotFound);
+    assert_err!(fs::remove_dir("/tmp/mnt/inner"), PermissionDenied);
+    assert_err!(fs::umount("/tmp"), ResourceBusy);
+    assert_err!(fs::umount("/tmp/mnt"), ResourceBusy);
+    fs::set_current_dir("/tmp/mnt/inner")?;
+    assert_err!(fs::umount("/tmp/mnt/inner"), ResourceBusy);
+    fs::set_current_dir("/")?;
+    let file = File::open("/tmp/mnt/inner/test.txt")?;
+    assert_err!(fs::umount("/tmp/mnt/inner"), ResourceBusy);
+    drop(file);
+    let dir = fs::read_dir("/tmp/mnt/inner")?;
+    assert_err!(fs::umount("/tmp/mnt/inner"), ResourceBusy);
+    drop(dir);
+
+    // umount from the innermost
+    fs::umount("/tmp/mnt/inner")?;
+    assert_err!(fs::metadata("/tmp/mnt/inner/test.txt"), NotFound);
+    assert!(fs::metadata("/tmp/mnt/inner")?.is_dir());
+    fs::umount("/tmp/mnt")?;
+    assert_err!(fs::metadata("/tmp/mnt/test.txt"), NotFound);
+    assert_err!(fs::umount("/tmp/mnt"), InvalidInput);
+
+    // lazy umount detaches the nested mounts as well
+    fs::mount_fs("", "/tmp/mnt", "tmpfs")?;
+    fs::create_dir("/tmp/mnt/inner")?;
+    fs::mount_fs("", "/tmp/mnt/inner", "tmpfs")?;
+    fs::umount_lazy("/tmp/mnt")?;
+    assert_err!(fs::metadata("/tmp/mnt/inner"), NotFound);
+    assert_eq!(fs::remove_dir("/tmp/mnt"), Ok(()));
+    assert_eq!(fs::read_dir("tmp").unwrap().count(), 0);
+
+    println!("test_mount_umount() OK!");
+    Ok(())
+}
+
+fn test_symlink_hard_link() -> Result<()> {
+    println!("test symbolic and hard links:");
+
+    // symbolic links in tmpfs, also to another mounted filesystem
+    fs::create_dir("/tmp/mnt")?;
+    fs::mount_fs("", "/tmp/mnt", "tmpfs")?;
+    fs::write("/tmp/mnt/test.txt", "linked")?;
+    fs::symlink("mnt/test.txt", "/tmp/file-link")?;
+    fs::symlink("/tmp/mnt", "/tmp/dir-link")?;
+    assert_eq!(fs::read_link("/tmp/file-link")?, "mnt/test.txt");
+    assert_eq!(fs::read("/tmp/file-link"), Ok("linked".into()));
+    assert_eq!(fs::read("/tmp/dir-link/test.txt"), Ok("linked".into()));
+    assert_eq!(fs::read("/tmp/dir-link/../file-link"), Ok("linked".into()));
+    assert!(fs::symlink_metadata("/tmp/file-link")?.is_symlink());
+    assert!(fs::metadata("/tmp/file-link")?.is_file());
+    assert!(fs::metadata("/tmp/dir-link/")?.is_dir());
+    assert_err!(fs::read_link("/tmp/mnt/test.txt"), InvalidInput);
+    assert_err!(fs::symlink("x", "/tmp/file-link"), AlreadyExists);
+
+    // loops
+    fs::symlink("loop2", "/tmp/loop1")?;
+    fs::symlink("loop1", "/tmp/loop2")?;
+    assert_eq!(fs::read("/tmp/loop1").err(), Some(fs::TOO_MANY_LINKS));
+    assert_eq!(fs::metadata("/tmp/loop2/x").err(), Some(fs::TOO_MANY_LINKS));
+    assert!(fs::symlink_metadata("/tmp/loop1")?.is_symlink());
+
+    // a dangling link creates its target
+    fs::symlink("new.txt", "/tmp/dangling")?;
+    assert_err!(fs::metadata("/tmp/dangling"), NotFound);
+    fs::write("/tmp/dangling", "new")?;
+    assert_eq!(fs::read("/tmp/new.txt"), Ok("new".into()));
+
+    // hard links
+    fs::hard_link("/tmp/dir-link/test.txt", "/tmp/mnt/hard.txt")?;
+    fs::remove_file("/tmp/mnt/test.txt")?;
+    assert_eq!(fs::read("/tmp/mnt/hard.txt"), Ok("linked".into()));
+    assert_err!(fs::metadata("/tmp/file-link"), NotFound);
+    assert_err!(fs::hard_link("/tmp/mnt", "/tmp/mnt2"), PermissionDenied);
+    assert_err!(fs::hard_link("/tmp/new.txt", "/tmp/mnt/x"), Unsupported);
+
+    // removing a link leaves the target
+    for link in ["file-link", "dir-link", "loop1", "loop2", "dangling"] {
+        fs::remove_file(&format!("/tmp/{link}"))?;
+    }
+    assert_eq!(fs::read("/tmp/mnt/hard.txt"), Ok("linked".into()));
+    fs::remove_file("/tmp/new.txt")?;
+    fs::umount("/tmp/mnt")?;
+    fs::remove_dir("/tmp/mnt")?;
+
+    println!("test_symlink_hard_link() OK!");
+    Ok(())
+}
+
+fn test_ownership_times() -> Result<()> {
+    println!("test ownership, permissions and timestamps:");
+
+    let path = "/tmp/meta.txt";
+    fs::write(path, "meta")?;
+    let metadata = fs::metadata(path)?;
+    assert_eq!(metadata.permissions().bits(), 0o644);
+    assert_eq!(metadata.raw_meta().map(|m| (m.uid, m.gid)), Some((0, 0)));
+    assert!(metadata.changed()? >= metadata.modified()?);
+
+    fs::set_permissions(path, Permissions::from_bits_truncate(0o444))?;
+    assert_eq!(fs::metadata(path)?.permissions().bits(), 0o444);
+    assert_err!(fs::write(path, "test"), PermissionDenied);
+    fs::set_permissions(path, Permissions::from_bits_truncate(0o600))?;
+
+    fs::chown(path, Some(1000), None)?;
+    let meta = fs::metadata(path)?.raw_meta().copied().unwrap();
+    assert_eq!((meta.uid, meta.gid), (1000, 0));
+
+    let (atime, mtime) = (Duration::from_secs(1), Duration::from_secs(2));
+    fs::set_times(path, Some(atime), Some(mtime))?;
+    let metadata = fs::symlink_metadata(path)?;
+    assert_eq!(metadata.accessed(), Ok(atime));
+    assert_eq!(metadata.modified(), Ok(mtime));
+    fs::set_times(path, None, Some(atime))?;
+    assert_eq!(fs::metadata(path)?.modified(), Ok(atime));
+    fs::remove_file(path)?;
+
+    // not kept by the other filesystems
+    assert_err!(fs::metadata("/dev/null")?.modified(), Unsupported);
+    assert_err!(fs::chown("/dev/null", Some(0), Some(0)), Unsupported);
+
+    println!("test_ownership_times() OK!");
+    Ok(())
+}
+
+fn test_sparse_size_limit() -> Result<()> {
+    println!("test sparse files and size limits:");
+
+    // a write far away only takes a page
+    let used = fs::statfs("/tmp")?.used_blocks;
+    let mut file = File::create("/tmp/sparse.bin")?;
+    file.seek(io::SeekFrom::Start(1 << 30))?;
+    file.write_all(b"data")?;
+    let metadata = file.metadata()?;
+    assert_eq!(metadata.len(), (1 << 30) + 4);
+    assert_eq!(metadata.raw_metadata().blocks(), 8);
+    assert_eq!(fs::statfs("/tmp")?.used_blocks, used + 1);
+    let mut buf = [1; 4];
+    file.seek(io::SeekFrom::Start(4096))?;
+    file.read_exact(&mut buf)?;
+    assert_eq!(buf, [0; 4]);
+    drop(file);
+    fs::remove_file("/tmp/sparse.bin")?;
+    assert_eq!(fs::statfs("/tmp")?.used_blocks, used);
+
+    // two pages at most
+    fs::create_dir("/tmp/small")?;
+    fs::mount_fs_with_options("", "/tmp/small", "tmpfs", "mode=755,size=8k")?;
+    let stat = fs::statfs("/tmp/small")?;
+    assert_eq!(
+        (stat.block_size, stat.blocks, stat.free_blocks),
+        (4096, 2, 2)
+    );
+    fs::write("/tmp/small/a.bin", [1; 4096])?;
+    assert_err!(fs::write("/tmp/small/b.bin", [1; 8192]), StorageFull);
+    assert_err!(fs::write("/tmp/small/c.bin", [1; 1]), StorageFull);
+    assert_eq!(fs::statfs("/tmp/small")?.free_blocks, 0);
+    fs::remove_file("/tmp/small/b.bin")?;
+    assert_eq!(fs::statfs("/tmp/small")?.free_blocks, 1);
+    fs::umount("/tmp/small")?;
+    assert_err!(
+        fs::mount_fs_with_options("", "/tmp/small", "tmpfs", "size=8x"),
+        InvalidInput
+    );
+    fs::remove_dir("/tmp/small")?;
+    assert_err!(fs::statfs("/dev"), Unsupported);
+
+    println!("test_sparse_size_limit() OK!");
+    Ok(())
+}
+
+fn test_file_locks() -> Result<()> {
+    println!("test advisory file locks:");
+
+    let path = "/tmp/lock.txt";
+    fs::write(path, "lock")?;
+    let (file1, file2) = (File::open(path)?, File::open(path)?);
+
+    // the locks of different opened files conflict
+    file1.lock()?;
+    assert!(!file2.try_lock()?);
+    assert!(!file2.try_lock_shared()?);
+    file1.unlock()?;
+    assert!(file2.try_lock_shared()?);
+    assert!(file1.try_lock_shared()?);
+    assert!(!file1.try_lock()?);
+
+    // converted by the same opened file, and released when it is closed
+    drop(file1);
+    assert!(file2.try_lock()?);
+    let file3 = File::open(path)?;
+    assert!(!file3.try_lock_shared()?);
+    drop(file2);
+    assert!(file3.try_lock()?);
+    drop(file3);
+    fs::remove_file(path)?;
+
+    println!("test_file_locks() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "overlayfs")]
+fn test_overlay() -> Result<()> {
+    println!("test overlay:");
+
+    fs::create_dir("/tmp/upper")?;
+    fs::create_dir("/tmp/merged")?;
+    let options = "lowerdir=/very/long,upperdir=/tmp/upper";
+    fs::mount_fs_with_options("", "/tmp/merged", "overlay", options)?;
+    let lower = "/very/long/path/test.txt";
+    let merged = "/tmp/merged/path/test.txt";
+    let contents = fs::read_to_string(lower)?;
+    assert_eq!(fs::read_to_string(merged)?, contents);
+
+    // copy up on write, leaving the lower layer intact
+    let mut file = OpenOptions::new().append(true).open(merged)?;
+    file.write_all(b"overlay\n")?;
+    drop(file);
+    assert_eq!(fs::read_to_string(merged)?, contents.clone() + "overlay\n");
+    assert_eq!(fs::read_to_string(lower)?, contents);
+    assert!(fs::metadata("/tmp/upper/path/test.txt").is_ok());
+    fs::write("/tmp/merged/new.txt", "new")?;
+    assert_err!(fs::metadata("/very/long/new.txt"), NotFound);
+    for name in ["new.txt", "path"] {
+        assert!(fs::read_dir("/tmp/merged")?.any(|e| e.unwrap().file_name() == name));
+    }
+
+    // a whiteout hides the lower file
+    fs::remove_file(merged)?;
+    assert_err!(fs::metadata(merged), NotFound);
+    assert!(!fs::read_dir("/tmp/merged/path")?.any(|e| e.unwrap().file_name() == "test.txt"));
+    assert_eq!(fs::read_to_string(lower)?, contents);
+    assert_err!(fs::write("/tmp/merged/.wh.new.txt", ""), InvalidInput);
+    assert!(fs::statfs("/tmp/merged").is_ok());
+
+    // a merged directory is copied up with its entries to be renamed
+    fs::write("/very/long/path/lower.txt", "lower")?;
+    assert_err!(fs::create_dir("/tmp/merged/path"), AlreadyExists);
+    fs::rename("/tmp/merged/path", "/tmp/merged/moved")?;
+    assert_err!(fs::metadata("/tmp/merged/path"), NotFound);
+    assert_eq!(fs::read_to_string("/tmp/merged/moved/lower.txt")?, "lower");
+    assert_err!(fs::metadata("/tmp/merged/moved/test.txt"), NotFound);
+    assert_eq!(fs::read_to_string(lower)?, contents);
+    fs::remove_file("/very/long/path/lower.txt")?;
+    assert!(fs::metadata("/tmp/merged/moved/lower.txt").is_ok());
+
+    fs::umount("/tmp/merged")?;
+    assert_err!(
+        fs::mount_fs_with_options("", "/tmp/merged", "overlay", "lowerdir=/very"),
+        InvalidInput
+    );
+    fs::remove_dir("/tmp/merged")?;
+    fs::remove_file("/tmp/upper/moved/lower.txt")?;
+    fs::remove_file("/tmp/upper/moved/.wh..wh..opq")?;
+    fs::remove_dir("/tmp/upper/moved")?;
+    fs::remove_file("/tmp/upper/.wh.path")?;
+    fs::remove_file("/tmp/upper/new.txt")?;
+    fs::remove_dir("/tmp/upper")?;
+
+    println!("test_overlay() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "procfs")]
+fn test_procfs() -> Result<()> {
+    println!("test procfs:");
+
+    let mounts = fs::read_to_string("/proc/mounts")?;
+    assert!(mounts.starts_with("/dev/root / "));
+    assert!(mounts.contains("tmpfs /tmp tmpfs rw 0 0\n"));
+    fs::create_dir("/tmp/mnt")?;
+    fs::mount_fs("", "/tmp/mnt", "tmpfs")?;
+    assert!(fs::read_to_string("/proc/mounts")?.contains(" /tmp/mnt tmpfs "));
+    fs::umount("/tmp/mnt")?;
+    fs::remove_dir("/tmp/mnt")?;
+
+    assert!(fs::read_to_string("/proc/uptime")?.ends_with(" 0.00\n"));
+    assert!(fs::read_to_string("/proc/cpuinfo")?.starts_with("processor\t: 0\n"));
+    assert!(fs::read_dir("/proc")?.any(|e| e.unwrap().file_name() == "sys"));
+
+    // tunables
+    let path = "/proc/sys/vm/block_cache_blocks";
+    let old = fs::read_to_string(path)?;
+    fs::write(path, "64\n")?;
+    assert_eq!(fs::read_to_string(path)?, "64\n");
+    assert_err!(fs::write(path, "0"), InvalidInput);
+    assert_err!(fs::write(path, "many"), InvalidInput);
+    fs::write(path, old)?;
+    assert_eq!(fs::read_to_string("/proc/sys/vm/overcommit_memory")?, "0\n");
+    assert_err!(
+        fs::write("/proc/sys/vm/overcommit_memory", "1"),
+        PermissionDenied
+    );
+
+    // files cannot be created or removed
+    assert_err!(fs::write("/proc/new.txt", "test"), PermissionDenied);
+    assert_err!(fs::create_dir("/proc/sys/new"), PermissionDenied);
+    assert_err!(fs::remove_file("/proc/mounts"), PermissionDenied);
+
+    println!("test_procfs() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "sysfs")]
+fn test_sysfs() -> Result<()> {
+    println!("test sysfs:");
+
+    let path = "/sys/kernel/log_level";
+    let old = fs::read_to_string(path)?;
+    fs::write(path, "debug")?;
+    assert_eq!(fs::read_to_string(path)?, "debug\n");
+    assert_err!(fs::write(path, "verbose"), InvalidInput);
+    fs::write(path, old)?;
+
+    let path = "/sys/kernel/mm/transparent_hugepage/enabled";
+    assert_eq!(fs::read_to_string(path)?, "always madvise [never]\n");
+    for dir in ["block", "bus", "class", "devices"] {
+        assert!(fs::read_dir("/sys")?.any(|e| e.unwrap().file_name() == dir));
+    }
+    assert_err!(fs::write("/sys/new.txt", "test"), PermissionDenied);
+
+    println!("test_sysfs() OK!");
+    Ok(())
+}
+
 pub fn test_all() {
     test_read_write_file().expect("test_read_write_file() failed");
     test_read_dir().expect("test_read_dir() failed");
@@ -259,4 +658,15 @@ pub fn test_all() {
     test_create_file_dir().expect("test_create_file_dir() failed");
     test_remove_file_dir().expect("test_remove_file_dir() failed");
     test_devfs_ramfs().expect("test_devfs_ramfs() failed");
+    test_mount_umount().expect("test_mount_umount() failed");
+    test_symlink_hard_link().expect("test_symlink_hard_link() failed");
+    test_ownership_times().expect("test_ownership_times() failed");
+    test_sparse_size_limit().expect("test_sparse_size_limit() failed");
+    test_file_locks().expect("test_file_locks() failed");
+    #[cfg(feature = "overlayfs")]
+    test_overlay().expect("test_overlay() failed");
+    #[cfg(feature = "procfs")]
+    test_procfs().expect("test_procfs() failed");
+    #[cfg(feature = "sysfs")]
+    test_sysfs().expect("test_sysfs() failed");
 }
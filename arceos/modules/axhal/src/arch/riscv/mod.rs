@@ -4,6 +4,9 @@ mod macros;
 mod context;
 mod trap;
 
+#[cfg(feature = "uspace")]
+pub(crate) mod uaccess;
+
 use memory_addr::{PhysAddr, VirtAddr};
 use riscv::asm;
 use riscv::register::{satp, sstatus, stvec};
@@ -16,11 +16,22 @@ fn handle_breakpoint(sepc: &mut usize) {
     *sepc += 2
 }
 
-fn handle_page_fault(tf: &TrapFrame, mut access_flags: MappingFlags, is_user: bool) {
+fn handle_page_fault(tf: &mut TrapFrame, mut access_flags: MappingFlags, is_user: bool) {
     if is_user {
         access_flags |= MappingFlags::USER;
     }
     let vaddr = va!(stval::read());
+    #[cfg(feature = "uspace")]
+    if !is_user && super::uaccess::search_ex_table(tf.sepc).is_some() {
+        // The kernel is accessing user memory on behalf of the user, which
+        // may fault in lazily mapped pages. On a real fault, resume at the
+        // fixup code of the access routine.
+        access_flags |= MappingFlags::USER;
+        if !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user) {
+            super::uaccess::fixup_exception(tf);
+        }
+        return;
+    }
     if !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user) {
         panic!(
             "Unhandled {} Page Fault @ {:#x}, fault_vaddr={:#x} ({:?}):\n{:#x?}",
@@ -0,0 +1,68 @@
+//! Low-level user memory access with fault recovery.
+
+use super::TrapFrame;
+
+include_asm_marcos!();
+
+core::arch::global_asm!(include_str!("uaccess.S"));
+
+extern "C" {
+    fn __user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    fn __user_strncpy(dst: *mut u8, src: *const u8, max: usize) -> isize;
+    fn _sex_table();
+    fn _eex_table();
+}
+
+/// An exception table entry: a fault at `insn` resumes at `fixup`.
+#[repr(C)]
+struct ExTableEntry {
+    insn: usize,
+    fixup: usize,
+}
+
+fn ex_table() -> &'static [ExTableEntry] {
+    let start = _sex_table as usize;
+    let len = (_eex_table as usize - start) / core::mem::size_of::<ExTableEntry>();
+    unsafe { core::slice::from_raw_parts(start as *const ExTableEntry, len) }
+}
+
+/// Returns the fixup address if the instruction at `pc` may fault when
+/// accessing user memory.
+pub(crate) fn search_ex_table(pc: usize) -> Option<usize> {
+    ex_table().iter().find(|e| e.insn == pc).map(|e| e.fixup)
+}
+
+/// Resumes the faulting user access in `tf` at its fixup code.
+///
+/// Returns `false` if the fault did not happen in a user access routine.
+pub(crate) fn fixup_exception(tf: &mut TrapFrame) -> bool {
+    if let Some(fixup) = search_ex_table(tf.sepc) {
+        tf.sepc = fixup;
+        true
+    } else {
+        false
+    }
+}
+
+/// Copies `len` bytes from `src` to `dst`. Returns the number of bytes not
+/// copied because of a fault.
+///
+/// # Safety
+///
+/// The kernel side of the copy must be valid for `len` bytes.
+pub(crate) unsafe fn user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    __user_copy(dst, src, len)
+}
+
+/// Copies a NUL-terminated string of at most `max` bytes from `src` to `dst`.
+///
+/// Returns the length of the string, `max` if it is not terminated within
+/// `max` bytes, or `None` on fault.
+///
+/// # Safety
+///
+/// `dst` must be valid for `max` bytes.
+pub(crate) unsafe fn user_strncpy(dst: *mut u8, src: *const u8, max: usize) -> Option<usize> {
+    let ret = __user_strncpy(dst, src, max);
+    (ret >= 0).then_some(ret as usize)
+}
@@ -1,17 +1,37 @@
 //! Interrupt management.
 
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use handler_table::HandlerTable;
 
-use crate::platform::irq::{dispatch_irq, MAX_IRQ_COUNT};
+use crate::platform::irq::dispatch_irq;
 use crate::trap::{register_trap_handler, IRQ};
 
-pub use crate::platform::irq::{register_handler, set_enable};
+pub use crate::platform::irq::{register_handler, set_enable, MAX_IRQ_COUNT};
 
 /// The type if an IRQ handler.
 pub type IrqHandler = handler_table::Handler;
 
 static IRQ_HANDLER_TABLE: HandlerTable<MAX_IRQ_COUNT> = HandlerTable::new();
 
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const ZEROS: [AtomicUsize; MAX_IRQ_COUNT] = [ZERO; MAX_IRQ_COUNT];
+
+/// The number of times each IRQ has been handled on each CPU.
+static IRQ_COUNTS: [[AtomicUsize; MAX_IRQ_COUNT]; axconfig::SMP] = [ZEROS; axconfig::SMP];
+
+/// Returns the number of times the IRQ has been handled on the CPU.
+///
+/// IRQ numbers are taken modulo [`MAX_IRQ_COUNT`], which drops the interrupt
+/// bit of the `scause` values used as IRQ numbers on RISC-V.
+pub fn irq_count(cpu_id: usize, irq_num: usize) -> usize {
+    IRQ_COUNTS.get(cpu_id).map_or(0, |counts| {
+        counts[irq_num % MAX_IRQ_COUNT].load(Ordering::Relaxed)
+    })
+}
+
 /// Platform-independent IRQ dispatching.
 #[allow(dead_code)]
 pub(crate) fn dispatch_irq_common(irq_num: usize) {
@@ -37,6 +57,9 @@ pub(crate) fn register_handler_common(irq_num: usize, handler: IrqHandler) -> bo
 
 #[register_trap_handler(IRQ)]
 fn handler_irq(irq_num: usize) -> bool {
+    if let Some(counts) = IRQ_COUNTS.get(crate::cpu::this_cpu_id()) {
+        counts[irq_num % MAX_IRQ_COUNT].fetch_add(1, Ordering::Relaxed);
+    }
     let guard = kernel_guard::NoPreempt::new();
     dispatch_irq(irq_num);
     drop(guard); // rescheduling may occur when preemption is re-enabled.
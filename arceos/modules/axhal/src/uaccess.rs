@@ -0,0 +1,251 @@
+//! Safe access to user memory.
+//!
+//! All accesses go through copy routines registered in an exception table.
+//! When one of them faults on an address that cannot be mapped, the page fault
+//! handler resumes it at its fixup code, so a bad pointer from the user makes
+//! the access fail with [`AxError::BadAddress`] (`EFAULT`) instead of crashing
+//! the kernel.
+//!
+//! The exception table is only implemented on RISC-V for now. On the other
+//! architectures, the user range is checked and then copied directly, so a bad
+//! pointer below the kernel address space still faults in the kernel.
+
+use core::ffi::c_char;
+use core::marker::PhantomData;
+use core::mem::{size_of, size_of_val, MaybeUninit};
+
+use axerrno::{ax_err, AxError, AxResult};
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+        use crate::arch::uaccess::{user_copy, user_strncpy};
+    } else {
+        use self::direct::{user_copy, user_strncpy};
+    }
+}
+
+/// Checks that `[addr, addr + len)` lies below the kernel address space.
+fn check_region(addr: usize, len: usize) -> AxResult {
+    match addr.checked_add(len) {
+        Some(end) if end <= axconfig::KERNEL_ASPACE_BASE => Ok(()),
+        _ => ax_err!(BadAddress, "not a user address"),
+    }
+}
+
+/// Plain copies without fault recovery, for the architectures without an
+/// exception table.
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+mod direct {
+    /// Copies `len` bytes from `src` to `dst`. Always returns 0, i.e. all
+    /// bytes are copied.
+    ///
+    /// # Safety
+    ///
+    /// Both sides of the copy must be valid for `len` bytes.
+    pub(super) unsafe fn user_copy(dst: *mut u8, src: *const u8, len: usize) -> usize {
+        unsafe { core::ptr::copy_nonoverlapping(src, dst, len) };
+        0
+    }
+
+    /// Copies a NUL-terminated string of at most `max` bytes from `src` to
+    /// `dst`.
+    ///
+    /// Returns the length of the string, `max` if it is not terminated within
+    /// `max` bytes, or `None` if it runs into the kernel address space.
+    ///
+    /// # Safety
+    ///
+    /// `dst` must be valid for `max` bytes, and `src` up to the end of the
+    /// string.
+    pub(super) unsafe fn user_strncpy(dst: *mut u8, src: *const u8, max: usize) -> Option<usize> {
+        for i in 0..max {
+            if src as usize + i >= axconfig::KERNEL_ASPACE_BASE {
+                return None;
+            }
+            let c = unsafe { src.add(i).read() };
+            unsafe { dst.add(i).write(c) };
+            if c == 0 {
+                return Some(i);
+            }
+        }
+        Some(max)
+    }
+}
+
+/// Copies `dst.len()` bytes from the user address `src` to `dst`.
+pub fn copy_from_user(dst: &mut [u8], src: *const u8) -> AxResult {
+    check_region(src as usize, dst.len())?;
+    match unsafe { user_copy(dst.as_mut_ptr(), src, dst.len()) } {
+        0 => Ok(()),
+        _ => Err(AxError::BadAddress),
+    }
+}
+
+/// Copies `src` to the user address `dst`.
+pub fn copy_to_user(dst: *mut u8, src: &[u8]) -> AxResult {
+    check_region(dst as usize, src.len())?;
+    match unsafe { user_copy(dst, src.as_ptr(), src.len()) } {
+        0 => Ok(()),
+        _ => Err(AxError::BadAddress),
+    }
+}
+
+/// Copies a NUL-terminated string from the user address `src` to `dst`,
+/// including the NUL.
+///
+/// Returns the length of the string without the NUL, or `dst.len()` if it is
+/// not terminated within `dst.len()` bytes.
+pub fn strncpy_from_user(dst: &mut [u8], src: *const c_char) -> AxResult<usize> {
+    // The string may end before the limit, so only the start is checked here.
+    // Copying past the user space runs into unmapped memory and faults.
+    check_region(src as usize, 1)?;
+    unsafe { user_strncpy(dst.as_mut_ptr(), src as *const u8, dst.len()) }
+        .ok_or(AxError::BadAddress)
+}
+
+/// A pointer to a `T` in user space.
+#[repr(transparent)]
+pub struct UserPtr<T> {
+    addr: usize,
+    _phantom: PhantomData<*mut T>,
+}
+
+impl<T> Clone for UserPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserPtr<T> {}
+
+impl<T> From<*const T> for UserPtr<T> {
+    fn from(ptr: *const T) -> Self {
+        Self::new(ptr as usize)
+    }
+}
+
+impl<T> From<*mut T> for UserPtr<T> {
+    fn from(ptr: *mut T) -> Self {
+        Self::new(ptr as usize)
+    }
+}
+
+impl<T> UserPtr<T> {
+    /// Creates a pointer to the user address `addr`.
+    pub const fn new(addr: usize) -> Self {
+        Self {
+            addr,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the user address.
+    pub const fn addr(&self) -> usize {
+        self.addr
+    }
+
+    /// Returns whether the pointer is null.
+    pub const fn is_null(&self) -> bool {
+        self.addr == 0
+    }
+
+    /// Returns the pointer to the `count`-th `T` after this one.
+    pub const fn add(&self, count: usize) -> Self {
+        Self::new(self.addr.wrapping_add(count * size_of::<T>()))
+    }
+}
+
+impl<T: Copy> UserPtr<T> {
+    /// Reads the value from user space.
+    pub fn read(&self) -> AxResult<T> {
+        let mut val = MaybeUninit::<T>::uninit();
+        let buf =
+            unsafe { core::slice::from_raw_parts_mut(val.as_mut_ptr() as *mut u8, size_of::<T>()) };
+        copy_from_user(buf, self.addr as *const u8)?;
+        Ok(unsafe { val.assume_init() })
+    }
+
+    /// Writes the value to user space.
+    pub fn write(&self, val: T) -> AxResult {
+        let buf = unsafe { core::slice::from_raw_parts(&val as *const T as *const u8, size_of::<T>()) };
+        copy_to_user(self.addr as *mut u8, buf)
+    }
+}
+
+impl UserPtr<c_char> {
+    /// Reads a NUL-terminated UTF-8 string into `buf`, and returns it without
+    /// the NUL.
+    ///
+    /// Returns [`AxError::InvalidInput`] if the string does not fit in `buf`,
+    /// or [`AxError::InvalidData`] if it is not valid UTF-8.
+    pub fn read_str<'a>(&self, buf: &'a mut [u8]) -> AxResult<&'a str> {
+        let len = strncpy_from_user(buf, self.addr as *const c_char)?;
+        if len == buf.len() {
+            return ax_err!(InvalidInput, "string too long");
+        }
+        core::str::from_utf8(&buf[..len]).map_err(|_| AxError::InvalidData)
+    }
+}
+
+/// A slice of `T` in user space.
+#[derive(Clone, Copy)]
+pub struct UserSlice<T> {
+    ptr: UserPtr<T>,
+    len: usize,
+}
+
+impl<T> UserSlice<T> {
+    /// Creates a slice of `len` elements at the user address `addr`.
+    pub const fn new(addr: usize, len: usize) -> Self {
+        Self {
+            ptr: UserPtr::new(addr),
+            len,
+        }
+    }
+
+    /// Returns the pointer to the first element.
+    pub const fn as_ptr(&self) -> UserPtr<T> {
+        self.ptr
+    }
+
+    /// Returns the number of elements.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the slice is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T: Copy> UserSlice<T> {
+    /// Reads the `idx`-th element.
+    pub fn get(&self, idx: usize) -> AxResult<T> {
+        if idx >= self.len {
+            return ax_err!(InvalidInput, "index out of bounds");
+        }
+        self.ptr.add(idx).read()
+    }
+
+    /// Reads the first `buf.len()` elements into `buf`.
+    pub fn read(&self, buf: &mut [T]) -> AxResult {
+        if buf.len() > self.len {
+            return ax_err!(InvalidInput, "buffer larger than the user slice");
+        }
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, size_of_val(buf))
+        };
+        copy_from_user(bytes, self.ptr.addr() as *const u8)
+    }
+
+    /// Writes `buf` to the first `buf.len()` elements.
+    pub fn write(&self, buf: &[T]) -> AxResult {
+        if buf.len() > self.len {
+            return ax_err!(InvalidInput, "buffer larger than the user slice");
+        }
+        let bytes =
+            unsafe { core::slice::from_raw_parts(buf.as_ptr() as *const u8, size_of_val(buf)) };
+        copy_to_user(self.ptr.addr() as *mut u8, bytes)
+    }
+}
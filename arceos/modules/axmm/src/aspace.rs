@@ -3,15 +3,17 @@ use core::fmt;
 use axerrno::{ax_err, AxError, AxResult};
 use axhal::{
     mem::phys_to_virt,
-    paging::{MappingFlags, PageTable},
+    paging::{MappingFlags, PageSize, PageTable},
 };
 use memory_addr::{
-    is_aligned_4k, pa, MemoryAddr, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange, PAGE_SIZE_4K,
+    is_aligned_4k, MemoryAddr, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange, PAGE_SIZE_4K,
 };
 use memory_set::{MemoryArea, MemorySet};
-use crate::backend::Backend;
+use crate::backend::{Backend, BackendType};
 use crate::paging_err_to_ax_err;
 use crate::mapping_err_to_ax_err;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::vec::Vec;
 
 /// The virtual memory address space.
@@ -19,6 +21,60 @@ pub struct AddrSpace {
     va_range: VirtAddrRange,
     areas: MemorySet<Backend>,
     pt: PageTable,
+    /// Names attached to address ranges, keyed by the range start.
+    names: BTreeMap<VirtAddr, AreaName>,
+    /// Areas that grow downward on page faults below them, keyed by the
+    /// area top.
+    grows_down: BTreeMap<VirtAddr, GrowsDown>,
+    /// The maximum size of a grows-down area, i.e. `RLIMIT_STACK`.
+    stack_limit: usize,
+}
+
+/// The size of the gap kept between a grows-down area and the mapping below
+/// it. A fault in the gap is not taken as stack growth, so a stack overflow
+/// cannot silently run into another mapping.
+pub const STACK_GUARD_GAP: usize = 256 * PAGE_SIZE_4K;
+
+/// A downward-growing area created by [`AddrSpace::map_grows_down`].
+struct GrowsDown {
+    /// The current lowest address of the area.
+    bottom: VirtAddr,
+    /// The maximum size the area may grow to.
+    max_size: usize,
+    /// Whether the range the area may grow into is kept free by
+    /// [`AddrSpace::find_free_area`].
+    reserve: bool,
+}
+
+/// A name attached to an address range by [`AddrSpace::set_area_name`].
+struct AreaName {
+    end: VirtAddr,
+    name: String,
+    /// The offset in the mapped file of the range start, if it maps a file.
+    file_offset: Option<usize>,
+}
+
+/// Information about a mapped area, yielded by [`AddrSpace::areas`].
+#[derive(Debug)]
+pub struct AreaInfo<'a> {
+    /// The virtual address range of the area.
+    pub va_range: VirtAddrRange,
+    /// The mapping permissions and attributes.
+    pub flags: MappingFlags,
+    /// The type of the mapping backend.
+    pub backend: BackendType,
+    /// The name of the area, e.g. `[stack]` or the path of the mapped file.
+    /// Empty if the area is anonymous.
+    pub name: &'a str,
+    /// The offset in the mapped file of the area start. Zero if the area does
+    /// not map a file.
+    pub file_offset: usize,
+    /// The number of pages backed by physical frames.
+    pub resident_pages: usize,
+    /// The number of resident pages whose contents would be lost if they were
+    /// dropped. Allocation mappings have no backing store, so all their
+    /// resident pages count as dirty.
+    pub dirty_pages: usize,
 }
 
 impl AddrSpace {
@@ -59,6 +115,9 @@ impl AddrSpace {
             va_range: VirtAddrRange::from_start_size(base, size),
             areas: MemorySet::new(),
             pt: PageTable::try_new().map_err(|_| AxError::NoMemory)?,
+            names: BTreeMap::new(),
+            grows_down: BTreeMap::new(),
+            stack_limit: usize::MAX,
         })
     }
 
@@ -88,7 +147,35 @@ impl AddrSpace {
         size: usize,
         limit: VirtAddrRange,
     ) -> Option<VirtAddr> {
-        self.areas.find_free_area(hint, size, limit)
+        let mut hint = hint;
+        loop {
+            let start = self.areas.find_free_area(hint, size, limit)?;
+            let range = VirtAddrRange::from_start_size(start, size);
+            // Skip the ranges reserved for the growth of grows-down areas.
+            let reserved = self.grows_down.iter().find_map(|(&top, gd)| {
+                let floor = top
+                    .as_usize()
+                    .saturating_sub(gd.max_size.saturating_add(STACK_GUARD_GAP));
+                let reserved = VirtAddrRange::new(floor.into(), gd.bottom);
+                (gd.reserve && reserved.overlaps(range)).then_some(gd.bottom)
+            });
+            match reserved {
+                Some(bottom) => hint = bottom,
+                None => return Some(start),
+            }
+        }
+    }
+
+    /// Returns the maximum size of a grows-down area.
+    pub const fn stack_limit(&self) -> usize {
+        self.stack_limit
+    }
+
+    /// Sets the maximum size of a grows-down area, i.e. `RLIMIT_STACK`.
+    ///
+    /// Areas that are already larger are kept, but do not grow any more.
+    pub fn set_stack_limit(&mut self, limit: usize) {
+        self.stack_limit = limit;
     }
 
     /// Add a new linear mapping.
@@ -115,17 +202,10 @@ impl AddrSpace {
         }
 
         let offset = start_vaddr.as_usize() - start_paddr.as_usize();
-        self.pt
-            .map_region(
-                start_vaddr,
-                |va| pa!(va.as_usize() - offset),
-                size,
-                flags,
-                false, // allow_huge
-                false, // flush_tlb_by_page
-            )
-            .map_err(paging_err_to_ax_err)?
-            .flush_all();
+        let area = MemoryArea::new(start_vaddr, size, flags, Backend::new_linear(offset));
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
         Ok(())
     }
 
@@ -158,8 +238,88 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Adds a new allocation mapping `[top - size, top)` that grows downward,
+    /// e.g. a user stack.
+    ///
+    /// A page fault within [`STACK_GUARD_GAP`] below the area extends it with
+    /// lazily allocated pages, as long as it stays within `max_size` and the
+    /// [stack limit](Self::stack_limit), and does not come within the guard
+    /// gap of the mapping below. If `reserve` is `true`, the range it may grow
+    /// into is skipped by [`find_free_area`](Self::find_free_area).
+    pub fn map_grows_down(
+        &mut self,
+        top: VirtAddr,
+        size: usize,
+        max_size: usize,
+        flags: MappingFlags,
+        populate: bool,
+        reserve: bool,
+    ) -> AxResult {
+        if size > max_size || top.as_usize() < size {
+            return ax_err!(InvalidInput, "invalid stack size");
+        }
+        let bottom = top - size;
+        self.map_alloc(bottom, size, flags, populate)?;
+        self.grows_down.insert(
+            top,
+            GrowsDown {
+                bottom,
+                max_size,
+                reserve,
+            },
+        );
+        Ok(())
+    }
+
+    /// Extends the grows-down area right above `vaddr` down to the page
+    /// containing `vaddr`.
+    ///
+    /// Returns `false` if there is no such area, or it is not allowed to grow
+    /// that far.
+    fn grow_down_to(&mut self, vaddr: VirtAddr) -> bool {
+        let Some((&top, gd)) = self.grows_down.range(vaddr + 1..).next() else {
+            return false;
+        };
+        let bottom = gd.bottom;
+        if vaddr >= bottom || bottom - vaddr > STACK_GUARD_GAP {
+            return false;
+        }
+        let new_bottom = vaddr.align_down_4k();
+        if top - new_bottom > gd.max_size.min(self.stack_limit) {
+            return false;
+        }
+        let guard_start = new_bottom
+            .as_usize()
+            .saturating_sub(STACK_GUARD_GAP)
+            .max(self.base().as_usize());
+        if self
+            .areas
+            .overlaps(VirtAddrRange::new(guard_start.into(), bottom))
+        {
+            return false;
+        }
+        let Some(flags) = self.areas.find(bottom).map(|area| area.flags()) else {
+            return false;
+        };
+
+        let size = bottom - new_bottom;
+        let area = MemoryArea::new(new_bottom, size, flags, Backend::new_alloc(false));
+        if self.areas.map(area, &mut self.pt, false).is_err() {
+            return false;
+        }
+        if let Some((name, None)) = self.area_name(bottom) {
+            let name = String::from(name);
+            self.set_area_name(new_bottom, size, &name, None);
+        }
+        self.grows_down.get_mut(&top).unwrap().bottom = new_bottom;
+        true
+    }
+
     /// Removes mappings within the specified virtual address range.
     ///
+    /// Areas that partially overlap the range are split, and only the
+    /// overlapped part is unmapped.
+    ///
     /// Returns an error if the address range is out of the address space or not
     /// aligned.
     pub fn unmap(&mut self, start: VirtAddr, size: usize) -> AxResult {
@@ -170,13 +330,288 @@ impl AddrSpace {
             return ax_err!(InvalidInput, "address not aligned");
         }
 
-        self.pt
-            .unmap_region(start, size, true)
-            .map_err(paging_err_to_ax_err)?
-            .ignore();
+        self.areas
+            .unmap(start, size, &mut self.pt)
+            .map_err(mapping_err_to_ax_err)?;
+        self.clear_area_names(start, size);
+        self.clear_grows_down(start, size);
         Ok(())
     }
 
+    /// Updates the grows-down areas after `[start, start + size)` is unmapped.
+    ///
+    /// An area that loses its top stops growing; one that loses only its
+    /// bottom part shrinks.
+    fn clear_grows_down(&mut self, start: VirtAddr, size: usize) {
+        let end = start + size;
+        self.grows_down.retain(|&top, gd| {
+            if end <= gd.bottom || start >= top {
+                true
+            } else if start <= gd.bottom && end < top {
+                gd.bottom = end;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    /// Resizes the allocation mapping starting at `old_start`.
+    ///
+    /// The old range must lie within a single allocation area. Shrinking
+    /// unmaps the tail of the range. Growing first tries to extend the mapping
+    /// in place; if the following range is occupied and `may_move` is `true`,
+    /// the mapping is moved to a free area, carrying its physical frames along.
+    ///
+    /// Returns the (possibly new) start address of the mapping.
+    pub fn remap(
+        &mut self,
+        old_start: VirtAddr,
+        old_size: usize,
+        new_size: usize,
+        may_move: bool,
+    ) -> AxResult<VirtAddr> {
+        if !self.contains_range(old_start, old_size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !old_start.is_aligned_4k() || !is_aligned_4k(old_size) || !is_aligned_4k(new_size) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+        if new_size == 0 {
+            return ax_err!(InvalidInput, "zero-sized mapping");
+        }
+
+        let old_end = old_start + old_size;
+        let (flags, backend) = match self.areas.find(old_start) {
+            Some(area) if area.end() >= old_end => (area.flags(), area.backend().clone()),
+            _ => return ax_err!(BadAddress, "range not within a single area"),
+        };
+        if !matches!(backend, Backend::Alloc { .. }) {
+            return ax_err!(InvalidInput, "only allocation mappings can be remapped");
+        }
+        let name = self
+            .area_name(old_start)
+            .map(|(name, file_offset)| (String::from(name), file_offset));
+
+        if new_size <= old_size {
+            if new_size < old_size {
+                self.unmap(old_start + new_size, old_size - new_size)?;
+            }
+            return Ok(old_start);
+        }
+
+        // Try to grow in place.
+        let grow_size = new_size - old_size;
+        if self.contains_range(old_end, grow_size)
+            && !self
+                .areas
+                .overlaps(VirtAddrRange::from_start_size(old_end, grow_size))
+        {
+            let area = MemoryArea::new(old_end, grow_size, flags, backend);
+            self.areas
+                .map(area, &mut self.pt, false)
+                .map_err(mapping_err_to_ax_err)?;
+            if let Some((name, file_offset)) = name {
+                let file_offset = file_offset.map(|off| off + old_size);
+                self.set_area_name(old_end, grow_size, &name, file_offset);
+            }
+            return Ok(old_start);
+        }
+        if !may_move {
+            return ax_err!(NoMemory, "cannot grow the mapping in place");
+        }
+
+        // Move the mapping. Frames already backing the old range are moved to
+        // the new one, the rest are allocated on demand.
+        let new_start = self
+            .find_free_area(self.base(), new_size, self.va_range)
+            .ok_or(AxError::NoMemory)?;
+        let area = MemoryArea::new(new_start, new_size, flags, Backend::new_alloc(false));
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        let mut moved = Vec::new();
+        for (i, vaddr) in PageIter4K::new(old_start, old_end)
+            .expect("Failed to create page iterator")
+            .enumerate()
+        {
+            let Ok((frame, _, tlb)) = self.pt.unmap(vaddr) else {
+                continue;
+            };
+            tlb.flush();
+            // The new area is lazily allocated, so the new entry is empty and
+            // there is nothing to flush.
+            match self
+                .pt
+                .map(new_start + i * PAGE_SIZE_4K, frame, PageSize::Size4K, flags)
+            {
+                Ok(tlb) => tlb.ignore(),
+                Err(e) => {
+                    // Put the frames back, so that the old mapping is intact.
+                    if let Ok(tlb) = self.pt.map(vaddr, frame, PageSize::Size4K, flags) {
+                        tlb.ignore();
+                    }
+                    for j in moved {
+                        let offset = j * PAGE_SIZE_4K;
+                        self.move_frame(new_start + offset, old_start + offset, flags);
+                    }
+                    self.unmap(new_start, new_size)?;
+                    return Err(paging_err_to_ax_err(e));
+                }
+            }
+            moved.push(i);
+        }
+        // No frames are left in the old range, so this only removes the area.
+        self.unmap(old_start, old_size)?;
+        if let Some((name, file_offset)) = name {
+            self.set_area_name(new_start, new_size, &name, file_offset);
+        }
+        Ok(new_start)
+    }
+
+    /// Moves the frame mapped at `from` to the unmapped page `to`.
+    fn move_frame(&mut self, from: VirtAddr, to: VirtAddr, flags: MappingFlags) {
+        if let Ok((frame, _, tlb)) = self.pt.unmap(from) {
+            tlb.flush();
+            if let Ok(tlb) = self.pt.map(to, frame, PageSize::Size4K, flags) {
+                tlb.ignore();
+            }
+        }
+    }
+
+    /// Discards the contents of the allocation mappings within the specified
+    /// virtual address range, i.e., `madvise(MADV_DONTNEED)`.
+    ///
+    /// Subsequent accesses to the range observe zero-filled pages. Lazy
+    /// mappings release their frames and allocate fresh ones on the next page
+    /// fault, while populated mappings are zeroed in place.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned.
+    pub fn discard(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() || !is_aligned_4k(size) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let end = start + size;
+        for area in self.areas.iter() {
+            let area_start = area.start().max(start);
+            let area_end = area.end().min(end);
+            if area_start < area_end {
+                area.backend()
+                    .discard(area_start, area_end - area_start, &mut self.pt);
+            }
+        }
+        Ok(())
+    }
+
+    /// Attaches a name to the given address range, e.g. `[stack]` or the path
+    /// of a mapped file. It replaces the names previously attached to any part
+    /// of the range.
+    ///
+    /// If `file_offset` is given, the range is reported as a file mapping
+    /// starting at that offset of the file.
+    ///
+    /// Names are kept across area splitting, and are removed together with the
+    /// mappings by [`unmap`](Self::unmap).
+    pub fn set_area_name(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        name: &str,
+        file_offset: Option<usize>,
+    ) {
+        self.clear_area_names(start, size);
+        self.names.insert(
+            start,
+            AreaName {
+                end: start + size,
+                name: name.into(),
+                file_offset,
+            },
+        );
+    }
+
+    /// Removes the names attached to the given address range, trimming names
+    /// that partially overlap it.
+    fn clear_area_names(&mut self, start: VirtAddr, size: usize) {
+        let end = start + size;
+        let overlapped: Vec<VirtAddr> = self
+            .names
+            .range(..end)
+            .filter(|(_, n)| n.end > start)
+            .map(|(&key, _)| key)
+            .collect();
+        for key in overlapped {
+            let n = self.names.remove(&key).unwrap();
+            if key < start {
+                let left = AreaName {
+                    end: start,
+                    name: n.name.clone(),
+                    file_offset: n.file_offset,
+                };
+                self.names.insert(key, left);
+            }
+            if n.end > end {
+                let right = AreaName {
+                    end: n.end,
+                    name: n.name,
+                    file_offset: n.file_offset.map(|off| off + (end - key)),
+                };
+                self.names.insert(end, right);
+            }
+        }
+    }
+
+    /// Returns the name attached to the given address, and the file offset
+    /// it maps if any.
+    fn area_name(&self, vaddr: VirtAddr) -> Option<(&str, Option<usize>)> {
+        let (&start, n) = self.names.range(..=vaddr).next_back()?;
+        if n.end <= vaddr {
+            return None;
+        }
+        Some((&n.name, n.file_offset.map(|off| off + (vaddr - start))))
+    }
+
+    /// Returns an iterator over the mapped areas, in ascending address order.
+    ///
+    /// Counting resident pages queries the page table for every page of the
+    /// allocation areas, so it is relatively expensive.
+    pub fn areas(&self) -> impl Iterator<Item = AreaInfo<'_>> {
+        self.areas.iter().map(|area| {
+            let (name, file_offset) = self.area_name(area.start()).unwrap_or(("", None));
+            let (backend, resident_pages, dirty_pages) = match area.backend() {
+                Backend::Linear { .. } => (BackendType::Linear, area.size() / PAGE_SIZE_4K, 0),
+                Backend::Alloc { .. } => {
+                    let resident = PageIter4K::new(area.start(), area.end())
+                        .expect("Failed to create page iterator")
+                        .filter(|&vaddr| {
+                            matches!(self.pt.query(vaddr), Ok((_, flags, _)) if !flags.is_empty())
+                        })
+                        .count();
+                    let ty = if file_offset.is_some() {
+                        BackendType::File
+                    } else {
+                        BackendType::Alloc
+                    };
+                    (ty, resident, resident)
+                }
+            };
+            AreaInfo {
+                va_range: area.va_range(),
+                flags: area.flags(),
+                backend,
+                name,
+                file_offset: file_offset.unwrap_or(0),
+                resident_pages,
+                dirty_pages,
+            }
+        })
+    }
+
     /// To process data in this area with the given function.
     ///
     /// Now it supports reading and writing data in the given interval.
@@ -237,6 +672,9 @@ impl AddrSpace {
 
     /// Updates mapping within the specified virtual address range.
     ///
+    /// Areas that partially overlap the range are split, and only the
+    /// overlapped part gets the new `flags`.
+    ///
     /// Returns an error if the address range is out of the address space or not
     /// aligned.
     pub fn protect(&mut self, start: VirtAddr, size: usize, flags: MappingFlags) -> AxResult {
@@ -247,16 +685,17 @@ impl AddrSpace {
             return ax_err!(InvalidInput, "address not aligned");
         }
 
-        self.pt
-            .protect_region(start, size, flags, true)
-            .map_err(paging_err_to_ax_err)?
-            .ignore();
+        self.areas
+            .protect(start, size, |_| Some(flags), &mut self.pt)
+            .map_err(mapping_err_to_ax_err)?;
         Ok(())
     }
 
     /// Handles a page fault at the given address.
     ///
     /// `access_flags` indicates the access type that caused the page fault.
+    /// A fault just below a grows-down area extends the area first, see
+    /// [`map_grows_down`](Self::map_grows_down).
     ///
     /// Returns `true` if the page fault is handled successfully (not a real
     /// fault).
@@ -264,6 +703,9 @@ impl AddrSpace {
         if !self.va_range.contains(vaddr) {
             return false;
         }
+        if self.areas.find(vaddr).is_none() && !self.grow_down_to(vaddr) {
+            return false;
+        }
         if let Some(area) = self.areas.find(vaddr) {
             let orig_flags = area.flags();
             if orig_flags.contains(access_flags) {
@@ -105,4 +105,60 @@ impl Backend {
             false
         }
     }
+    /// Updates the flags of the pages that have been faulted in. Pages not
+    /// yet allocated keep their empty entries and pick up the new flags from
+    /// the area on the next page fault.
+    pub(crate) fn protect_lazy(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        debug!(
+            "protect_lazy: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            new_flags
+        );
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            match pt.query(addr) {
+                Ok((_, flags, _)) if !flags.is_empty() => {
+                    if let Ok((_, tlb)) = pt.protect(addr, new_flags) {
+                        tlb.flush();
+                    } else {
+                        return false;
+                    }
+                }
+                _ => {} // Not faulted in yet.
+            }
+        }
+        true
+    }
+
+    pub(crate) fn discard_alloc(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        pt: &mut PageTable,
+        populate: bool,
+    ) {
+        debug!("discard_alloc: [{:#x}, {:#x})", start, start + size);
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            if populate {
+                // Populated mappings must stay mapped, so just clear the frame.
+                if let Ok((frame, _, _)) = pt.query(addr) {
+                    unsafe {
+                        core::ptr::write_bytes(phys_to_virt(frame).as_mut_ptr(), 0, PAGE_SIZE_4K)
+                    };
+                }
+            } else if let Ok((frame, page_size, tlb)) = pt.unmap(addr) {
+                // The page will be allocated again on the next access.
+                if !page_size.is_huge() {
+                    tlb.flush();
+                    dealloc_frame(frame);
+                }
+            }
+        }
+    }
 }
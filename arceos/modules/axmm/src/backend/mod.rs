@@ -39,6 +39,18 @@ pub enum Backend {
     },
 }
 
+/// The type of backend that maps an area, as reported by
+/// [`AddrSpace::areas`](crate::AddrSpace::areas).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendType {
+    /// Linear mapping to contiguous physical frames.
+    Linear,
+    /// Anonymous allocation mapping.
+    Alloc,
+    /// Allocation mapping holding the contents of a file.
+    File,
+}
+
 impl MappingBackend for Backend {
     type Addr = VirtAddr;
     type Flags = MappingFlags;
@@ -64,10 +76,13 @@ impl MappingBackend for Backend {
         new_flags: Self::Flags,
         page_table: &mut Self::PageTable,
     ) -> bool {
-        page_table
-            .protect_region(start, size, new_flags, true)
-            .map(|tlb| tlb.ignore())
-            .is_ok()
+        match *self {
+            Self::Alloc { populate: false } => self.protect_lazy(start, size, new_flags, page_table),
+            _ => page_table
+                .protect_region(start, size, new_flags, true)
+                .map(|tlb| tlb.ignore())
+                .is_ok(),
+        }
     }
 }
 
@@ -85,4 +100,10 @@ impl Backend {
             }
         }
     }
+    pub(crate) fn discard(&self, start: VirtAddr, size: usize, page_table: &mut PageTable) {
+        match *self {
+            Self::Linear { .. } => {} // Linear mappings have no private contents.
+            Self::Alloc { populate } => self.discard_alloc(start, size, page_table, populate),
+        }
+    }
 }
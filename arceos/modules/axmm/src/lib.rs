@@ -9,7 +9,8 @@ extern crate alloc;
 mod aspace;
 mod backend;
 
-pub use self::aspace::AddrSpace;
+pub use self::aspace::{AddrSpace, AreaInfo, STACK_GUARD_GAP};
+pub use self::backend::BackendType;
 
 use axerrno::{AxError, AxResult};
 use axhal::mem::phys_to_virt;
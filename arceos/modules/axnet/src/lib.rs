@@ -35,6 +35,8 @@ pub use self::net_impl::TcpSocket;
 pub use self::net_impl::UdpSocket;
 pub use self::net_impl::{bench_receive, bench_transmit};
 pub use self::net_impl::{dns_query, poll_interfaces};
+pub use self::net_impl::{interface_stats, InterfaceStats};
+pub use self::net_impl::{listen_queue_size, set_listen_queue_size};
 
 use axdriver::{prelude::*, AxDeviceContainer};
 
@@ -7,7 +7,7 @@ use smoltcp::iface::{SocketHandle, SocketSet};
 use smoltcp::socket::tcp::{self, State};
 use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
 
-use super::{SocketSetWrapper, LISTEN_QUEUE_SIZE, SOCKET_SET};
+use super::{listen_queue_size, SocketSetWrapper, SOCKET_SET};
 
 const PORT_NUM: usize = 65536;
 
@@ -20,7 +20,7 @@ impl ListenTableEntry {
     pub fn new(listen_endpoint: IpListenEndpoint) -> Self {
         Self {
             listen_endpoint,
-            syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE),
+            syn_queue: VecDeque::with_capacity(listen_queue_size()),
         }
     }
 
@@ -121,7 +121,7 @@ impl ListenTable {
                 // not listening on this address
                 return;
             }
-            if entry.syn_queue.len() >= LISTEN_QUEUE_SIZE {
+            if entry.syn_queue.len() >= listen_queue_size() {
                 // SYN queue is full, drop the packet
                 warn!("SYN queue overflow!");
                 return;
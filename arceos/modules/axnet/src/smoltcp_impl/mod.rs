@@ -5,9 +5,10 @@ mod listen_table;
 mod tcp;
 mod udp;
 
-use alloc::vec;
+use alloc::{vec, vec::Vec};
 use core::cell::RefCell;
 use core::ops::DerefMut;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 use axdriver::prelude::*;
 use axdriver_net::{DevError, NetBufPtr};
@@ -48,7 +49,10 @@ const TCP_RX_BUF_LEN: usize = 64 * 1024;
 const TCP_TX_BUF_LEN: usize = 64 * 1024;
 const UDP_RX_BUF_LEN: usize = 64 * 1024;
 const UDP_TX_BUF_LEN: usize = 64 * 1024;
-const LISTEN_QUEUE_SIZE: usize = 512;
+const DEFAULT_LISTEN_QUEUE_SIZE: usize = 512;
+
+/// The maximum number of pending connections of a listening socket.
+static LISTEN_QUEUE_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_LISTEN_QUEUE_SIZE);
 
 static LISTEN_TABLE: LazyInit<ListenTable> = LazyInit::new();
 static SOCKET_SET: LazyInit<SocketSetWrapper> = LazyInit::new();
@@ -58,6 +62,39 @@ struct SocketSetWrapper<'a>(Mutex<SocketSet<'a>>);
 
 struct DeviceWrapper {
     inner: RefCell<AxNetDevice>, // use `RefCell` is enough since it's wrapped in `Mutex` in `InterfaceWrapper`.
+    stats: DeviceStats,
+}
+
+/// Traffic counters of a device.
+#[derive(Default)]
+struct DeviceStats {
+    rx_bytes: AtomicU64,
+    rx_packets: AtomicU64,
+    rx_errors: AtomicU64,
+    tx_bytes: AtomicU64,
+    tx_packets: AtomicU64,
+    tx_errors: AtomicU64,
+}
+
+/// Statistics of a network interface.
+#[derive(Debug, Clone)]
+pub struct InterfaceStats {
+    /// The interface name, such as `eth0`.
+    pub name: &'static str,
+    /// The MAC address.
+    pub mac: [u8; 6],
+    /// Bytes received.
+    pub rx_bytes: u64,
+    /// Packets received.
+    pub rx_packets: u64,
+    /// Receive errors.
+    pub rx_errors: u64,
+    /// Bytes transmitted.
+    pub tx_bytes: u64,
+    /// Packets transmitted.
+    pub tx_packets: u64,
+    /// Transmit errors.
+    pub tx_errors: u64,
 }
 
 struct InterfaceWrapper {
@@ -170,6 +207,21 @@ impl InterfaceWrapper {
         };
     }
 
+    pub fn stats(&self) -> InterfaceStats {
+        let dev = self.dev.lock();
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        InterfaceStats {
+            name: self.name,
+            mac: self.ether_addr.0,
+            rx_bytes: load(&dev.stats.rx_bytes),
+            rx_packets: load(&dev.stats.rx_packets),
+            rx_errors: load(&dev.stats.rx_errors),
+            tx_bytes: load(&dev.stats.tx_bytes),
+            tx_packets: load(&dev.stats.tx_packets),
+            tx_errors: load(&dev.stats.tx_errors),
+        }
+    }
+
     pub fn poll(&self, sockets: &Mutex<SocketSet>) {
         let mut dev = self.dev.lock();
         let mut iface = self.iface.lock();
@@ -183,6 +235,7 @@ impl DeviceWrapper {
     fn new(inner: AxNetDevice) -> Self {
         Self {
             inner: RefCell::new(inner),
+            stats: DeviceStats::default(),
         }
     }
 }
@@ -195,6 +248,7 @@ impl Device for DeviceWrapper {
         let mut dev = self.inner.borrow_mut();
         if let Err(e) = dev.recycle_tx_buffers() {
             warn!("recycle_tx_buffers failed: {:?}", e);
+            self.stats.tx_errors.fetch_add(1, Ordering::Relaxed);
             return None;
         }
 
@@ -206,21 +260,26 @@ impl Device for DeviceWrapper {
             Err(err) => {
                 if !matches!(err, DevError::Again) {
                     warn!("receive failed: {:?}", err);
+                    self.stats.rx_errors.fetch_add(1, Ordering::Relaxed);
                 }
                 return None;
             }
         };
-        Some((AxNetRxToken(&self.inner, rx_buf), AxNetTxToken(&self.inner)))
+        Some((
+            AxNetRxToken(&self.inner, rx_buf, &self.stats),
+            AxNetTxToken(&self.inner, &self.stats),
+        ))
     }
 
     fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
         let mut dev = self.inner.borrow_mut();
         if let Err(e) = dev.recycle_tx_buffers() {
             warn!("recycle_tx_buffers failed: {:?}", e);
+            self.stats.tx_errors.fetch_add(1, Ordering::Relaxed);
             return None;
         }
         if dev.can_transmit() {
-            Some(AxNetTxToken(&self.inner))
+            Some(AxNetTxToken(&self.inner, &self.stats))
         } else {
             None
         }
@@ -235,8 +294,8 @@ impl Device for DeviceWrapper {
     }
 }
 
-struct AxNetRxToken<'a>(&'a RefCell<AxNetDevice>, NetBufPtr);
-struct AxNetTxToken<'a>(&'a RefCell<AxNetDevice>);
+struct AxNetRxToken<'a>(&'a RefCell<AxNetDevice>, NetBufPtr, &'a DeviceStats);
+struct AxNetTxToken<'a>(&'a RefCell<AxNetDevice>, &'a DeviceStats);
 
 impl<'a> RxToken for AxNetRxToken<'a> {
     fn preprocess(&self, sockets: &mut SocketSet<'_>) {
@@ -253,6 +312,11 @@ impl<'a> RxToken for AxNetRxToken<'a> {
             rx_buf.packet_len(),
             rx_buf.packet()
         );
+        let stats = self.2;
+        stats.rx_packets.fetch_add(1, Ordering::Relaxed);
+        stats
+            .rx_bytes
+            .fetch_add(rx_buf.packet_len() as u64, Ordering::Relaxed);
         let result = f(rx_buf.packet_mut());
         self.0.borrow_mut().recycle_rx_buffer(rx_buf).unwrap();
         result
@@ -269,6 +333,8 @@ impl<'a> TxToken for AxNetTxToken<'a> {
         let ret = f(tx_buf.packet_mut());
         trace!("SEND {} bytes: {:02X?}", len, tx_buf.packet());
         dev.transmit(tx_buf).unwrap();
+        self.1.tx_packets.fetch_add(1, Ordering::Relaxed);
+        self.1.tx_bytes.fetch_add(len as u64, Ordering::Relaxed);
         ret
     }
 }
@@ -300,6 +366,28 @@ pub fn poll_interfaces() {
     SOCKET_SET.poll_interfaces();
 }
 
+/// Returns the statistics of all network interfaces, or nothing if the
+/// network is not initialized.
+pub fn interface_stats() -> Vec<InterfaceStats> {
+    if ETH0.is_inited() {
+        vec![ETH0.stats()]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Returns the maximum number of pending connections of a listening socket,
+/// like `somaxconn` of Linux.
+pub fn listen_queue_size() -> usize {
+    LISTEN_QUEUE_SIZE.load(Ordering::Relaxed)
+}
+
+/// Sets the maximum number of pending connections of listening sockets. It
+/// must be positive.
+pub fn set_listen_queue_size(size: usize) {
+    LISTEN_QUEUE_SIZE.store(size.max(1), Ordering::Relaxed);
+}
+
 /// Benchmark raw socket transmit bandwidth.
 pub fn bench_transmit() {
     ETH0.dev.lock().bench_transmit_bandwidth();
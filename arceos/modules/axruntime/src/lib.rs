@@ -141,8 +141,10 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
         );
     }
 
-    #[cfg(any(feature = "alloc", feature = "alt_alloc"))]
+    #[cfg(feature = "alloc")]
     init_allocator();
+    #[cfg(feature = "alt_alloc")]
+    init_early_allocator();
 
     #[cfg(feature = "paging")]
     axmm::init_memory_management();
@@ -150,6 +152,11 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     info!("Initialize platform devices...");
     axhal::platform_init();
 
+    // Hand over from the early allocator once the boot-time page tables and
+    // platform data are in place.
+    #[cfg(feature = "alt_alloc")]
+    init_allocator();
+
     #[cfg(feature = "multitask")]
     axtask::init_scheduler();
 
@@ -193,11 +200,29 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     unsafe { main() };
 
     #[cfg(feature = "multitask")]
-    axtask::exit(0);
+    {
+        sync_fs();
+        axtask::exit(0);
+    }
     #[cfg(not(feature = "multitask"))]
     {
         debug!("main task exited: exit_code={}", 0);
-        axhal::misc::terminate();
+        terminate();
+    }
+}
+
+/// Writes back the cached data of all filesystems and shuts down the system.
+pub fn terminate() -> ! {
+    sync_fs();
+    axhal::misc::terminate()
+}
+
+/// Writes back the cached data of all filesystems, e.g. before the system
+/// shuts down.
+pub fn sync_fs() {
+    #[cfg(feature = "fs")]
+    if let Err(e) = axfs::api::sync() {
+        warn!("failed to sync the filesystems: {:?}", e);
     }
 }
 
@@ -230,27 +255,38 @@ fn init_allocator() {
     }
 }
 
+#[cfg(feature = "alt_alloc")]
+fn max_free_region() -> axhal::mem::MemRegion {
+    use axhal::mem::{memory_regions, MemRegionFlags};
+
+    memory_regions()
+        .filter(|r| r.flags.contains(MemRegionFlags::FREE))
+        .max_by_key(|r| r.size)
+        .expect("no free memory region")
+}
+
+#[cfg(feature = "alt_alloc")]
+fn init_early_allocator() {
+    use axhal::mem::phys_to_virt;
+
+    info!("Initialize early memory allocator...");
+    let r = max_free_region();
+    alt_axalloc::global_early_init(phys_to_virt(r.paddr).as_usize(), r.size);
+}
+
 #[cfg(feature = "alt_alloc")]
 fn init_allocator() {
     use axhal::mem::{memory_regions, phys_to_virt, MemRegionFlags};
 
     info!("Initialize global memory allocator...");
-    info!("  use {} allocator.", alt_axalloc::global_allocator().name());
+    let max_region = max_free_region();
+    let max_region_paddr = max_region.paddr;
+    alt_axalloc::global_init(phys_to_virt(max_region_paddr).as_usize(), max_region.size);
+    info!(
+        "  use {} allocator.",
+        alt_axalloc::global_allocator().name()
+    );
 
-    let mut max_region_size = 0;
-    let mut max_region_paddr = 0.into();
-    for r in memory_regions() {
-        if r.flags.contains(MemRegionFlags::FREE) && r.size > max_region_size {
-            max_region_size = r.size;
-            max_region_paddr = r.paddr;
-        }
-    }
-    for r in memory_regions() {
-        if r.flags.contains(MemRegionFlags::FREE) && r.paddr == max_region_paddr {
-            alt_axalloc::global_init(phys_to_virt(r.paddr).as_usize(), r.size);
-            break;
-        }
-    }
     for r in memory_regions() {
         if r.flags.contains(MemRegionFlags::FREE) && r.paddr != max_region_paddr {
             alt_axalloc::global_add_memory(phys_to_virt(r.paddr).as_usize(), r.size)
@@ -5,7 +5,7 @@ use alloc::{string::String, sync::Arc};
 pub(crate) use crate::run_queue::{AxRunQueue, RUN_QUEUE};
 
 #[doc(cfg(feature = "multitask"))]
-pub use crate::task::{CurrentTask, TaskId, TaskInner};
+pub use crate::task::{CurrentTask, TaskId, TaskInner, TaskState};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::task_ext::{TaskExtMut, TaskExtRef};
 #[doc(cfg(feature = "multitask"))]
@@ -119,6 +119,17 @@ where
     spawn_raw(f, "".into(), axconfig::TASK_STACK_SIZE)
 }
 
+/// Returns all tasks that are not yet released, including the exited ones
+/// that are still referenced, in the order of their IDs.
+pub fn all_tasks() -> alloc::vec::Vec<AxTaskRef> {
+    crate::task::all_tasks()
+}
+
+/// Returns the task with the given ID, if it is not yet released.
+pub fn find_task(id: u64) -> Option<AxTaskRef> {
+    crate::task::find_task(id)
+}
+
 /// Set the priority for current task.
 ///
 /// The range of the priority is dependent on the underlying scheduler. For
@@ -166,6 +177,9 @@ pub fn exit(exit_code: i32) -> ! {
 pub fn run_idle() -> ! {
     loop {
         yield_now();
+        // Give the memory cached by this CPU back to the others.
+        #[cfg(feature = "axalloc")]
+        axalloc::global_allocator().drain_cpu_cache();
         debug!("idle task: waiting for IRQs...");
         #[cfg(feature = "irq")]
         axhal::arch::wait_for_irqs();
@@ -1,4 +1,6 @@
-use alloc::{boxed::Box, string::String, sync::Arc};
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::{boxed::Box, string::String, vec::Vec};
 use core::ops::Deref;
 use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, Ordering};
 use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
@@ -10,6 +12,7 @@ use core::sync::atomic::AtomicUsize;
 use axhal::tls::TlsArea;
 
 use axhal::arch::TaskContext;
+use kspin::SpinNoIrq;
 use memory_addr::{align_up_4k, VirtAddr};
 
 use crate::task_ext::AxTaskExt;
@@ -22,13 +25,20 @@ pub struct TaskId(u64);
 /// The possible states of a task.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub(crate) enum TaskState {
+pub enum TaskState {
+    /// The task is running on a CPU.
     Running = 1,
+    /// The task is in the run queue, waiting for a CPU.
     Ready = 2,
+    /// The task is waiting for an event, such as in a wait queue.
     Blocked = 3,
+    /// The task has exited, but is not yet released.
     Exited = 4,
 }
 
+/// All tasks that are not yet released, by their IDs.
+static TASKS: SpinNoIrq<BTreeMap<u64, Weak<AxTask>>> = SpinNoIrq::new(BTreeMap::new());
+
 /// The inner task structure.
 pub struct TaskInner {
     id: TaskId,
@@ -206,11 +216,15 @@ impl TaskInner {
     }
 
     pub(crate) fn into_arc(self) -> AxTaskRef {
-        Arc::new(AxTask::new(self))
+        let id = self.id.as_u64();
+        let task = Arc::new(AxTask::new(self));
+        TASKS.lock().insert(id, Arc::downgrade(&task));
+        task
     }
 
+    /// Gets the state of the task.
     #[inline]
-    pub(crate) fn state(&self) -> TaskState {
+    pub fn state(&self) -> TaskState {
         self.state.load(Ordering::Acquire).into()
     }
 
@@ -340,9 +354,22 @@ impl fmt::Debug for TaskInner {
     }
 }
 
+/// Returns all tasks that are not yet released, in the order of their IDs.
+pub(crate) fn all_tasks() -> Vec<AxTaskRef> {
+    // The references are dropped by the caller, out of the lock, as dropping
+    // the last one removes the task from `TASKS`.
+    TASKS.lock().values().filter_map(Weak::upgrade).collect()
+}
+
+/// Returns the task with the given ID, if it is not yet released.
+pub(crate) fn find_task(id: u64) -> Option<AxTaskRef> {
+    TASKS.lock().get(&id).and_then(Weak::upgrade)
+}
+
 impl Drop for TaskInner {
     fn drop(&mut self) {
         debug!("task drop: {}", self.id_name());
+        TASKS.lock().remove(&self.id.as_u64());
     }
 }
 
@@ -48,6 +48,33 @@ impl<const SIZE: usize> EarlyAllocator<SIZE> {
             alloc_count: 0,
         }
     }
+
+    /// Returns the number of live byte allocations.
+    pub const fn alloc_count(&self) -> usize {
+        self.alloc_count
+    }
+
+    /// Returns the byte-used area `[start, b_pos)`.
+    pub const fn byte_area(&self) -> (usize, usize) {
+        (self.start, self.b_pointer)
+    }
+
+    /// Returns the page-used area `[p_pos, end)`.
+    pub const fn page_area(&self) -> (usize, usize) {
+        (self.p_pointer, self.end)
+    }
+
+    /// Takes the available area `[b_pos, p_pos)` away from the allocator,
+    /// e.g. to hand it over to another allocator.
+    ///
+    /// Later allocations fail, except byte allocations after all bytes have
+    /// been freed, which reuse the byte area `[start, b_pos)`. Call it again
+    /// at that time to take the byte area as well.
+    pub fn take_free_area(&mut self) -> (usize, usize) {
+        let area = (self.b_pointer, self.p_pointer);
+        self.p_pointer = self.b_pointer;
+        area
+    }
 }
 
 impl<const SIZE: usize> BaseAllocator for EarlyAllocator<SIZE> {
@@ -1,3 +1,4 @@
+#define _GNU_SOURCE
 #include <stdio.h>
 #include <stdlib.h>
 #include <string.h>
@@ -44,6 +45,32 @@ void verify_file(const char *fname)
     close(fd);
 }
 
+void verify_remap(void)
+{
+    char *addr;
+    char *moved;
+
+    addr = mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    if (addr == MAP_FAILED) {
+        printf("Map anonymous error!\n");
+        exit(-1);
+    }
+    strcpy(addr, "remapped, arceos!");
+    // occupy the next page, so that the mapping has to move to grow
+    if (mmap(addr + 4096, 4096, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1, 0)
+        == MAP_FAILED) {
+        printf("Map fixed error!\n");
+        exit(-1);
+    }
+    moved = mremap(addr, 4096, 4 * 4096, MREMAP_MAYMOVE);
+    if (moved == MAP_FAILED || moved == addr) {
+        printf("Remap error!\n");
+        exit(-1);
+    }
+    moved[3 * 4096] = 1;
+    printf("Read back remapped content: %s\n", moved);
+}
+
 int main()
 {
     int fd;
@@ -54,6 +81,7 @@ int main()
 
     create_file(fname);
     verify_file(fname);
+    verify_remap();
 
     printf("MapFile ok!\n");
     return 0;
@@ -48,6 +48,10 @@ ifeq ($(BUS),mmio)
   ax_feat += bus-mmio
 endif
 
+ifneq ($(INITRAMFS),)
+  ax_feat += initramfs
+endif
+
 ifeq ($(shell test $(SMP) -gt 1; echo $$?),0)
   lib_feat += smp
 endif
@@ -2,5 +2,8 @@
 
 define unit_test
   $(call run_cmd,cargo test,-p axfs $(1) --features "myfs" -- --nocapture)
+  @test -f modules/axfs/resources/ext2.img || modules/axfs/resources/create_ext2_img.sh
+  $(call run_cmd,cargo test,-p axfs $(1) --features "ext4" -- --nocapture)
+  $(call run_cmd,cargo test,-p axalloc $(1) --features "alloc-debug alloc-stats percpu-cache" -- --nocapture)
   $(call run_cmd,cargo test,--workspace $(1) -- --nocapture)
 endef
@@ -18,8 +18,17 @@ define make_disk_image_fat32
   @mkfs.fat -F 32 $(1)
 endef
 
+# Without the features that `axfs` can only read, such as extents and 64-bit
+# block numbers, so that the image is writable.
+define make_disk_image_ext4
+  @printf "    $(GREEN_C)Creating$(END_C) ext4 disk image \"$(1)\" ...\n"
+  @dd if=/dev/zero of=$(1) bs=1M count=64
+  @mkfs.ext4 -q -F -O ^extent,^64bit,^flex_bg,^huge_file,^dir_nlink,^extra_isize,^metadata_csum $(1)
+endef
+
 define make_disk_image
   $(if $(filter $(1),fat32), $(call make_disk_image_fat32,$(2)))
+  $(if $(filter $(1),ext4), $(call make_disk_image_ext4,$(2)))
 endef
 
 define mk_pflash
@@ -43,6 +52,11 @@ define setup_disk
   @rm -rf mnt
 endef
 
+define make_initramfs
+  @printf "    $(GREEN_C)Creating$(END_C) initramfs \"$(1)\" from \"$(2)\" ...\n"
+  @cd $(2) && find . | cpio -o -H newc --quiet > $(abspath $(1))
+endef
+
 define build_origin
   @RUSTFLAGS="" cargo build -p origin  --target riscv64gc-unknown-none-elf --release
   @rust-objcopy --binary-architecture=riscv64 --strip-all -O binary ./target/riscv64gc-unknown-none-elf/release/origin /tmp/origin.bin
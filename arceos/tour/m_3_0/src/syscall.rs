@@ -3,6 +3,7 @@
 use core::ffi::c_void;
 use axhal::arch::TrapFrame;
 use axhal::trap::{register_trap_handler, SYSCALL};
+use axhal::uaccess::UserSlice;
 use axerrno::LinuxError;
 use axtask::current;
 use axtask::TaskExtRef;
@@ -14,6 +15,9 @@ const SYS_EXIT: usize = 93;
 const SYS_EXIT_GROUP: usize = 94;
 const SYS_SET_TID_ADDRESS: usize = 96;
 
+/// Size of the kernel buffer that user data is copied through.
+const IO_CHUNK_SIZE: usize = 256;
+
 #[register_trap_handler(SYSCALL)]
 fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
     ax_println!("handle_syscall [{}] ...", syscall_num);
@@ -37,8 +41,49 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
     ret
 }
 
+/// Writes `len` bytes at the user address `buf` to `fd`, through a buffer on
+/// the kernel stack.
+fn write_from_user(fd: i32, buf: usize, len: usize) -> isize {
+    let mut kbuf = [0u8; IO_CHUNK_SIZE];
+    let mut written = 0;
+    while written < len {
+        let chunk = &mut kbuf[..(len - written).min(IO_CHUNK_SIZE)];
+        if UserSlice::new(buf + written, chunk.len()).read(chunk).is_err() {
+            return if written > 0 { written as isize } else { -LinuxError::EFAULT.code() as _ };
+        }
+        let n = api::sys_write(fd, chunk.as_ptr() as *const c_void, chunk.len());
+        if n < 0 {
+            return if written > 0 { written as isize } else { n };
+        }
+        written += n as usize;
+        if (n as usize) < chunk.len() {
+            break;
+        }
+    }
+    written as isize
+}
+
 fn sys_writev(fd: i32, iov: *const api::ctypes::iovec, iocnt: i32) -> isize {
-    unsafe { api::sys_writev(fd, iov, iocnt) }
+    if iocnt < 0 {
+        return -LinuxError::EINVAL.code() as _;
+    }
+    let iovs = UserSlice::<api::ctypes::iovec>::new(iov as usize, iocnt as usize);
+    let mut total = 0;
+    for i in 0..iovs.len() {
+        let Ok(iov) = iovs.get(i) else {
+            return -LinuxError::EFAULT.code() as _;
+        };
+        let n = write_from_user(fd, iov.iov_base as usize, iov.iov_len);
+        if n < 0 {
+            // Report the error only if nothing has been written.
+            return if total > 0 { total } else { n };
+        }
+        total += n;
+        if (n as usize) < iov.iov_len {
+            break;
+        }
+    }
+    total
 }
 
 pub(crate) fn sys_set_tid_address(tid_ptd: *const i32) -> isize {
@@ -3,6 +3,7 @@
 use core::ffi::{c_void, c_char, c_int};
 use axhal::arch::TrapFrame;
 use axhal::trap::{register_trap_handler, SYSCALL};
+use axhal::uaccess::{UserPtr, UserSlice};
 use axerrno::LinuxError;
 use axtask::current;
 use axtask::TaskExtRef;
@@ -19,6 +20,10 @@ const SYS_EXIT_GROUP: usize = 94;
 const SYS_SET_TID_ADDRESS: usize = 96;
 
 const AT_FDCWD: i32 = -100;
+const PATH_MAX: usize = 4096;
+
+/// Size of the kernel buffer that user data is copied through.
+const IO_CHUNK_SIZE: usize = 256;
 
 #[register_trap_handler(SYSCALL)]
 fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
@@ -49,7 +54,11 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
 
 fn sys_openat(dfd: c_int, fname: *const c_char, flags: c_int, mode: api::ctypes::mode_t) -> isize {
     assert_eq!(dfd, AT_FDCWD);
-    api::sys_open(fname, flags, mode) as isize
+    let mut buf = [0u8; PATH_MAX];
+    match UserPtr::from(fname).read_str(&mut buf) {
+        Ok(_) => api::sys_open(buf.as_ptr() as *const c_char, flags, mode) as isize,
+        Err(e) => -LinuxError::from(e).code() as _,
+    }
 }
 
 fn sys_close(fd: i32) -> isize {
@@ -57,15 +66,62 @@ fn sys_close(fd: i32) -> isize {
 }
 
 fn sys_read(fd: i32, buf: *mut c_void, count: usize) -> isize {
-    api::sys_read(fd, buf, count)
+    let mut kbuf = [0u8; IO_CHUNK_SIZE];
+    let len = count.min(IO_CHUNK_SIZE);
+    let n = api::sys_read(fd, kbuf.as_mut_ptr() as *mut c_void, len);
+    if n > 0 && UserSlice::new(buf as usize, n as usize).write(&kbuf[..n as usize]).is_err() {
+        return -LinuxError::EFAULT.code() as _;
+    }
+    n
 }
 
 fn sys_write(fd: i32, buf: *const c_void, count: usize) -> isize {
-    api::sys_write(fd, buf, count)
+    write_from_user(fd, buf as usize, count)
+}
+
+/// Writes `len` bytes at the user address `buf` to `fd`, through a buffer on
+/// the kernel stack.
+fn write_from_user(fd: i32, buf: usize, len: usize) -> isize {
+    let mut kbuf = [0u8; IO_CHUNK_SIZE];
+    let mut written = 0;
+    while written < len {
+        let chunk = &mut kbuf[..(len - written).min(IO_CHUNK_SIZE)];
+        if UserSlice::new(buf + written, chunk.len()).read(chunk).is_err() {
+            return if written > 0 { written as isize } else { -LinuxError::EFAULT.code() as _ };
+        }
+        let n = api::sys_write(fd, chunk.as_ptr() as *const c_void, chunk.len());
+        if n < 0 {
+            return if written > 0 { written as isize } else { n };
+        }
+        written += n as usize;
+        if (n as usize) < chunk.len() {
+            break;
+        }
+    }
+    written as isize
 }
 
 fn sys_writev(fd: i32, iov: *const api::ctypes::iovec, iocnt: i32) -> isize {
-    unsafe { api::sys_writev(fd, iov, iocnt) }
+    if iocnt < 0 {
+        return -LinuxError::EINVAL.code() as _;
+    }
+    let iovs = UserSlice::<api::ctypes::iovec>::new(iov as usize, iocnt as usize);
+    let mut total = 0;
+    for i in 0..iovs.len() {
+        let Ok(iov) = iovs.get(i) else {
+            return -LinuxError::EFAULT.code() as _;
+        };
+        let n = write_from_user(fd, iov.iov_base as usize, iov.iov_len);
+        if n < 0 {
+            // Report the error only if nothing has been written.
+            return if total > 0 { total } else { n };
+        }
+        total += n;
+        if (n as usize) < iov.iov_len {
+            break;
+        }
+    }
+    total
 }
 
 fn sys_set_tid_address(tid_ptd: *const i32) -> isize {
@@ -17,33 +17,6 @@ void srandom(unsigned int s)
     srand(s);
 }
 
-#ifdef AX_CONFIG_ALLOC
-
-void *calloc(size_t m, size_t n)
-{
-    void *mem = malloc(m * n);
-
-    return memset(mem, 0, n * m);
-}
-
-void *realloc(void *memblock, size_t size)
-{
-    if (!memblock)
-        return malloc(size);
-
-    size_t o_size = *(size_t *)(memblock - 8);
-
-    void *mem = malloc(size);
-
-    for (int i = 0; i < (o_size < size ? o_size : size); i++)
-        ((char *)mem)[i] = ((char *)memblock)[i];
-
-    free(memblock);
-    return mem;
-}
-
-#endif // AX_CONFIG_ALLOC
-
 long long llabs(long long a)
 {
     return a > 0 ? a : -a;
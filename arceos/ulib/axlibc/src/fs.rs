@@ -1,7 +1,10 @@
 use core::ffi::{c_char, c_int};
 
 use arceos_posix_api::{
-    sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_rename, sys_stat,
+    sys_chmod, sys_chown, sys_fchmod, sys_fchown, sys_fdatasync, sys_flock, sys_fstat, sys_fstatfs,
+    sys_fsync, sys_getcwd, sys_lchown, sys_link, sys_lseek, sys_lstat, sys_mount, sys_open,
+    sys_readlink, sys_rename, sys_stat, sys_statfs, sys_symlink, sys_sync, sys_umount2,
+    sys_utimensat,
 };
 
 use crate::{ctypes, utils::e};
@@ -51,6 +54,101 @@ pub unsafe extern "C" fn lstat(path: *const c_char, buf: *mut ctypes::stat) -> c
     e(sys_lstat(path, buf) as _)
 }
 
+/// Get the space usage of the filesystem that `path` is in, and write into
+/// `buf`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn statfs(path: *const c_char, buf: *mut ctypes::statfs) -> c_int {
+    e(sys_statfs(path, buf))
+}
+
+/// Get the space usage of the filesystem that `fd` is in, and write into
+/// `buf`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn fstatfs(fd: c_int, buf: *mut ctypes::statfs) -> c_int {
+    e(sys_fstatfs(fd, buf))
+}
+
+/// Apply or remove an advisory lock on the whole file `fd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn flock(fd: c_int, operation: c_int) -> c_int {
+    e(sys_flock(fd, operation))
+}
+
+/// Change the permission bits of the file at `path` to `mode`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn chmod(path: *const c_char, mode: ctypes::mode_t) -> c_int {
+    e(sys_chmod(path, mode))
+}
+
+/// Change the permission bits of the file `fd` to `mode`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn fchmod(fd: c_int, mode: ctypes::mode_t) -> c_int {
+    e(sys_fchmod(fd, mode))
+}
+
+/// Change the owner and the group of the file at `path`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn chown(
+    path: *const c_char,
+    uid: ctypes::uid_t,
+    gid: ctypes::gid_t,
+) -> c_int {
+    e(sys_chown(path, uid, gid))
+}
+
+/// Change the owner and the group of the file `fd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn fchown(fd: c_int, uid: ctypes::uid_t, gid: ctypes::gid_t) -> c_int {
+    e(sys_fchown(fd, uid, gid))
+}
+
+/// Change the owner and the group of the symbolic link at `path`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn lchown(
+    path: *const c_char,
+    uid: ctypes::uid_t,
+    gid: ctypes::gid_t,
+) -> c_int {
+    e(sys_lchown(path, uid, gid))
+}
+
+/// Change the access and modification times of the file at `path`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn utimensat(
+    dirfd: c_int,
+    path: *const c_char,
+    times: *const ctypes::timespec,
+    flags: c_int,
+) -> c_int {
+    e(sys_utimensat(dirfd, path, times, flags))
+}
+
+/// Change the access and modification times of the file `fd`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn futimens(fd: c_int, times: *const ctypes::timespec) -> c_int {
+    e(sys_utimensat(fd, core::ptr::null(), times, 0))
+}
+
 /// Get the path of the current directory.
 #[no_mangle]
 pub unsafe extern "C" fn getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
@@ -65,3 +163,84 @@ pub unsafe extern "C" fn getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
 pub unsafe extern "C" fn rename(old: *const c_char, new: *const c_char) -> c_int {
     e(sys_rename(old, new))
 }
+
+/// Create a symbolic link `linkpath` pointing to `target`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn symlink(target: *const c_char, linkpath: *const c_char) -> c_int {
+    e(sys_symlink(target, linkpath))
+}
+
+/// Read the target of the symbolic link `path` into `buf`, without the
+/// terminating null byte.
+///
+/// Return the number of bytes placed in `buf`.
+#[no_mangle]
+pub unsafe extern "C" fn readlink(
+    path: *const c_char,
+    buf: *mut c_char,
+    bufsize: usize,
+) -> ctypes::ssize_t {
+    e(sys_readlink(path, buf, bufsize) as _) as _
+}
+
+/// Create a hard link `new` to the file `old`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn link(old: *const c_char, new: *const c_char) -> c_int {
+    e(sys_link(old, new))
+}
+
+/// Mount the filesystem `source` of the type `fstype` on `target`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn mount(
+    source: *const c_char,
+    target: *const c_char,
+    fstype: *const c_char,
+    flags: core::ffi::c_ulong,
+    data: *const core::ffi::c_void,
+) -> c_int {
+    e(sys_mount(source, target, fstype, flags as _, data))
+}
+
+/// Unmount the filesystem mounted on `target`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn umount(target: *const c_char) -> c_int {
+    e(sys_umount2(target, 0))
+}
+
+/// Unmount the filesystem mounted on `target` with `flags`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn umount2(target: *const c_char, flags: c_int) -> c_int {
+    e(sys_umount2(target, flags))
+}
+
+/// Write the cached data of the file `fd` back to the storage device.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn fsync(fd: c_int) -> c_int {
+    e(sys_fsync(fd))
+}
+
+/// Write the cached data of the file `fd` back to the storage device.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn fdatasync(fd: c_int) -> c_int {
+    e(sys_fdatasync(fd))
+}
+
+/// Write all cached filesystem data back to the storage devices.
+#[no_mangle]
+pub unsafe extern "C" fn sync() {
+    sys_sync()
+}
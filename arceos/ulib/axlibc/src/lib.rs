@@ -92,7 +92,7 @@ pub use self::time::{clock_gettime, nanosleep};
 pub use self::unistd::{abort, exit, getpid};
 
 #[cfg(feature = "alloc")]
-pub use self::malloc::{free, malloc};
+pub use self::malloc::{calloc, free, malloc, realloc};
 #[cfg(feature = "alloc")]
 pub use self::strftime::strftime;
 
@@ -100,7 +100,11 @@ pub use self::strftime::strftime;
 pub use self::fd_ops::{ax_fcntl, close, dup, dup2, dup3};
 
 #[cfg(feature = "fs")]
-pub use self::fs::{ax_open, fstat, getcwd, lseek, lstat, rename, stat};
+pub use self::fs::{
+    ax_open, chmod, chown, fchmod, fchown, fdatasync, flock, fstat, fsync, futimens, getcwd,
+    lchown, link, lseek, lstat, mount, readlink, rename, stat, symlink, sync, umount, umount2,
+    utimensat,
+};
 
 #[cfg(feature = "net")]
 pub use self::net::{
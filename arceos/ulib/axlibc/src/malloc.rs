@@ -1,4 +1,5 @@
-//! Provides the corresponding malloc(size_t) and free(size_t) when using the C user program.
+//! Provides the corresponding malloc(size_t) and free(size_t) when using the C user program,
+//! along with calloc and realloc.
 //!
 //! The normal malloc(size_t) and free(size_t) are provided by the library malloc.h, and
 //! sys_brk is used internally to apply for memory from the kernel. But in a unikernel like
@@ -6,7 +7,8 @@
 //! order to maintain consistency, C user programs also choose to share the kernel heap,
 //! skipping the sys_brk step.
 
-use alloc::alloc::{alloc, dealloc};
+use alloc::alloc::dealloc;
+use axalloc::{caller_site, global_allocator};
 use core::alloc::Layout;
 use core::ffi::c_void;
 
@@ -22,14 +24,60 @@ const CTRL_BLK_SIZE: usize = core::mem::size_of::<MemoryControlBlock>();
 ///
 /// Returns 0 on failure (the current implementation does not trigger an exception)
 #[no_mangle]
+#[cfg_attr(feature = "alloc-debug", inline(never))]
 pub unsafe extern "C" fn malloc(size: ctypes::size_t) -> *mut c_void {
+    let site = caller_site();
+    unsafe { alloc_block(size, site) }
+}
+
+/// Allocate zeroed memory for an array of `nmemb` elements of `size` bytes.
+///
+/// Returns 0 if the total size overflows.
+#[no_mangle]
+#[cfg_attr(feature = "alloc-debug", inline(never))]
+pub unsafe extern "C" fn calloc(nmemb: ctypes::size_t, size: ctypes::size_t) -> *mut c_void {
+    let site = caller_site();
+    let Some(total) = nmemb.checked_mul(size) else {
+        return core::ptr::null_mut();
+    };
+    unsafe {
+        let ptr = alloc_block(total, site);
+        ptr.cast::<u8>().write_bytes(0, total);
+        ptr
+    }
+}
+
+/// Change the size of the memory block pointed to by `ptr` to `size` bytes.
+///
+/// The contents are moved to a new block and the old one is freed. If `ptr` is
+/// null, it is the same as `malloc(size)`.
+#[no_mangle]
+#[cfg_attr(feature = "alloc-debug", inline(never))]
+pub unsafe extern "C" fn realloc(ptr: *mut c_void, size: ctypes::size_t) -> *mut c_void {
+    let site = caller_site();
+    unsafe {
+        let new = alloc_block(size, site);
+        if !ptr.is_null() {
+            let old_size = ptr.cast::<MemoryControlBlock>().sub(1).read().size;
+            core::ptr::copy_nonoverlapping(ptr.cast::<u8>(), new.cast(), old_size.min(size));
+            free(ptr);
+        }
+        new
+    }
+}
+
+/// Allocates `size` bytes after a control block, with the allocation site
+/// reported by the debug heap.
+unsafe fn alloc_block(size: usize, site: usize) -> *mut c_void {
     // Allocate `(actual length) + 8`. The lowest 8 Bytes are stored in the actual allocated space size.
     // This is because free(uintptr_t) has only one parameter representing the address,
     // So we need to save in advance to know the size of the memory space that needs to be released
     let layout = Layout::from_size_align(size + CTRL_BLK_SIZE, 8).unwrap();
+    let ptr = global_allocator()
+        .alloc_at(layout, site)
+        .expect("malloc failed");
     unsafe {
-        let ptr = alloc(layout).cast::<MemoryControlBlock>();
-        assert!(!ptr.is_null(), "malloc failed");
+        let ptr = ptr.as_ptr().cast::<MemoryControlBlock>();
         ptr.write(MemoryControlBlock { size });
         ptr.add(1).cast()
     }
@@ -75,3 +75,26 @@ pub fn remove_file(path: &str) -> io::Result<()> {
 pub fn rename(old: &str, new: &str) -> io::Result<()> {
     arceos_api::fs::ax_rename(old, new)
 }
+
+/// Queries the metadata about a file without following symbolic links.
+pub fn symlink_metadata(path: &str) -> io::Result<Metadata> {
+    arceos_api::fs::ax_symlink_metadata(path).map(Metadata::new)
+}
+
+/// Creates a new symbolic link at `link` pointing to `target`.
+pub fn symlink(target: &str, link: &str) -> io::Result<()> {
+    arceos_api::fs::ax_symlink(target, link)
+}
+
+/// Reads a symbolic link, returning the path that the link points to.
+#[cfg(feature = "alloc")]
+pub fn read_link(path: &str) -> io::Result<String> {
+    arceos_api::fs::ax_read_link(path)
+}
+
+/// Creates a new hard link at `link` to the `original` file.
+///
+/// Both paths must be in the same filesystem.
+pub fn hard_link(original: &str, link: &str) -> io::Result<()> {
+    arceos_api::fs::ax_hard_link(original, link)
+}
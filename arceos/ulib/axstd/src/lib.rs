@@ -30,6 +30,8 @@
 //! - Upperlayer stacks
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
+//!     - `overlayfs`: Keep the root filesystem intact, writing the changes to the memory.
+//!     - `initramfs`: Embed a cpio archive as the root filesystem if there is no disk.
 //!     - `net`: Enable networking support.
 //!     - `dns`: Enable DNS lookup support.
 //!     - `display`: Enable graphics support.
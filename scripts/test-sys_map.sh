@@ -2,6 +2,7 @@
 
 tmp_file=mmap_test_output.txt
 grep_content="Read back content: hello, arceos!"
+remap_content="Read back remapped content: remapped, arceos!"
 
 cd arceos/ || exit
 
@@ -17,10 +18,11 @@ make payload
 make run A=exercises/sys_map/ BLK=y 2>/dev/null | tee $tmp_file
 
 output=$(grep -Ea "$grep_content" ./$tmp_file)
+remap_output=$(grep -Ea "$remap_content" ./$tmp_file)
 
 rm -rf $tmp_file 
 
-if [[ -z "$output" ]]; then
+if [[ -z "$output" || -z "$remap_output" ]]; then
     echo "sys_mmap default"
     exit 1
 else 